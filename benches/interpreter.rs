@@ -0,0 +1,38 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use gem::{interpreter, lexer, parser};
+
+const SCRIPTS: [(&str, &str); 3] = [
+    ("fib", include_str!("scripts/fib.em")),
+    ("arrays", include_str!("scripts/arrays.em")),
+    ("objects", include_str!("scripts/objects.em")),
+];
+
+fn lexing(c: &mut Criterion) {
+    for (name, script) in SCRIPTS {
+        c.bench_function(&format!("lex {}", name), |b| b.iter(|| lexer::run(script)));
+    }
+}
+
+fn parsing(c: &mut Criterion) {
+    for (name, script) in SCRIPTS {
+        let tokens = lexer::run(script);
+        c.bench_function(&format!("parse {}", name), |b| b.iter(|| parser::parse(tokens.clone())));
+    }
+}
+
+//parsing happens once up front, so this only measures walk_tree and everything under it
+fn running(c: &mut Criterion) {
+    for (name, script) in SCRIPTS {
+        let tree = parser::parse(lexer::run(script)).unwrap();
+        c.bench_function(&format!("run {}", name), |b| {
+            b.iter(|| {
+                let mut runtime = interpreter::Runtime::new();
+                runtime.set_stdout(Box::new(std::io::sink()));
+                interpreter::run_on(&mut runtime, tree.clone(), vec![]);
+            })
+        });
+    }
+}
+
+criterion_group!(benches, lexing, parsing, running);
+criterion_main!(benches);
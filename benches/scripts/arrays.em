@@ -0,0 +1,17 @@
+fn total(nums) {
+    t = 0;
+    for (i = 0; i < len(nums); i++) {
+        t += nums[i];
+    }
+    return t;
+}
+
+fn main(args) {
+    nums = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    for (i = 0; i < len(nums); i++) {
+        nums[i] = i * i;
+    }
+    for (j = 0; j < 50; j++) {
+        println(total(nums));
+    }
+}
@@ -0,0 +1,10 @@
+fn fib(n) {
+    if n < 2 {
+        return n;
+    }
+    return fib(n - 1) + fib(n - 2);
+}
+
+fn main(args) {
+    println(fib(18));
+}
@@ -0,0 +1,17 @@
+class Counter {
+    fn ~init(self) {
+        self.count = 0;
+    }
+
+    fn bumped(self, by) {
+        return self.count + by;
+    }
+}
+
+fn main(args) {
+    c = new Counter();
+    for (i = 0; i < 2000; i++) {
+        c.count = c.bumped(i);
+    }
+    println(c.count);
+}
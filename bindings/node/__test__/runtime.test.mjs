@@ -0,0 +1,31 @@
+import { test } from "node:test";
+import assert from "node:assert";
+import { createRequire } from "node:module";
+
+const { EmeraldRuntime } = createRequire(import.meta.url)("../index.js");
+
+test("eval and globals", () => {
+  const rt = new EmeraldRuntime();
+  assert.strictEqual(rt.eval("x = 1 + 2"), 3);
+  rt.setGlobal("names", ["a", "b", { c: true }]);
+  assert.strictEqual(rt.eval("len(names)"), 3);
+  assert.deepStrictEqual(rt.getGlobal("names"), ["a", "b", { c: true }]);
+  assert.strictEqual(rt.getGlobal("missing"), null);
+});
+
+test("call", () => {
+  const rt = new EmeraldRuntime();
+  rt.eval("fn double(n) { return n * 2 }");
+  assert.strictEqual(rt.call("double", [21]), 42);
+  assert.throws(() => rt.call("len", []), /len takes 1 argument, got 0/);
+});
+
+test("js callbacks", () => {
+  const rt = new EmeraldRuntime();
+  rt.registerFn("shout", (s) => s.toUpperCase() + "!");
+  rt.registerFn("fail", () => {
+    throw new Error("nope");
+  });
+  assert.strictEqual(rt.eval('shout("hi")'), "HI!");
+  assert.throws(() => rt.eval("fail()"), /nope/);
+});
@@ -0,0 +1,154 @@
+//! Lets JavaScript run scripts through `EmeraldRuntime`. Values are converted both ways, arrays
+//! become arrays and objects become plain objects of their fields. Anything without a JS equivalent
+//! (functions, classes, natives) comes out as the text it would print as
+
+#[macro_use]
+extern crate napi_derive;
+
+use gem::interpreter::{EmObject, OrderedMap, Signature, Value};
+use gem::Engine;
+use napi::{Env, Error, JsFunction, JsObject, JsUnknown, Ref, Result, ValueType};
+use std::sync::{Arc, Mutex};
+
+fn to_js(env: &Env, val: &Value) -> Result<JsUnknown> {
+    Ok(match val {
+        Value::Null => env.get_null()?.into_unknown(),
+        Value::Float(f) => env.create_double(*f )?.into_unknown(),
+        Value::EmBool(b) => env.get_boolean(*b)?.into_unknown(),
+        Value::EmString(s) => env.create_string(s)?.into_unknown(),
+        Value::EmArray(a) => {
+            let mut arr = env.create_array_with_length(a.len())?;
+            for (i, v) in a.iter().enumerate() {
+                arr.set_element(i as u32, to_js(env, v)?)?;
+            }
+            arr.into_unknown()
+        }
+        Value::Object(o) => {
+            let mut obj = env.create_object()?;
+            for (k, v) in o.members.iter() {
+                obj.set_named_property(k, to_js(env, v)?)?;
+            }
+            obj.into_unknown()
+        }
+        other => env.create_string(&other.to_string())?.into_unknown(),
+    })
+}
+
+fn from_js(val: JsUnknown) -> Result<Value> {
+    Ok(match val.get_type()? {
+        ValueType::Null | ValueType::Undefined => Value::Null,
+        ValueType::Boolean => Value::EmBool(val.coerce_to_bool()?.get_value()?),
+        ValueType::Number => Value::Float(val.coerce_to_number()?.get_double()?),
+        ValueType::String => Value::EmString(val.coerce_to_string()?.into_utf8()?.as_str()?.into()),
+        ValueType::Object => {
+            let obj: JsObject = unsafe { val.cast() };
+            if obj.is_array()? {
+                let items = (0..obj.get_array_length()?)
+                    .map(|i| from_js(obj.get_element(i)?))
+                    .collect::<Result<Vec<Value>>>()?;
+                Value::EmArray(Arc::new(items))
+            } else {
+                let names = obj.get_property_names()?;
+                let mut members = OrderedMap::new();
+                for i in 0..names.get_array_length()? {
+                    let key = names.get_element::<JsUnknown>(i)?.coerce_to_string()?.into_utf8()?.into_owned()?;
+                    let v = from_js(obj.get_named_property(&key)?)?;
+                    members.insert(key, Box::new(v));
+                }
+                Value::Object(Arc::new(EmObject::new(members)))
+            }
+        }
+        other => return Err(Error::from_reason(format!("Can't pass a {} to a script", other))),
+    })
+}
+
+///Calls a registered JS function with the arguments a script gave it
+fn call_js(env: &Env, func: &Ref<()>, args: Vec<Value>) -> Result<Value> {
+    let func: JsFunction = env.get_reference_value(func)?;
+    let args = args.iter().map(|v| to_js(env, v)).collect::<Result<Vec<JsUnknown>>>()?;
+    from_js(func.call(None, &args)?)
+}
+
+///Something from the JS engine, which can't leave its thread. Runtimes are made and used from JS,
+///so everything inside one stays on that thread even though the runtime could be moved
+struct OnJsThread<T>(T);
+
+unsafe impl<T> Send for OnJsThread<T> {}
+unsafe impl<T> Sync for OnJsThread<T> {}
+
+#[napi]
+pub struct EmeraldRuntime {
+    engine: Engine,
+    //JS callbacks can only be called with the env of whatever call is running right now, so this
+    //holds it while eval or call is running
+    env: Arc<Mutex<OnJsThread<Option<Env>>>>,
+}
+
+#[napi]
+impl EmeraldRuntime {
+    #[napi(constructor)]
+    pub fn new() -> Self {
+        EmeraldRuntime {
+            engine: Engine::new(),
+            env: Arc::new(Mutex::new(OnJsThread(None))),
+        }
+    }
+
+    fn set_env(&self, env: Option<Env>) {
+        self.env.lock().unwrap_or_else(|e| e.into_inner()).0 = env;
+    }
+
+    ///Runs code and gives back the value of the last statement
+    #[napi]
+    pub fn eval(&mut self, env: Env, code: String) -> Result<JsUnknown> {
+        self.set_env(Some(env));
+        let res = self.engine.eval(&code);
+        self.set_env(None);
+        to_js(&env, &res.map_err(Error::from_reason)?)
+    }
+
+    ///Calls a function the scripts have defined, or a builtin
+    #[napi]
+    pub fn call(&mut self, env: Env, name: String, args: Option<Vec<JsUnknown>>) -> Result<JsUnknown> {
+        let args = args.unwrap_or_default().into_iter().map(from_js).collect::<Result<Vec<Value>>>()?;
+        self.set_env(Some(env));
+        let res = self.engine.call(&name, args);
+        self.set_env(None);
+        to_js(&env, &res.map_err(Error::from_reason)?)
+    }
+
+    #[napi]
+    pub fn get_global(&self, env: Env, name: String) -> Result<JsUnknown> {
+        to_js(&env, self.engine.get_global(&name).unwrap_or(&Value::Null))
+    }
+
+    #[napi]
+    pub fn set_global(&mut self, name: String, value: JsUnknown) -> Result<()> {
+        self.engine.set_global(&name, from_js(value)?);
+        Ok(())
+    }
+
+    ///Lets scripts call `callback` as `name(...)`. If it throws, the script gets the error
+    #[napi]
+    pub fn register_fn(&mut self, env: Env, name: String, callback: JsFunction) -> Result<()> {
+        //the runtime lives as long as the JS object, so the reference is kept for good
+        let func = OnJsThread(env.create_reference(callback)?);
+        let current = self.env.clone();
+        self.engine.register_fn(
+            &name,
+            Signature::ANY,
+            Arc::new(move |rt, args| {
+                let env = current.lock().unwrap_or_else(|e| e.into_inner()).0;
+                let res = match env {
+                    Some(env) => call_js(&env, &func.0, args).map_err(|e| e.reason),
+                    None => Err("JS functions can only be called while the runtime is running".to_owned()),
+                };
+                res.unwrap_or_else(|e| {
+                    rt.raise(e);
+                    Value::Null
+                })
+            }),
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,108 @@
+//! Lets Python run scripts through `emerald.EmeraldRuntime`. Values are converted both ways, arrays
+//! become lists, tuples stay tuples, bytes stay bytes, and objects become dicts of their fields. Anything without a Python equivalent
+//! (functions, classes, natives) comes out as the text it would print as
+
+use gem::interpreter::{EmObject, OrderedMap, Value};
+use gem::Engine;
+use pyo3::exceptions::{PyRuntimeError, PyTypeError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBool, PyBytes, PyDict, PyList, PyTuple};
+use std::sync::Arc;
+
+fn to_py(py: Python, val: &Value) -> PyObject {
+    match val {
+        Value::Null => py.None(),
+        Value::Float(f) => f.into_py(py),
+        Value::EmBool(b) => b.into_py(py),
+        Value::EmString(s) => s.as_ref().into_py(py),
+        Value::Bytes(b) => PyBytes::new(py, b).into_py(py),
+        Value::EmArray(a) => PyList::new(py, a.iter().map(|v| to_py(py, v))).into_py(py),
+        Value::Tuple(t) => PyTuple::new(py, t.iter().map(|v| to_py(py, v))).into_py(py),
+        Value::Object(o) => {
+            let dict = PyDict::new(py);
+            for (k, v) in o.members.iter() {
+                //setting a str key on a fresh dict can't fail
+                dict.set_item(k, to_py(py, v)).ok();
+            }
+            dict.into_py(py)
+        }
+        other => other.to_string().into_py(py),
+    }
+}
+
+fn from_py(obj: &PyAny) -> PyResult<Value> {
+    if obj.is_none() {
+        return Ok(Value::Null);
+    }
+    //bool is a subclass of int in Python, so it has to be checked first
+    if let Ok(b) = obj.downcast::<PyBool>() {
+        return Ok(Value::EmBool(b.is_true()));
+    }
+    if let Ok(f) = obj.extract::<f64>() {
+        return Ok(Value::Float(f));
+    }
+    if let Ok(s) = obj.extract::<&str>() {
+        return Ok(Value::EmString(s.into()));
+    }
+    if let Ok(b) = obj.downcast::<PyBytes>() {
+        return Ok(Value::Bytes(Arc::new(b.as_bytes().to_vec())));
+    }
+    if let Ok(l) = obj.downcast::<PyList>() {
+        return Ok(Value::EmArray(Arc::new(l.iter().map(from_py).collect::<PyResult<_>>()?)));
+    }
+    if let Ok(t) = obj.downcast::<PyTuple>() {
+        return Ok(Value::Tuple(Arc::new(t.iter().map(from_py).collect::<PyResult<_>>()?)));
+    }
+    if let Ok(d) = obj.downcast::<PyDict>() {
+        let mut members = OrderedMap::new();
+        for (k, v) in d.iter() {
+            members.insert(k.extract::<String>()?, Box::new(from_py(v)?));
+        }
+        return Ok(Value::Object(Arc::new(EmObject::new(members))));
+    }
+    Err(PyTypeError::new_err(format!("Can't pass a {} to a script", obj.get_type().name()?)))
+}
+
+///A runtime with its own globals. It can be handed to other threads, but only one of them can use
+///it at a time
+#[pyclass]
+struct EmeraldRuntime {
+    engine: Engine,
+}
+
+#[pymethods]
+impl EmeraldRuntime {
+    #[new]
+    fn new() -> Self {
+        EmeraldRuntime { engine: Engine::new() }
+    }
+
+    ///Runs code and gives back the value of the last statement
+    fn eval(&mut self, py: Python, code: &str) -> PyResult<PyObject> {
+        let val = self.engine.eval(code).map_err(PyRuntimeError::new_err)?;
+        Ok(to_py(py, &val))
+    }
+
+    ///Calls a function the scripts have defined, or a builtin
+    #[pyo3(signature = (name, *args))]
+    fn call(&mut self, py: Python, name: &str, args: &PyTuple) -> PyResult<PyObject> {
+        let args = args.iter().map(from_py).collect::<PyResult<Vec<Value>>>()?;
+        let val = self.engine.call(name, args).map_err(PyRuntimeError::new_err)?;
+        Ok(to_py(py, &val))
+    }
+
+    fn get_global(&self, py: Python, name: &str) -> PyObject {
+        self.engine.get_global(name).map_or_else(|| py.None(), |v| to_py(py, v))
+    }
+
+    fn set_global(&mut self, name: &str, val: &PyAny) -> PyResult<()> {
+        self.engine.set_global(name, from_py(val)?);
+        Ok(())
+    }
+}
+
+#[pymodule]
+fn emerald(_py: Python, m: &PyModule) -> PyResult<()> {
+    m.add_class::<EmeraldRuntime>()?;
+    Ok(())
+}
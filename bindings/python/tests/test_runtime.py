@@ -0,0 +1,24 @@
+from emerald import EmeraldRuntime
+import pytest
+
+
+def test_eval_and_globals():
+    rt = EmeraldRuntime()
+    assert rt.eval("x = 1 + 2") == 3.0
+    rt.set_global("names", ["a", "b", {"c": True}])
+    assert rt.eval("len(names)") == 3.0
+    assert rt.get_global("names") == ["a", "b", {"c": True}]
+    assert rt.get_global("missing") is None
+
+
+def test_call():
+    rt = EmeraldRuntime()
+    rt.eval("fn double(n) { return n * 2 }")
+    assert rt.call("double", 21) == 42.0
+    assert rt.call("len", "four") == 4.0
+
+
+def test_errors():
+    rt = EmeraldRuntime()
+    with pytest.raises(RuntimeError, match="len takes 1 argument, got 0"):
+        rt.eval("len()")
@@ -0,0 +1,75 @@
+//! The interpreter compiled to WebAssembly for `gem-bin export-playground`. wasm-bindgen is only
+//! used with the `bindgen` feature, the playground's exports are plain functions so the loader is a
+//! few lines of JS that can be inlined into the page along with the module
+
+use gem::interpreter::Runtime;
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+thread_local! {
+    static OUTPUT: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+}
+
+#[derive(Clone, Default)]
+struct Captured(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+///Runs a script like `gem-bin script.em` would and gives back everything it printed, errors included
+fn run(script: String) -> Vec<u8> {
+    let captured = Captured::default();
+    let mut runtime = Runtime::new();
+    runtime.set_stdout(Box::new(captured.clone()));
+    runtime.set_stderr(Box::new(captured.clone()));
+    //there's nothing to run in a browser anyway
+    runtime.set_allow_exec(false);
+    gem::run_with(runtime, script, vec![], false);
+    let out = captured.0.lock().unwrap_or_else(|e| e.into_inner());
+    out.clone()
+}
+
+///`eval(source)` from JS, runs a whole script and gives back what it printed
+#[cfg(feature = "bindgen")]
+#[wasm_bindgen::prelude::wasm_bindgen]
+pub fn eval(source: &str) -> String {
+    String::from_utf8_lossy(&run(source.to_owned())).into_owned()
+}
+
+///Makes room for the script, the page copies it in and then hands the buffer to `em_run`
+#[no_mangle]
+pub extern "C" fn em_alloc(len: usize) -> *mut u8 {
+    let mut buf = Vec::with_capacity(len);
+    let ptr = buf.as_mut_ptr();
+    std::mem::forget(buf);
+    ptr
+}
+
+///Runs a script the same way `gem-bin script.em` would and gives back where everything it printed
+///starts, `em_output_len` says how long that is. Takes back the buffer from `em_alloc`
+///
+/// # Safety
+/// `ptr` has to come from `em_alloc(len)`, and can't be used again afterwards
+#[no_mangle]
+pub unsafe extern "C" fn em_run(ptr: *mut u8, len: usize) -> *const u8 {
+    let script = String::from_utf8_lossy(&Vec::from_raw_parts(ptr, len, len)).into_owned();
+    let out = run(script);
+    OUTPUT.with(|o| {
+        *o.borrow_mut() = out;
+        o.borrow().as_ptr()
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn em_output_len() -> usize {
+    OUTPUT.with(|o| o.borrow().len())
+}
@@ -0,0 +1,27 @@
+/* cargo build && cc examples/embed.c -Iinclude -Ltarget/debug -lgem -o embed && LD_LIBRARY_PATH=target/debug ./embed */
+#include <stdio.h>
+#include "emerald.h"
+
+static EmValue *square(const EmValue *const *args, size_t argc, void *user_data) {
+    (void)user_data;
+    float n = argc > 0 ? em_value_as_number(args[0]) : 0;
+    return em_value_number(n * n);
+}
+
+int main(void) {
+    EmRuntime *rt = em_runtime_new();
+    em_register_fn(rt, "square", square, NULL);
+
+    EmValue *val = em_eval(rt, "x = square(4) + 1");
+    if (val == NULL) {
+        fprintf(stderr, "error: %s\n", em_last_error(rt));
+        return 1;
+    }
+    char *text = em_value_to_string(val);
+    printf("x = %s\n", text);
+    em_string_free(text);
+    em_value_free(val);
+
+    em_runtime_free(rt);
+    return 0;
+}
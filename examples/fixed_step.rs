@@ -0,0 +1,36 @@
+//! Drives a script the way a game loop would, `cargo run --example fixed_step`
+
+use gem::Engine;
+
+const SCRIPT: &str = "
+// the state is whatever on_init gives back, and each update hands back the next one
+fn on_init() { return [0, 10]; }
+
+fn on_update(ball, dt) {
+    ball[0] = ball[0] + ball[1] * dt;
+    ball[1] = ball[1] - 9.8 * dt;
+    if ball[0] < 0 {
+        ball[0] = 0;
+        ball[1] = -ball[1] * 0.8;
+    }
+    return ball;
+}
+
+fn on_shutdown(ball) { println(\"stopped at\", ball[0]); }
+";
+
+fn main() {
+    let mut engine = Engine::new();
+    engine.eval(SCRIPT).expect("the script should load");
+    let rt = engine.runtime();
+    //the script always sees 60 steps a second, however uneven the frames are
+    rt.set_fixed_step(Some(1.0 / 60.0));
+    rt.set_tick_fuel(Some(100_000));
+    for frame in [0.016, 0.033, 0.010, 0.050].iter().cycle().take(40) {
+        match rt.tick(*frame) {
+            Ok(steps) => println!("{} steps, ball is at {}", steps, rt.state()),
+            Err(e) => eprintln!("The script failed this frame: {}", e),
+        }
+    }
+    rt.shutdown().expect("shutting down shouldn't fail");
+}
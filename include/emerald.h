@@ -0,0 +1,55 @@
+/* C interface for embedding the gem interpreter, see src/ffi/mod.rs for the details of each
+ * function. Link against the cdylib or staticlib built from this crate. */
+
+#ifndef EMERALD_H
+#define EMERALD_H
+
+#include <stdbool.h>
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct EmRuntime EmRuntime;
+typedef struct EmValue EmValue;
+
+typedef enum EmType {
+    EM_NULL,
+    EM_NUMBER,
+    EM_STRING,
+    EM_BOOL,
+    EM_OTHER,
+} EmType;
+
+/* Arguments belong to the runtime. Return a new value, or NULL for null. It's called on whatever
+   thread the runtime is being used from */
+typedef EmValue *(*EmCallback)(const EmValue *const *args, size_t argc, void *user_data);
+
+EmRuntime *em_runtime_new(void);
+void em_runtime_free(EmRuntime *rt);
+
+EmValue *em_eval(EmRuntime *rt, const char *code);
+const char *em_last_error(const EmRuntime *rt);
+EmValue *em_get_global(const EmRuntime *rt, const char *name);
+void em_set_global(EmRuntime *rt, const char *name, const EmValue *val);
+void em_register_fn(EmRuntime *rt, const char *name, EmCallback callback, void *user_data);
+
+EmValue *em_value_null(void);
+EmValue *em_value_number(double n);
+EmValue *em_value_bool(bool b);
+EmValue *em_value_string(const char *s);
+
+EmType em_value_type(const EmValue *val);
+double em_value_as_number(const EmValue *val);
+bool em_value_as_bool(const EmValue *val);
+char *em_value_to_string(const EmValue *val);
+
+void em_value_free(EmValue *val);
+void em_string_free(char *s);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,9 @@
+fn main(args) {
+    x = 5;
+    x += 3;
+    x -= 1;
+    x *= 2;
+    x /= 7;
+    x++;
+    println(x);
+}
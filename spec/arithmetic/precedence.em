@@ -0,0 +1,7 @@
+fn main(args) {
+    println(1 + 2 * 3);
+    println((1 + 2) * 3);
+    println(10 - 4 - 3);
+    println(7 / 2);
+    println(-2 * 3);
+}
@@ -0,0 +1,9 @@
+fn main(args) {
+    arr = [3, -1, 4, 0, 5];
+    x = "kept";
+    println([x * 2 for x in arr if x > 0]);
+    println([x for x in arr], x);
+    println([c + c for c in "abc"]);
+    println([[r, c] for r in range(0, 2)]);
+    println([n for n in [i * i for i in range(0, 5)] if n > 3]);
+}
@@ -0,0 +1,9 @@
+fn main(args) {
+    a = [1, [2, 3], "four"];
+    println(a[0], a[1][1], a[2]);
+    a[0] = 9;
+    println(a);
+    println(len(a), push(a, 5));
+    a[-1] = "last";
+    println(a[-1], a[-3], a[1][-2]);
+}
@@ -0,0 +1,16 @@
+fn one_hot(i) {
+    row = [0, 0, 0];
+    row[i] = 1;
+    return row;
+}
+
+fn main(args) {
+    i = 0;
+    while i < 3 {
+        println(one_hot(i));
+        greeting = "hello " + "world";
+        i = i + 1;
+    }
+    println(greeting);
+    println([1, 2] + 1, -(2 * 3), [1, 2][0], 0..2);
+}
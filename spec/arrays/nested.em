@@ -0,0 +1,15 @@
+fn main(args) {
+    g = grid(2, 3, 0);
+    for (r = 0; r < 2; r++) {
+        for (c = 0; c < 3; c++) {
+            g[r][c] = r * 3 + c;
+        }
+    }
+    println(g);
+    println(transpose(g));
+    cube = [grid(2, 2, 0)];
+    i = 1;
+    cube[0][i][i - 1] = 7;
+    cube[i - 1][-1][-1] = 8;
+    println(cube);
+}
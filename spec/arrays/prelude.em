@@ -0,0 +1,8 @@
+fn big(n) {
+    return n > 2;
+}
+
+fn main(args) {
+    r = range(0, 5);
+    println(filter(r, big), reverse(r), find(r, big), max(r));
+}
@@ -0,0 +1,9 @@
+fn main(args) {
+    words = ["zero", "one", "two", "three", "four"];
+    for i in 1..len(words) - 1 {
+        println(i, words[i]);
+    }
+    println(words[1..3], words[-2..5], "emerald"[0..3]);
+    println(range(5), range(0, 10, 3).to_array(), sum(1..101));
+    println([n * n for n in range(1, 10, 2)]);
+}
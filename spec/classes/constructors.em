@@ -0,0 +1,17 @@
+class Point {
+    fn ~init(self, x, y) {
+        self.x = x;
+        self.y = y;
+    }
+
+    fn sum(self) {
+        return self.x + self.y;
+    }
+}
+
+fn main(args) {
+    p = new Point(3, 4);
+    println(p.x, p.y, p.sum());
+    p.x = 10;
+    println(p.sum());
+}
@@ -0,0 +1,37 @@
+// A function that yields gives back an iterator when it's called, and only runs up to each yield
+fn fibs() {
+    a = 0;
+    b = 1;
+    while true {
+        yield a;
+        next = a + b;
+        a = b;
+        b = next;
+    }
+}
+
+fn words(text) {
+    word = "";
+    for c in text {
+        if c == " " {
+            if len(word) > 0 {
+                yield word;
+            }
+            word = "";
+        } else {
+            word += c;
+        }
+    }
+    if len(word) > 0 {
+        yield word;
+    }
+}
+
+fn main(args) {
+    println(fibs().take(10).to_array());
+    for w in words("  lazy   words here") {
+        println(w);
+    }
+    gen = words("one two");
+    println(type(gen), gen.~next(), to_array(gen), gen.~next());
+}
@@ -0,0 +1,13 @@
+fn sign(n) {
+    if n < 0 {
+        return "negative";
+    } elif n == 0 {
+        return "zero";
+    } else {
+        return "positive";
+    }
+}
+
+fn main(args) {
+    println(sign(-3), sign(0), sign(8));
+}
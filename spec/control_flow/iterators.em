@@ -0,0 +1,37 @@
+// Anything with a ~next method can be looped over, ~next gives back null when it's done
+class Countdown {
+    fn ~init(self, from) {
+        self.n = from;
+    }
+
+    fn ~next(self) {
+        if self.n <= 0 {
+            return null;
+        }
+        self.n -= 1;
+        return self.n + 1;
+    }
+}
+
+class Naturals {
+    fn ~init(self) {
+        self.n = 0;
+    }
+
+    fn ~next(self) {
+        self.n += 1;
+        return self.n;
+    }
+}
+
+fn main(args) {
+    c = new Countdown(3);
+    for x in c {
+        println(x);
+    }
+    println(c.n, to_array(c));
+    println(take(new Naturals(), 4).to_array(), skip(1..6, 3).to_array());
+    for pair in enumerate(zip(["a", "b"], new Countdown(9))) {
+        println(pair);
+    }
+}
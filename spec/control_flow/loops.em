@@ -0,0 +1,13 @@
+fn main(args) {
+    total = 0;
+    for (i = 0; i < 5; i++) {
+        total += i;
+    }
+    println(total);
+    n = 3;
+    while n > 0 {
+        print(n);
+        n--;
+    }
+    println("");
+}
@@ -0,0 +1,22 @@
+// Functions that await run as tasks, and each await lets the other tasks take a turn
+fn ticker(name, times, ms) {
+    for (i = 0; i < times; i++) {
+        await sleep_async(ms);
+        println(name, i);
+    }
+    return name + " finished";
+}
+
+fn add_later(a, b) {
+    await sleep_async(5);
+    return a + b;
+}
+
+fn main(args) {
+    fast = spawn(ticker, "fast", 3, 10);
+    slow = ticker("slow", 2, 25);
+    println(type(fast), fast.done());
+    println(await slow);
+    println(await fast, fast.done());
+    println(await add_later(1, 2) * 10);
+}
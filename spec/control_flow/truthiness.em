@@ -0,0 +1,11 @@
+fn check(v) {
+    if v {
+        return "yes";
+    }
+    return "no";
+}
+
+fn main(args) {
+    println(check(0), check(1), check(""), check("a"), check([]), check([0]), check(null));
+    println(true && false, true || false, !true);
+}
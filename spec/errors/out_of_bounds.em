@@ -0,0 +1,6 @@
+fn main(args) {
+    a = [1, 2];
+    println("before");
+    println(a[-3]);
+    println("after");
+}
@@ -0,0 +1,8 @@
+fn two(a, b) {
+    return a + b;
+}
+
+fn main(args) {
+    f = two;
+    println(f(1));
+}
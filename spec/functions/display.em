@@ -0,0 +1,33 @@
+fn add(a, b) {
+    return a + b;
+}
+
+fn nothing() {}
+
+class Point {
+    fn ~init(self, x) {
+        self.x = x;
+    }
+
+    fn norm(self) {
+        return self.x;
+    }
+}
+
+fn main(args) {
+    println(add);
+    f = nothing;
+    println(f);
+    p = new Point(3);
+    println(p.norm);
+    println(type(add));
+
+    all = builtins();
+    println(len(all) > 10);
+    for b in all {
+        if b.name() == "len" || b.name() == "range" || b.name() == "print" || b.name() == "format" {
+            println(b);
+        }
+    }
+    println(type(all[0]));
+}
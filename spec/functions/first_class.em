@@ -0,0 +1,12 @@
+fn double(n) {
+    return n * 2;
+}
+
+fn apply(f, v) {
+    return f(v);
+}
+
+fn main(args) {
+    f = double;
+    println(f(4), apply(double, 5), map([1, 2, 3], double));
+}
@@ -0,0 +1,7 @@
+fn main(args) {
+    println(later(2));
+}
+
+fn later(n) {
+    return n * 10;
+}
@@ -0,0 +1,14 @@
+class Fields {
+    fn ~init(self, user, note) {
+        self.user = user;
+        self.note = note;
+    }
+}
+
+fn main(args) {
+    log.debug("starting");
+    log.info("signed in", new Fields("ada", "first time"));
+    log.warn("disk almost full");
+    log.error("gave up", new Fields(3, ""));
+    println("done");
+}
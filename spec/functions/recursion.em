@@ -0,0 +1,10 @@
+fn fact(n) {
+    if n <= 1 {
+        return 1;
+    }
+    return n * fact(n - 1);
+}
+
+fn main(args) {
+    println(fact(10));
+}
@@ -0,0 +1,17 @@
+// thread_spawn runs a function on another thread, and channels carry copies of values between them
+fn sum_squares(from, to, results) {
+    total = 0;
+    for i in from..to {
+        total += i * i;
+    }
+    send(results, total);
+    return to - from;
+}
+
+fn main(args) {
+    results = channel();
+    first = thread_spawn(sum_squares, 0, 50, results);
+    second = thread_spawn(sum_squares, 50, 100, results);
+    counted = first.join() + second.join();
+    println(counted, recv(results) + recv(results));
+}
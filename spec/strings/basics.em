@@ -0,0 +1,8 @@
+fn main(args) {
+    s = "Hello";
+    println(s + ", world");
+    println(len(s), s.upper(), s.lower());
+    println("a,b,c".split(","));
+    println(" pad ".trim() + "!");
+    println(s[0], s[-1], "héllo"[1]);
+}
@@ -0,0 +1,165 @@
+//! Counts how often values and nodes get copied and maps get made, and where, so there's something
+//! better than a hunch to go on when deciding which copies are worth getting rid of. Only built with
+//! the `debug-alloc` feature, since every copy has to take a lock to be counted.
+//! Counts are for the whole process, `reset` before a run to only see that run. Copies made through
+//! things like `Option::cloned` show up as the standard library's code, since it doesn't pass on
+//! where it was called from
+
+#[cfg(test)]
+mod tests;
+
+use crate::interpreter::Value;
+use crate::parser::ExprNode;
+use std::collections::HashMap;
+use std::fmt;
+use std::panic::Location;
+use std::sync::Mutex;
+
+///What got copied or made
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Kind {
+    Value,
+    Node,
+    Map,
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Kind::Value => write!(f, "values"),
+            Kind::Node => write!(f, "nodes"),
+            Kind::Map => write!(f, "maps"),
+        }
+    }
+}
+
+type Site = (Kind, &'static Location<'static>);
+
+static COUNTS: Mutex<Option<HashMap<Site, u64>>> = Mutex::new(None);
+
+pub(crate) fn record(kind: Kind, at: &'static Location<'static>) {
+    let mut counts = COUNTS.lock().unwrap_or_else(|e| e.into_inner());
+    *counts.get_or_insert_with(HashMap::new).entry((kind, at)).or_insert(0) += 1;
+}
+
+///Forgets everything counted so far
+pub fn reset() {
+    *COUNTS.lock().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+///How many of a kind have been counted since the last reset
+pub fn total(kind: Kind) -> u64 {
+    let counts = COUNTS.lock().unwrap_or_else(|e| e.into_inner());
+    counts.iter().flatten().filter(|((k, _), _)| *k == kind).map(|(_, n)| n).sum()
+}
+
+///The `top` places in the source that copied or made the most, biggest first
+pub fn top_sites(top: usize) -> Vec<(Kind, String, u64)> {
+    let counts = COUNTS.lock().unwrap_or_else(|e| e.into_inner());
+    let mut sites: Vec<(Kind, String, u64)> = counts
+        .iter()
+        .flatten()
+        .map(|((kind, at), n)| (*kind, at.to_string(), *n))
+        .collect();
+    sites.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| (a.0, &a.1).cmp(&(b.0, &b.1))));
+    sites.truncate(top);
+    sites
+}
+
+///Totals for each kind, then the `top` busiest places
+pub fn report(top: usize) -> String {
+    let mut out = String::new();
+    for kind in [Kind::Value, Kind::Node, Kind::Map] {
+        out.push_str(&format!("{} copied or made: {}\n", kind, total(kind)));
+    }
+    for (kind, at, n) in top_sites(top) {
+        out.push_str(&format!("{:>10} {:<6} {}\n", n, kind, at));
+    }
+    out
+}
+
+//with the feature on these replace the derived clones. Anything nested inside (array items, a function's
+//body, child nodes) gets counted against whoever copied the outer one
+
+impl Clone for Value {
+    #[track_caller]
+    fn clone(&self) -> Value {
+        clone_value(self, Location::caller())
+    }
+}
+
+impl Clone for ExprNode {
+    #[track_caller]
+    fn clone(&self) -> ExprNode {
+        clone_node(self, Location::caller())
+    }
+}
+
+fn clone_value(val: &Value, at: &'static Location<'static>) -> Value {
+    record(Kind::Value, at);
+    match val {
+        Value::Null => Value::Null,
+        Value::Float(f) => Value::Float(*f),
+        Value::EmString(s) => Value::EmString(s.clone()),
+        Value::Bytes(b) => Value::Bytes(b.clone()),
+        Value::EmBool(b) => Value::EmBool(*b),
+        Value::EmArray(items) => Value::EmArray(items.clone()),
+        Value::Tuple(items) => Value::Tuple(items.clone()),
+        Value::Range(a, b, c) => Value::Range(*a, *b, *c),
+        Value::EmSet(s) => Value::EmSet(s.clone()),
+        Value::Name(n) => Value::Name(n.clone()),
+        Value::Function(name, params, body) => Value::Function(
+            name.clone(),
+            params.iter().map(|p| clone_value(p, at)).collect(),
+            clone_node(body, at),
+        ),
+        Value::Object(o) => Value::Object(o.clone()),
+        Value::Class(c) => Value::Class(c.clone()),
+        Value::Native(n) => Value::Native(n.clone()),
+    }
+}
+
+fn clone_node(node: &ExprNode, at: &'static Location<'static>) -> ExprNode {
+    record(Kind::Node, at);
+    let one = |n: &ExprNode| Box::new(clone_node(n, at));
+    let all = |nodes: &[ExprNode]| nodes.iter().map(|n| clone_node(n, at)).collect::<Vec<_>>();
+    match node {
+        ExprNode::Operation(op, a, b) => ExprNode::Operation(op.clone(), one(a), one(b)),
+        ExprNode::StrLiteral(s) => ExprNode::StrLiteral(s.clone()),
+        ExprNode::BytesLiteral(b) => ExprNode::BytesLiteral(b.clone()),
+        ExprNode::NumLiteral(n) => ExprNode::NumLiteral(n.clone()),
+        ExprNode::BoolLiteral(b) => ExprNode::BoolLiteral(*b),
+        ExprNode::NullLiteral => ExprNode::NullLiteral,
+        ExprNode::Name(n) => ExprNode::Name(n.clone()),
+        ExprNode::Call(name, args) => ExprNode::Call(name.clone(), all(args)),
+        ExprNode::MethodCall(target, args) => ExprNode::MethodCall(one(target), all(args)),
+        ExprNode::CallExpr(target, args) => ExprNode::CallExpr(one(target), all(args)),
+        ExprNode::Block(v) => ExprNode::Block(all(v)),
+        ExprNode::Func(name, params, body) => ExprNode::Func(name.clone(), all(params), one(body)),
+        ExprNode::Class(name, body) => ExprNode::Class(name.clone(), one(body)),
+        ExprNode::New(class, args) => ExprNode::New(one(class), all(args)),
+        ExprNode::Delete(a) => ExprNode::Delete(one(a)),
+        ExprNode::Unary(op, a) => ExprNode::Unary(op.clone(), one(a)),
+        ExprNode::Loop(kind, con, body) => ExprNode::Loop(kind.clone(), one(con), one(body)),
+        ExprNode::ForLoopDec(a, b, c) => ExprNode::ForLoopDec(one(a), one(b), one(c)),
+        ExprNode::Statement(a) => ExprNode::Statement(one(a)),
+        ExprNode::ReturnVal(a) => ExprNode::ReturnVal(one(a)),
+        ExprNode::IfStatement(a, b, c) => ExprNode::IfStatement(one(a), one(b), one(c)),
+        ExprNode::ElseStatement(a) => ExprNode::ElseStatement(one(a)),
+        ExprNode::Array(items) => ExprNode::Array(all(items)),
+        ExprNode::Tuple(items) => ExprNode::Tuple(all(items)),
+        ExprNode::Comprehension(item, name, source, con) => {
+            ExprNode::Comprehension(one(item), name.clone(), one(source), one(con))
+        }
+        ExprNode::ForIn(name, source, body) => ExprNode::ForIn(name.clone(), one(source), one(body)),
+        ExprNode::Yield(a) => ExprNode::Yield(one(a)),
+        ExprNode::Generator(a) => ExprNode::Generator(one(a)),
+        ExprNode::Await(a) => ExprNode::Await(one(a)),
+        ExprNode::Async(a) => ExprNode::Async(one(a)),
+        ExprNode::Index(a, b) => ExprNode::Index(one(a), one(b)),
+        ExprNode::Constant(a, id) => ExprNode::Constant(one(a), *id),
+        ExprNode::At(span) => ExprNode::At(*span),
+        ExprNode::Illegal(e) => ExprNode::Illegal(e.clone()),
+        ExprNode::EOF => ExprNode::EOF,
+    }
+}
@@ -0,0 +1,21 @@
+use super::*;
+use crate::{lexer, parser};
+
+#[test]
+fn counts_copies_where_they_happen() {
+    reset();
+    let tree = parser::parse(lexer::run("fn f(x) { return [x, x + 1]; }\n")).unwrap();
+    let copy = tree.clone();
+    assert_eq!(copy, tree);
+    let here = format!("{}:{}", file!(), line!() - 2);
+    //every node in the tree is counted against the line that copied it
+    let (kind, at, n) = top_sites(100).into_iter().find(|(_, at, _)| at.starts_with(&here)).unwrap();
+    assert_eq!((kind, n > 5), (Kind::Node, true), "{}", at);
+
+    let val = Value::Function(lexer::Expression::Ident("f".to_owned()), vec![], tree);
+    let _copy = val.clone();
+    assert!(total(Kind::Value) >= 1 && total(Kind::Node) >= 2 * n);
+    let _map = crate::interpreter::OrderedMap::<Value>::new();
+    assert!(total(Kind::Map) >= 1);
+    assert!(report(3).starts_with("values copied or made: "));
+}
@@ -1,33 +1,425 @@
+use clap::{Arg, SubCommand};
+use console::style;
+use std::ffi::OsString;
+use gem::interpreter::Value;
 use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::process;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
 
 
 #[macro_use]
 extern crate clap;
 
 fn main() {
-    
-    let matches = clap_app!(app => 
+    //scripts can recurse pretty deep, so the interpreter gets its own thread with plenty of stack
+    let child = std::thread::Builder::new()
+        .stack_size(256 * 1024 * 1024)
+        .spawn(run)
+        .expect("Couldn't start the interpreter thread");
+    if child.join().is_err() {
+        process::exit(101);
+    }
+}
+
+//the hook runs before unwinding, so it leaves the message here for the crash dump
+static LAST_PANIC: Mutex<Option<String>> = Mutex::new(None);
+
+///Script errors get reported normally, so a panic always means something is wrong with the
+///interpreter itself. This makes that clear and gives enough context to file a useful issue
+fn install_panic_hook(path: String) {
+    panic::set_hook(Box::new(move |info| {
+        if let Ok(mut last) = LAST_PANIC.lock() {
+            *last = Some(info.to_string());
+        }
+        eprintln!("{}", style("The interpreter hit an internal bug. This isn't a problem with your script").red().bold());
+        eprintln!("  {}", info);
+        eprintln!("  while running {}", path);
+        let stack = gem::interpreter::call_stack();
+        if !stack.is_empty() {
+            eprintln!("  inside {}", stack.join(" > "));
+        }
+        eprintln!(
+            "{}",
+            style("Please open an issue at https://github.com/AnActualEmerald/EmeraldScript/issues with this message and the script if you can").yellow()
+        );
+    }));
+}
+
+///`script.em` becomes `script.emc`, anything else just gets `.emc` added
+fn compiled_path(path: &str) -> String {
+    match path.strip_suffix(".em") {
+        Some(stem) => format!("{}.emc", stem),
+        None => format!("{}.emc", path),
+    }
+}
+
+fn app() -> clap::App<'static, 'static> {
+    let app = clap_app!(app => 
         (name: "Gem")
         (version:env!("CARGO_PKG_VERSION"))
         (author: "Emerald <@Emerald#6666>")
         (about: "Parses and runs emerald script")
+        (@setting SubcommandsNegateReqs)
+        (@setting ArgsNegateSubcommands)
         (@arg debug: -d --debug "Display debugging information")
-        (@arg PATH: +required "Path of the file to run")
-        (@arg ARGS: ... +use_delimiter "Arguments to pass to the script")
-        ).get_matches();
+        (@arg strict: --strict "Only allow objects to get new properties in their constructor")
+        (@arg no_exec: --("no-exec") "Don't allow the script to run other programs")
+        (@arg no_cache: --("no-cache") "Parse the script again instead of using the compiled copy cached from an earlier run")
+        (@arg record: --record +takes_value conflicts_with[replay] "Save all of the script's inputs to a trace file")
+        (@arg replay: --replay +takes_value "Replay the inputs saved in a trace file")
+        (@arg log_level: --("log-level") +takes_value possible_values(gem::interpreter::LogLevel::NAMES) "Only show script log messages at least this important, defaults to debug")
+        (@arg max_output_lines: --("max-output-lines") +takes_value conflicts_with[max_output_bytes] "Stop the script if it prints more than this many lines")
+        (@arg max_output_bytes: --("max-output-bytes") +takes_value "Stop the script if it prints more than this many bytes")
+        (@arg truncate_output: --("truncate-output") "Cut the output off at the output limit instead of stopping the script")
+        (@arg watch_expr: --("watch-expr") +takes_value +multiple number_of_values(1) "Print this expression whenever its value changes while the script runs, can be given more than once")
+        (@arg watch: --watch conflicts_with[bench] conflicts_with[check] "Run the script again every time the file is saved")
+        (@arg keep_state: --("keep-state") requires[watch] "Keep the globals from the last run when --watch runs the script again")
+        (@arg numeric: --numeric +takes_value possible_values(gem::interpreter::NumericPolicy::NAMES) "What dividing by zero and numbers too big for a float turn into: ieee (infinity and NaN, the default), saturate, or error")
+        (@arg dry_run: --("dry-run") "Log side effects like writing files or running commands instead of doing them")
+        (@arg check: --check conflicts_with[bench] "Report every syntax error in the script, or if there aren't any, code that's probably a mistake, without running it")
+        (@arg bench: --bench "Time the script instead of just running it, and report how many nodes it evaluates per second")
+        (@arg runs: --runs +takes_value requires[bench] "How many times --bench runs the script, defaults to 10")
+        (@arg crash_dump: --("crash-dump") +takes_value "If the interpreter itself crashes, write what it was doing to this file so it can go with a bug report")
+        (@arg render: --render conflicts_with[bench] "Run a markdown file's emerald blocks and print the document with each block's output beneath it")
+        (@arg PATH: +required "Path of the file to run, markdown files (.md) run each of their emerald code blocks in order")
+        (@arg ARGS: ... "Arguments to pass to the script")
+        (@subcommand repl =>
+            (about: "Starts an interactive session, type :help once it's running to see the commands")
+            (@arg no_rc: --("no-rc") "Don't run ~/.emeraldscript/replrc.em (or $EM_REPLRC) first")
+        )
+        (@subcommand test =>
+            (about: "Runs every function starting with test_ in a script and reports which ones failed")
+            (@arg FILE: +required "Path of the script to test")
+        )
+        (@subcommand emc =>
+            (about: "Compiles a script ahead of time, running the .emc file it writes skips lexing and parsing")
+            (@arg FILE: +required "Path of the script to compile")
+            (@arg output: -o --output +takes_value "Where to write the compiled script, defaults to the script's path ending in .emc")
+        )
+        (@subcommand kernel =>
+            (about: "Runs as a Jupyter kernel, Jupyter starts this itself once the kernelspec in jupyter/ is installed")
+            (@arg CONNECTION_FILE: +required "The connection file Jupyter passes in")
+        )
+        (@subcommand highlight =>
+            (about: "Prints syntax highlighting definitions for an editor")
+            (@arg format: -f --format +takes_value +required possible_values(gem::highlight::FORMATS) "tmLanguage for VS Code and friends, vim, or pygments")
+        )
+        (@subcommand completions =>
+            (about: "Prints a completion script for your shell")
+            (@arg SHELL: +required possible_values(&clap::Shell::variants()) "The shell to generate completions for")
+        )
+    )
+    //the macro only takes plain identifiers for subcommand names
+    .subcommand(
+        SubCommand::with_name("export-playground")
+            .about("Writes a single HTML page with the script in an editor that runs it in the browser")
+            .arg(Arg::with_name("SCRIPT").required(true).help("Path of the script to put in the page"))
+            .arg(Arg::from_usage("-o, --output [FILE] 'Where to write the page, defaults to index.html'"))
+            .arg(Arg::from_usage("--title [TITLE] 'Title of the page, defaults to the script's file name'"))
+            .arg(Arg::from_usage("--wasm [FILE] 'The interpreter built from bindings/wasm, defaults to where that build puts it'")),
+    );
+    #[cfg(feature = "debug-alloc")]
+    let app = app.arg(Arg::from_usage(
+        "--alloc-stats [TOP] 'After the script runs, print how many values, nodes, and maps it copied or made and the places that did the most, 20 of them unless TOP is given'",
+    ));
+    app
+}
+
+///Makes a runtime set up the way the command line asked for
+fn configure(matches: &clap::ArgMatches) -> gem::interpreter::Runtime {
+    let mut runtime = gem::interpreter::Runtime::new();
+    runtime.set_dry_run(matches.is_present("dry_run"));
+    runtime.set_allow_exec(!matches.is_present("no_exec"));
+    runtime.set_strict(matches.is_present("strict"));
+    if let Some(level) = matches.value_of("log_level").and_then(|l| l.parse().ok()) {
+        runtime.set_log_level(level);
+    }
+    if let Some(policy) = matches.value_of("numeric").and_then(|p| p.parse().ok()) {
+        runtime.set_numeric_policy(policy);
+    }
+    let limit = match (matches.value_of("max_output_lines"), matches.value_of("max_output_bytes")) {
+        (Some(n), _) => n.parse().map(gem::interpreter::OutputLimit::Lines).map(Some),
+        (_, Some(n)) => n.parse().map(gem::interpreter::OutputLimit::Bytes).map(Some),
+        _ => Ok(None),
+    };
+    runtime.set_output_limit(limit.unwrap_or_else(|e| {
+        eprintln!("Expected a number for the output limit: {}", e);
+        process::exit(1);
+    }));
+    runtime.set_truncate_output(matches.is_present("truncate_output"));
+    for expr in matches.values_of("watch_expr").into_iter().flatten() {
+        runtime.add_watch(expr).unwrap_or_else(|e| {
+            eprintln!("Couldn't watch {}: {}", expr, e);
+            process::exit(1);
+        });
+    }
+    if let Some(trace) = matches.value_of("record") {
+        runtime.record_to(trace).unwrap_or_else(|e| {
+            eprintln!("Couldn't create trace file {}: {}", trace, e);
+            process::exit(1);
+        });
+    }
+    if let Some(trace) = matches.value_of("replay") {
+        runtime.replay_from(trace).unwrap_or_else(|e| {
+            eprintln!("Couldn't read trace file {}: {}", trace, e);
+            process::exit(1);
+        });
+    }
+    runtime
+}
+
+///Runs the script, then runs it again each time the file changes. With `keep` the same runtime and
+///globals are used every time, so only what the new top level sets again is reset. A change made
+///while the script is still running is picked up once it finishes
+fn watch(path: &str, args: &[Value], matches: &clap::ArgMatches, keep: bool) {
+    let mut engine = None;
+    let mut last = None;
+    loop {
+        let modified = fs::metadata(path).and_then(|m| m.modified()).ok();
+        if modified.is_some() && modified != last {
+            if last.is_some() {
+                //editors often save in more than one write, give them a moment to finish
+                thread::sleep(Duration::from_millis(50));
+                eprintln!("{}", style(format!("{} changed, running it again", path)).dim());
+            }
+            last = modified;
+            match fs::read_to_string(path) {
+                Ok(data) if keep => {
+                    let engine = engine.get_or_insert_with(|| gem::Engine::with_runtime(configure(matches)));
+                    if let Err(e) = engine.reload(&data, args.to_vec()) {
+                        engine.runtime().report(&format!("Interpreter crashed because: {}", e));
+                    }
+                }
+                //the watcher keeps going until it's stopped, so exit codes don't go anywhere
+                Ok(data) => {
+                    gem::run_with(configure(matches), data, args.to_vec(), false);
+                }
+                Err(e) => eprintln!("Couldn't read file {}: {}", path, e),
+            }
+        }
+        thread::sleep(Duration::from_millis(200));
+    }
+}
+
+///Puts a `--` right after the script's path, so everything after it goes to the script even when it
+///looks like one of our flags. That's what lets a script run from a `#!/usr/bin/env gem-bin` line
+///and still take flags of its own. Clap works out which argument is the path, since it knows
+///which of our flags take a value
+fn script_args_last(args: Vec<OsString>) -> Vec<OsString> {
+    for i in 1..args.len() {
+        if args[i].to_string_lossy().starts_with('-') {
+            continue;
+        }
+        match app().get_matches_from_safe(&args[..=i]) {
+            Ok(m) if m.subcommand_name().is_some() => return args,
+            Ok(m) if m.value_of_os("PATH") == Some(args[i].as_os_str()) => {
+                if args.get(i + 1).map_or(true, |a| a == "--") {
+                    return args;
+                }
+                let mut out = args[..=i].to_vec();
+                out.push("--".into());
+                out.extend_from_slice(&args[i + 1..]);
+                return out;
+            }
+            _ => continue,
+        }
+    }
+    args
+}
+
+fn run() {
+    let matches = app().get_matches_from(script_args_last(std::env::args_os().collect()));
+
+    if let Some(sub) = matches.subcommand_matches("repl") {
+        use gem::interpreter::repl::Repl;
+        let mut repl = Repl::new();
+        //not having an rc file is normal, one that's broken gets reported but the session still starts
+        match Repl::default_rc_path() {
+            Some(rc) if rc.exists() && !sub.is_present("no_rc") => {
+                if let Err(e) = repl.load_rc(&rc) {
+                    eprintln!("{}", e);
+                }
+            }
+            _ => {}
+        }
+        repl.start();
+        return;
+    }
+
+    if let Some(sub) = matches.subcommand_matches("test") {
+        let path = sub.value_of("FILE").unwrap_or_default();
+        let data = fs::read_to_string(path).unwrap_or_else(|e| {
+            eprintln!("Couldn't read file {}: {}", path, e);
+            process::exit(1);
+        });
+        install_panic_hook(path.to_owned());
+        let mut runtime = gem::interpreter::Runtime::new();
+        runtime.set_source(path, &data);
+        match gem::test_with(runtime, data) {
+            Ok(0) => {}
+            _ => process::exit(1),
+        }
+        return;
+    }
+
+    if let Some(sub) = matches.subcommand_matches("emc") {
+        let path = sub.value_of("FILE").unwrap_or_default();
+        let output = sub.value_of("output").map_or_else(|| compiled_path(path), str::to_owned);
+        let res = fs::read_to_string(path)
+            .map_err(|e| format!("Couldn't read file {}: {}", path, e))
+            .and_then(|data| gem::Program::compile_to_file(&data, &output));
+        if let Err(e) = res {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(sub) = matches.subcommand_matches("kernel") {
+        if let Err(e) = gem::kernel::run(sub.value_of("CONNECTION_FILE").unwrap_or_default()) {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(sub) = matches.subcommand_matches("highlight") {
+        match gem::highlight::generate(sub.value_of("format").unwrap_or_default()) {
+            Ok(defs) => print!("{}", defs),
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if let Some(sub) = matches.subcommand_matches("export-playground") {
+        let path = sub.value_of("SCRIPT").unwrap_or_default();
+        let output = sub.value_of("output").unwrap_or("index.html");
+        let wasm_path = sub.value_of("wasm").unwrap_or(gem::playground::DEFAULT_WASM);
+        let script = fs::read_to_string(path).unwrap_or_else(|e| {
+            eprintln!("Couldn't read file {}: {}", path, e);
+            process::exit(1);
+        });
+        let wasm = fs::read(wasm_path).unwrap_or_else(|e| {
+            eprintln!("Couldn't read the interpreter's WebAssembly module at {}: {}", wasm_path, e);
+            eprintln!("Build it with `cargo build --release --target wasm32-unknown-unknown` in bindings/wasm, or point --wasm at one");
+            process::exit(1);
+        });
+        let name = std::path::Path::new(path).file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        let res = gem::playground::export(sub.value_of("title").unwrap_or(&name), &script, &wasm)
+            .and_then(|page| fs::write(output, page).map_err(|e| format!("Couldn't write {}: {}", output, e)));
+        if let Err(e) = res {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(sub) = matches.subcommand_matches("completions") {
+        //clap already made sure this is one of the shells it knows about
+        let shell = value_t!(sub, "SHELL", clap::Shell).unwrap_or_else(|e| e.exit());
+        app().gen_completions_to("gem-bin", shell, &mut std::io::stdout());
+        return;
+    }
 
     let debug = matches.is_present("debug");
 
     if let Some(path) = matches.value_of("PATH") {
         let data = fs::read_to_string(&path).unwrap_or_else(|e| {
-            panic!("Couldn't read file {}: {}", path, e);
+            eprintln!("Couldn't read file {}: {}", path, e);
+            process::exit(1);
         });
-        let args = if let Some(tmp) = matches.values_of("ARGS"){
-            tmp.map(|e| format!("\"{}\"", e)).collect::<Vec<String>>().join(",")
-        }else {
-            "".to_string()
+        install_panic_hook(path.to_owned());
+        //handed over as strings, never parsed, since whoever runs a shebang script picks these
+        let args: Vec<Value> = matches.values_of("ARGS").map_or_else(Vec::new, |args| args.map(|a| Value::EmString(a.into())).collect());
+        if matches.is_present("check") {
+            let problems = match gem::parser::parse_all(gem::lexer::run(&data)) {
+                Ok(tree) => gem::lint::check(&tree).iter().map(|w| format!("warning: {}", w)).collect(),
+                Err(errs) => errs,
+            };
+            for p in problems.iter() {
+                eprintln!("{}: {}", path, p);
+            }
+            if !problems.is_empty() {
+                process::exit(1);
+            }
+            return;
+        }
+        if matches.is_present("bench") {
+            let runs = matches.value_of("runs").map_or(Ok(10), str::parse).unwrap_or_else(|e| {
+                eprintln!("Expected a number of runs: {}", e);
+                process::exit(1);
+            });
+            match gem::bench(&data, args, runs) {
+                Ok(report) => println!("{}", report),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }
+            }
+            return;
+        }
+        let mut runtime = configure(&matches);
+        runtime.set_source(path, &data);
+        if path.ends_with(".emc") {
+            match gem::Program::from_compiled(&data) {
+                Ok(program) => process::exit(program.run_main(runtime, args)),
+                Err(e) => {
+                    eprintln!("{}: {}", path, e);
+                    process::exit(1);
+                }
+            }
+        }
+        if path.ends_with(".md") || matches.is_present("render") {
+            let failed = if matches.is_present("render") {
+                let (doc, failed) = gem::literate::render(&data, runtime);
+                print!("{}", doc);
+                failed
+            } else {
+                gem::literate::run(&data, runtime)
+            };
+            if failed > 0 {
+                process::exit(1);
+            }
+            return;
+        }
+        if matches.is_present("watch") {
+            watch(path, &args, &matches, matches.is_present("keep_state"));
+            return;
+        }
+        //scripts that don't parse go the long way so the error gets reported like always
+        let cached = match gem::Program::default_cache_dir() {
+            Some(dir) if !debug && !matches.is_present("no_cache") => gem::Program::cached(&data, &dir).ok(),
+            _ => None,
         };
-        gem::run(data, &args, debug);
-        return;
+        #[cfg(feature = "debug-alloc")]
+        gem::alloc_stats::reset();
+        let res = panic::catch_unwind(AssertUnwindSafe(|| match cached {
+            Some(program) => program.run_main(runtime, args),
+            None => gem::run_with(runtime, data.clone(), args, debug),
+        }));
+        #[cfg(feature = "debug-alloc")]
+        if matches.is_present("alloc-stats") {
+            let top = matches.value_of("alloc-stats").and_then(|n| n.parse().ok()).unwrap_or(20);
+            eprint!("{}", gem::alloc_stats::report(top));
+        }
+        if let Err(e) = res {
+            if let Some(dump) = matches.value_of("crash_dump") {
+                let msg = LAST_PANIC.lock().ok().and_then(|m| m.clone()).unwrap_or_default();
+                match gem::interpreter::crash::write_dump(dump, &data, &msg) {
+                    Ok(_) => eprintln!("Wrote a crash dump to {}, nothing was sent anywhere", dump),
+                    Err(err) => eprintln!("Couldn't write a crash dump to {}: {}", dump, err),
+                }
+            }
+            panic::resume_unwind(e);
+        }
+        if let Ok(code) = res {
+            process::exit(code);
+        }
     }
 }
\ No newline at end of file
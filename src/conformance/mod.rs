@@ -0,0 +1,124 @@
+//! Runs the programs in `spec/` and checks what they print. Each case is a `.em` file with a
+//! `.out` file next to it holding the exact stdout it should give, and optionally a `.err` file
+//! with the exact stderr. Cases are grouped into one directory per language feature.
+//! Anything that can run EmeraldScript can be checked against the same cases by implementing `Backend`
+
+#[cfg(test)]
+mod tests;
+
+use crate::{interpreter, lexer, parser};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+///What a program printed
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Output {
+    pub stdout: String,
+    pub stderr: String,
+}
+
+///Something that can run a whole script the same way `gem-bin` would, top level first and then `main`
+pub trait Backend {
+    fn run(&self, source: &str) -> Output;
+}
+
+///The tree walking interpreter
+pub struct TreeWalker;
+
+impl Backend for TreeWalker {
+    fn run(&self, source: &str) -> Output {
+        let (out, err) = (Capture::default(), Capture::default());
+        let mut runtime = interpreter::Runtime::new();
+        runtime.set_stdout(Box::new(out.clone()));
+        runtime.set_stderr(Box::new(err.clone()));
+        match parser::parse(lexer::run(source)) {
+            Ok(tree) => {
+                interpreter::run_on(&mut runtime, tree, vec![]);
+            }
+            Err(e) => runtime.report(&e),
+        }
+        drop(runtime);
+        Output {
+            stdout: out.contents(),
+            stderr: err.contents(),
+        }
+    }
+}
+
+//a writer that can still be read after the runtime has it
+#[derive(Clone, Default)]
+struct Capture(Arc<Mutex<Vec<u8>>>);
+
+impl Capture {
+    fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.lock().unwrap_or_else(|e| e.into_inner())).into_owned()
+    }
+}
+
+impl Write for Capture {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).extend_from_slice(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+///One program from the spec and what it should print
+#[derive(Debug, Clone)]
+pub struct Case {
+    ///Like `classes/constructors`
+    pub name: String,
+    pub path: PathBuf,
+    pub expected: Output,
+}
+
+///A case that didn't print what it should have
+#[derive(Debug, Clone)]
+pub struct Failure {
+    pub case: Case,
+    pub actual: Output,
+}
+
+///Finds every case under `dir`, sorted by name. A `.em` file without a `.out` file is an error,
+///since a case that can't fail isn't testing anything
+pub fn load_cases(dir: &Path) -> Result<Vec<Case>, String> {
+    let mut cases = vec![];
+    let features = fs::read_dir(dir).map_err(|e| format!("Couldn't read {}: {}", dir.display(), e))?;
+    for feature in features.filter_map(Result::ok).filter(|f| f.path().is_dir()) {
+        let files = fs::read_dir(feature.path()).map_err(|e| format!("Couldn't read {}: {}", feature.path().display(), e))?;
+        for file in files.filter_map(Result::ok).map(|f| f.path()) {
+            if file.extension().is_none_or(|e| e != "em") {
+                continue;
+            }
+            let stdout = fs::read_to_string(file.with_extension("out"))
+                .map_err(|e| format!("Couldn't read the expected output for {}: {}", file.display(), e))?;
+            //most cases shouldn't print any errors, so they can leave this out
+            let stderr = fs::read_to_string(file.with_extension("err")).unwrap_or_default();
+            let stem = file.file_stem().unwrap_or_default().to_string_lossy();
+            cases.push(Case {
+                name: format!("{}/{}", feature.file_name().to_string_lossy(), stem),
+                path: file.clone(),
+                expected: Output { stdout, stderr },
+            });
+        }
+    }
+    cases.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(cases)
+}
+
+///Runs every case in `dir` on `backend` and gives back the ones that failed
+pub fn check(backend: &dyn Backend, dir: &Path) -> Result<Vec<Failure>, String> {
+    let mut failures = vec![];
+    for case in load_cases(dir)? {
+        let source = fs::read_to_string(&case.path).map_err(|e| format!("Couldn't read {}: {}", case.path.display(), e))?;
+        let actual = backend.run(&source);
+        if actual != case.expected {
+            failures.push(Failure { case, actual });
+        }
+    }
+    Ok(failures)
+}
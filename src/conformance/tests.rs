@@ -0,0 +1,21 @@
+use super::*;
+
+fn spec_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("spec")
+}
+
+#[test]
+fn tree_walker_conforms() {
+    let failures = check(&TreeWalker, &spec_dir()).unwrap();
+    for f in failures.iter() {
+        eprintln!("{} expected {:?}, got {:?}", f.case.name, f.case.expected, f.actual);
+    }
+    assert!(failures.is_empty());
+}
+
+#[test]
+fn finds_every_case() {
+    let cases = load_cases(&spec_dir()).unwrap();
+    assert!(cases.iter().any(|c| c.name == "errors/out_of_bounds"));
+    assert!(cases.windows(2).all(|w| w[0].name < w[1].name));
+}
@@ -0,0 +1,245 @@
+//! Shows an error with the line of code it's about underneath, like rustc does. Errors are plain
+//! strings, the place comes from the lexer: parse errors know the token they stopped on, and the
+//! interpreter knows which statement it was running. When the message names something in that
+//! statement, like an unknown identifier, just that gets underlined. The CLI, the REPL, and the test
+//! runner all print their errors through here
+
+#[cfg(test)]
+mod tests;
+
+use crate::highlight::terminal::{colorize, spans, Kind};
+use crate::lexer::{Expression, Span};
+use console::style;
+use regex::Regex;
+use std::fmt;
+
+///Where in the source an error points
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    pub file: String,
+    ///Counted from 1
+    pub line: usize,
+    ///In characters, counted from 1
+    pub column: usize,
+    ///How many characters get underlined
+    pub len: usize,
+    ///The whole line the location is on
+    pub text: String,
+}
+
+///An error message, with where it happened and a hint if either could be worked out
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub message: String,
+    pub location: Option<Location>,
+    //the closest name found so far to the one that doesn't exist, and how far off it is
+    closest: Option<(usize, String)>,
+}
+
+impl Diagnostic {
+    pub fn new(message: &str) -> Diagnostic {
+        Diagnostic {
+            message: message.to_owned(),
+            location: None,
+            closest: None,
+        }
+    }
+
+    ///Points at `span` in `source`, or at the thing the message is about if that's inside of it.
+    ///Only the first line of a span that goes over more than one gets underlined
+    pub fn locate(mut self, file: &str, source: &str, span: Span) -> Diagnostic {
+        let text = match source.get(span.start..span.end) {
+            Some(text) => text,
+            None => return self,
+        };
+        let found = subject(&self.message).and_then(|(token, _)| occurrences(text, &token).into_iter().next());
+        let (start, len) = match found {
+            Some((at, len)) => (span.start + at, len),
+            None => (span.start, text.lines().next().unwrap_or("").trim_end().chars().count()),
+        };
+        self.location = Some(location(file, source, start, len));
+        self
+    }
+
+    ///If the message is about a name that doesn't exist, offers the closest of `names` as a hint.
+    ///Can be called more than once, the best name out of all of them wins
+    pub fn suggest<'a>(mut self, names: impl IntoIterator<Item = &'a str>) -> Diagnostic {
+        let missing = match subject(&self.message) {
+            Some((Expression::Ident(name), true)) => name,
+            _ => return self,
+        };
+        let most = (missing.chars().count() / 3).max(1);
+        for name in names {
+            if name == missing || name.starts_with('~') {
+                continue;
+            }
+            let d = distance(&missing, name);
+            let better = match &self.closest {
+                Some((best, best_name)) => d < *best || d == *best && name < best_name.as_str(),
+                None => true,
+            };
+            if d <= most && better {
+                self.closest = Some((d, name.to_owned()));
+            }
+        }
+        self
+    }
+
+    ///The suggestion from `suggest`, like "did you mean `lenght` → `length`?"
+    pub fn hint(&self) -> Option<String> {
+        match (subject(&self.message), &self.closest) {
+            (Some((Expression::Ident(missing), true)), Some((_, name))) => Some(format!("did you mean `{}` → `{}`?", missing, name)),
+            _ => None,
+        }
+    }
+
+    ///Everything that goes under the message: the file and position, the line with the location
+    ///underlined, and the hint. Empty if there's neither a location nor a hint
+    pub fn snippet(&self) -> String {
+        let gutter = " ".repeat(self.location.as_ref().map_or(1, |s| s.line.to_string().len()));
+        let bar = |s: &str| style(s.to_owned()).blue().bold();
+        let mut lines = vec![];
+        if let Some(loc) = &self.location {
+            //tabs would make the underline land in the wrong place
+            let text = loc.text.replace('\t', "    ");
+            let before: String = loc.text.chars().take(loc.column - 1).collect();
+            let pad = " ".repeat(before.replace('\t', "    ").chars().count());
+            lines.push(format!("{}{} {}:{}:{}", gutter, bar("-->"), loc.file, loc.line, loc.column));
+            lines.push(format!("{} {}", gutter, bar("|")));
+            lines.push(format!("{} {} {}", bar(&loc.line.to_string()), bar("|"), colorize(&text)));
+            lines.push(format!("{} {} {}{}", gutter, bar("|"), pad, style("^".repeat(loc.len.max(1))).red().bold()));
+        }
+        if let Some(hint) = self.hint() {
+            lines.push(format!("{} {} {} {}", gutter, bar("="), style("help:").bold(), hint));
+        }
+        lines.join("\n")
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let snippet = self.snippet();
+        if snippet.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}\n{}", self.message, snippet)
+        }
+    }
+}
+
+///Every name used in `source`, which are good guesses for what a misspelled one was meant to be
+pub fn words(source: &str) -> Vec<&str> {
+    spans(source).into_iter().filter(|(kind, _)| *kind == Kind::Plain).flat_map(|(_, text)| names_in(text)).map(|(_, w)| w).collect()
+}
+
+//the names in a piece of plain text and where they start
+fn names_in(text: &str) -> Vec<(usize, &str)> {
+    let is_name = |c: char| c.is_alphanumeric() || c == '_';
+    let mut out = vec![];
+    let mut start = None;
+    for (i, c) in text.char_indices().chain(std::iter::once((text.len(), ' '))) {
+        match (start, is_name(c)) {
+            (None, true) => start = Some(i),
+            (Some(s), false) => {
+                if !text[s..].starts_with(|c: char| c.is_ascii_digit()) {
+                    out.push((s, &text[s..i]));
+                }
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    out
+}
+
+///The token an error message is about, if it names one, and whether it's a name that doesn't exist
+///as opposed to one in the wrong place
+fn subject(message: &str) -> Option<(Expression, bool)> {
+    let missing = Regex::new(r"Couldn't find identifier (\S+)|Class (\S+) is not defined").unwrap();
+    if let Some(c) = missing.captures(message) {
+        return c.get(1).or_else(|| c.get(2)).map(|m| (Expression::Ident(m.as_str().to_owned()), true));
+    }
+    //the parser puts tokens in its messages with {:?}, like Ident("x")
+    let token = Regex::new(r#"(Ident|Key|Word)\("((?:[^"\\]|\\.)*)"\)"#).unwrap();
+    let c = token.captures(message)?;
+    let text = unescape(&c[2]);
+    let token = match &c[1] {
+        "Ident" => Expression::Ident(text),
+        "Key" => Expression::Key(text),
+        _ => Expression::Word(text),
+    };
+    Some((token, false))
+}
+
+fn unescape(s: &str) -> String {
+    let mut out = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+///The byte offset and length in characters of everywhere `token` is written in `source`, skipping
+///comments and anything inside strings
+fn occurrences(source: &str, token: &Expression) -> Vec<(usize, usize)> {
+    let mut out = vec![];
+    let mut offset = 0;
+    for (kind, text) in spans(source) {
+        match (token, kind) {
+            (Expression::Ident(name), Kind::Plain) => {
+                for (at, word) in names_in(text) {
+                    if word == name {
+                        out.push((offset + at, name.chars().count()));
+                    }
+                }
+            }
+            (Expression::Key(name), Kind::Keyword) | (Expression::Key(name), Kind::Constant) if text == name => {
+                out.push((offset, name.chars().count()))
+            }
+            (Expression::Word(s), Kind::Str) if text.strip_prefix('"').and_then(|t| t.strip_suffix('"')) == Some(s.as_str()) => {
+                out.push((offset, text.chars().count()))
+            }
+            _ => {}
+        }
+        offset += text.len();
+    }
+    out
+}
+
+fn location(file: &str, source: &str, start: usize, len: usize) -> Location {
+    let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = source[start..].find('\n').map_or(source.len(), |i| start + i);
+    Location {
+        file: file.to_owned(),
+        line: source[..start].matches('\n').count() + 1,
+        column: source[line_start..start].chars().count() + 1,
+        len,
+        text: source[line_start..line_end].trim_end_matches('\r').to_owned(),
+    }
+}
+
+///How many characters have to be added, removed, or changed to turn `a` into `b`
+pub fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let next = (prev + if ca == *cb { 0 } else { 1 }).min(row[j] + 1).min(row[j + 1] + 1);
+            prev = row[j + 1];
+            row[j + 1] = next;
+        }
+    }
+    row[b.len()]
+}
@@ -0,0 +1,51 @@
+use super::*;
+use crate::{lexer, parser};
+
+#[test]
+fn undefined_names() {
+    let src = "fn main() {\n\tlet s = \"lenght\"; // lenght\n\tprint(lenght(s));\n}";
+    let start = src.find("print").unwrap();
+    let statement = Span { start, end: start + "print(lenght(s))".len() };
+    let diag = Diagnostic::new("Couldn't find identifier lenght").locate("a.em", src, statement).suggest(vec!["length", "len", "print"]);
+    assert_eq!(diag.location.as_ref().map(|s| (s.line, s.column, s.len)), Some((3, 8, 6)));
+    assert_eq!(diag.hint(), Some("did you mean `lenght` → `length`?".to_owned()));
+    assert_eq!(
+        console::strip_ansi_codes(&diag.to_string()),
+        "Couldn't find identifier lenght\n --> a.em:3:8\n  |\n3 |     print(lenght(s));\n  |           ^^^^^^\n  = help: did you mean `lenght` → `length`?"
+    );
+    //strings and comments in the statement don't count as using the name, so all of it gets underlined
+    let start = src.find("let").unwrap();
+    let statement = Span { start, end: src.find("; //").unwrap() };
+    let diag = Diagnostic::new("Couldn't find identifier lenght").locate("a.em", src, statement);
+    assert_eq!(diag.location.map(|s| (s.line, s.column, s.len)), Some((2, 2, 16)));
+    //nothing close enough, and nothing to point at
+    let diag = Diagnostic::new("Couldn't find identifier zzz").suggest(vec!["length"]);
+    assert_eq!((diag.location.clone(), diag.hint()), (None, None));
+    assert_eq!(diag.to_string(), "Couldn't find identifier zzz");
+    assert_eq!(distance("lenght", "length"), 2);
+    assert_eq!(words("let x = y.z(1, \"w\") // v"), vec!["x", "y", "z"]);
+}
+
+#[test]
+fn parse_errors() {
+    let at = |src: &str| {
+        let (tokens, spans) = lexer::run_spanned(src);
+        let (err, span) = parser::parse_spanned(&tokens, &spans).unwrap_err();
+        let diag = Diagnostic::new(&err).locate("b.em", src, span.unwrap());
+        (err, diag.location.map(|s| (s.line, s.column, s.len)))
+    };
+    //the second y is the one the parser trips on
+    let (err, loc) = at("f(y);\nf(x y);");
+    assert!(err.contains("Ident(\"y\")"), "{}", err);
+    assert_eq!(loc, Some((2, 5, 1)));
+    //punctuation doesn't show up in the source the way the message writes it, it still gets pointed at
+    let (err, loc) = at("x = 1;\ny = ;\n");
+    assert!(err.contains("Semicolon"), "{}", err);
+    assert_eq!(loc, Some((2, 5, 1)));
+    let (err, loc) = at("x = [1, 2;\n");
+    assert!(err.contains("Semicolon"), "{}", err);
+    assert_eq!(loc, Some((1, 10, 1)));
+    let (err, loc) = at("x = (1 + 2]];\n");
+    assert!(err.contains("Rbracket"), "{}", err);
+    assert_eq!(loc, Some((1, 11, 1)));
+}
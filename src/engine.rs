@@ -0,0 +1,71 @@
+use crate::interpreter::{self, Builtin, Runtime, Signature, StackFrame, Value};
+use crate::{lexer, parser};
+use std::sync::Arc;
+
+///A runtime that keeps its globals between runs, for programs that embed the language and want to
+///go back and forth with scripts instead of running a whole file once
+pub struct Engine {
+    runtime: Runtime,
+    globals: StackFrame,
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Engine {
+    pub fn new() -> Engine {
+        Engine::with_runtime(Runtime::new())
+    }
+
+    ///Uses a runtime that has already been configured, like one with its output redirected
+    pub fn with_runtime(runtime: Runtime) -> Engine {
+        Engine {
+            runtime,
+            globals: StackFrame::new(),
+        }
+    }
+
+    ///Runs some code in the global scope and gives back the value of the last statement
+    pub fn eval(&mut self, code: &str) -> Result<Value, String> {
+        //the lexer only finishes a token when something comes after it
+        let tree = parser::parse(lexer::run(&format!("{}\n", code)))?;
+        interpreter::eval_tree(&tree, &mut self.runtime, &mut self.globals)
+    }
+
+    ///Runs a whole script on top of what earlier runs left behind, then its `main` if it has one and
+    ///any tasks that started. Functions and classes get replaced, and globals keep their values
+    ///unless the new top level sets them again
+    pub fn reload(&mut self, src: &str, args: Vec<Value>) -> Result<Value, String> {
+        self.eval(src)?;
+        if self.runtime.defined("main").is_none() {
+            return Ok(Value::Null);
+        }
+        let res = self.call("main", vec![Value::EmArray(Arc::new(args))])?;
+        self.runtime.run_tasks()?;
+        Ok(res)
+    }
+
+    ///Calls a function the scripts have defined, or a builtin
+    pub fn call(&mut self, name: &str, args: Vec<Value>) -> Result<Value, String> {
+        self.runtime.call(name, args, &self.globals)
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<&Value> {
+        self.globals.lookup(name)
+    }
+
+    pub fn set_global(&mut self, name: &str, val: Value) {
+        self.globals.set(name, val);
+    }
+
+    pub fn register_fn(&mut self, name: &str, sig: Signature, func: Builtin) {
+        self.runtime.register_fn(name, sig, func);
+    }
+
+    pub fn runtime(&mut self) -> &mut Runtime {
+        &mut self.runtime
+    }
+}
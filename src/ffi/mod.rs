@@ -0,0 +1,262 @@
+//! A C interface for embedding the interpreter in programs that aren't written in rust. Everything
+//! is passed through opaque pointers, and anything this gives back has a matching `_free` function.
+//! `include/emerald.h` is the header for this, and has to be kept up to date by hand.
+//! A panic never crosses into C, it's turned into an error like any other
+
+#[cfg(test)]
+mod tests;
+
+use crate::interpreter::{Signature, Value};
+use crate::Engine;
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::sync::Arc;
+
+///An engine along with the last error it ran into
+pub struct EmRuntime {
+    engine: Engine,
+    last_error: Option<CString>,
+}
+
+///A value going into or coming out of a script
+pub struct EmValue(Value);
+
+///What kind of value an `EmValue` holds. Anything that doesn't have its own accessor (arrays,
+///objects, functions...) is `EM_OTHER` and can still be turned into a string
+#[repr(C)]
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EmType {
+    EM_NULL,
+    EM_NUMBER,
+    EM_STRING,
+    EM_BOOL,
+    EM_OTHER,
+}
+
+///A function C code can give to scripts. It gets the arguments, how many there are, and the user
+///data it was registered with. It gives back a new value that the runtime takes ownership of, or
+///null to give the script null
+pub type EmCallback = extern "C" fn(args: *const *const EmValue, argc: usize, user_data: *mut c_void) -> *mut EmValue;
+
+fn to_str<'a>(s: *const c_char) -> Option<&'a str> {
+    if s.is_null() {
+        return None;
+    }
+    unsafe { CStr::from_ptr(s) }.to_str().ok()
+}
+
+fn boxed(val: Value) -> *mut EmValue {
+    Box::into_raw(Box::new(EmValue(val)))
+}
+
+//interior nul bytes can't go into a C string, so they get dropped
+fn c_string(s: String) -> CString {
+    CString::new(s.replace('\0', "")).unwrap_or_default()
+}
+
+impl EmRuntime {
+    fn fail(&mut self, e: String) -> *mut EmValue {
+        self.last_error = Some(c_string(e));
+        ptr::null_mut()
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn em_runtime_new() -> *mut EmRuntime {
+    Box::into_raw(Box::new(EmRuntime {
+        engine: Engine::new(),
+        last_error: None,
+    }))
+}
+
+/// # Safety
+/// `rt` has to come from `em_runtime_new` and can't be used after this
+#[no_mangle]
+pub unsafe extern "C" fn em_runtime_free(rt: *mut EmRuntime) {
+    if !rt.is_null() {
+        drop(Box::from_raw(rt));
+    }
+}
+
+///Runs code in the runtime's global scope and gives back the value of the last statement.
+///Gives back null if something went wrong, and `em_last_error` says what
+///
+/// # Safety
+/// `rt` has to be a live runtime and `code` a nul terminated string
+#[no_mangle]
+pub unsafe extern "C" fn em_eval(rt: *mut EmRuntime, code: *const c_char) -> *mut EmValue {
+    let rt = match rt.as_mut() {
+        Some(rt) => rt,
+        None => return ptr::null_mut(),
+    };
+    let code = match to_str(code) {
+        Some(c) => c,
+        None => return rt.fail("Code has to be valid UTF-8".to_owned()),
+    };
+    match panic::catch_unwind(AssertUnwindSafe(|| rt.engine.eval(code))) {
+        Ok(Ok(val)) => boxed(val),
+        Ok(Err(e)) => rt.fail(e),
+        Err(_) => rt.fail("The interpreter hit an internal bug".to_owned()),
+    }
+}
+
+///The error from the last call that failed, or null. The string belongs to the runtime and is
+///only good until the next call that fails
+///
+/// # Safety
+/// `rt` has to be a live runtime
+#[no_mangle]
+pub unsafe extern "C" fn em_last_error(rt: *const EmRuntime) -> *const c_char {
+    match rt.as_ref().and_then(|rt| rt.last_error.as_ref()) {
+        Some(e) => e.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+///A copy of a global variable, or null if there isn't one with that name
+///
+/// # Safety
+/// `rt` has to be a live runtime and `name` a nul terminated string
+#[no_mangle]
+pub unsafe extern "C" fn em_get_global(rt: *const EmRuntime, name: *const c_char) -> *mut EmValue {
+    match (rt.as_ref(), to_str(name)) {
+        (Some(rt), Some(name)) => rt.engine.get_global(name).cloned().map_or(ptr::null_mut(), boxed),
+        _ => ptr::null_mut(),
+    }
+}
+
+///Sets a global variable to a copy of `val`
+///
+/// # Safety
+/// `rt` has to be a live runtime, `name` a nul terminated string, and `val` a live value
+#[no_mangle]
+pub unsafe extern "C" fn em_set_global(rt: *mut EmRuntime, name: *const c_char, val: *const EmValue) {
+    if let (Some(rt), Some(name), Some(val)) = (rt.as_mut(), to_str(name), val.as_ref()) {
+        rt.engine.set_global(name, val.0.clone());
+    }
+}
+
+///The user data a callback was registered with. It's only ever handed back to C
+struct UserData(*mut c_void);
+
+//the runtime can move to another thread, so the C side has to be fine with its user data
+//being used from whichever thread the runtime is on
+unsafe impl Send for UserData {}
+unsafe impl Sync for UserData {}
+
+///Lets scripts call `callback` as `name(...)`. `user_data` is handed back to it on every call
+///and has to stay valid for as long as the runtime does, on whatever thread the runtime is used from
+///
+/// # Safety
+/// `rt` has to be a live runtime and `name` a nul terminated string
+#[no_mangle]
+pub unsafe extern "C" fn em_register_fn(rt: *mut EmRuntime, name: *const c_char, callback: EmCallback, user_data: *mut c_void) {
+    if let (Some(rt), Some(name)) = (rt.as_mut(), to_str(name)) {
+        let user_data = UserData(user_data);
+        rt.engine.register_fn(
+            name,
+            Signature::ANY,
+            Arc::new(move |_rt, args| {
+                let args: Vec<EmValue> = args.into_iter().map(EmValue).collect();
+                let ptrs: Vec<*const EmValue> = args.iter().map(|a| a as *const EmValue).collect();
+                let res = callback(ptrs.as_ptr(), ptrs.len(), user_data.0);
+                if res.is_null() {
+                    Value::Null
+                } else {
+                    Box::from_raw(res).0
+                }
+            }),
+        );
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn em_value_null() -> *mut EmValue {
+    boxed(Value::Null)
+}
+
+#[no_mangle]
+pub extern "C" fn em_value_number(n: f64) -> *mut EmValue {
+    boxed(Value::Float(n))
+}
+
+#[no_mangle]
+pub extern "C" fn em_value_bool(b: bool) -> *mut EmValue {
+    boxed(Value::EmBool(b))
+}
+
+///Gives back null if `s` isn't valid UTF-8
+///
+/// # Safety
+/// `s` has to be a nul terminated string
+#[no_mangle]
+pub unsafe extern "C" fn em_value_string(s: *const c_char) -> *mut EmValue {
+    to_str(s).map_or(ptr::null_mut(), |s| boxed(Value::EmString(s.into())))
+}
+
+/// # Safety
+/// `val` has to be a live value
+#[no_mangle]
+pub unsafe extern "C" fn em_value_type(val: *const EmValue) -> EmType {
+    match val.as_ref().map(|v| &v.0) {
+        None | Some(Value::Null) => EmType::EM_NULL,
+        Some(Value::Float(_)) => EmType::EM_NUMBER,
+        Some(Value::EmString(_)) => EmType::EM_STRING,
+        Some(Value::EmBool(_)) => EmType::EM_BOOL,
+        Some(_) => EmType::EM_OTHER,
+    }
+}
+
+///The number in `val`, or 0 if it isn't a number
+///
+/// # Safety
+/// `val` has to be a live value
+#[no_mangle]
+pub unsafe extern "C" fn em_value_as_number(val: *const EmValue) -> f64 {
+    match val.as_ref().map(|v| &v.0) {
+        Some(Value::Float(f)) => *f,
+        _ => 0.0,
+    }
+}
+
+///Whether `val` counts as true in a condition
+///
+/// # Safety
+/// `val` has to be a live value
+#[no_mangle]
+pub unsafe extern "C" fn em_value_as_bool(val: *const EmValue) -> bool {
+    val.as_ref().is_some_and(|v| v.0.is_truthy())
+}
+
+///`val` as text, the same way `println` would show it. Free it with `em_string_free`
+///
+/// # Safety
+/// `val` has to be a live value
+#[no_mangle]
+pub unsafe extern "C" fn em_value_to_string(val: *const EmValue) -> *mut c_char {
+    match val.as_ref() {
+        Some(v) => c_string(format!("{}", v.0)).into_raw(),
+        None => ptr::null_mut(),
+    }
+}
+
+/// # Safety
+/// `val` has to come from this library and can't be used after this. Values passed to a callback
+/// belong to the runtime and shouldn't be freed
+#[no_mangle]
+pub unsafe extern "C" fn em_value_free(val: *mut EmValue) {
+    if !val.is_null() {
+        drop(Box::from_raw(val));
+    }
+}
+
+/// # Safety
+/// `s` has to come from `em_value_to_string` and can't be used after this
+#[no_mangle]
+pub unsafe extern "C" fn em_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
@@ -0,0 +1,61 @@
+use super::*;
+
+extern "C" fn add_all(args: *const *const EmValue, argc: usize, user_data: *mut c_void) -> *mut EmValue {
+    let calls = unsafe { &mut *(user_data as *mut u32) };
+    *calls += 1;
+    let args = unsafe { std::slice::from_raw_parts(args, argc) };
+    let total: f64 = args.iter().map(|a| unsafe { em_value_as_number(*a) }).sum();
+    em_value_number(total)
+}
+
+fn c(s: &str) -> CString {
+    CString::new(s).unwrap()
+}
+
+#[test]
+fn embed_from_c() {
+    unsafe {
+        let rt = em_runtime_new();
+        let mut calls = 0u32;
+        em_register_fn(rt, c("add_all").as_ptr(), add_all, &mut calls as *mut u32 as *mut c_void);
+
+        let val = em_eval(rt, c("x = add_all(1, 2, 3) * 2").as_ptr());
+        assert_eq!(em_value_type(val), EmType::EM_NUMBER);
+        assert_eq!(em_value_as_number(val), 12.0);
+        assert_eq!(calls, 1);
+        em_value_free(val);
+
+        let name = em_value_string(c("gem").as_ptr());
+        em_set_global(rt, c("name").as_ptr(), name);
+        em_value_free(name);
+        let val = em_eval(rt, c("greeting = \"hi \" + name").as_ptr());
+        em_value_free(val);
+
+        let x = em_get_global(rt, c("greeting").as_ptr());
+        assert_eq!(em_value_type(x), EmType::EM_STRING);
+        let text = em_value_to_string(x);
+        assert_eq!(CStr::from_ptr(text).to_str().unwrap(), "hi gem");
+        em_string_free(text);
+        em_value_free(x);
+        assert!(em_get_global(rt, c("missing").as_ptr()).is_null());
+
+        //errors come back as null with a message
+        assert!(em_eval(rt, c("len()").as_ptr()).is_null());
+        assert_eq!(CStr::from_ptr(em_last_error(rt)).to_str().unwrap(), "len takes 1 argument, got 0");
+        em_runtime_free(rt);
+    }
+}
+
+#[test]
+fn header_has_every_function() {
+    let header = include_str!("../../include/emerald.h");
+    for line in include_str!("mod.rs").lines() {
+        if let Some(rest) = line.split("extern \"C\" fn ").nth(1) {
+            let name = &rest[..rest.find('(').unwrap()];
+            if name.is_empty() {
+                continue;
+            }
+            assert!(header.contains(&format!(" {}(", name)) || header.contains(&format!("*{}(", name)), "{} is missing from emerald.h", name);
+        }
+    }
+}
@@ -0,0 +1,164 @@
+//! Writes syntax highlighting definitions for editors. Everything comes from the lexer's own
+//! tables and the runtime's builtins, so regenerating these after changing the language is all it
+//! takes to keep editors in sync. `terminal` colors code the same way for the REPL and error output
+
+#[cfg(test)]
+mod tests;
+pub mod terminal;
+
+use crate::interpreter::Runtime;
+use crate::lexer::{BLOCK_COMMENT, KEYWORDS, LINE_COMMENTS, OPERATORS};
+use serde_json::json;
+
+pub const FORMATS: &[&str] = &["tmLanguage", "vim", "pygments"];
+
+//keywords that are really values get highlighted like constants
+const CONSTANTS: &[&str] = &["true", "false", "null"];
+
+fn keywords() -> Vec<&'static str> {
+    KEYWORDS.iter().copied().filter(|k| !CONSTANTS.contains(k)).collect()
+}
+
+fn builtins() -> Vec<String> {
+    Runtime::new().builtin_names()
+}
+
+///Generates the definitions in one of `FORMATS`
+pub fn generate(format: &str) -> Result<String, String> {
+    match format {
+        "tmLanguage" => Ok(tm_language()),
+        "vim" => Ok(vim()),
+        "pygments" => Ok(pygments()),
+        other => Err(format!("Unknown format {}, expected one of {}", other, FORMATS.join(", "))),
+    }
+}
+
+fn alternatives<S: AsRef<str>>(items: &[S], escape: fn(&str) -> String) -> String {
+    items.iter().map(|i| escape(i.as_ref())).collect::<Vec<String>>().join("|")
+}
+
+///A TextMate grammar as JSON, which is what VS Code and most other editors load
+fn tm_language() -> String {
+    let escape = regex::escape;
+    let grammar = json!({
+        "name": "EmeraldScript",
+        "scopeName": "source.emerald",
+        "fileTypes": ["em"],
+        "patterns": [
+            { "name": "comment.line.emerald", "match": format!("({}).*$", alternatives(LINE_COMMENTS, escape)) },
+            { "include": "#block-comment" },
+            { "name": "string.quoted.double.emerald", "begin": "\"", "end": "\"" },
+            { "name": "constant.numeric.emerald", "match": "\\b\\d+(\\.\\d+)?\\b" },
+            { "name": "constant.language.emerald", "match": format!("\\b({})\\b", CONSTANTS.join("|")) },
+            { "name": "keyword.control.emerald", "match": format!("\\b({})\\b", keywords().join("|")) },
+            { "name": "support.function.builtin.emerald", "match": format!("\\b({})\\b", builtins().join("|")) },
+            { "name": "keyword.operator.emerald", "match": alternatives(OPERATORS, escape) },
+        ],
+        "repository": {
+            "block-comment": {
+                "name": "comment.block.emerald",
+                "begin": regex::escape(BLOCK_COMMENT.0),
+                "end": regex::escape(BLOCK_COMMENT.1),
+                "patterns": [{ "include": "#block-comment" }],
+            },
+        },
+    });
+    //serializing a json value can't fail
+    serde_json::to_string_pretty(&grammar).unwrap_or_default()
+}
+
+//vim's default regex flavor treats only a few of these characters specially
+fn vim_escape(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            '*' | '.' | '/' | '~' | '[' | ']' | '\\' => format!("\\{}", c),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+fn vim() -> String {
+    let mut out = String::from("\" Vim syntax file for EmeraldScript, generated by `gem-bin highlight --format vim`\n");
+    out.push_str("if exists(\"b:current_syntax\")\n  finish\nendif\n\n");
+    out.push_str(&format!("syn keyword emeraldKeyword {}\n", keywords().join(" ")));
+    out.push_str(&format!("syn keyword emeraldConstant {}\n", CONSTANTS.join(" ")));
+    out.push_str(&format!("syn keyword emeraldBuiltin {}\n", builtins().join(" ")));
+    out.push_str("syn match emeraldNumber \"\\<\\d\\+\\(\\.\\d\\+\\)\\=\\>\"\n");
+    out.push_str(&format!("syn match emeraldOperator \"{}\"\n", vim_alternatives(OPERATORS)));
+    out.push_str("syn region emeraldString start=+\"+ end=+\"+\n");
+    out.push_str(&format!("syn match emeraldComment \"\\({}\\).*$\"\n", vim_alternatives(LINE_COMMENTS)));
+    out.push_str(&format!(
+        "syn region emeraldBlockComment start=\"{}\" end=\"{}\" contains=emeraldBlockComment\n\n",
+        vim_escape(BLOCK_COMMENT.0),
+        vim_escape(BLOCK_COMMENT.1)
+    ));
+    for (group, link) in &[
+        ("Keyword", "Keyword"),
+        ("Constant", "Constant"),
+        ("Builtin", "Function"),
+        ("Number", "Number"),
+        ("Operator", "Operator"),
+        ("String", "String"),
+        ("Comment", "Comment"),
+        ("BlockComment", "Comment"),
+    ] {
+        out.push_str(&format!("hi def link emerald{} {}\n", group, link));
+    }
+    out.push_str("\nlet b:current_syntax = \"emerald\"\n");
+    out
+}
+
+fn vim_alternatives(items: &[&str]) -> String {
+    items.iter().map(|i| vim_escape(i)).collect::<Vec<String>>().join("\\|")
+}
+
+fn python_tuple<S: AsRef<str>>(items: &[S]) -> String {
+    //rust's debug formatting of plain ascii strings is also a valid python literal
+    let items: Vec<String> = items.iter().map(|i| format!("{:?}", i.as_ref())).collect();
+    format!("({},)", items.join(", "))
+}
+
+fn pygments() -> String {
+    let line_comments = alternatives(LINE_COMMENTS, regex::escape);
+    let (open, close) = (regex::escape(BLOCK_COMMENT.0), regex::escape(BLOCK_COMMENT.1));
+    format!(
+        r##"# Pygments lexer for EmeraldScript, generated by `gem-bin highlight --format pygments`
+from pygments.lexer import RegexLexer, words
+from pygments.token import Comment, Keyword, Name, Number, Operator, Punctuation, String, Text
+
+
+class EmeraldScriptLexer(RegexLexer):
+    name = "EmeraldScript"
+    aliases = ["emerald", "emeraldscript"]
+    filenames = ["*.em"]
+
+    tokens = {{
+        "root": [
+            (r"\s+", Text),
+            (r"({line_comments}).*?$", Comment.Single),
+            (r"{open}", Comment.Multiline, "comment"),
+            (r'"[^"]*"', String),
+            (r"\d+(\.\d+)?", Number),
+            (words({constants}, suffix=r"\b"), Keyword.Constant),
+            (words({keywords}, suffix=r"\b"), Keyword),
+            (words({builtins}, suffix=r"\b"), Name.Builtin),
+            (words({operators}), Operator),
+            (r"[{{}}()\[\];,]", Punctuation),
+            (r"[A-Za-z_~]\w*", Name),
+        ],
+        "comment": [
+            (r"{open}", Comment.Multiline, "#push"),
+            (r"{close}", Comment.Multiline, "#pop"),
+            (r".|\n", Comment.Multiline),
+        ],
+    }}
+"##,
+        line_comments = line_comments,
+        open = open,
+        close = close,
+        constants = python_tuple(CONSTANTS),
+        keywords = python_tuple(&keywords()),
+        builtins = python_tuple(&builtins()),
+        operators = python_tuple(OPERATORS),
+    )
+}
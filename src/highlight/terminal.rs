@@ -0,0 +1,125 @@
+//! Colors code for a terminal, for the REPL's input and the snippets shown with errors. Words are
+//! sorted out with the same keyword and comment tables the lexer uses, but the text is kept exactly
+//! as it was typed, spacing and comments included
+
+use super::CONSTANTS;
+use crate::lexer::{BLOCK_COMMENT, KEYWORDS, LINE_COMMENTS};
+use console::style;
+
+///What a piece of code gets colored as
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Keyword,
+    ///`true`, `false` and `null`
+    Constant,
+    Number,
+    ///Strings and byte strings, quotes included
+    Str,
+    Comment,
+    ///Names, operators, punctuation and spaces
+    Plain,
+}
+
+///Splits `src` into pieces that together are exactly `src`
+pub fn spans(src: &str) -> Vec<(Kind, &str)> {
+    let mut out: Vec<(Kind, &str)> = vec![];
+    let mut i = 0;
+    while i < src.len() {
+        let rest = &src[i..];
+        let (kind, len) = next(rest);
+        //runs of plain text are kept together so there are fewer escape codes
+        match out.last_mut() {
+            Some((Kind::Plain, text)) if kind == Kind::Plain => *text = &src[i - text.len()..i + len],
+            _ => out.push((kind, &rest[..len])),
+        }
+        i += len;
+    }
+    out
+}
+
+///The kind and length in bytes of the piece at the start of `rest`
+fn next(rest: &str) -> (Kind, usize) {
+    if let Some(c) = LINE_COMMENTS.iter().find(|c| rest.starts_with(*c)) {
+        return (Kind::Comment, rest.find('\n').unwrap_or(rest.len()).max(c.len()));
+    }
+    if rest.starts_with(BLOCK_COMMENT.0) {
+        return (Kind::Comment, block_comment(rest));
+    }
+    let first = rest.chars().next().unwrap_or_default();
+    if first == '"' || rest.starts_with("b\"") {
+        let open = if first == '"' { 1 } else { 2 };
+        return (Kind::Str, string(rest, open));
+    }
+    if first.is_ascii_digit() {
+        let mut len = 0;
+        let chars: Vec<char> = rest.chars().collect();
+        while len < chars.len() && (chars[len].is_ascii_digit() || chars[len] == '.' && chars.get(len + 1).is_some_and(|c| c.is_ascii_digit())) {
+            len += 1;
+        }
+        return (Kind::Number, len);
+    }
+    if first.is_alphanumeric() || first == '_' {
+        let len = rest.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(rest.len());
+        let word = &rest[..len];
+        let kind = match word {
+            w if CONSTANTS.contains(&w) => Kind::Constant,
+            w if KEYWORDS.contains(&w) => Kind::Keyword,
+            _ => Kind::Plain,
+        };
+        return (kind, len);
+    }
+    (Kind::Plain, first.len_utf8())
+}
+
+//block comments nest, and one that never closes runs to the end
+fn block_comment(rest: &str) -> usize {
+    let (open, close) = BLOCK_COMMENT;
+    let mut depth = 0;
+    let mut i = 0;
+    while i < rest.len() {
+        if rest[i..].starts_with(open) {
+            depth += 1;
+            i += open.len();
+        } else if rest[i..].starts_with(close) {
+            depth -= 1;
+            i += close.len();
+            if depth == 0 {
+                return i;
+            }
+        } else {
+            i += rest[i..].chars().next().map_or(1, char::len_utf8);
+        }
+    }
+    rest.len()
+}
+
+//plain strings have no escapes, but `\"` doesn't end a byte string
+fn string(rest: &str, open: usize) -> usize {
+    let escapes = open == 2;
+    let mut chars = rest[open..].char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' if escapes => {
+                chars.next();
+            }
+            '"' => return open + i + 1,
+            _ => {}
+        }
+    }
+    rest.len()
+}
+
+///`src` with escape codes for colors. Nothing is added when the terminal can't show colors
+pub fn colorize(src: &str) -> String {
+    spans(src)
+        .into_iter()
+        .map(|(kind, text)| match kind {
+            Kind::Keyword => style(text).magenta().to_string(),
+            Kind::Constant => style(text).yellow().to_string(),
+            Kind::Number => style(text).cyan().to_string(),
+            Kind::Str => style(text).green().to_string(),
+            Kind::Comment => style(text).dim().to_string(),
+            Kind::Plain => text.to_owned(),
+        })
+        .collect()
+}
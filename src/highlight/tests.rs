@@ -0,0 +1,49 @@
+use super::*;
+use regex::Regex;
+
+#[test]
+fn tm_language_matches_the_lexer() {
+    let grammar: serde_json::Value = serde_json::from_str(&generate("tmLanguage").unwrap()).unwrap();
+    let patterns = grammar["patterns"].as_array().unwrap();
+    let pattern = |name: &str| {
+        let p = patterns.iter().find(|p| p["name"] == name).unwrap();
+        Regex::new(&format!("^(?:{})$", p["match"].as_str().unwrap())).unwrap()
+    };
+    for op in OPERATORS {
+        assert!(pattern("keyword.operator.emerald").is_match(op), "{} isn't highlighted", op);
+    }
+    for k in keywords() {
+        assert!(pattern("keyword.control.emerald").is_match(k), "{} isn't highlighted", k);
+    }
+    assert!(pattern("support.function.builtin.emerald").is_match("println"));
+    assert!(pattern("constant.numeric.emerald").is_match("4.5"));
+}
+
+#[test]
+fn every_format() {
+    let vim = generate("vim").unwrap();
+    assert!(vim.contains(&format!("syn keyword emeraldKeyword {}\n", keywords().join(" "))));
+    assert!(vim.contains("syn keyword emeraldConstant true false null\n"));
+    let pygments = generate("pygments").unwrap();
+    assert!(pygments.contains("class EmeraldScriptLexer(RegexLexer):"));
+    assert!(pygments.contains("\"elif\""));
+    assert_eq!(generate("emacs"), Err("Unknown format emacs, expected one of tmLanguage, vim, pygments".to_owned()));
+}
+
+#[test]
+fn terminal_spans() {
+    use terminal::{spans, Kind};
+    let src = "fn f(x) { return x * 2.5; } // done\nlet s = \"a // b\"; t = b\"\\\"\" /* a /* b */ c */ null";
+    let found = spans(src);
+    //nothing gets lost or moved around
+    assert_eq!(found.iter().map(|(_, t)| *t).collect::<String>(), src);
+    let of = |kind| found.iter().filter(|(k, _)| *k == kind).map(|(_, t)| *t).collect::<Vec<&str>>();
+    assert_eq!(of(Kind::Keyword), vec!["fn", "return", "let"]);
+    assert_eq!(of(Kind::Number), vec!["2.5"]);
+    assert_eq!(of(Kind::Str), vec!["\"a // b\"", "b\"\\\"\""]);
+    assert_eq!(of(Kind::Comment), vec!["// done", "/* a /* b */ c */"]);
+    assert_eq!(of(Kind::Constant), vec!["null"]);
+    //names that start with a keyword are still names, and so is a b that isn't a byte string
+    assert_eq!(spans("format(b, 1..3)"), vec![(Kind::Plain, "format(b, "), (Kind::Number, "1"), (Kind::Plain, ".."), (Kind::Number, "3"), (Kind::Plain, ")")]);
+    assert_eq!(console::strip_ansi_codes(&terminal::colorize(src)), src);
+}
@@ -1,69 +1,1455 @@
 use std::collections::HashMap;
-use crate::interpreter::Value;
+use std::io::BufRead;
+use std::process::Command;
+use std::sync::Arc;
+use crate::interpreter::{compare, interrupt, range_len, Runtime, Value};
+use crate::interpreter::bytes::{self, Encoding};
+use crate::interpreter::iterator::{to_iter, Iter};
+use crate::interpreter::native::{FileHandle, NativeObject};
+use crate::interpreter::threads::Channel;
+use crate::parser::ExprNode;
+use crate::interpreter::types::{EmObject, MapKey, OrderedMap, OrderedSet};
 use console::Term;
 
-pub fn get_functions() -> HashMap<String, Box<dyn Fn(Vec<Value>) -> Value>> {
-    let mut hash: HashMap<String, Box<dyn Fn(Vec<Value>) -> Value>> = HashMap::new();
-    hash.insert("print".to_owned(), Box::new(em_print));
-    hash.insert("println".to_owned(), Box::new(em_println));
-    hash.insert("number".to_owned(), Box::new(em_number));
-    hash.insert("readln".to_owned(), Box::new(em_readln));
-    hash.insert("read".to_owned(), Box::new(em_read));
+///A native function callable from scripts. Builtins get the runtime so they can check things like the dry run flag
+pub type Builtin = Arc<dyn Fn(&mut Runtime, Vec<Value>) -> Value + Send + Sync>;
+
+///What a builtin accepts. The runtime checks this before calling it, so builtins can count on
+///getting at least `min` arguments of the right types
+#[derive(Debug, Clone, Copy)]
+pub struct Signature {
+    pub min: usize,
+    ///None means any number of arguments
+    pub max: Option<usize>,
+    ///The type each argument has to be, using the same names as `type()`. Alternatives are split
+    ///with `|`, and `any` takes anything. Arguments past the end of this can be anything
+    pub types: &'static [&'static str],
+}
+
+impl Signature {
+    ///Takes any number of arguments of any type
+    pub const ANY: Signature = Signature::new(0, None, &[]);
+
+    pub const fn new(min: usize, max: Option<usize>, types: &'static [&'static str]) -> Signature {
+        Signature { min, max, types }
+    }
+
+    ///Checks the arguments, the error says what `name` wanted and what it got
+    pub fn check(&self, name: &str, args: &[Value]) -> Result<(), String> {
+        let plural = |n: usize| if n == 1 { "argument" } else { "arguments" };
+        let count = match self.max {
+            Some(max) if max == self.min => format!("{} {}", max, plural(max)),
+            Some(max) => format!("{} to {} arguments", self.min, max),
+            None => format!("at least {} {}", self.min, plural(self.min)),
+        };
+        if args.len() < self.min || self.max.is_some_and(|m| args.len() > m) {
+            return Err(format!("{} takes {}, got {}", name, count, args.len()));
+        }
+        for (i, (arg, expected)) in args.iter().zip(self.types.iter()).enumerate() {
+            let actual = type_name(arg);
+            if *expected != "any" && !expected.split('|').any(|t| t == actual) {
+                return Err(format!(
+                    "Argument {} of {} should be {}, got {}",
+                    i + 1,
+                    name,
+                    any_of(expected),
+                    with_article(&actual)
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    ///Reads like a call, `range(float, float?, float?)` or `print(...)`. Arguments that can be
+    ///left out end in `?`
+    pub fn usage(&self, name: &str) -> String {
+        let shown = self.max.unwrap_or_else(|| self.min.max(self.types.len()));
+        let mut params: Vec<String> = (0..shown)
+            .map(|i| {
+                let t = self.types.get(i).copied().unwrap_or("any");
+                if i < self.min { t.to_owned() } else { format!("{}?", t) }
+            })
+            .collect();
+        if self.max.is_none() {
+            params.push("...".to_owned());
+        }
+        format!("{}({})", name, params.join(", "))
+    }
+}
+
+///Turns `array|string|object` into "an array, a string, or an object"
+fn any_of(types: &str) -> String {
+    let types: Vec<String> = types.split('|').map(with_article).collect();
+    match types.split_last() {
+        Some((last, rest)) if rest.len() > 1 => format!("{}, or {}", rest.join(", "), last),
+        Some((last, rest)) if rest.len() == 1 => format!("{} or {}", rest[0], last),
+        _ => types.join(""),
+    }
+}
+
+fn with_article(t: &str) -> String {
+    match t.chars().next() {
+        _ if t == "bytes" => t.to_owned(),
+        Some('a' | 'e' | 'i' | 'o' | 'u') => format!("an {}", t),
+        _ => format!("a {}", t),
+    }
+}
+
+///A builtin along with what it accepts
+#[derive(Clone)]
+pub struct BuiltinFn {
+    pub sig: Signature,
+    pub func: Builtin,
+}
+
+impl BuiltinFn {
+    pub fn new(sig: Signature, func: Builtin) -> BuiltinFn {
+        BuiltinFn { sig, func }
+    }
+}
+
+///What `builtins()` lists, shows up as `<builtin len(array|string|object|range)>`
+pub(crate) struct BuiltinInfo {
+    name: String,
+    sig: Signature,
+}
+
+impl NativeObject for BuiltinInfo {
+    fn type_name(&self) -> &str {
+        "builtin"
+    }
+
+    fn call_method(&self, _rt: &mut Runtime, name: &str, _args: Vec<Value>) -> Result<Value, String> {
+        match name {
+            "name" => Ok(Value::EmString(self.name.clone().into())),
+            "min_args" => Ok(Value::Float(self.sig.min as f64)),
+            "max_args" => Ok(self.sig.max.map_or(Value::Null, |m| Value::Float(m as f64))),
+            _ => Err(format!("builtin has no method {}", name)),
+        }
+    }
+
+    fn display(&self) -> String {
+        format!("<builtin {}>", self.sig.usage(&self.name))
+    }
+}
+
+//anything that can be looped over, see `Runtime::iterate`
+const ITERABLE: &str = "array|tuple|string|bytes|object|range|set|iterator";
+
+fn add(hash: &mut HashMap<String, BuiltinFn>, name: &str, sig: Signature, func: fn(&mut Runtime, Vec<Value>) -> Value) {
+    hash.insert(name.to_owned(), BuiltinFn::new(sig, Arc::new(func)));
+}
+
+pub fn get_functions() -> HashMap<String, BuiltinFn> {
+    let mut hash: HashMap<String, BuiltinFn> = HashMap::new();
+    let one = Signature::new(1, Some(1), &[]);
+    add(&mut hash, "print", Signature::ANY, em_print);
+    add(&mut hash, "println", Signature::ANY, em_println);
+    add(&mut hash, "set_print_separator", one, em_set_print_separator);
+    add(&mut hash, "number", one, em_number);
+    add(&mut hash, "to_number", one, em_number);
+    add(&mut hash, "to_int", one, em_to_int);
+    add(&mut hash, "to_string", one, em_to_string);
+    add(&mut hash, "to_bool", one, em_to_bool);
+    add(&mut hash, "parse_float", one, em_parse_float);
+    add(&mut hash, "is_nan", Signature::new(1, Some(1), &["float"]), em_is_nan);
+    add(&mut hash, "is_finite", Signature::new(1, Some(1), &["float"]), em_is_finite);
+    add(&mut hash, "round_to", Signature::new(2, Some(2), &["float", "float"]), em_round_to);
+    add(&mut hash, "to_fixed", Signature::new(2, Some(2), &["float", "float"]), em_to_fixed);
+    add(&mut hash, "bytes", Signature::new(1, Some(2), &["string|bytes|array|tuple", "string"]), em_bytes);
+    add(&mut hash, "decode", Signature::new(1, Some(2), &["bytes", "string"]), em_decode);
+    add(&mut hash, "base64_encode", Signature::new(1, Some(1), &["string|bytes"]), em_base64_encode);
+    add(&mut hash, "base64_decode", Signature::new(1, Some(1), &["string"]), em_base64_decode);
+    add(&mut hash, "hex_encode", Signature::new(1, Some(1), &["string|bytes"]), em_hex_encode);
+    add(&mut hash, "hex_decode", Signature::new(1, Some(1), &["string"]), em_hex_decode);
+    add(&mut hash, "url_encode", Signature::new(1, Some(1), &["string|bytes"]), em_url_encode);
+    add(&mut hash, "url_decode", Signature::new(1, Some(1), &["string"]), em_url_decode);
+    add(&mut hash, "utf8_valid", Signature::new(1, Some(1), &["string|bytes"]), em_utf8_valid);
+    #[cfg(feature = "hashing")]
+    {
+        add(&mut hash, "md5", Signature::new(1, Some(1), &["string|bytes"]), em_md5);
+        add(&mut hash, "sha1", Signature::new(1, Some(1), &["string|bytes"]), em_sha1);
+        add(&mut hash, "sha256", Signature::new(1, Some(1), &["string|bytes"]), em_sha256);
+        add(&mut hash, "hmac_sha256", Signature::new(2, Some(2), &["string|bytes", "string|bytes"]), em_hmac_sha256);
+        add(&mut hash, "crc32", Signature::new(1, Some(1), &["string|bytes"]), em_crc32);
+    }
+    add(&mut hash, "readln", Signature::new(0, Some(1), &[]), em_readln);
+    add(&mut hash, "read", Signature::new(0, Some(1), &[]), em_read);
+    add(&mut hash, "format", Signature::new(1, None, &["string"]), em_format);
+    add(&mut hash, "props", Signature::new(1, Some(1), &["object|class"]), em_props);
+    add(&mut hash, "has_prop", Signature::new(2, Some(2), &["object|class", "string"]), em_has_prop);
+    add(&mut hash, "remove_prop", Signature::new(2, Some(2), &["object", "string"]), em_remove_prop);
+    add(&mut hash, "type", one, em_type);
+    add(&mut hash, "builtins", Signature::new(0, Some(0), &[]), em_builtins);
+    add(&mut hash, "is_instance", Signature::new(2, Some(2), &["any", "class|string"]), em_is_instance);
+    add(&mut hash, "make", Signature::new(1, Some(2), &["class"]), em_make);
+    add(&mut hash, "len", Signature::new(1, Some(1), &["array|tuple|string|bytes|object|range|set"]), em_len);
+    add(&mut hash, "range", Signature::new(1, Some(3), &["float", "float", "float"]), em_range);
+    add(&mut hash, "to_array", Signature::new(1, Some(1), &[ITERABLE]), em_to_array);
+    add(&mut hash, "set", Signature::new(0, Some(1), &[ITERABLE]), em_set);
+    add(&mut hash, "iter", Signature::new(1, Some(1), &[ITERABLE]), em_iter);
+    add(&mut hash, "take", Signature::new(2, Some(2), &[ITERABLE, "float"]), em_take);
+    add(&mut hash, "skip", Signature::new(2, Some(2), &[ITERABLE, "float"]), em_skip);
+    add(&mut hash, "zip", Signature::new(2, Some(2), &[ITERABLE, ITERABLE]), em_zip);
+    add(&mut hash, "enumerate", Signature::new(1, Some(1), &[ITERABLE]), em_enumerate);
+    add(&mut hash, "sum", Signature::new(1, Some(1), &[ITERABLE]), em_sum);
+    add(&mut hash, "sort", Signature::new(1, Some(1), &[ITERABLE]), em_sort);
+    add(&mut hash, "map", Signature::new(2, Some(2), &[ITERABLE, "function"]), em_map);
+    add(&mut hash, "join", Signature::new(1, Some(2), &[ITERABLE]), em_join);
+    add(&mut hash, "lines", Signature::new(1, Some(1), &["string"]), em_lines);
+    add(&mut hash, "chars", Signature::new(1, Some(1), &["string"]), em_chars);
+    #[cfg(feature = "graphemes")]
+    add(&mut hash, "graphemes", Signature::new(1, Some(1), &["string"]), em_graphemes);
+    add(&mut hash, "assert", Signature::new(1, Some(2), &[]), em_assert);
+    add(&mut hash, "assert_eq", Signature::new(2, Some(2), &[]), em_assert_eq);
+    add(&mut hash, "assert_throws", Signature::new(1, Some(1), &["function"]), em_assert_throws);
+    add(&mut hash, "exit", Signature::new(0, Some(1), &["float"]), em_exit);
+    add(&mut hash, "printf", Signature::new(1, None, &["string"]), em_printf);
+    add(&mut hash, "push", Signature::new(2, Some(2), &["array"]), em_push);
+    add(&mut hash, "on", Signature::new(2, Some(2), &["string", "function"]), em_on);
+    add(&mut hash, "spawn", Signature::new(1, None, &["function"]), em_spawn);
+    add(&mut hash, "sleep_async", Signature::new(1, Some(1), &["float"]), em_sleep_async);
+    add(&mut hash, "channel", Signature::new(0, Some(0), &[]), em_channel);
+    add(&mut hash, "send", Signature::new(2, Some(2), &["channel"]), em_send);
+    add(&mut hash, "recv", Signature::new(1, Some(1), &["channel"]), em_recv);
+    //a browser doesn't give wasm modules files or threads
+    if cfg!(not(target_arch = "wasm32")) {
+        add(&mut hash, "open", Signature::new(1, Some(1), &["string"]), em_open);
+        add(&mut hash, "read_file_bytes", Signature::new(1, Some(1), &["string"]), em_read_file_bytes);
+        add(&mut hash, "write_file_bytes", Signature::new(2, Some(2), &["string", "bytes"]), em_write_file_bytes);
+        add(&mut hash, "thread_spawn", Signature::new(1, None, &["function"]), em_thread_spawn);
+        add(&mut hash, "on_interrupt", Signature::new(1, Some(1), &["function"]), em_on_interrupt);
+    }
+    add_exec(&mut hash);
+    add_prelude(&mut hash);
 
     hash
 }
 
-fn em_print(args: Vec<Value>) -> Value {
-    print!("{}", args[0]);
+thread_local! {
+    //parsing the prelude every time a runtime is made would be a waste, it never changes
+    static PRELUDE: Vec<(String, Value)> = parse_prelude();
+}
+
+fn parse_prelude() -> Vec<(String, Value)> {
+    let tree = crate::parser::parse(crate::lexer::run(include_str!("prelude.em"))).expect("The prelude should always parse");
+    let mut funcs = vec![];
+    if let ExprNode::Block(v) = tree {
+        for node in v {
+            if let ExprNode::Func(name, params, body) = node {
+                let params = params
+                    .into_iter()
+                    .filter_map(|p| match p {
+                        ExprNode::Name(n) => Some(Value::Name(*n)),
+                        _ => None,
+                    })
+                    .collect();
+                funcs.push((super::ident_name(&name), Value::Function(*name, params, *body)));
+            }
+        }
+    }
+    funcs
+}
+
+///Adds the builtins from `prelude.em`. They're plain script functions underneath, so errors in
+///them stop the script the same way errors in any other function would
+fn add_prelude(hash: &mut HashMap<String, BuiltinFn>) {
+    PRELUDE.with(|p| {
+        for (name, func) in p.iter() {
+            let params = match func {
+                Value::Function(_, params, _) => params.len(),
+                _ => 0,
+            };
+            let func = func.clone();
+            hash.insert(
+                name.clone(),
+                BuiltinFn::new(
+                    Signature::new(params, Some(params), &[]),
+                    Arc::new(move |rt: &mut Runtime, args| {
+                        rt.call_function(&func, args).unwrap_or_else(|e| {
+                            rt.raise(e);
+                            Value::Null
+                        })
+                    }),
+                ),
+            );
+        }
+    });
+}
+
+///Whether a method on a value that isn't an object changes it, like `items.push(x)`. What these give
+///back gets put in the variable they were called on, so they give back the value they were called on
+///even when they raise
+pub(crate) fn writes_back(name: &str) -> bool {
+    matches!(name, "push" | "add" | "remove")
+}
+
+///Whether a builtin came from `prelude.em`, which scripts are expected to have their own versions of
+pub(crate) fn in_prelude(name: &str) -> bool {
+    PRELUDE.with(|p| p.iter().any(|(n, _)| n == name))
+}
+
+///Methods that work on values that aren't objects, like `"abc".len()`. The value the method is
+///called on gets passed in as the first argument, and the signature is for the arguments after it
+pub fn primitive_method(val: &Value, name: &str) -> Option<BuiltinFn> {
+    let func = match val {
+        Value::EmString(_) => string_method(name),
+        Value::Bytes(_) => bytes_method(name),
+        Value::EmArray(_) | Value::Range(..) => array_method(name),
+        Value::Tuple(_) => tuple_method(name),
+        Value::Float(_) => number_method(name),
+        Value::EmSet(_) => set_method(name),
+        _ => None,
+    }?;
+    Some(BuiltinFn::new(method_signature(name), func))
+}
+
+///What a primitive method takes, not counting the value it's called on. The value's type already
+///picked the method, so only the other arguments need checking
+fn method_signature(name: &str) -> Signature {
+    match name {
+        "contains" | "push" | "has" | "add" | "remove" => Signature::new(1, Some(1), &[]),
+        "map" => Signature::new(1, Some(1), &["function"]),
+        "take" | "skip" | "round_to" | "to_fixed" => Signature::new(1, Some(1), &["float"]),
+        "zip" | "union" | "intersect" | "difference" => Signature::new(1, Some(1), &[ITERABLE]),
+        "split" | "join" => Signature::new(0, Some(1), &[]),
+        "to_bytes" | "decode" => Signature::new(0, Some(1), &["string"]),
+        _ => Signature::new(0, Some(0), &[]),
+    }
+}
+
+fn string_method(name: &str) -> Option<Builtin> {
+    Some(match name {
+        "len" => Arc::new(em_len),
+        "upper" => Arc::new(|_rt, args| with_str(&args, |s| Value::EmString(s.to_uppercase().into()))),
+        "lower" => Arc::new(|_rt, args| with_str(&args, |s| Value::EmString(s.to_lowercase().into()))),
+        "trim" => Arc::new(|_rt, args| with_str(&args, |s| Value::EmString(s.trim().into()))),
+        "contains" => Arc::new(em_contains),
+        "split" => Arc::new(|_rt, args| {
+            let sep = args.get(1).map(|s| format!("{}", s)).unwrap_or_else(|| " ".to_owned());
+            with_str(&args, |s| {
+                str_array(s.split(sep.as_str()))
+            })
+        }),
+        "to_number" => Arc::new(em_number),
+        "to_bytes" => Arc::new(em_bytes),
+        "lines" => Arc::new(em_lines),
+        "chars" => Arc::new(em_chars),
+        #[cfg(feature = "graphemes")]
+        "graphemes" => Arc::new(em_graphemes),
+        _ => return None,
+    })
+}
+
+fn array_method(name: &str) -> Option<Builtin> {
+    Some(match name {
+        "len" => Arc::new(em_len),
+        "push" => Arc::new(em_push),
+        "sum" => Arc::new(em_sum),
+        "sort" => Arc::new(em_sort),
+        "map" => Arc::new(em_map),
+        "join" => Arc::new(em_join),
+        "contains" => Arc::new(em_contains),
+        "to_array" => Arc::new(em_to_array),
+        "iter" => Arc::new(em_iter),
+        "take" => Arc::new(em_take),
+        "skip" => Arc::new(em_skip),
+        "zip" => Arc::new(em_zip),
+        "enumerate" => Arc::new(em_enumerate),
+        _ => return None,
+    })
+}
+
+fn bytes_method(name: &str) -> Option<Builtin> {
+    Some(match name {
+        "len" => Arc::new(em_len),
+        "decode" => Arc::new(em_decode),
+        "to_array" => Arc::new(em_to_array),
+        "iter" => Arc::new(em_iter),
+        _ => return None,
+    })
+}
+
+fn tuple_method(name: &str) -> Option<Builtin> {
+    Some(match name {
+        "len" => Arc::new(em_len),
+        "contains" => Arc::new(em_contains),
+        "to_array" => Arc::new(em_to_array),
+        "iter" => Arc::new(em_iter),
+        _ => return None,
+    })
+}
+
+fn set_method(name: &str) -> Option<Builtin> {
+    Some(match name {
+        "len" => Arc::new(em_len),
+        "has" => Arc::new(em_set_has),
+        "add" => Arc::new(em_set_add),
+        "remove" => Arc::new(em_set_remove),
+        "union" => Arc::new(|rt, args| set_op(rt, args, |a, b| a.iter().chain(b.iter()).cloned().collect())),
+        "intersect" => Arc::new(|rt, args| set_op(rt, args, |a, b| a.iter().filter(|k| b.contains(k)).cloned().collect())),
+        "difference" => Arc::new(|rt, args| set_op(rt, args, |a, b| a.iter().filter(|k| !b.contains(k)).cloned().collect())),
+        "to_array" => Arc::new(em_to_array),
+        "iter" => Arc::new(em_iter),
+        _ => return None,
+    })
+}
+
+fn number_method(name: &str) -> Option<Builtin> {
+    let op: fn(f64) -> f64 = match name {
+        "floor" => f64::floor,
+        "ceil" => f64::ceil,
+        "round" => f64::round,
+        "abs" => f64::abs,
+        "to_string" => return Some(Arc::new(em_to_string)),
+        "is_nan" => return Some(Arc::new(em_is_nan)),
+        "is_finite" => return Some(Arc::new(em_is_finite)),
+        "round_to" => return Some(Arc::new(em_round_to)),
+        "to_fixed" => return Some(Arc::new(em_to_fixed)),
+        _ => return None,
+    };
+    Some(Arc::new(move |_rt, args| match args.first() {
+        Some(Value::Float(f)) => Value::Float(op(*f)),
+        _ => Value::Null,
+    }))
+}
+
+fn with_str(args: &[Value], f: impl Fn(&str) -> Value) -> Value {
+    match args.first() {
+        Some(Value::EmString(s)) => f(s),
+        _ => Value::Null,
+    }
+}
+
+fn str_array<'a>(parts: impl Iterator<Item = &'a str>) -> Value {
+    Value::EmArray(Arc::new(parts.map(|p| Value::EmString(p.into())).collect()))
+}
+
+///Splits on both \n and \r\n, without a trailing empty line
+fn em_lines(_rt: &mut Runtime, args: Vec<Value>) -> Value {
+    with_str(&args, |s| str_array(s.lines()))
+}
+
+///Splits into unicode scalar values, so an accented letter made of two code points comes out as two
+fn em_chars(_rt: &mut Runtime, args: Vec<Value>) -> Value {
+    with_str(&args, |s| {
+        Value::EmArray(Arc::new(s.chars().map(|c| Value::EmString(c.to_string().into())).collect()))
+    })
+}
+
+///Splits into what a person would call characters, keeping combining marks and emoji sequences together
+#[cfg(feature = "graphemes")]
+fn em_graphemes(_rt: &mut Runtime, args: Vec<Value>) -> Value {
+    use unicode_segmentation::UnicodeSegmentation;
+    with_str(&args, |s| str_array(s.graphemes(true)))
+}
+
+///Strings look for a substring, arrays look for an equal element
+fn em_contains(rt: &mut Runtime, args: Vec<Value>) -> Value {
+    match (args.first(), args.get(1)) {
+        (Some(Value::EmString(s)), Some(sub)) => Value::EmBool(s.contains(&format!("{}", sub))),
+        (Some(Value::EmArray(a)), Some(v)) | (Some(Value::Tuple(a)), Some(v)) => {
+            let res = (|| {
+                for e in a.iter() {
+                    if rt.equals(e, v)? {
+                        return Ok(Value::EmBool(true));
+                    }
+                }
+                Ok(Value::EmBool(false))
+            })();
+            raise_err(rt, res)
+        }
+        (Some(Value::Range(start, end, step)), Some(Value::Float(f))) => {
+            let i = (f - start) / step;
+            Value::EmBool(i.fract() == 0.0 && i >= 0.0 && (i as usize) < range_len(*start, *end, *step))
+        }
+        _ => Value::EmBool(false),
+    }
+}
+
+///Registers the builtins that can run other programs. Does nothing on wasm, where there aren't any
+///other programs to run
+pub fn add_exec(hash: &mut HashMap<String, BuiltinFn>) {
+    if cfg!(not(target_arch = "wasm32")) {
+        add(hash, "exec", Signature::new(1, Some(2), &["string", "array"]), em_exec);
+        add(hash, "shell", Signature::new(1, Some(1), &["string"]), em_shell);
+    }
+}
+
+///Removes everything added by `add_exec`
+pub fn remove_exec(hash: &mut HashMap<String, BuiltinFn>) {
+    hash.remove("exec");
+    hash.remove("shell");
+}
+
+fn em_print(rt: &mut Runtime, args: Vec<Value>) -> Value {
+    let text = join_args(rt, &args);
+    let res = rt.print(&text).map(|_| Value::Null);
+    raise_err(rt, res)
+}
+
+fn em_println(rt: &mut Runtime, args: Vec<Value>) -> Value {
+    let text = join_args(rt, &args);
+    let res = rt.print(&format!("{}\n", text)).map(|_| Value::Null);
+    raise_err(rt, res)
+}
+
+fn em_set_print_separator(rt: &mut Runtime, args: Vec<Value>) -> Value {
+    match args.first() {
+        Some(sep) => Value::EmString(std::mem::replace(&mut rt.print_sep, format!("{}", sep)).into()),
+        None => Value::Null,
+    }
+}
+
+fn join_args(rt: &Runtime, args: &[Value]) -> String {
+    args.iter()
+        .map(|a| format!("{}", a))
+        .collect::<Vec<String>>()
+        .join(&rt.print_sep)
+}
+
+fn em_format(rt: &mut Runtime, args: Vec<Value>) -> Value {
+    let res = match args.split_first() {
+        Some((template, rest)) => format_template(&format!("{}", template), rest).map(|s| Value::EmString(s.into())),
+        None => Err("format needs a template".to_owned()),
+    };
+    raise_err(rt, res)
+}
+
+fn em_printf(rt: &mut Runtime, args: Vec<Value>) -> Value {
+    let res = em_format(rt, args);
+    if let Value::EmString(s) = &res {
+        if let Err(e) = rt.print(s) {
+            return raise_err(rt, Err(e));
+        }
+    }
+    res
+}
+
+///Fills each `{}` in the template with the next argument. A placeholder can have a spec like
+///`{:8}`, `{:.2}`, or `{:<8.2}` for width, precision, and alignment, and `{{` or `}}` are literal braces.
+///Errors say which placeholder is wrong, counting from 1, if the template is malformed or there
+///aren't enough arguments
+fn format_template(template: &str, args: &[Value]) -> Result<String, String> {
+    let mut res = String::new();
+    let mut args = args.iter();
+    let mut placeholders = 0;
+    let mut chars = template.chars().enumerate().peekable();
+    while let Some((at, c)) = chars.next() {
+        let next = chars.peek().map(|(_, c)| *c);
+        match c {
+            '{' if next == Some('{') => {
+                chars.next();
+                res.push('{');
+            }
+            '}' if next == Some('}') => {
+                chars.next();
+                res.push('}');
+            }
+            '{' => {
+                placeholders += 1;
+                let mut spec = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '}')) => break,
+                        Some((_, c)) => spec.push(c),
+                        None => return Err(format!("Placeholder {} in the format string is missing its }}", placeholders)),
+                    }
+                }
+                let arg = args
+                    .next()
+                    .ok_or_else(|| format!("Placeholder {} in the format string has no argument to fill it", placeholders))?;
+                let text = format_arg(arg, &spec)
+                    .ok_or_else(|| format!("Placeholder {} in the format string has an invalid spec {{{}}}", placeholders, spec))?;
+                res.push_str(&text);
+            }
+            '}' => return Err(format!("The }} at {} in the format string doesn't close anything, use }}}} for a literal one", at)),
+            _ => res.push(c),
+        }
+    }
+    Ok(res)
+}
+
+fn format_arg(val: &Value, spec: &str) -> Option<String> {
+    let spec = match spec.strip_prefix(':') {
+        Some(s) => s,
+        None if spec.is_empty() => spec,
+        None => return None,
+    };
+    let (align, spec) = match spec.chars().next() {
+        Some(a) if a == '<' || a == '>' || a == '^' => (Some(a), &spec[1..]),
+        _ => (None, spec),
+    };
+    let (width, precision) = match spec.find('.') {
+        Some(i) => (&spec[..i], Some(spec[i + 1..].parse::<usize>().ok()?)),
+        None => (spec, None),
+    };
+    let width = if width.is_empty() { 0 } else { width.parse::<usize>().ok()? };
+
+    let text = match (val, precision) {
+        (Value::Float(f), Some(p)) => format!("{:.*}", p, f),
+        (_, Some(p)) => format!("{:.*}", p, format!("{}", val)),
+        _ => format!("{}", val),
+    };
+    //numbers line up on the right by default, everything else on the left
+    let align = match (align, val) {
+        (Some(a), _) => a,
+        (None, Value::Float(_)) => '>',
+        _ => '<',
+    };
+    Some(match align {
+        '>' => format!("{:>1$}", text, width),
+        '^' => format!("{:^1$}", text, width),
+        _ => format!("{:<1$}", text, width),
+    })
+}
+
+///Lists the names of an object's own properties in the order they were added
+fn em_props(_rt: &mut Runtime, args: Vec<Value>) -> Value {
+    match args.first() {
+        Some(Value::Object(e)) => str_array(e.members.keys().map(String::as_str)),
+        Some(Value::Class(c)) => str_array(c.members.keys().map(String::as_str)),
+        _ => Value::Null,
+    }
+}
+
+///Checks for a property on the object or its class
+fn em_has_prop(_rt: &mut Runtime, args: Vec<Value>) -> Value {
+    match (args.first(), args.get(1)) {
+        (Some(Value::Object(e)), Some(name)) => Value::EmBool(e.get_prop(&format!("{}", name)).is_some()),
+        (Some(Value::Class(c)), Some(name)) => Value::EmBool(c.get_prop(&format!("{}", name)).is_some()),
+        _ => Value::EmBool(false),
+    }
+}
+
+///Objects are passed by value, so this returns a copy of the object without the property.
+///Use `delete obj.prop` to change the object in place
+fn em_remove_prop(_rt: &mut Runtime, args: Vec<Value>) -> Value {
+    match (args.first(), args.get(1)) {
+        (Some(Value::Object(e)), Some(name)) => {
+            let mut res = e.clone();
+            Arc::make_mut(&mut res).remove_prop(&format!("{}", name));
+            Value::Object(res)
+        }
+        _ => Value::Null,
+    }
+}
+
+fn em_type(_rt: &mut Runtime, args: Vec<Value>) -> Value {
+    Value::EmString(type_name(args.first().unwrap_or(&Value::Null)).into())
+}
+
+///Every builtin this runtime has, in alphabetical order. Builtins a sandbox profile left out aren't
+///there, and ones the host added with `register_fn` are
+fn em_builtins(rt: &mut Runtime, _args: Vec<Value>) -> Value {
+    let list = rt
+        .builtin_names()
+        .into_iter()
+        .filter_map(|name| {
+            let sig = rt.functions.get(&name)?.sig;
+            Some(Value::Native(Arc::new(BuiltinInfo { name, sig })))
+        })
+        .collect();
+    Value::EmArray(Arc::new(list))
+}
+
+///The name `type()` gives back for a value
+pub(crate) fn type_name(val: &Value) -> String {
+    match val {
+        Value::Float(_) => "float",
+        Value::EmString(_) | Value::Name(_) => "string",
+        Value::Bytes(_) => "bytes",
+        Value::EmBool(_) => "bool",
+        Value::EmArray(_) => "array",
+        Value::Tuple(_) => "tuple",
+        Value::Range(..) => "range",
+        Value::EmSet(_) => "set",
+        Value::Object(_) => "object",
+        Value::Function(..) => "function",
+        Value::Class(_) => "class",
+        Value::Native(n) => n.type_name(),
+        Value::Null => "null",
+    }
+    .to_owned()
+}
+
+///Checks whether an object was made from the given class, which can be passed as the class
+///itself or just its name
+fn em_is_instance(_rt: &mut Runtime, args: Vec<Value>) -> Value {
+    let name = match args.get(1) {
+        Some(Value::Class(c)) => c.get_prop("~name").map(|n| format!("{}", n)),
+        Some(Value::EmString(s)) => Some(s.to_string()),
+        _ => None,
+    };
+    let mut class = match args.first() {
+        Some(Value::Object(e)) => e.class.clone(),
+        _ => None,
+    };
+    while let (Some(c), Some(n)) = (class, &name) {
+        if c.get_prop("~name").map(|v| format!("{}", v)).as_ref() == Some(n) {
+            return Value::EmBool(true);
+        }
+        class = c.class.clone();
+    }
+    Value::EmBool(false)
+}
+
+///Same as `new cls(args...)` but takes the constructor arguments as an array
+fn em_make(rt: &mut Runtime, args: Vec<Value>) -> Value {
+    let init_args = match args.get(1) {
+        Some(Value::EmArray(a)) => a.iter().cloned().collect(),
+        None => vec![],
+        Some(other) => vec![other.clone()],
+    };
+    match args.first() {
+        Some(Value::Class(c)) => {
+            let res = rt.instantiate(c.clone(), init_args);
+            raise_err(rt, res)
+        }
+        _ => Value::Null,
+    }
+}
+
+///Strings count characters, bytes count bytes, objects count their own properties
+fn em_len(rt: &mut Runtime, args: Vec<Value>) -> Value {
+    match args.first() {
+        Some(Value::EmString(s)) => Value::Float(s.chars().count() as f64),
+        Some(Value::Bytes(b)) => Value::Float(b.len() as f64),
+        Some(Value::EmArray(a)) | Some(Value::Tuple(a)) => Value::Float(a.len() as f64),
+        Some(Value::Object(e)) => Value::Float(e.members.len() as f64),
+        Some(Value::Range(start, end, step)) => Value::Float(range_len(*start, *end, *step) as f64),
+        Some(Value::EmSet(s)) => Value::Float(s.len() as f64),
+        Some(other) => {
+            rt.raise(format!("Can't get the length of {}, only strings, bytes, arrays, tuples, sets, and objects have one", other));
+            Value::Null
+        }
+        None => Value::Null,
+    }
+}
+
+///`range(end)`, `range(start, end)`, or `range(start, end, step)`. Counts up to but not including
+///`end`, so a step that goes away from `end` gives an empty range
+fn em_range(rt: &mut Runtime, args: Vec<Value>) -> Value {
+    let nums: Vec<f64> = args.iter().map(|a| if let Value::Float(f) = a { *f } else { 0.0 }).collect();
+    match nums[..] {
+        [end] => Value::Range(0.0, end, 1.0),
+        [start, end] => Value::Range(start, end, 1.0),
+        [_, _, 0.0] => raise_err(rt, Err("A range's step can't be 0".to_owned())),
+        [start, end, step] => Value::Range(start, end, step),
+        _ => Value::Null,
+    }
+}
+
+fn em_to_array(rt: &mut Runtime, args: Vec<Value>) -> Value {
+    let res = rt.iterate(args.into_iter().next().unwrap_or(Value::Null)).map(|items| Value::EmArray(Arc::new(items)));
+    raise_err(rt, res)
+}
+
+///Runs `adapt` on an iterator over the first argument, with the rest of the arguments
+fn with_iter(rt: &mut Runtime, args: Vec<Value>, adapt: impl FnOnce(&mut Runtime, Iter, Vec<Value>) -> Result<Iter, String>) -> Value {
+    let mut args = args.into_iter();
+    let res = to_iter(rt, args.next().unwrap_or(Value::Null))
+        .and_then(|it| adapt(rt, it, args.collect()))
+        .map(|it| Value::Native(Arc::new(it)));
+    raise_err(rt, res)
+}
+
+///The count `take` and `skip` need, `name` is whichever one is asking
+pub(crate) fn count(name: &str, args: &[Value]) -> Result<usize, String> {
+    match args.first() {
+        Some(Value::Float(n)) if *n >= 0.0 && n.fract() == 0.0 => Ok(*n as usize),
+        other => Err(format!("{} needs a whole number that isn't negative, got {}", name, other.unwrap_or(&Value::Null))),
+    }
+}
+
+fn em_iter(rt: &mut Runtime, args: Vec<Value>) -> Value {
+    with_iter(rt, args, |_, it, _| Ok(it))
+}
+
+fn em_take(rt: &mut Runtime, args: Vec<Value>) -> Value {
+    with_iter(rt, args, |_, it, rest| Ok(it.take(count("take", &rest)?)))
+}
+
+fn em_skip(rt: &mut Runtime, args: Vec<Value>) -> Value {
+    with_iter(rt, args, |_, it, rest| Ok(it.skip(count("skip", &rest)?)))
+}
+
+fn em_zip(rt: &mut Runtime, args: Vec<Value>) -> Value {
+    with_iter(rt, args, |rt, it, rest| Ok(it.zip(to_iter(rt, rest.into_iter().next().unwrap_or(Value::Null))?)))
+}
+
+fn em_enumerate(rt: &mut Runtime, args: Vec<Value>) -> Value {
+    with_iter(rt, args, |_, it, _| Ok(it.enumerate()))
+}
+
+///`on(event, handler)` runs `handler` every time the host emits `event`, see `Runtime::emit`
+fn em_on(rt: &mut Runtime, args: Vec<Value>) -> Value {
+    let mut args = args.into_iter();
+    if let (Some(event), Some(handler)) = (args.next(), args.next()) {
+        rt.on(&format!("{}", event), handler);
+    }
+    Value::Null
+}
+
+///`on_interrupt(f)` calls `f` when Ctrl-C is pressed, then stops the script
+fn em_on_interrupt(rt: &mut Runtime, args: Vec<Value>) -> Value {
+    if let Some(handler) = args.into_iter().next() {
+        rt.on_interrupt(handler);
+    }
+    Value::Null
+}
+
+///`spawn(f, args...)` calls `f` and gives back a task for it. Async functions start a task when
+///they're called anyway, anything else has already finished by the time it comes back
+fn em_spawn(rt: &mut Runtime, args: Vec<Value>) -> Value {
+    let mut args = args.into_iter();
+    let func = args.next().unwrap_or(Value::Null);
+    match rt.call_function(&func, args.collect()) {
+        Ok(val) => rt.as_task(val),
+        Err(e) => {
+            rt.raise(e);
+            Value::Null
+        }
+    }
+}
+
+///`sleep_async(ms)` is a task that finishes after `ms` milliseconds, so `await sleep_async(ms)`
+///lets every other task run in the meantime
+fn em_sleep_async(rt: &mut Runtime, args: Vec<Value>) -> Value {
+    match args.first() {
+        Some(Value::Float(ms)) => {
+            let res = rt.timer(*ms);
+            raise_err(rt, res)
+        }
+        _ => Value::Null,
+    }
+}
+
+///`thread_spawn(f, args...)` calls `f` on its own thread. Everything it gets is copied, so
+///channels are the only way for threads to talk to each other
+fn em_thread_spawn(rt: &mut Runtime, args: Vec<Value>) -> Value {
+    let res = rt.spawn_thread(&args[0], &args[1..]);
+    raise_err(rt, res)
+}
+
+fn em_channel(_rt: &mut Runtime, _args: Vec<Value>) -> Value {
+    Value::Native(Arc::new(Channel::new()))
+}
+
+//the signature already made sure these get a channel, which does the real work in its methods
+fn em_send(rt: &mut Runtime, args: Vec<Value>) -> Value {
+    match &args[0] {
+        Value::Native(ch) => {
+            let res = ch.call_method(rt, "send", args[1..].to_vec());
+            raise_err(rt, res)
+        }
+        _ => Value::Null,
+    }
+}
+
+///`recv(ch)` waits until something is sent on the channel
+fn em_recv(rt: &mut Runtime, args: Vec<Value>) -> Value {
+    match &args[0] {
+        Value::Native(ch) => {
+            let res = ch.call_method(rt, "recv", vec![]);
+            raise_err(rt, res)
+        }
+        _ => Value::Null,
+    }
+}
+
+///Runs a builtin that can fail. An error stops the script the way it would from a function it called,
+///and `assert_throws` sees it like any other
+fn raise_err(rt: &mut Runtime, res: Result<Value, String>) -> Value {
+    res.unwrap_or_else(|e| {
+        rt.raise(e);
+        Value::Null
+    })
+}
+
+fn em_assert(rt: &mut Runtime, args: Vec<Value>) -> Value {
+    if !args.first().is_some_and(Value::is_truthy) {
+        match args.get(1) {
+            Some(msg) => rt.raise(format!("Assertion failed: {}", msg)),
+            None => rt.raise("Assertion failed".to_owned()),
+        }
+    }
     Value::Null
 }
 
-fn em_println(args: Vec<Value>) -> Value {
-    println!("{}", args[0]);
+fn em_assert_eq(rt: &mut Runtime, args: Vec<Value>) -> Value {
+    let (a, b) = match (args.first(), args.get(1)) {
+        (Some(a), Some(b)) => (a, b),
+        _ => {
+            rt.raise("assert_eq needs two values to compare".to_owned());
+            return Value::Null;
+        }
+    };
+    match rt.equals(a, b) {
+        Ok(true) => {}
+        Ok(false) => rt.raise(format!("Assertion failed: {} != {}", a, b)),
+        Err(e) => rt.raise(e),
+    }
     Value::Null
 }
 
-fn em_number(args: Vec<Value>) -> Value {
-    let v = args[0].clone();
-    match v {
-        Value::EmString(s) => {
-            if let Ok(p) = s.parse::<f32>() {
-                Value::Float(p)
-            }else {
-                Value::Null
+///Passes if calling the function with no arguments gives an error
+fn em_assert_throws(rt: &mut Runtime, args: Vec<Value>) -> Value {
+    match args.first() {
+        Some(f @ Value::Function(..)) => {
+            let at = rt.at;
+            match rt.call_function(f, vec![]) {
+                Ok(_) => rt.raise(format!("Assertion failed: expected {} to throw", f)),
+                //exiting isn't something the function threw, it still has to stop the script
+                Err(e) if rt.exit_code().is_some() => rt.raise(e),
+                //the error was caught, so it's back to the statement that caught it
+                Err(_) => rt.at = at,
             }
         }
-        Value::EmBool(b) => Value::Float(b as i32 as f32),
-        Value::Float(_) => v,
-        _ => Value::Null
+        _ => rt.raise("assert_throws expects a function".to_owned()),
+    }
+    Value::Null
+}
+
+///`exit(code)` stops the script, and `gem-bin` exits with `code`, 0 if there isn't one
+fn em_exit(rt: &mut Runtime, args: Vec<Value>) -> Value {
+    let code = match args.first() {
+        Some(Value::Float(f)) if f.fract() == 0.0 && (0.0..=255.0).contains(f) => *f as i32,
+        Some(other) => {
+            rt.raise(format!("Exit codes are whole numbers from 0 to 255, got {}", other));
+            return Value::Null;
+        }
+        None => 0,
+    };
+    let e = rt.exit(code);
+    rt.raise(e);
+    Value::Null
+}
+
+///`set(items)` makes a set out of anything that can be looped over, leaving out repeats. Every item
+///has to be something that can be a key, see `MapKey`
+fn em_set(rt: &mut Runtime, args: Vec<Value>) -> Value {
+    let res = match args.into_iter().next() {
+        Some(items) => to_set(rt, items),
+        None => Ok(Arc::new(OrderedSet::new())),
+    };
+    raise_err(rt, res.map(Value::EmSet))
+}
+
+fn to_set(rt: &mut Runtime, items: Value) -> Result<Arc<OrderedSet>, String> {
+    match items {
+        Value::EmSet(s) => Ok(s),
+        other => Ok(Arc::new(rt.iterate(other)?.iter().map(MapKey::from_value).collect::<Result<_, _>>()?)),
+    }
+}
+
+///`a.union(b)`, `a.intersect(b)`, and `a.difference(b)` all give back a new set. Items keep the
+///order they had in `a`, and anything from `b` comes after. `b` can be anything `set` takes
+fn set_op(rt: &mut Runtime, args: Vec<Value>, op: fn(&OrderedSet, &OrderedSet) -> OrderedSet) -> Value {
+    let mut args = args.into_iter();
+    let res = match (args.next(), args.next()) {
+        (Some(Value::EmSet(a)), Some(b)) => to_set(rt, b).map(|b| Value::EmSet(Arc::new(op(&a, &b)))),
+        _ => Err("Expected a set and something to make a set from".to_owned()),
+    };
+    raise_err(rt, res)
+}
+
+fn em_set_has(rt: &mut Runtime, args: Vec<Value>) -> Value {
+    let res = match (args.first(), args.get(1)) {
+        (Some(Value::EmSet(s)), Some(item)) => MapKey::from_value(item).map(|k| Value::EmBool(s.contains(&k))),
+        _ => Err("has needs a set and an item to look for".to_owned()),
+    };
+    raise_err(rt, res)
+}
+
+///Like `push`, gives back the set with the item in it. As a method it changes the set in the variable
+///it was called on. The set comes back unchanged if the item can't go in one, so it isn't lost
+fn em_set_add(rt: &mut Runtime, args: Vec<Value>) -> Value {
+    let mut args = args.into_iter();
+    match (args.next(), args.next()) {
+        (Some(Value::EmSet(mut s)), Some(item)) => {
+            match MapKey::from_value(&item) {
+                Ok(k) => {
+                    Arc::make_mut(&mut s).insert(k);
+                }
+                Err(e) => rt.raise(e),
+            }
+            Value::EmSet(s)
+        }
+        _ => raise_err(rt, Err("add needs a set and an item to add".to_owned())),
+    }
+}
+
+///Gives back the set without the item, which doesn't have to have been there. Changes the set in
+///the variable when it's called as a method, like `add`
+fn em_set_remove(rt: &mut Runtime, args: Vec<Value>) -> Value {
+    let mut args = args.into_iter();
+    match (args.next(), args.next()) {
+        (Some(Value::EmSet(mut s)), Some(item)) => {
+            match MapKey::from_value(&item) {
+                Ok(k) if s.contains(&k) => {
+                    Arc::make_mut(&mut s).remove(&k);
+                }
+                Ok(_) => {}
+                Err(e) => rt.raise(e),
+            }
+            Value::EmSet(s)
+        }
+        _ => raise_err(rt, Err("remove needs a set and an item to remove".to_owned())),
+    }
+}
+
+///`push(items, item)` gives back a copy of the array with one more item on the end
+fn em_push(rt: &mut Runtime, args: Vec<Value>) -> Value {
+    let mut args = args.into_iter();
+    match (args.next(), args.next()) {
+        (Some(Value::EmArray(mut items)), Some(item)) => {
+            Arc::make_mut(&mut items).push(item);
+            Value::EmArray(items)
+        }
+        (Some(other), _) => raise_err(rt, Err(format!("Can't push onto {}, only arrays", other))),
+        _ => raise_err(rt, Err("push needs an array and an item".to_owned())),
+    }
+}
+
+fn em_sum(rt: &mut Runtime, args: Vec<Value>) -> Value {
+    let res = (|| {
+        let mut total = 0.0;
+        for v in rt.iterate(args.into_iter().next().unwrap_or(Value::Null))? {
+            match v {
+                Value::Float(f) => total += f,
+                other => return Err(format!("Can't add {} to a sum", other)),
+            }
+        }
+        Ok(Value::Float(total))
+    })();
+    raise_err(rt, res)
+}
+
+///Sorting values that can't be compared, like an object and a number, is an error. NaN stays where it is
+fn em_sort(rt: &mut Runtime, args: Vec<Value>) -> Value {
+    let res = rt.iterate(args.into_iter().next().unwrap_or(Value::Null)).and_then(|mut items| {
+        let mut err = None;
+        items.sort_by(|a, b| match compare::order(a, b) {
+            Ok(ord) => ord.unwrap_or(std::cmp::Ordering::Equal),
+            Err(e) => {
+                err.get_or_insert(e);
+                std::cmp::Ordering::Equal
+            }
+        });
+        match err {
+            Some(e) => Err(e),
+            None => Ok(Value::EmArray(Arc::new(items))),
+        }
+    });
+    raise_err(rt, res)
+}
+
+///`map(items, f)` calls `f` on every item and gives back an array of the results
+fn em_map(rt: &mut Runtime, args: Vec<Value>) -> Value {
+    let mut args = args.into_iter();
+    let (items, func) = (args.next().unwrap_or(Value::Null), args.next().unwrap_or(Value::Null));
+    let res = (|| {
+        let mut out = vec![];
+        for v in rt.iterate(items)? {
+            out.push(rt.call_function(&func, vec![v])?);
+        }
+        Ok(Value::EmArray(Arc::new(out)))
+    })();
+    raise_err(rt, res)
+}
+
+///`join(items, sep)` turns every item into a string and puts `sep` between them
+fn em_join(rt: &mut Runtime, args: Vec<Value>) -> Value {
+    let mut args = args.into_iter();
+    let items = args.next().unwrap_or(Value::Null);
+    let sep = args.next().map(|s| format!("{}", s)).unwrap_or_default();
+    let res = rt.iterate(items).map(|items| {
+        Value::EmString(items.iter().map(|v| format!("{}", v)).collect::<Vec<String>>().join(&sep).into())
+    });
+    raise_err(rt, res)
+}
+
+///Gives back a file handle, the file itself isn't touched until one of its methods is called
+fn em_open(_rt: &mut Runtime, args: Vec<Value>) -> Value {
+    match args.first() {
+        Some(Value::EmString(path)) => Value::Native(Arc::new(FileHandle::new(path))),
+        _ => Value::Null,
+    }
+}
+
+fn encoding(arg: Option<&Value>) -> Result<Encoding, String> {
+    match arg {
+        Some(name) => format!("{}", name).parse(),
+        None => Ok(Encoding::Utf8),
+    }
+}
+
+///`bytes(s, encoding)` encodes a string, utf-8 if no encoding is given. Arrays have to be whole
+///numbers from 0 to 255
+fn em_bytes(rt: &mut Runtime, args: Vec<Value>) -> Value {
+    let res = match args.first() {
+        Some(Value::EmString(s)) => encoding(args.get(1)).and_then(|e| e.encode(s)),
+        Some(Value::Bytes(b)) => Ok(b.to_vec()),
+        Some(Value::EmArray(items)) | Some(Value::Tuple(items)) => items
+            .iter()
+            .map(|i| match i {
+                Value::Float(f) if f.fract() == 0.0 && (0.0..=255.0).contains(f) => Ok(*f as u8),
+                other => Err(format!("{} isn't a byte, bytes are whole numbers from 0 to 255", other)),
+            })
+            .collect(),
+        _ => Err("bytes needs a string or an array of numbers".to_owned()),
+    };
+    raise_err(rt, res.map(|b| Value::Bytes(Arc::new(b))))
+}
+
+///`decode(b, encoding)` turns bytes back into a string, utf-8 if no encoding is given
+fn em_decode(rt: &mut Runtime, args: Vec<Value>) -> Value {
+    let res = match args.first() {
+        Some(Value::Bytes(b)) => encoding(args.get(1)).and_then(|e| e.decode(b)),
+        _ => Err("decode needs bytes to turn into a string".to_owned()),
+    };
+    raise_err(rt, res.map(|s| Value::EmString(s.into())))
+}
+
+///What a string or bytes look like as bytes, strings are utf-8
+fn raw(val: Option<&Value>) -> &[u8] {
+    match val {
+        Some(Value::EmString(s)) => s.as_bytes(),
+        Some(Value::Bytes(b)) => b,
+        _ => &[],
+    }
+}
+
+fn text(val: Option<&Value>) -> &str {
+    match val {
+        Some(Value::EmString(s)) => s,
+        _ => "",
+    }
+}
+
+fn em_base64_encode(_rt: &mut Runtime, args: Vec<Value>) -> Value {
+    Value::EmString(bytes::base64_encode(raw(args.first())).into())
+}
+
+///Decoding gives back bytes, call `decode()` on them if they're text
+fn em_base64_decode(rt: &mut Runtime, args: Vec<Value>) -> Value {
+    let res = bytes::base64_decode(text(args.first())).map(|b| Value::Bytes(Arc::new(b)));
+    raise_err(rt, res)
+}
+
+fn em_hex_encode(_rt: &mut Runtime, args: Vec<Value>) -> Value {
+    Value::EmString(bytes::hex_encode(raw(args.first())).into())
+}
+
+fn em_hex_decode(rt: &mut Runtime, args: Vec<Value>) -> Value {
+    let res = bytes::hex_decode(text(args.first())).map(|b| Value::Bytes(Arc::new(b)));
+    raise_err(rt, res)
+}
+
+fn em_url_encode(_rt: &mut Runtime, args: Vec<Value>) -> Value {
+    Value::EmString(bytes::url_encode(raw(args.first())).into())
+}
+
+///Urls are nearly always text, so unlike the others this gives back a string
+fn em_url_decode(rt: &mut Runtime, args: Vec<Value>) -> Value {
+    let res = bytes::url_decode(text(args.first()))
+        .and_then(|b| Encoding::Utf8.decode(&b))
+        .map(|s| Value::EmString(s.into()));
+    raise_err(rt, res)
+}
+
+fn em_utf8_valid(_rt: &mut Runtime, args: Vec<Value>) -> Value {
+    Value::EmBool(std::str::from_utf8(raw(args.first())).is_ok())
+}
+
+///Digests are given back as lowercase hex, since that's how they get compared and sent
+#[cfg(feature = "hashing")]
+fn hex_digest(digest: impl AsRef<[u8]>) -> Value {
+    Value::EmString(bytes::hex_encode(digest.as_ref()).into())
+}
+
+#[cfg(feature = "hashing")]
+fn em_md5(_rt: &mut Runtime, args: Vec<Value>) -> Value {
+    hex_digest(crate::interpreter::hashing::md5(raw(args.first())))
+}
+
+#[cfg(feature = "hashing")]
+fn em_sha1(_rt: &mut Runtime, args: Vec<Value>) -> Value {
+    hex_digest(crate::interpreter::hashing::sha1(raw(args.first())))
+}
+
+#[cfg(feature = "hashing")]
+fn em_sha256(_rt: &mut Runtime, args: Vec<Value>) -> Value {
+    hex_digest(crate::digest::sha256(raw(args.first())))
+}
+
+///`hmac_sha256(key, message)`, the signature most webhooks send
+#[cfg(feature = "hashing")]
+fn em_hmac_sha256(_rt: &mut Runtime, args: Vec<Value>) -> Value {
+    Value::EmString(crate::digest::hmac_sha256(raw(args.first()), &[raw(args.get(1))]).into())
+}
+
+#[cfg(feature = "hashing")]
+fn em_crc32(_rt: &mut Runtime, args: Vec<Value>) -> Value {
+    Value::EmString(format!("{:08x}", crate::interpreter::hashing::crc32(raw(args.first()))).into())
+}
+
+fn em_read_file_bytes(rt: &mut Runtime, args: Vec<Value>) -> Value {
+    let res = match args.first() {
+        Some(Value::EmString(path)) => FileHandle::new(path).read_bytes(rt).map(|b| Value::Bytes(Arc::new(b))),
+        _ => Err("read_file_bytes needs a path".to_owned()),
+    };
+    raise_err(rt, res)
+}
+
+///Replaces whatever was in the file with exactly these bytes
+fn em_write_file_bytes(rt: &mut Runtime, args: Vec<Value>) -> Value {
+    let res = match args.first() {
+        Some(Value::EmString(path)) => FileHandle::new(path).write(rt, args.get(1), false),
+        _ => Err("write_file_bytes needs a path".to_owned()),
+    };
+    raise_err(rt, res)
+}
+
+///Conversions all return null when the value can't be converted
+fn em_number(_rt: &mut Runtime, args: Vec<Value>) -> Value {
+    match args.first() {
+        Some(Value::EmString(s)) => match s.trim().parse::<f64>() {
+            Ok(p) => Value::Float(p),
+            Err(_) => Value::Null,
+        },
+        Some(Value::EmBool(b)) => Value::Float(*b as i32 as f64),
+        Some(Value::Float(f)) => Value::Float(*f),
+        _ => Value::Null,
+    }
+}
+
+///Like `to_number` but drops anything after the decimal point
+fn em_to_int(rt: &mut Runtime, args: Vec<Value>) -> Value {
+    match em_number(rt, args) {
+        Value::Float(f) => Value::Float(f.trunc()),
+        _ => Value::Null,
+    }
+}
+
+fn em_to_string(_rt: &mut Runtime, args: Vec<Value>) -> Value {
+    match args.first() {
+        Some(v) => Value::EmString(format!("{}", v).into()),
+        None => Value::Null,
+    }
+}
+
+fn em_to_bool(_rt: &mut Runtime, args: Vec<Value>) -> Value {
+    Value::EmBool(args.first().is_some_and(|v| v.is_truthy()))
+}
+
+fn em_is_nan(_rt: &mut Runtime, args: Vec<Value>) -> Value {
+    Value::EmBool(matches!(args.first(), Some(Value::Float(f)) if f.is_nan()))
+}
+
+fn em_is_finite(_rt: &mut Runtime, args: Vec<Value>) -> Value {
+    Value::EmBool(matches!(args.first(), Some(Value::Float(f)) if f.is_finite()))
+}
+
+///The number of decimal places for `round_to` and `to_fixed`, which has to be a whole number
+fn places(args: &[Value], min: f64) -> Result<(f64, i32), String> {
+    match (args.first(), args.get(1)) {
+        (Some(Value::Float(x)), Some(Value::Float(p))) if p.fract() == 0.0 && *p >= min && *p <= 100.0 => Ok((*x, *p as i32)),
+        (_, Some(p)) => Err(format!("Expected a whole number of places from {} to 100, found {}", min, p)),
+        _ => Err("Expected a number and how many places to keep".to_owned()),
+    }
+}
+
+///Rounds to a number of decimal places, or to tens, hundreds and so on when it's negative
+fn em_round_to(rt: &mut Runtime, args: Vec<Value>) -> Value {
+    let res = places(&args, -100.0).map(|(x, p)| {
+        Value::Float(if p >= 0 {
+            //formatting rounds the exact value, multiplying by a power of ten first would add error of its own
+            format!("{:.*}", p as usize, x).parse().unwrap_or(x)
+        } else {
+            let scale = 10f64.powi(-p);
+            (x / scale).round() * scale
+        })
+    });
+    raise_err(rt, res)
+}
+
+///Writes a number with exactly that many decimal places, like `to_fixed(3.14159, 2)` giving "3.14"
+fn em_to_fixed(rt: &mut Runtime, args: Vec<Value>) -> Value {
+    let res = places(&args, 0.0).map(|(x, p)| Value::EmString(format!("{:.*}", p as usize, x).into()));
+    raise_err(rt, res)
+}
+
+///Reads as much of a number as it can from the start of the string, so "12.5px" gives 12.5
+fn em_parse_float(_rt: &mut Runtime, args: Vec<Value>) -> Value {
+    let s = match args.first() {
+        Some(Value::EmString(s)) => s.trim_start(),
+        Some(Value::Float(f)) => return Value::Float(*f),
+        _ => return Value::Null,
+    };
+    let mut end = 0;
+    let mut seen_dot = false;
+    for (i, c) in s.char_indices() {
+        match c {
+            '-' | '+' if i == 0 => {}
+            '.' if !seen_dot => seen_dot = true,
+            '0'..='9' => {}
+            _ => break,
+        }
+        end = i + c.len_utf8();
+    }
+    match s[..end].parse::<f64>() {
+        Ok(f) => Value::Float(f),
+        Err(_) => Value::Null,
     }
 }
 
-fn em_readln(args: Vec<Value>) -> Value {
-    let buf = Term::stdout();
-    if args.len() > 0 {
-        buf.write_str(&format!("{}", args[0])).unwrap_or(());
+fn em_readln(rt: &mut Runtime, args: Vec<Value>) -> Value {
+    if let Some(prompt) = args.first() {
+        if let Err(e) = rt.print(&format!("{}", prompt)) {
+            return raise_err(rt, Err(e));
+        }
+    }
+    let stdin = &mut rt.stdin;
+    let input = rt.trace.input("stdin", || match stdin {
+        Some(r) => read_line_from(r),
+        //a terminal gives back an empty line at the end instead of null
+        None if Term::stdout().is_term() => read_line_from(&mut std::io::stdin().lock()).or_else(|| Some(String::new())),
+        None => read_line_from(&mut std::io::stdin().lock()),
+    });
+    //Ctrl-C stops the wait, and the script with it
+    if let Err(e) = rt.check_interrupt() {
+        return raise_err(rt, Err(e));
     }
-    let input = buf.read_line();
     match input {
-        Ok(s) => Value::EmString(s),
-        Err(_) => Value::Null
+        Some(s) => Value::EmString(s.into()),
+        None => Value::Null
+    }
+}
+
+///Reads a line the way `BufRead::read_line` does, except that it gives up if Ctrl-C comes in while
+///it's waiting and the script is catching it
+fn read_line_from(r: &mut dyn BufRead) -> Option<String> {
+    let mut line = vec![];
+    loop {
+        let (used, done) = match r.fill_buf() {
+            Ok([]) => break,
+            Ok(buf) => match buf.iter().position(|b| *b == b'\n') {
+                Some(i) => {
+                    line.extend_from_slice(&buf[..=i]);
+                    (i + 1, true)
+                }
+                None => {
+                    line.extend_from_slice(buf);
+                    (buf.len(), false)
+                }
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted && !interrupt::pending() => continue,
+            Err(_) => return None,
+        };
+        r.consume(used);
+        if done {
+            break;
+        }
+    }
+    match String::from_utf8(line) {
+        Ok(line) if !line.is_empty() => Some(line.trim_end_matches(&['\r', '\n'][..]).to_owned()),
+        _ => None,
     }
 }
 
-fn em_read(args: Vec<Value>) -> Value {
-    let buf = Term::stdout();
-    if args.len() > 0 {
-        buf.write_str(&format!("{}", args[0])).unwrap_or(());
+fn em_read(rt: &mut Runtime, args: Vec<Value>) -> Value {
+    if let Some(prompt) = args.first() {
+        if let Err(e) = rt.print(&format!("{}", prompt)) {
+            return raise_err(rt, Err(e));
+        }
     }
-    let input = buf.read_char();
+    let stdin = &mut rt.stdin;
+    let input = rt.trace.input("stdin", || match stdin {
+        Some(r) => {
+            let mut buf = [0; 1];
+            match r.read(&mut buf) {
+                Ok(1) => Some(String::from(buf[0] as char)),
+                _ => None,
+            }
+        }
+        None => Term::stdout().read_char().ok().map(String::from),
+    });
     match input {
-        Ok(s) => Value::EmString(String::from(s)),
-        Err(_) => Value::Null
+        Some(s) => Value::EmString(s.into()),
+        None => Value::Null
     }
 }
 
-// fn em_readKey(args: Vec<Value>) -> Value {
+fn em_exec(rt: &mut Runtime, args: Vec<Value>) -> Value {
+    let cmd = match args.first() {
+        Some(c) => format!("{}", c),
+        None => return Value::Null,
+    };
+    let cmd_args: Vec<String> = match args.get(1) {
+        Some(Value::EmArray(v)) => v.iter().map(|e| format!("{}", e)).collect(),
+        _ => vec![],
+    };
+    run_command(rt, Command::new(&cmd).args(&cmd_args), &cmd, &format!("exec {} {:?}", cmd, cmd_args))
+}
+
+fn em_shell(rt: &mut Runtime, args: Vec<Value>) -> Value {
+    let cmd = match args.first() {
+        Some(c) => format!("{}", c),
+        None => return Value::Null,
+    };
+    let mut command = if cfg!(windows) {
+        let mut c = Command::new("cmd");
+        c.arg("/C");
+        c
+    } else {
+        let mut c = Command::new("sh");
+        c.arg("-c");
+        c
+    };
+    command.arg(&cmd);
+    run_command(rt, &mut command, &cmd, &format!("shell {:?}", cmd))
+}
+
+///Runs the command and packs its output into an object with stdout, stderr, and status members. Raises
+///an error naming the command if it couldn't be started at all
+fn run_command(rt: &mut Runtime, command: &mut Command, name: &str, action: &str) -> Value {
+    let (stdout, stderr, status) = if rt.side_effect(action) {
+        //the output of a command is an input to the script, so it goes through the trace
+        let mut output = None;
+        let stdout = rt.trace.fallible("stdout", || {
+            let o = command.output().map_err(|e| format!("Couldn't run {}: {}", name, e))?;
+            let stdout = String::from_utf8_lossy(&o.stdout).to_string();
+            output = Some(o);
+            Ok(stdout)
+        });
+        let stdout = match stdout {
+            Ok(s) => s,
+            Err(e) => return raise_err(rt, Err(e)),
+        };
+        let stderr = rt.trace.input("stderr", || {
+            output.as_ref().map(|o| String::from_utf8_lossy(&o.stderr).to_string())
+        });
+        let status = rt.trace.input("status", || {
+            output.as_ref().map(|o| o.status.code().unwrap_or(-1).to_string())
+        });
+        match (stderr, status) {
+            (Some(err), Some(code)) => (stdout, err, code.parse::<i32>().unwrap_or(-1)),
+            _ => return Value::Null,
+        }
+    } else {
+        (String::new(), String::new(), 0)
+    };
+
+    let mut res = EmObject::new(OrderedMap::new());
+    res.set_prop("stdout", Value::EmString(stdout.into()));
+    res.set_prop("stderr", Value::EmString(stderr.into()));
+    res.set_prop("status", Value::Float(status as f64));
+    Value::Object(Arc::new(res))
+}
+
+// fn em_readKey(_rt: &mut Runtime, args: Vec<Value>) -> Value {
 //     let buf = Term::stdout();
 //     if args.len() > 0 {
 //         buf.write_str(&format!("{}", args[0])).unwrap_or(());
@@ -0,0 +1,148 @@
+use std::convert::TryFrom;
+use std::str::FromStr;
+
+///How text is turned into bytes and back by `bytes(s, encoding)` and `decode(b, encoding)`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    ///The default, and what strings are stored as
+    Utf8,
+    ///Every byte is the character with the same number, so any bytes can be decoded
+    Latin1,
+    ///Only the first 128 characters, anything past them is an error either way
+    Ascii,
+}
+
+impl Encoding {
+    pub const NAMES: &'static [&'static str] = &["utf-8", "latin1", "ascii"];
+
+    pub fn encode(self, s: &str) -> Result<Vec<u8>, String> {
+        match self {
+            Encoding::Utf8 => Ok(s.as_bytes().to_vec()),
+            Encoding::Latin1 => s
+                .chars()
+                .map(|c| u8::try_from(c as u32).map_err(|_| format!("{} can't be written as latin1", c)))
+                .collect(),
+            Encoding::Ascii => s
+                .chars()
+                .map(|c| if c.is_ascii() { Ok(c as u8) } else { Err(format!("{} can't be written as ascii", c)) })
+                .collect(),
+        }
+    }
+
+    pub fn decode(self, b: &[u8]) -> Result<String, String> {
+        match self {
+            Encoding::Utf8 => String::from_utf8(b.to_vec()).map_err(|e| format!("The bytes aren't valid utf-8: {}", e)),
+            Encoding::Latin1 => Ok(b.iter().map(|b| *b as char).collect()),
+            Encoding::Ascii => match b.iter().position(|b| !b.is_ascii()) {
+                Some(i) => Err(format!("Byte {} is {}, which isn't ascii", i, b[i])),
+                None => Ok(b.iter().map(|b| *b as char).collect()),
+            },
+        }
+    }
+}
+
+impl FromStr for Encoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Encoding, String> {
+        match s.to_lowercase().as_str() {
+            "utf-8" | "utf8" => Ok(Encoding::Utf8),
+            "latin1" | "latin-1" | "iso-8859-1" => Ok(Encoding::Latin1),
+            "ascii" => Ok(Encoding::Ascii),
+            other => Err(format!("{} isn't an encoding, expected one of {}", other, Encoding::NAMES.join(", "))),
+        }
+    }
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+///Standard base64 with `=` padding
+pub fn base64_encode(b: &[u8]) -> String {
+    let mut out = String::with_capacity(b.len().div_ceil(3) * 4);
+    for chunk in b.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, b)| n | ((*b as u32) << (16 - 8 * i)));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64[((n >> (18 - 6 * i)) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+///Padding can be left off, and whitespace is skipped so wrapped lines decode too
+pub fn base64_decode(s: &str) -> Result<Vec<u8>, String> {
+    let mut out = Vec::with_capacity(s.len() / 4 * 3);
+    let (mut n, mut bits) = (0u32, 0);
+    for (i, c) in s.trim_end_matches(|c: char| c == '=' || c.is_whitespace()).char_indices() {
+        if c.is_whitespace() {
+            continue;
+        }
+        let v = match BASE64.iter().position(|b| *b as char == c) {
+            Some(v) => v as u32,
+            None => return Err(format!("{} at {} isn't base64", c, i)),
+        };
+        n = (n << 6) | v;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((n >> bits) as u8);
+        }
+    }
+    if bits >= 6 {
+        return Err("The base64 ends part way through a byte".to_owned());
+    }
+    Ok(out)
+}
+
+///Lowercase, two digits a byte
+pub fn hex_encode(b: &[u8]) -> String {
+    b.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn hex_decode(s: &str) -> Result<Vec<u8>, String> {
+    if !s.len().is_multiple_of(2) {
+        return Err("Hex needs two digits for every byte".to_owned());
+    }
+    s.as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            let pair = std::str::from_utf8(pair).unwrap_or("");
+            u8::from_str_radix(pair, 16).map_err(|_| format!("{} isn't a hex byte", pair))
+        })
+        .collect()
+}
+
+///Everything but letters, digits and `-_.~` gets written as `%XX`, so the result is safe anywhere in a url
+pub fn url_encode(b: &[u8]) -> String {
+    let mut out = String::new();
+    for b in b {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => out.push(*b as char),
+            _ => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    out
+}
+
+///`+` decodes to a space too, since that's how forms send them
+pub fn url_decode(s: &str) -> Result<Vec<u8>, String> {
+    let mut out = vec![];
+    let mut bytes = s.bytes().enumerate();
+    while let Some((i, b)) = bytes.next() {
+        match b {
+            b'%' => {
+                let hex: Vec<u8> = bytes.by_ref().take(2).map(|(_, b)| b).collect();
+                match std::str::from_utf8(&hex).ok().filter(|h| h.len() == 2).and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                    Some(b) => out.push(b),
+                    None => return Err(format!("The % at {} isn't followed by two hex digits", i)),
+                }
+            }
+            b'+' => out.push(b' '),
+            b => out.push(b),
+        }
+    }
+    Ok(out)
+}
@@ -0,0 +1,88 @@
+use super::builtins::type_name;
+use super::{Runtime, Value};
+use std::cmp::Ordering;
+
+///Works out which of two values comes first. Only numbers, strings, bytes, bools, and arrays or
+///tuples of those have an order, and only with their own type, so anything else is an error. Arrays and tuples
+///go item by item like words in a dictionary. NaN is the one value of the right type that still has no order, which is
+///why an order can come back as `None`
+pub(crate) fn order(a: &Value, b: &Value) -> Result<Option<Ordering>, String> {
+    match (a, b) {
+        (Value::Float(x), Value::Float(y)) => Ok(x.partial_cmp(y)),
+        (Value::EmString(x), Value::EmString(y)) => Ok(Some(x.cmp(y))),
+        (Value::Bytes(x), Value::Bytes(y)) => Ok(Some(x.cmp(y))),
+        (Value::EmBool(x), Value::EmBool(y)) => Ok(Some(x.cmp(y))),
+        (Value::EmArray(x), Value::EmArray(y)) | (Value::Tuple(x), Value::Tuple(y)) => {
+            for (l, r) in x.iter().zip(y.iter()) {
+                match order(l, r)? {
+                    Some(Ordering::Equal) => {}
+                    other => return Ok(other),
+                }
+            }
+            Ok(Some(x.len().cmp(&y.len())))
+        }
+        (l, r) => Err(format!("Can't compare {} and {}", type_name(l), type_name(r))),
+    }
+}
+
+///Does `<`, `<=`, `>`, or `>=`. Anything compared with NaN is false, like floats anywhere else
+pub(crate) fn compare(op: &str, a: &Value, b: &Value) -> Result<bool, String> {
+    let ord = match order(a, b)? {
+        Some(ord) => ord,
+        None => return Ok(false),
+    };
+    match op {
+        "<" => Ok(ord == Ordering::Less),
+        "<=" => Ok(ord != Ordering::Greater),
+        ">" => Ok(ord == Ordering::Greater),
+        ">=" => Ok(ord != Ordering::Less),
+        other => Err(format!("Invalid Operator: {}", other)),
+    }
+}
+
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Value) -> Option<Ordering> {
+        order(self, other).ok().flatten()
+    }
+}
+
+impl Runtime {
+    ///What `==` means in a script. Arrays and tuples are equal if their items are, and objects if they come
+    ///from the same class and have equal members. An object with a `~eq(self, other)` method decides
+    ///for itself instead, whichever side of the `==` it's on
+    pub(crate) fn equals(&mut self, a: &Value, b: &Value) -> Result<bool, String> {
+        for (obj, other) in [(a, b), (b, a)] {
+            if let Value::Object(o) = obj {
+                if let Some(eq @ Value::Function(..)) = self.find_method(o, "~eq") {
+                    return Ok(self.call_function(&eq, vec![obj.clone(), other.clone()])?.is_truthy());
+                }
+            }
+        }
+        match (a, b) {
+            (Value::EmArray(x), Value::EmArray(y)) | (Value::Tuple(x), Value::Tuple(y)) => {
+                if x.len() != y.len() {
+                    return Ok(false);
+                }
+                for (l, r) in x.iter().zip(y.iter()) {
+                    if !self.equals(l, r)? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+            (Value::Object(x), Value::Object(y)) => {
+                if x.class != y.class || x.members.len() != y.members.len() {
+                    return Ok(false);
+                }
+                for (key, l) in x.members.iter() {
+                    match y.members.get(key) {
+                        Some(r) if self.equals(l, r)? => {}
+                        _ => return Ok(false),
+                    }
+                }
+                Ok(true)
+            }
+            _ => Ok(a == b),
+        }
+    }
+}
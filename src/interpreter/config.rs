@@ -0,0 +1,97 @@
+use super::{EmObject, OrderedMap, Runtime, StackFrame, Value};
+use crate::lexer::{self, Expression};
+use crate::parser::{self, ExprNode};
+use std::sync::Arc;
+
+impl Runtime {
+    ///Reads a config file written in EmeraldScript and gives back all of its keys as one object. The
+    ///file is a list of `key = value;` lines, and `a.b = value` puts `b` in a map under `a`. Values
+    ///can use literals, arrays, operators, and keys set earlier in the file, but nothing that could
+    ///loop forever or reach outside the script like calls, loops, or functions
+    pub fn eval_config(&mut self, src: &str) -> Result<Value, String> {
+        let statements = match parser::parse(lexer::run(&format!("{}\n", src)))? {
+            ExprNode::Block(v) => v,
+            other => vec![other],
+        };
+        //keys are variables in here so later values can refer to them
+        let mut frame = StackFrame::new();
+        let mut root = EmObject::new(OrderedMap::new());
+        for s in statements.iter() {
+            let (target, value) = match s {
+                ExprNode::Operation(op, target, value) if **op == Expression::Equal => (target, value),
+                other => {
+                    return Err(format!(
+                        "Config files can only set keys like `name = value;`, found `{}`",
+                        parser::to_source(other)
+                    ))
+                }
+            };
+            let path = key_path(target)?;
+            check_pure(value, &frame)?;
+            let val = self.walk_tree(value, &mut frame)?;
+            insert(&mut root, &path, val)?;
+            frame.set(&path[0], root.get_prop(&path[0]).cloned().unwrap_or(Value::Null));
+        }
+        Ok(Value::Object(Arc::new(root)))
+    }
+}
+
+fn key_path(target: &ExprNode) -> Result<Vec<String>, String> {
+    match target {
+        ExprNode::Name(n) => Ok(vec![n.to_string()]),
+        ExprNode::Operation(op, parent, key) if **op == Expression::Operator('.') => match &**key {
+            ExprNode::Name(k) => {
+                let mut path = key_path(parent)?;
+                path.push(k.to_string());
+                Ok(path)
+            }
+            other => Err(format!("Expected a key name, found `{}`", parser::to_source(other))),
+        },
+        other => Err(format!("Can't set `{}` in a config file", parser::to_source(other))),
+    }
+}
+
+///Makes sure a value only uses things that can't have side effects
+fn check_pure(node: &ExprNode, frame: &StackFrame) -> Result<(), String> {
+    let not_allowed = |what: &str| Err(format!("Config files can't use {}", what));
+    match node {
+        ExprNode::StrLiteral(_) | ExprNode::BytesLiteral(_) | ExprNode::NumLiteral(_) | ExprNode::BoolLiteral(_) | ExprNode::NullLiteral => Ok(()),
+        ExprNode::Name(n) if frame.lookup(n).is_some() => Ok(()),
+        ExprNode::Name(n) => Err(format!("{} has to be set before it's used", n)),
+        ExprNode::Array(items) | ExprNode::Tuple(items) => items.iter().try_for_each(|i| check_pure(i, frame)),
+        ExprNode::Unary(_, a) | ExprNode::Statement(a) => check_pure(a, frame),
+        ExprNode::Index(a, b) => check_pure(a, frame).and_then(|_| check_pure(b, frame)),
+        ExprNode::Operation(op, a, b) => match &**op {
+            Expression::Equal | Expression::CompoundOp(_) => not_allowed("assignments inside of values"),
+            //the right side of a.b is the key, not something to look up
+            Expression::Operator('.') => check_pure(a, frame),
+            _ => check_pure(a, frame).and_then(|_| check_pure(b, frame)),
+        },
+        ExprNode::Call(..) | ExprNode::MethodCall(..) | ExprNode::CallExpr(..) => not_allowed("function calls"),
+        ExprNode::Loop(..) | ExprNode::ForIn(..) | ExprNode::Comprehension(..) => not_allowed("loops"),
+        ExprNode::Func(..) | ExprNode::Class(..) | ExprNode::New(..) => not_allowed("functions or classes"),
+        other => not_allowed(&format!("`{}`", parser::to_source(other))),
+    }
+}
+
+fn insert(obj: &mut EmObject, path: &[String], val: Value) -> Result<(), String> {
+    let (key, rest) = match path.split_first() {
+        Some((key, [])) => {
+            obj.set_prop(key, val);
+            return Ok(());
+        }
+        Some(split) => split,
+        None => return Ok(()),
+    };
+    if obj.get_prop(key).is_none() {
+        obj.set_prop(key, Value::Object(Arc::new(EmObject::new(OrderedMap::new()))));
+    }
+    match obj.members.get_mut(key).map(|v| &mut **v) {
+        Some(Value::Object(child)) => insert(Arc::make_mut(child), rest, val),
+        other => Err(format!(
+            "{} is already set to {}, so it can't have keys of its own",
+            key,
+            other.map_or(Value::Null, |v| v.clone())
+        )),
+    }
+}
@@ -0,0 +1,100 @@
+use super::{Runtime, StackFrame, Value};
+use crate::lexer::Expression;
+use crate::parser::ExprNode;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+//ids are unique across every runtime, so a tree marked once can be run by any of them
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+///Wraps every subtree that always comes out to the same value in `ExprNode::Constant`, so each runtime
+///only works it out once instead of every time it's reached, like an array literal in a loop body.
+///Subtrees that are already marked keep their ids, so marking a tree again doesn't add anything
+pub(crate) fn mark(tree: &ExprNode) -> ExprNode {
+    let mut tree = tree.clone();
+    mark_in(&mut tree);
+    tree
+}
+
+fn mark_in(node: &mut ExprNode) {
+    if is_constant(node) {
+        //numbers and bools are as cheap to make as they are to look up
+        let cheap = matches!(node, ExprNode::NumLiteral(_) | ExprNode::BoolLiteral(_) | ExprNode::NullLiteral);
+        if !cheap && !matches!(node, ExprNode::Constant(..)) {
+            let inner = std::mem::replace(node, ExprNode::EOF);
+            *node = ExprNode::Constant(Box::new(inner), NEXT_ID.fetch_add(1, Ordering::Relaxed));
+        }
+        return;
+    }
+    let all = |nodes: &mut Vec<ExprNode>| nodes.iter_mut().for_each(mark_in);
+    match node {
+        //the left side of an assignment and the name after a dot are looked at as they are, not run
+        ExprNode::Operation(op, a, b) => match &**op {
+            Expression::Equal | Expression::CompoundOp(_) => mark_in(b),
+            Expression::Operator('.') => mark_in(a),
+            _ => {
+                mark_in(a);
+                mark_in(b);
+            }
+        },
+        ExprNode::MethodCall(target, args) => {
+            if let ExprNode::Operation(_, a, _) = &mut **target {
+                mark_in(a);
+            }
+            all(args);
+        }
+        ExprNode::CallExpr(a, args) => {
+            mark_in(a);
+            all(args);
+        }
+        ExprNode::Call(_, args) | ExprNode::New(_, args) | ExprNode::Block(args) | ExprNode::Array(args) | ExprNode::Tuple(args) => all(args),
+        ExprNode::Loop(_, a, b) | ExprNode::ForIn(_, a, b) | ExprNode::Index(a, b) => {
+            mark_in(a);
+            mark_in(b);
+        }
+        ExprNode::ForLoopDec(a, b, c) | ExprNode::IfStatement(a, b, c) | ExprNode::Comprehension(a, _, b, c) => {
+            mark_in(a);
+            mark_in(b);
+            mark_in(c);
+        }
+        ExprNode::Func(_, _, body) | ExprNode::Class(_, body) => mark_in(body),
+        ExprNode::Unary(_, a)
+        | ExprNode::Statement(a)
+        | ExprNode::ReturnVal(a)
+        | ExprNode::ElseStatement(a)
+        | ExprNode::Yield(a)
+        | ExprNode::Await(a)
+        | ExprNode::Generator(a)
+        | ExprNode::Async(a) => mark_in(a),
+        _ => {}
+    }
+}
+
+///Whether a node is made only of literals and operators that can't do anything but give back a value
+fn is_constant(node: &ExprNode) -> bool {
+    match node {
+        ExprNode::StrLiteral(_) | ExprNode::BytesLiteral(_) | ExprNode::NumLiteral(_) | ExprNode::BoolLiteral(_) | ExprNode::NullLiteral => true,
+        ExprNode::Constant(..) => true,
+        ExprNode::Array(items) | ExprNode::Tuple(items) => items.iter().all(is_constant),
+        ExprNode::Unary(_, a) => is_constant(a),
+        ExprNode::Operation(op, a, b) => match &**op {
+            Expression::Operator('.') | Expression::Equal | Expression::CompoundOp(_) | Expression::Lbracket => false,
+            Expression::Operator(_) | Expression::BoolOp(_) | Expression::DotDot => is_constant(a) && is_constant(b),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+impl Runtime {
+    ///Gives back the value of a marked subtree, working it out the first time. Values only get copied
+    ///for real when they're changed, so everything that gets one can change it without affecting the others.
+    ///Errors aren't kept, so a constant that fails fails the same way every time it's reached
+    pub(crate) fn constant(&mut self, node: &ExprNode, id: usize, frame: &mut StackFrame) -> Result<Value, String> {
+        if let Some(val) = self.constants.get(&id) {
+            return Ok(val.clone());
+        }
+        let val = self.walk_tree(node, frame)?;
+        self.constants.insert(id, val.clone());
+        Ok(val)
+    }
+}
@@ -0,0 +1,95 @@
+use super::{call_stack, Runtime, Value};
+use crate::parser::ExprNode;
+use std::cell::RefCell;
+use std::fs;
+
+///What the interpreter could save about itself while a panic unwound through it. Everything is
+///recorded on the way out, so none of this costs anything unless something actually breaks
+#[derive(Default, Debug, Clone)]
+pub struct CrashInfo {
+    ///The innermost node that was being walked
+    pub node: Option<String>,
+    ///A short description of the runtime, like what's on the heap and how deep the calls went
+    pub runtime: Option<String>,
+}
+
+thread_local! {
+    static CRASH: RefCell<CrashInfo> = RefCell::new(CrashInfo::default());
+}
+
+///Lives for as long as `walk_tree` is working on a node. The innermost guard drops first when
+///unwinding, so it's the one that gets to record its node
+pub(crate) struct NodeGuard<'a>(pub &'a ExprNode);
+
+impl Drop for NodeGuard<'_> {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            CRASH.with(|c| {
+                let mut c = c.borrow_mut();
+                if c.node.is_none() {
+                    c.node = Some(format!("{:?}", self.0));
+                }
+            });
+        }
+    }
+}
+
+impl Drop for Runtime {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            let summary = summarize(self);
+            CRASH.with(|c| c.borrow_mut().runtime = Some(summary));
+        }
+        self.release_interrupt();
+    }
+}
+
+fn summarize(rt: &Runtime) -> String {
+    let mut heap: Vec<String> = rt
+        .heap
+        .iter()
+        .map(|(k, v)| match v {
+            Value::Function(_, params, _) => format!("fn {}/{}", k, params.len()),
+            Value::Class(_) => format!("class {}", k),
+            other => format!("{} {}", super::builtins::type_name(other), k),
+        })
+        .collect();
+    heap.sort();
+    format!(
+        "call depth: {} of {}\nstrict: {}\ndry run: {}\nbuiltins: {}\nheap: {}",
+        rt.depth,
+        rt.max_depth,
+        rt.strict,
+        rt.dry_run,
+        rt.functions.len(),
+        heap.join(", ")
+    )
+}
+
+///Gives back everything recorded about the last crash on this thread and forgets it
+pub fn take() -> CrashInfo {
+    CRASH.with(|c| c.replace(CrashInfo::default()))
+}
+
+///Writes a plain text file with the script, what broke, and what the interpreter was doing at the
+///time. It only ever goes to `path`, it's up to the user to attach it to an issue if they want to
+pub fn write_dump(path: &str, source: &str, panic: &str) -> std::io::Result<()> {
+    let info = take();
+    let stack = call_stack();
+    let dump = format!(
+        "EmeraldScript crash dump, gem {}\n\
+        This file was only written to your disk, nothing has been sent anywhere.\n\n\
+        == panic ==\n{}\n\n\
+        == call stack ==\n{}\n\n\
+        == node being run ==\n{}\n\n\
+        == runtime ==\n{}\n\n\
+        == source ==\n{}\n",
+        crate::version(),
+        panic,
+        if stack.is_empty() { "(top level)".to_owned() } else { stack.join(" > ") },
+        info.node.unwrap_or_else(|| "(unknown)".to_owned()),
+        info.runtime.unwrap_or_else(|| "(unknown)".to_owned()),
+        source
+    );
+    fs::write(path, dump)
+}
@@ -0,0 +1,131 @@
+use console::{Key, Term};
+use std::io::{self, BufRead, Write};
+
+///Reads lines with editing, history and tab completion when there's someone typing at a terminal.
+///Piped input is read a line at a time like before, so scripts fed through the REPL still work
+pub(crate) struct LineEditor {
+    term: Term,
+    history: Vec<String>,
+}
+
+///Where the word being completed starts in the line, and what it could be finished with
+pub(crate) type Completions = (usize, Vec<String>);
+
+impl LineEditor {
+    pub(crate) fn new() -> LineEditor {
+        LineEditor {
+            term: Term::stdout(),
+            history: vec![],
+        }
+    }
+
+    ///Gives back None once there's no more input
+    pub(crate) fn read_line(&mut self, prompt: &str, complete: impl Fn(&str) -> Completions) -> io::Result<Option<String>> {
+        if !interactive() {
+            print!("{}", prompt);
+            io::stdout().flush()?;
+            let mut line = String::new();
+            return match io::stdin().lock().read_line(&mut line)? {
+                0 => Ok(None),
+                _ => Ok(Some(line)),
+            };
+        }
+
+        let mut line: Vec<char> = vec![];
+        let mut cursor = 0;
+        //one past the end means the line being typed rather than something from the history
+        let mut recalled = self.history.len();
+        self.redraw(prompt, &line, cursor)?;
+        loop {
+            match self.term.read_key()? {
+                Key::Enter => break,
+                //ctrl-d on an empty line
+                Key::Char('\u{4}') if line.is_empty() => {
+                    self.term.write_line("")?;
+                    return Ok(None);
+                }
+                Key::Char(c) if !c.is_control() => {
+                    line.insert(cursor, c);
+                    cursor += 1;
+                }
+                Key::Backspace if cursor > 0 => {
+                    cursor -= 1;
+                    line.remove(cursor);
+                }
+                Key::Del if cursor < line.len() => {
+                    line.remove(cursor);
+                }
+                Key::ArrowLeft => cursor = cursor.saturating_sub(1),
+                Key::ArrowRight => cursor = (cursor + 1).min(line.len()),
+                Key::Home => cursor = 0,
+                Key::End => cursor = line.len(),
+                Key::ArrowUp if recalled > 0 => {
+                    recalled -= 1;
+                    line = self.history[recalled].chars().collect();
+                    cursor = line.len();
+                }
+                Key::ArrowDown if recalled < self.history.len() => {
+                    recalled += 1;
+                    line = self.history.get(recalled).map(|l| l.chars().collect()).unwrap_or_default();
+                    cursor = line.len();
+                }
+                Key::Tab => {
+                    let before: String = line[..cursor].iter().collect();
+                    let (start, options) = complete(&before);
+                    let typed = before[start..].chars().count();
+                    let common = common_prefix(&options);
+                    if common.chars().count() > typed {
+                        let rest: Vec<char> = common.chars().skip(typed).collect();
+                        let n = rest.len();
+                        line.splice(cursor..cursor, rest);
+                        cursor += n;
+                    } else if options.len() > 1 {
+                        //nothing more to fill in, so show what it could be
+                        self.term.write_line("")?;
+                        self.term.write_line(&options.join("  "))?;
+                    }
+                }
+                _ => {}
+            }
+            self.redraw(prompt, &line, cursor)?;
+        }
+        self.term.write_line("")?;
+        let line: String = line.into_iter().collect();
+        if !line.trim().is_empty() && self.history.last() != Some(&line) {
+            self.history.push(line.clone());
+        }
+        Ok(Some(line))
+    }
+
+    fn redraw(&self, prompt: &str, line: &[char], cursor: usize) -> io::Result<()> {
+        self.term.clear_line()?;
+        self.term.write_str(prompt)?;
+        self.term.write_str(&crate::highlight::terminal::colorize(&line.iter().collect::<String>()))?;
+        if cursor < line.len() {
+            self.term.move_cursor_left(line.len() - cursor)?;
+        }
+        self.term.flush()
+    }
+}
+
+fn common_prefix(options: &[String]) -> String {
+    let first = match options.first() {
+        Some(f) => f,
+        None => return String::new(),
+    };
+    let mut len = first.len();
+    for o in &options[1..] {
+        len = first.chars().zip(o.chars()).take_while(|(a, b)| a == b).map(|(a, _)| a.len_utf8()).sum::<usize>().min(len);
+    }
+    first[..len].to_owned()
+}
+
+#[cfg(unix)]
+fn interactive() -> bool {
+    unsafe { libc::isatty(libc::STDIN_FILENO) == 1 }
+}
+
+#[cfg(not(unix))]
+fn interactive() -> bool {
+    Term::stdout().is_term()
+}
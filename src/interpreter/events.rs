@@ -0,0 +1,118 @@
+use super::{Runtime, Value};
+use std::collections::HashMap;
+
+///Everything `tick` keeps between calls. Hosts that drive scripts every frame (games, simulations)
+///go through this instead of calling functions themselves, so every host drives scripts the same way
+pub(crate) struct Harness {
+    handlers: HashMap<String, Vec<Value>>,
+    state: Value,
+    step: Option<f64>,
+    //time that hasn't added up to a whole fixed step yet
+    carry: f64,
+    tick_fuel: Option<u64>,
+    started: bool,
+}
+
+impl Harness {
+    pub(crate) fn new() -> Harness {
+        Harness {
+            handlers: HashMap::new(),
+            state: Value::Null,
+            step: None,
+            carry: 0.0,
+            tick_fuel: None,
+            started: false,
+        }
+    }
+}
+
+impl Runtime {
+    ///Adds a handler for an event, the same as `on(event, handler)` in a script
+    pub fn on(&mut self, event: &str, handler: Value) {
+        self.harness.handlers.entry(event.to_owned()).or_default().push(handler);
+    }
+
+    ///Runs everything listening for an event. A function named `on_<event>` goes first, then the
+    ///handlers added with `on` in the order they were added. Each one is called with the state and
+    ///then `args`, and whatever it gives back becomes the new state unless that's null. Handlers
+    ///can leave off parameters they don't need
+    pub fn emit(&mut self, event: &str, args: Vec<Value>) -> Result<(), String> {
+        let mut handlers = vec![];
+        if let Some(f @ Value::Function(..)) = self.heap.get(&format!("on_{}", event)).cloned() {
+            handlers.push(f);
+        }
+        handlers.extend(self.harness.handlers.get(event).cloned().unwrap_or_default());
+        for handler in handlers {
+            let mut call_args = vec![self.harness.state.clone()];
+            call_args.extend(args.iter().cloned());
+            if let Value::Function(_, params, _) = &handler {
+                call_args.truncate(params.len());
+            }
+            match self.call_function(&handler, call_args)? {
+                Value::Null => {}
+                state => self.harness.state = state,
+            }
+        }
+        Ok(())
+    }
+
+    ///The state the handlers have built up, starting from null
+    pub fn state(&self) -> &Value {
+        &self.harness.state
+    }
+
+    ///With a step, `tick` runs `update` once for every whole step of time that's passed and carries
+    ///the rest over to the next tick, so the script sees the same steps no matter the frame rate
+    pub fn set_fixed_step(&mut self, step: Option<f64>) {
+        self.harness.step = step;
+        self.harness.carry = 0.0;
+    }
+
+    ///Limits how many nodes each call to `tick` can evaluate, so a script stuck in a loop only costs
+    ///a frame instead of hanging the host
+    pub fn set_tick_fuel(&mut self, fuel: Option<u64>) {
+        self.harness.tick_fuel = fuel;
+    }
+
+    ///Moves the script forward by `dt`, emitting `init` first if this is the first tick. Gives back
+    ///how many times `update` ran
+    pub fn tick(&mut self, dt: f64) -> Result<usize, String> {
+        self.fueled(|rt| {
+            if !rt.harness.started {
+                rt.harness.started = true;
+                rt.emit("init", vec![])?;
+            }
+            match rt.harness.step {
+                Some(step) if step <= 0.0 => Err(format!("The fixed step has to be more than 0, got {}", step)),
+                Some(step) => {
+                    rt.harness.carry += dt;
+                    let mut updates = 0;
+                    while rt.harness.carry >= step {
+                        rt.harness.carry -= step;
+                        rt.emit("update", vec![Value::Float(step)])?;
+                        updates += 1;
+                    }
+                    Ok(updates)
+                }
+                None => rt.emit("update", vec![Value::Float(dt)]).map(|_| 1),
+            }
+        })
+    }
+
+    ///Emits `shutdown` if the script has been started, after which the next tick starts it again
+    pub fn shutdown(&mut self) -> Result<(), String> {
+        if !self.harness.started {
+            return Ok(());
+        }
+        self.harness.started = false;
+        self.fueled(|rt| rt.emit("shutdown", vec![]))
+    }
+
+    fn fueled<T>(&mut self, f: impl FnOnce(&mut Runtime) -> Result<T, String>) -> Result<T, String> {
+        let outer = self.fuel.take();
+        self.fuel = self.harness.tick_fuel;
+        let res = f(self);
+        self.fuel = outer;
+        res
+    }
+}
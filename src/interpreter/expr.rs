@@ -0,0 +1,107 @@
+use super::builtins::BuiltinFn;
+use super::{eval_tree, Builtin, Runtime, Signature, StackFrame, Value};
+use crate::lexer::{self, Expression};
+use crate::parser::{self, ExprNode};
+use std::collections::HashMap;
+
+///The names an expression given to `Runtime::eval_expr` can see. Nothing else is visible to it,
+///not even the builtins, so a host decides exactly what a formula can do
+#[derive(Clone, Default)]
+pub struct Bindings {
+    values: HashMap<String, Value>,
+    functions: HashMap<String, BuiltinFn>,
+}
+
+impl Bindings {
+    pub fn new() -> Bindings {
+        Bindings::default()
+    }
+
+    pub fn set(&mut self, name: &str, val: Value) {
+        self.values.insert(name.to_owned(), val);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.values.get(name)
+    }
+
+    ///Lets expressions call a rust function, the same way `Runtime::register_fn` does for scripts
+    pub fn register_fn(&mut self, name: &str, sig: Signature, func: Builtin) {
+        self.functions.insert(name.to_owned(), BuiltinFn::new(sig, func));
+    }
+}
+
+impl Runtime {
+    ///Runs a whole program with the bindings' values as its globals. Their functions are added to
+    ///the builtins for the run and taken back out afterwards
+    pub(crate) fn eval_bound(&mut self, tree: &ExprNode, bindings: &Bindings) -> Result<Value, String> {
+        let mut frame = StackFrame::new();
+        for (name, val) in bindings.values.iter() {
+            frame.set(name, val.clone());
+        }
+        let replaced: Vec<(String, Option<BuiltinFn>)> = bindings
+            .functions
+            .iter()
+            .map(|(name, f)| (name.clone(), self.functions.insert(name.clone(), f.clone())))
+            .collect();
+        let res = eval_tree(tree, self, &mut frame);
+        for (name, old) in replaced {
+            match old {
+                Some(f) => self.functions.insert(name, f),
+                None => self.functions.remove(&name),
+            };
+        }
+        res
+    }
+
+    ///Evaluates a single expression, like a spreadsheet formula or a rule's condition. Statements and
+    ///assignments are rejected, and names only come from `bindings`
+    pub fn eval_expr(&mut self, src: &str, bindings: &Bindings) -> Result<Value, String> {
+        let node = parser::parse_single(lexer::run(&format!("{}\n", src)))?;
+        check(&node, bindings, &[])?;
+        let mut frame = StackFrame::new();
+        for (name, val) in bindings.values.iter() {
+            frame.set(name, val.clone());
+        }
+        //the script's own functions and the builtins are put aside while it runs
+        let functions = std::mem::replace(&mut self.functions, bindings.functions.clone());
+        let heap = std::mem::take(&mut self.heap);
+        let res = self.walk_tree(&node, &mut frame);
+        self.functions = functions;
+        self.heap = heap;
+        res
+    }
+}
+
+fn check(node: &ExprNode, bindings: &Bindings, locals: &[&str]) -> Result<(), String> {
+    let all = |nodes: &[ExprNode]| nodes.iter().try_for_each(|n| check(n, bindings, locals));
+    match node {
+        ExprNode::StrLiteral(_) | ExprNode::BytesLiteral(_) | ExprNode::NumLiteral(_) | ExprNode::BoolLiteral(_) | ExprNode::NullLiteral => Ok(()),
+        ExprNode::Name(n) if bindings.values.contains_key(n.as_str()) || locals.contains(&n.as_str()) => Ok(()),
+        ExprNode::Name(n) => Err(format!("Unknown name {}", n)),
+        ExprNode::Call(name, args) => match &**name {
+            Expression::Ident(n) if bindings.functions.contains_key(n) => all(args),
+            Expression::Ident(n) => Err(format!("Unknown function {}", n)),
+            other => Err(format!("Can't call {:?}", other)),
+        },
+        ExprNode::Operation(op, a, b) => match &**op {
+            Expression::Equal | Expression::CompoundOp(_) => Err("Expressions can't assign to anything".to_owned()),
+            //the right side of a.b is the property, not a name to look up
+            Expression::Operator('.') => check(a, bindings, locals),
+            _ => check(a, bindings, locals).and_then(|_| check(b, bindings, locals)),
+        },
+        ExprNode::Index(a, b) => check(a, bindings, locals).and_then(|_| check(b, bindings, locals)),
+        ExprNode::Unary(_, a) => check(a, bindings, locals),
+        ExprNode::Array(items) | ExprNode::Tuple(items) => all(items),
+        ExprNode::MethodCall(target, args) | ExprNode::CallExpr(target, args) => {
+            check(target, bindings, locals).and_then(|_| all(args))
+        }
+        ExprNode::Comprehension(item, name, source, condition) => {
+            check(source, bindings, locals)?;
+            let mut inner = locals.to_vec();
+            inner.push(name.as_str());
+            check(item, bindings, &inner).and_then(|_| check(condition, bindings, &inner))
+        }
+        other => Err(format!("`{}` isn't allowed in an expression", parser::to_source(other))),
+    }
+}
@@ -0,0 +1,224 @@
+use super::iterator::{to_iter, Iter};
+use super::{Runtime, StackFrame, Value};
+use crate::lexer::Expression;
+use crate::parser::{contains_await, contains_yield, ExprNode};
+use std::sync::Arc;
+
+///A block or loop a generator is partway through
+enum Cursor {
+    Block(Arc<ExprNode>, usize),
+    While(Arc<ExprNode>, Arc<ExprNode>),
+    For { con: Arc<ExprNode>, inc: Arc<ExprNode>, body: Arc<ExprNode>, started: bool },
+    ForIn(String, Iter, Arc<ExprNode>),
+}
+
+///Where the value an await comes out to should go
+enum Into {
+    Nowhere,
+    Name(String),
+    Return,
+}
+
+enum Flow {
+    Next,
+    Enter(Cursor),
+    Yield(Value),
+    Await(Value, Into),
+    Done(Value),
+}
+
+///Why a generator stopped running
+pub(crate) enum Step {
+    Yield(Value),
+    Await(Value),
+    Done(Value),
+}
+
+///The tree walker can't stop in the middle of a node, so the statements that yield or await are stepped
+///through here one at a time, keeping track of where each enclosing block and loop is up to. Statements
+///that don't do either run normally
+pub(crate) struct Generator {
+    frame: StackFrame,
+    stack: Vec<Cursor>,
+    awaiting: Into,
+    finished: Option<Value>,
+}
+
+///Gives back the iterator a generator function's call turns into. Nothing in the body runs until the
+///first item is asked for
+pub(crate) fn start(body: &ExprNode, frame: StackFrame) -> Iter {
+    let mut gen = Some(Generator::new(body, frame));
+    Iter::new(move |rt| loop {
+        let g = match gen.as_mut() {
+            Some(g) => g,
+            None => return Ok(None),
+        };
+        //something looping over a generator needs the item now, so awaits just wait right here
+        let res = match g.resume(rt) {
+            Ok(Step::Yield(v)) => return Ok(Some(v)),
+            Ok(Step::Await(v)) => rt.wait_for(v).map(|v| {
+                g.give(v);
+                true
+            }),
+            Ok(Step::Done(_)) => Ok(false),
+            Err(e) => Err(e),
+        };
+        //finishing or an error ends it for good
+        match res {
+            Ok(true) => {}
+            Ok(false) => {
+                gen = None;
+                return Ok(None);
+            }
+            Err(e) => {
+                gen = None;
+                return Err(e);
+            }
+        }
+    })
+}
+
+fn statements(node: &ExprNode) -> &[ExprNode] {
+    match node {
+        ExprNode::Block(v) => v,
+        other => std::slice::from_ref(other),
+    }
+}
+
+impl Generator {
+    pub(crate) fn new(body: &ExprNode, frame: StackFrame) -> Generator {
+        Generator {
+            frame,
+            stack: vec![Cursor::Block(Arc::new(body.clone()), 0)],
+            awaiting: Into::Nowhere,
+            finished: None,
+        }
+    }
+
+    ///Hands over what the last await came out to
+    pub(crate) fn give(&mut self, val: Value) {
+        match std::mem::replace(&mut self.awaiting, Into::Nowhere) {
+            Into::Nowhere => {}
+            Into::Name(n) => self.frame.set(&n, val),
+            Into::Return => {
+                self.stack.clear();
+                self.finished = Some(val);
+            }
+        }
+    }
+
+    ///Runs up to the next yield or await, or to the end of the body
+    pub(crate) fn resume(&mut self, rt: &mut Runtime) -> Result<Step, String> {
+        if let Some(val) = self.finished.take() {
+            return Ok(Step::Done(val));
+        }
+        while let Some(top) = self.stack.last_mut() {
+            let enter = match top {
+                Cursor::Block(block, i) => {
+                    if *i >= statements(block).len() {
+                        None
+                    } else {
+                        let (block, at) = (block.clone(), *i);
+                        *i += 1;
+                        match self.run(rt, &statements(&block)[at])? {
+                            Flow::Next => continue,
+                            Flow::Enter(c) => {
+                                self.stack.push(c);
+                                continue;
+                            }
+                            Flow::Yield(v) => return Ok(Step::Yield(v)),
+                            Flow::Await(v, into) => {
+                                self.awaiting = into;
+                                return Ok(Step::Await(v));
+                            }
+                            Flow::Done(v) => {
+                                self.stack.clear();
+                                return Ok(Step::Done(v));
+                            }
+                        }
+                    }
+                }
+                Cursor::While(con, body) => rt.walk_tree(con, &mut self.frame)?.is_truthy().then(|| body.clone()),
+                Cursor::For { con, inc, body, started } => {
+                    if *started {
+                        rt.walk_tree(inc, &mut self.frame)?;
+                    }
+                    *started = true;
+                    rt.walk_tree(con, &mut self.frame)?.is_truthy().then(|| body.clone())
+                }
+                Cursor::ForIn(name, items, body) => match items.next(rt)? {
+                    Some(v) => {
+                        self.frame.set(name, v);
+                        Some(body.clone())
+                    }
+                    None => None,
+                },
+            };
+            match enter {
+                Some(body) => self.stack.push(Cursor::Block(body, 0)),
+                None => {
+                    self.stack.pop();
+                }
+            }
+        }
+        Ok(Step::Done(Value::Null))
+    }
+
+    fn run(&mut self, rt: &mut Runtime, node: &ExprNode) -> Result<Flow, String> {
+        let block = |n: &ExprNode| Flow::Enter(Cursor::Block(Arc::new(n.clone()), 0));
+        match node {
+            ExprNode::ReturnVal(v) => match &**v {
+                ExprNode::Await(v) => self.await_into(rt, v, Into::Return),
+                v => Ok(Flow::Done(rt.walk_tree(v, &mut self.frame)?)),
+            },
+            ExprNode::Await(v) => self.await_into(rt, v, Into::Nowhere),
+            ExprNode::Operation(op, target, value) if **op == Expression::Equal => match (&**target, &**value) {
+                (ExprNode::Name(n), ExprNode::Await(v)) => self.await_into(rt, v, Into::Name(n.to_string())),
+                _ => self.run_plain(rt, node),
+            },
+            n if !contains_yield(n) && !contains_await(n) => self.run_plain(rt, n),
+            ExprNode::Yield(v) => Ok(Flow::Yield(rt.walk_tree(v, &mut self.frame)?)),
+            ExprNode::Statement(n) | ExprNode::ElseStatement(n) => self.run(rt, n),
+            ExprNode::Block(_) => Ok(block(node)),
+            ExprNode::IfStatement(con, body, branch) => {
+                if rt.walk_tree(con, &mut self.frame)?.is_truthy() {
+                    Ok(block(body))
+                } else {
+                    self.run(rt, branch)
+                }
+            }
+            ExprNode::Loop(_, con, body) => match &**con {
+                ExprNode::ForLoopDec(dec, con, inc) => {
+                    rt.walk_tree(dec, &mut self.frame)?;
+                    Ok(Flow::Enter(Cursor::For {
+                        con: Arc::new((**con).clone()),
+                        inc: Arc::new((**inc).clone()),
+                        body: Arc::new((**body).clone()),
+                        started: false,
+                    }))
+                }
+                con => Ok(Flow::Enter(Cursor::While(Arc::new(con.clone()), Arc::new((**body).clone())))),
+            },
+            ExprNode::ForIn(name, source, body) => {
+                let source = rt.walk_tree(source, &mut self.frame)?;
+                let items = to_iter(rt, source)?;
+                Ok(Flow::Enter(Cursor::ForIn(name.to_string(), items, Arc::new((**body).clone()))))
+            }
+            n if contains_await(n) => Err("await can only be a statement of its own, assigned to a name, or returned".to_owned()),
+            _ => Err("yield has to be a statement of its own".to_owned()),
+        }
+    }
+
+    fn await_into(&mut self, rt: &mut Runtime, node: &ExprNode, into: Into) -> Result<Flow, String> {
+        Ok(Flow::Await(rt.walk_tree(node, &mut self.frame)?, into))
+    }
+
+    fn run_plain(&mut self, rt: &mut Runtime, node: &ExprNode) -> Result<Flow, String> {
+        let val = rt.walk_tree(node, &mut self.frame)?;
+        if rt.returning {
+            rt.returning = false;
+            return Ok(Flow::Done(val));
+        }
+        Ok(Flow::Next)
+    }
+}
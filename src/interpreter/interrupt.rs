@@ -0,0 +1,100 @@
+use super::{Runtime, Value};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+//set by the signal handler, which can't do anything more than this safely
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+//what Ctrl-C did before the script started catching it, put back when it stops
+#[cfg(not(target_arch = "wasm32"))]
+static PREVIOUS: std::sync::Mutex<Option<libc::sigaction>> = std::sync::Mutex::new(None);
+
+#[cfg(not(target_arch = "wasm32"))]
+extern "C" fn on_sigint(_: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+///Catches Ctrl-C instead of letting it kill the process, or goes back to what it did before. It's
+///installed without `SA_RESTART`, so a read that's waiting when Ctrl-C comes in gets woken up
+#[cfg(not(target_arch = "wasm32"))]
+fn catch(on: bool) {
+    let mut previous = PREVIOUS.lock().unwrap_or_else(|e| e.into_inner());
+    //only a plain function that stores to an atomic gets installed
+    unsafe {
+        if on {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = on_sigint as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            libc::sigemptyset(&mut action.sa_mask);
+            let mut old: libc::sigaction = std::mem::zeroed();
+            if libc::sigaction(libc::SIGINT, &action, &mut old) == 0 && previous.is_none() {
+                *previous = Some(old);
+            }
+        } else if let Some(old) = previous.take() {
+            libc::sigaction(libc::SIGINT, &old, std::ptr::null_mut());
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn catch(_: bool) {}
+
+///Whether Ctrl-C has been pressed and the script is waiting to handle it. Builtins that block check
+///this when they're woken up, so they can give up and let `check_interrupt` run the handler
+pub(crate) fn pending() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+impl Runtime {
+    ///Calls `handler` the next time the process gets Ctrl-C, then stops the script with exit code 130.
+    ///The handler only runs once, a second Ctrl-C while it's running kills the process like normal.
+    ///Ctrl-C also stops `readln`, `recv`, and `join` from waiting, but programs started with `exec`
+    ///have to stop on their own, which they usually do since they get the Ctrl-C too
+    pub fn on_interrupt(&mut self, handler: Value) {
+        INTERRUPTED.store(false, Ordering::SeqCst);
+        self.interrupt_handler = Some(handler);
+        catch(true);
+    }
+
+    ///Gives back a flag that stops this runtime's script when it's set, like Ctrl-C does, even if it
+    ///never called `on_interrupt`. The script's handler still runs first if it has one. Only this
+    ///runtime and the threads it starts are stopped, not every runtime in the process
+    pub(crate) fn stop_flag(&mut self) -> Arc<AtomicBool> {
+        self.stop.get_or_insert_with(|| Arc::new(AtomicBool::new(false))).clone()
+    }
+
+    ///Like `pending`, but also true when this runtime's stop flag is set
+    pub(crate) fn interrupt_pending(&self) -> bool {
+        pending() || self.stop.as_ref().is_some_and(|s| s.load(Ordering::SeqCst))
+    }
+
+    ///Runs the interrupt handler if Ctrl-C has been pressed or the stop flag set since the last check.
+    ///The error stops the script the same way `exit` does, or is just "Interrupted" if there was no
+    ///handler to run
+    pub(crate) fn check_interrupt(&mut self) -> Result<(), String> {
+        let stopped = self.stop.as_ref().is_some_and(|s| s.swap(false, Ordering::SeqCst));
+        let pressed = self.interrupt_handler.is_some() && INTERRUPTED.swap(false, Ordering::SeqCst);
+        if !stopped && !pressed {
+            return Ok(());
+        }
+        if self.interrupt_handler.is_none() {
+            return Err("Interrupted".to_owned());
+        }
+        catch(false);
+        if let Some(handler) = self.interrupt_handler.take() {
+            if let Err(e) = self.call_function(&handler, vec![]) {
+                if self.exit_code.is_some() {
+                    return Err(e);
+                }
+                self.report(&format!("The interrupt handler crashed because: {}", e));
+            }
+        }
+        Err(self.exit(130))
+    }
+
+    ///Puts Ctrl-C back the way it was if this runtime was catching it, for when it's dropped
+    pub(crate) fn release_interrupt(&mut self) {
+        if self.interrupt_handler.take().is_some() {
+            catch(false);
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use super::builtins::count;
+use super::native::NativeObject;
+use super::{range_values, EmObject, Runtime, Value};
+use std::sync::{Arc, Mutex};
+
+type Step = Box<dyn FnMut(&mut Runtime) -> Result<Option<Value>, String> + Send>;
+
+///A lazy sequence, made by `iter()` and the builtins that wrap one like `take`. Copies share their
+///position, so an iterator that's been looped over once is used up everywhere
+#[derive(Clone)]
+pub struct Iter(Arc<Mutex<Step>>);
+
+impl Iter {
+    pub fn new(step: impl FnMut(&mut Runtime) -> Result<Option<Value>, String> + Send + 'static) -> Iter {
+        Iter(Arc::new(Mutex::new(Box::new(step))))
+    }
+
+    pub fn next(&self, rt: &mut Runtime) -> Result<Option<Value>, String> {
+        let mut step = self
+            .0
+            .try_lock()
+            .map_err(|_| "An iterator can't ask itself for its next item".to_owned())?;
+        step(rt)
+    }
+
+    fn from_items(items: Vec<Value>) -> Iter {
+        let mut items = items.into_iter();
+        Iter::new(move |_| Ok(items.next()))
+    }
+
+    ///Every item left, which never finishes for an iterator that doesn't end
+    pub fn collect(&self, rt: &mut Runtime) -> Result<Vec<Value>, String> {
+        let mut out = vec![];
+        while let Some(v) = self.next(rt)? {
+            out.push(v);
+        }
+        Ok(out)
+    }
+
+    pub fn take(self, n: usize) -> Iter {
+        let mut left = n;
+        Iter::new(move |rt| {
+            if left == 0 {
+                return Ok(None);
+            }
+            left -= 1;
+            self.next(rt)
+        })
+    }
+
+    pub fn skip(self, n: usize) -> Iter {
+        let mut skipping = n;
+        Iter::new(move |rt| {
+            while skipping > 0 {
+                skipping -= 1;
+                if self.next(rt)?.is_none() {
+                    return Ok(None);
+                }
+            }
+            self.next(rt)
+        })
+    }
+
+    ///Pairs up items as two item arrays, stopping at the end of the shorter one
+    pub fn zip(self, other: Iter) -> Iter {
+        Iter::new(move |rt| match self.next(rt)? {
+            Some(a) => Ok(other.next(rt)?.map(|b| Value::EmArray(Arc::new(vec![a, b])))),
+            None => Ok(None),
+        })
+    }
+
+    ///Gives `[index, item]` for each item
+    pub fn enumerate(self) -> Iter {
+        let mut i = 0.0;
+        Iter::new(move |rt| {
+            let item = self.next(rt)?.map(|v| Value::EmArray(Arc::new(vec![Value::Float(i), v])));
+            i += 1.0;
+            Ok(item)
+        })
+    }
+}
+
+impl NativeObject for Iter {
+    fn type_name(&self) -> &str {
+        "iterator"
+    }
+
+    fn call_method(&self, rt: &mut Runtime, name: &str, args: Vec<Value>) -> Result<Value, String> {
+        let wrap = |it: Iter| Value::Native(Arc::new(it));
+        match name {
+            //iterators follow the same protocol as objects, so they can go anywhere an object could
+            "~next" => Ok(self.next(rt)?.unwrap_or(Value::Null)),
+            "take" => Ok(wrap(self.clone().take(count(name, &args)?))),
+            "skip" => Ok(wrap(self.clone().skip(count(name, &args)?))),
+            "zip" => Ok(wrap(self.clone().zip(to_iter(rt, args.into_iter().next().unwrap_or(Value::Null))?))),
+            "enumerate" => Ok(wrap(self.clone().enumerate())),
+            "to_array" => Ok(Value::EmArray(Arc::new(self.collect(rt)?))),
+            _ => Err(format!("iterator has no method {}", name)),
+        }
+    }
+}
+
+///Turns anything that can be looped over into an iterator. Ranges and objects with a `~next`
+///method are stepped through lazily, everything else is the items `Runtime::iterate` gives
+pub(crate) fn to_iter(rt: &mut Runtime, val: Value) -> Result<Iter, String> {
+    match val {
+        Value::Range(start, end, step) => {
+            let mut values = range_values(start, end, step);
+            Ok(Iter::new(move |_| Ok(values.next().map(Value::Float))))
+        }
+        //builtin iterators go through `~next` too, which also lets embedders write their own
+        Value::Native(n) => Ok(Iter::new(move |rt| {
+            let res = n.call_method(rt, "~next", vec![]);
+            if let Some(e) = rt.raised.take() {
+                return Err(e);
+            }
+            match res? {
+                Value::Null => Ok(None),
+                v => Ok(Some(v)),
+            }
+        })),
+        Value::Object(obj) if rt.find_method(&obj, "~next").is_some() => {
+            let mut state: Arc<EmObject> = obj;
+            Ok(Iter::new(move |rt| rt.next_of(&mut state)))
+        }
+        other => Ok(Iter::from_items(rt.iterate(other)?)),
+    }
+}
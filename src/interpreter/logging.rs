@@ -0,0 +1,100 @@
+use super::native::NativeObject;
+use super::{Runtime, Value};
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+
+///How important a log message is. Messages below the runtime's level are dropped
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl LogLevel {
+    pub const NAMES: &'static [&'static str] = &["debug", "info", "warn", "error"];
+}
+
+impl FromStr for LogLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<LogLevel, String> {
+        match s {
+            "debug" => Ok(LogLevel::Debug),
+            "info" => Ok(LogLevel::Info),
+            "warn" => Ok(LogLevel::Warn),
+            "error" => Ok(LogLevel::Error),
+            other => Err(format!("{} isn't a log level, expected one of {}", other, LogLevel::NAMES.join(", "))),
+        }
+    }
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", LogLevel::NAMES[*self as usize])
+    }
+}
+
+///What `log` is in a script, unless something else has that name. `log.info(msg, fields)` and
+///the others write to the runtime's log sink
+pub(crate) struct Log;
+
+impl NativeObject for Log {
+    fn type_name(&self) -> &str {
+        "log"
+    }
+
+    fn call_method(&self, rt: &mut Runtime, name: &str, args: Vec<Value>) -> Result<Value, String> {
+        let level = name.parse::<LogLevel>().map_err(|_| format!("log has no method {}", name))?;
+        let msg = args.first().map_or_else(String::new, |m| m.to_string());
+        rt.log(level, &msg, args.get(1).unwrap_or(&Value::Null))?;
+        Ok(Value::Null)
+    }
+}
+
+///Strings with spaces in them get quotes so each field still reads as one `key=value`
+fn field(val: &Value) -> String {
+    match val {
+        Value::EmString(s) if s.is_empty() || s.contains(char::is_whitespace) || s.contains('=') => format!("{:?}", s),
+        other => other.to_string(),
+    }
+}
+
+impl Runtime {
+    ///Only messages at least this important get written, defaults to `LogLevel::Debug`
+    pub fn set_log_level(&mut self, level: LogLevel) {
+        self.log_level = level;
+    }
+
+    ///Sets where log messages get written, defaults to the runtime's error stream
+    pub fn set_log_sink(&mut self, sink: Box<dyn Write + Send + Sync>) {
+        self.log_sink = Some(sink);
+        self.shared_log = None;
+    }
+
+    ///Writes one line like `[warn] disk almost full used=93 mount=/`. `fields` has to be an object
+    ///or null, its properties go after the message in order
+    pub fn log(&mut self, level: LogLevel, msg: &str, fields: &Value) -> Result<(), String> {
+        let mut line = format!("[{}] {}", level, msg);
+        match fields {
+            Value::Null => {}
+            Value::Object(obj) => {
+                for (k, v) in obj.members.iter().filter(|(k, _)| !k.starts_with('~')) {
+                    line.push_str(&format!(" {}={}", k, field(v)));
+                }
+            }
+            other => return Err(format!("Log fields have to be an object, found {}", other)),
+        }
+        if level < self.log_level {
+            return Ok(());
+        }
+        let sink = match &mut self.log_sink {
+            Some(sink) => sink,
+            None => &mut self.stderr,
+        };
+        writeln!(sink, "{}", line).and_then(|_| sink.flush()).unwrap_or(());
+        Ok(())
+    }
+}
@@ -2,28 +2,91 @@
 mod tests;
 mod types;
 mod builtins;
+pub(crate) mod bytes;
+mod compare;
+mod config;
+mod constants;
+mod editor;
+mod events;
+mod expr;
+mod generator;
+pub(crate) mod interrupt;
+#[cfg(feature = "hashing")]
+mod hashing;
+mod iterator;
+mod logging;
+mod numeric;
+mod output;
+mod profile;
+mod tasks;
+mod threads;
+mod trace;
+mod watch;
+pub mod crash;
+pub mod native;
+pub mod repl;
 
-use crate::interpreter::types::EmObject;
-use crate::interpreter::types::Indexable;
+pub use crate::interpreter::builtins::{Builtin, Signature};
+pub use crate::interpreter::bytes::Encoding;
+pub use crate::interpreter::expr::Bindings;
+pub use crate::interpreter::logging::LogLevel;
+pub use crate::interpreter::numeric::NumericPolicy;
+pub use crate::interpreter::output::OutputLimit;
+pub use crate::interpreter::profile::Profile;
+pub(crate) use crate::interpreter::constants::mark as mark_constants;
+pub use crate::interpreter::types::{EmObject, OrderedMap, OrderedSet};
+use crate::interpreter::types::{Indexable, MapKey};
+use crate::interpreter::native::NativeObject;
 
-use super::lexer::Expression;
+use super::lexer::{Expression, Span};
 use super::parser::ExprNode;
+use super::diagnostics::{self, Diagnostic};
 
 use std::fmt;
-use std::{cell::RefCell, collections::HashMap};
+use std::io::{BufRead, Write};
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
+use std::cell::RefCell;
+use std::collections::HashMap;
 
-///Represents everything that exists in the language currently
-#[derive(Debug, Clone, PartialEq, PartialOrd)]
+///Represents everything that exists in the language currently. Strings, arrays, and objects are
+///shared when copied and only get cloned for real when one of the copies is changed
+#[derive(Debug, PartialEq)]
+#[cfg_attr(not(feature = "debug-alloc"), derive(Clone))]
 pub enum Value {
     Null,
-    Float(f32),
-    EmString(String),
+    Float(f64),
+    EmString(Arc<str>),
+    Bytes(Arc<Vec<u8>>),
     EmBool(bool),
-    EmArray(Vec<Box<Value>>),
+    EmArray(Arc<Vec<Value>>),
+    Tuple(Arc<Vec<Value>>),
+    Range(f64, f64, f64), //start, end (not included), step
+    EmSet(Arc<OrderedSet>),
     //Char(u8),
     Name(String),
     Function(Expression, Vec<Value>, ExprNode),
-    Object(EmObject),
+    Object(Arc<EmObject>),
+    Class(Arc<EmObject>),
+    Native(Arc<dyn NativeObject>),
+}
+
+impl Value {
+    ///Conditions use this instead of needing an actual bool. Zero, empty strings, bytes, arrays,
+    ///tuples and sets, and null are false, everything else is true
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Value::Null => false,
+            Value::EmBool(b) => *b,
+            Value::Float(f) => *f != 0.0,
+            Value::EmString(s) => !s.is_empty(),
+            Value::Bytes(b) => !b.is_empty(),
+            Value::EmArray(a) | Value::Tuple(a) => !a.is_empty(),
+            Value::EmSet(s) => !s.is_empty(),
+            Value::Range(start, end, step) => range_len(*start, *end, *step) > 0,
+            _ => true,
+        }
+    }
 }
 
 impl std::fmt::Display for Value {
@@ -31,28 +94,34 @@ impl std::fmt::Display for Value {
         match self {
             Value::Float(s) => write!(f, "{}", s),
             Value::EmString(s) => write!(f, "{}", s),
+            Value::Bytes(b) => write!(f, "b\"{}\"", crate::lexer::escape_bytes(b)),
             // Value::Char(c) => write!(f, "{}", c),
             Value::Name(n) => write!(f, "{}", n),
             Value::Null => write!(f, "null"),
-            Value::Function(n, p, _) => write!(f, "{:?}({:?})", n, p),
+            Value::Function(n, p, _) => {
+                let params: Vec<String> = p.iter().map(Value::to_string).collect();
+                write!(f, "fn {}({}) {{ ... }}", ident_name(n), params.join(", "))
+            }
             Value::EmBool(b) => write!(f, "{}", b),
             Value::EmArray(v) => {
-                let mut tmp = String::new();
-                for val in v.iter() {
-                    if let Value::EmString(_) = **val{
-                        tmp = format!("{}\"{}\", ", tmp, val);
-                    }else {
-                        tmp = format!("{}{}, ", tmp, val);
-
-                    }
-                }
-                tmp.pop();
-                tmp.pop();
-                write!(f, "[{}]", tmp)
+                write!(f, "[")?;
+                write_items(f, v)?;
+                write!(f, "]")
+            }
+            //the comma is what makes one item a tuple, same as writing one
+            Value::Tuple(v) if v.len() == 1 => {
+                write!(f, "(")?;
+                write_items(f, v)?;
+                write!(f, ",)")
+            }
+            Value::Tuple(v) => {
+                write!(f, "(")?;
+                write_items(f, v)?;
+                write!(f, ")")
             }
             Value::Object(e) => {
                 if let Some(Value::Function(_, _, t)) = e.get_prop("~display") {
-                    let mut rt = Runtime::new();
+                    let mut rt = Runtime::confined();
                     let mut gf = StackFrame::new();
                     gf.set_var(String::from("self"), self.clone());
                     let res = repl_run(t.clone(), &mut rt, &mut gf).unwrap_or_default();
@@ -61,42 +130,70 @@ impl std::fmt::Display for Value {
                     write!(f, "{:?}", e.members)
                 }
             }
+            Value::Class(c) => write!(f, "<class {}>", c),
+            Value::Native(n) => write!(f, "{}", n.display()),
+            Value::Range(start, end, step) if *step == 1.0 => write!(f, "{}..{}", start, end),
+            Value::Range(start, end, step) => write!(f, "range({}, {}, {})", start, end, step),
+            Value::EmSet(s) => write!(f, "set({})", Value::EmArray(Arc::new(s.iter().map(MapKey::to_value).collect()))),
         }
     }
 }
 
+///Items separated by commas, with strings in quotes so `["1"]` and `[1]` can be told apart
+fn write_items(f: &mut fmt::Formatter<'_>, items: &[Value]) -> fmt::Result {
+    for (i, val) in items.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        if let Value::EmString(s) = val {
+            write!(f, "\"{}\"", s)?;
+        } else {
+            write!(f, "{}", val)?;
+        }
+    }
+    Ok(())
+}
+
 impl types::Indexable<Value> for Value {
-    fn index<'a>(&'a self, index: usize) -> Result<&'a Value, String> {
+    fn index(&self, index: f64) -> Result<Value, String> {
         match self {
-            Value::EmArray(v) => {
-                if let Some(val) = v.get(index) {
-                    Ok(val)
-                } else {
-                    Err(format!(
-                        "Index {} out of bounds (I hope I can include line numbers some day)",
-                        index
-                    ))
-                }
+            Value::EmArray(v) | Value::Tuple(v) => Ok(v[array_index(index, v.len())?].clone()),
+            //strings are indexed by character, the same way len counts them
+            Value::EmString(s) => {
+                let i = array_index(index, s.chars().count())?;
+                Ok(Value::EmString(s.chars().nth(i).unwrap_or_default().to_string().into()))
             }
+            Value::Range(start, end, step) => {
+                let i = array_index(index, range_len(*start, *end, *step))?;
+                Ok(Value::Float(start + i as f64 * step))
+            }
+            Value::Bytes(b) => Ok(Value::Float(b[array_index(index, b.len())?] as f64)),
             _ => Err(format!("Type {} isn't indexable", self)),
         }
     }
 
-    fn index_mut<'a>(&'a mut self, index: usize) -> Result<&'a mut Value, String> {
+    fn index_mut(&mut self, index: f64) -> Result<&mut Value, String> {
         match self {
             Value::EmArray(v) => {
-                if let Some(val) = v.get_mut(index) {
-                    Ok(val)
-                } else {
-                    Err(format!(
-                        "Index {} out of bounds (I hope I can include line numbers some day)",
-                        index
-                    ))
-                }
+                let i = array_index(index, v.len())?;
+                Ok(&mut Arc::make_mut(v)[i])
             }
+            Value::EmString(_) => Err("Strings can't be changed by index, build a new one instead".to_owned()),
+            Value::Range(..) => Err("Ranges can't be changed by index, use to_array() to get an array".to_owned()),
+            Value::Tuple(_) => Err("Tuples can't be changed, make a new one instead".to_owned()),
+            Value::Bytes(_) => Err("Bytes can't be changed by index, build new ones with + or bytes()".to_owned()),
             _ => Err(format!("Type {} isn't indexable", self)),
         }
     }
+
+    fn index_key<'a>(&'a self, key: &str) -> Result<&'a Value, String> {
+        match self {
+            Value::Object(e) => e
+                .get_prop(key)
+                .ok_or_else(|| format!("{} has no property {}", e, key)),
+            _ => Err(format!("Type {} can't be indexed by name", self)),
+        }
+    }
 }
 
 ///Stores variables in a hashmap for a given function block. Only created on function call, with the exception of the global frame
@@ -108,9 +205,57 @@ pub struct StackFrame {
 pub struct Runtime {
     // tree: ExprNode,
     // stack: Vec<StackFrame>,
-    heap: HashMap<String, RefCell<Value>>,
-    functions: HashMap<String, Box<dyn Fn(Vec<Value>) -> Value>>,
+    heap: HashMap<String, Value>,
+    functions: HashMap<String, builtins::BuiltinFn>,
     returning: bool,
+    dry_run: bool,
+    audit: Box<dyn Write + Send + Sync>,
+    trace: trace::Trace,
+    strict: bool,
+    constructing: usize,
+    depth: usize,
+    max_depth: usize,
+    warn_redefinitions: bool,
+    stdout: Box<dyn Write + Send + Sync>,
+    //shared with any threads the script starts, so they all count against the same limit
+    output_budget: Option<Arc<Mutex<output::Budget>>>,
+    truncate_output: bool,
+    stderr: Box<dyn Write + Send + Sync>,
+    //the sinks once they've been handed to a thread, see `output::Shared`
+    shared_stdout: Option<output::Shared>,
+    shared_stderr: Option<output::Shared>,
+    shared_audit: Option<output::Shared>,
+    shared_log: Option<output::Shared>,
+    //log messages go to stderr unless this is set
+    log_sink: Option<Box<dyn Write + Send + Sync>>,
+    log_level: LogLevel,
+    profile: Profile,
+    //whether `exec` and `shell` are registered, which the profile might not allow
+    allow_exec: bool,
+    stdin: Option<Box<dyn BufRead + Send + Sync>>,
+    print_sep: String,
+    raised: Option<String>,
+    //set by `exit`, the error it raises unwinds everything until the script stops
+    exit_code: Option<i32>,
+    //what `on_interrupt` was given, if the script is catching Ctrl-C
+    interrupt_handler: Option<Value>,
+    //set from outside to stop this runtime's script even when it isn't catching Ctrl-C, for the kernel
+    stop: Option<Arc<AtomicBool>>,
+    //the file name and text of the script, for pointing at the line an error is about
+    source: Option<(String, String)>,
+    //where the statement being run is in the source, when the parser marked where statements are
+    at: Option<Span>,
+    nodes: u64,
+    //how many more nodes can be evaluated before stopping, if there's a limit
+    fuel: Option<u64>,
+    //how many times each function was called, only kept once counting is turned on
+    calls: Option<HashMap<String, u64>>,
+    harness: events::Harness,
+    tasks: tasks::Scheduler,
+    //values of the constant parts of the tree, by the id they were marked with
+    constants: HashMap<usize, Value>,
+    watches: Vec<watch::Watch>,
+    numeric: NumericPolicy,
 }
 
 ///A run function that accepts a runtime and global frame, mostly for use with the REPL
@@ -119,27 +264,125 @@ pub fn repl_run(
     runtime: &mut Runtime,
     glob_frame: &mut StackFrame,
 ) -> Result<String, String> {
-    match runtime.walk_tree(&tree, glob_frame) {
-        Ok(val) => Ok(format!("{}", val)),
-        Err(e) => Err(e),
+    eval_tree(&tree, runtime, glob_frame).map(|val| format!("{}", val))
+}
+
+///Same as `repl_run` but gives back the value itself, which is the value of the last statement
+///so typing an expression into a REPL shows what it came out to
+pub(crate) fn eval_tree(tree: &ExprNode, runtime: &mut Runtime, glob_frame: &mut StackFrame) -> Result<Value, String> {
+    runtime.at = None;
+    let statements = match runtime.hoist(tree, glob_frame)? {
+        ExprNode::Block(v) => v,
+        other => vec![other],
+    };
+    let mut res = Ok(Value::Null);
+    for statement in statements.iter() {
+        if let ExprNode::At(span) = statement {
+            runtime.at = Some(*span);
+            continue;
+        }
+        if let ExprNode::ReturnVal(v) = statement {
+            res = runtime.walk_tree(v, glob_frame);
+            break;
+        }
+        res = runtime.walk_tree(statement, glob_frame);
+        if !runtime.watches.is_empty() {
+            runtime.check_watches(glob_frame);
+        }
+        //a return at the top level only stops this run
+        if res.is_err() || runtime.returning {
+            break;
+        }
     }
+    runtime.returning = false;
+    res
+}
+
+///Walks through the provided tree and executes all the nodes, giving back the code the process
+///should exit with
+pub fn run(tree: ExprNode, args: Vec<Value>) -> i32 {
+    run_with(Runtime::new(), tree, args)
+}
+
+///Same as `run`, but uses an already configured runtime
+pub fn run_with(runtime: Runtime, tree: ExprNode, args: Vec<Value>) -> i32 {
+    let mut r = runtime;
+    run_on(&mut r, tree, args)
 }
 
-///Walks through the provided tree and executes all the nodes
-pub fn run(tree: ExprNode, args: ExprNode) {
-    let mut r = Runtime::new();
+///Same as `run_with`, but the runtime is only borrowed so it can be looked at afterwards. The exit
+///code is whatever the script gave `exit`, or 1 if it crashed and 0 if it didn't. `main` gets `args`
+///as an array, they're values already so nothing in them is ever run as code
+pub fn run_on(r: &mut Runtime, tree: ExprNode, args: Vec<Value>) -> i32 {
     // r.find_global_vars();
     let mut glob_frame = StackFrame::new();
+    //redefining things is normal in the REPL, but in a file it's probably a mistake
+    r.warn_redefinitions = true;
+    r.at = None;
+
+    let mut crashed = false;
+    let mut check = |r: &mut Runtime, frame: &StackFrame, res: Result<(), String>| match res {
+        Err(e) if r.exit_code.is_none() => {
+            r.report_crash(&e, frame);
+            crashed = true;
+        }
+        _ => {}
+    };
 
     //define all functions and any global variables
-    if let Err(e) = r.walk_tree(&tree, &mut glob_frame) {
-        println!("Interpreter crashed because: {}", e);
-    }
+    let res = r.hoist(&tree, &mut glob_frame).and_then(|t| r.walk_tree(&t, &mut glob_frame)).map(|_| ());
+    check(r, &glob_frame, res);
+    r.returning = false;
 
-    if let Err(e) = r.do_call(&Expression::Ident("main".to_owned()), &[args], &mut glob_frame) {
-        println!("Interpreter crashed because: {}", e);
+    if r.exit_code.is_none() {
+        let res = r.call("main", vec![Value::EmArray(Arc::new(args))], &glob_frame).and_then(|_| r.run_tasks());
+        check(r, &glob_frame, res);
     }
     // println!("{:?}", glob_frame.stack);
+    r.flush();
+    r.exit_code.unwrap_or(if crashed { 1 } else { 0 })
+}
+
+///Runs the top level of the tree, then calls every function whose name starts with `test_` in
+///alphabetical order. Each one gets a fresh frame, so they can't see each other's variables. Results go
+///to the runtime's output and the number of failed tests is given back
+pub fn run_tests(runtime: Runtime, tree: ExprNode) -> usize {
+    let mut r = runtime;
+    let mut glob_frame = StackFrame::new();
+    r.warn_redefinitions = true;
+    if let Err(e) = r.hoist(&tree, &mut glob_frame).and_then(|t| r.walk_tree(&t, &mut glob_frame)) {
+        r.report_crash(&e, &glob_frame);
+        return 1;
+    }
+    r.returning = false;
+
+    let mut tests: Vec<(String, Value)> = r
+        .heap
+        .iter()
+        .filter(|(k, v)| k.starts_with("test_") && matches!(v, Value::Function(..)))
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect();
+    tests.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut failed = 0;
+    for (name, func) in tests.iter() {
+        r.at = None;
+        match r.call_function(func, vec![]) {
+            Ok(_) => r.print(&format!("test {} ... ok\n", name)).unwrap_or(()),
+            Err(e) => {
+                //a test that exits fails, but the rest still get to run
+                r.exit_code = None;
+                failed += 1;
+                r.print(&format!("test {} ... FAILED: {}\n", name, e)).unwrap_or(());
+                let snippet = r.diagnose(&e, None).suggest(glob_frame.stack.keys().map(String::as_str)).snippet();
+                if !snippet.is_empty() {
+                    r.print(&format!("{}\n", snippet)).unwrap_or(());
+                }
+            }
+        }
+    }
+    r.print(&format!("{} passed, {} failed\n", tests.len() - failed, failed)).unwrap_or(());
+    failed
 }
 
 // Basically *is* the interpreter, walks through the AST and executes the nodes as needed
@@ -152,11 +395,282 @@ impl Runtime {
             heap: HashMap::new(),
             returning: false,
             functions: builtins::get_functions(),
+            dry_run: false,
+            audit: Box::new(std::io::stderr()),
+            trace: trace::Trace::Off,
+            strict: false,
+            constructing: 0,
+            depth: 0,
+            max_depth: 1000,
+            warn_redefinitions: false,
+            stdout: Box::new(std::io::stdout()),
+            output_budget: None,
+            truncate_output: false,
+            stderr: Box::new(std::io::stderr()),
+            shared_stdout: None,
+            shared_stderr: None,
+            shared_audit: None,
+            shared_log: None,
+            log_sink: None,
+            log_level: LogLevel::Debug,
+            profile: Profile::Full,
+            allow_exec: cfg!(not(target_arch = "wasm32")),
+            stdin: None,
+            print_sep: String::from(" "),
+            raised: None,
+            exit_code: None,
+            interrupt_handler: None,
+            stop: None,
+            source: None,
+            at: None,
+            nodes: 0,
+            fuel: None,
+            calls: None,
+            harness: events::Harness::new(),
+            tasks: tasks::Scheduler::default(),
+            constants: HashMap::new(),
+            watches: vec![],
+            numeric: NumericPolicy::Ieee,
+        }
+    }
+
+    ///Sets where `print` and friends write to, defaults to stdout
+    pub fn set_stdout(&mut self, out: Box<dyn Write + Send + Sync>) {
+        self.stdout = out;
+        self.shared_stdout = None;
+    }
+
+    ///Sets where errors get reported, defaults to stderr
+    pub fn set_stderr(&mut self, err: Box<dyn Write + Send + Sync>) {
+        self.stderr = err;
+        self.shared_stderr = None;
+    }
+
+    ///Sets where `readln` and `read` get input from, defaults to the terminal
+    pub fn set_stdin(&mut self, input: Box<dyn BufRead + Send + Sync>) {
+        self.stdin = Some(input);
+    }
+
+    ///Sets what goes between the arguments of `print` and `println`, defaults to a space
+    pub fn set_print_separator(&mut self, sep: &str) {
+        self.print_sep = sep.to_owned();
+    }
+
+    ///Adds a function scripts can call like any other builtin. Arguments are checked against `sig`
+    ///before `func` ever sees them, and a builtin with the same name gets replaced
+    pub fn register_fn(&mut self, name: &str, sig: Signature, func: Builtin) {
+        self.functions.insert(name.to_owned(), builtins::BuiltinFn::new(sig, func));
+    }
+
+    ///A function or class the scripts run on this runtime have defined
+    pub(crate) fn defined(&self, name: &str) -> Option<&Value> {
+        self.heap.get(name)
+    }
+
+    ///The names of every builtin this runtime has, in alphabetical order
+    pub fn builtin_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.functions.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    ///How many nodes have been walked so far, for measuring how fast the interpreter is going
+    pub fn nodes_evaluated(&self) -> u64 {
+        self.nodes
+    }
+
+    ///Starts or stops counting calls for `call_counts`. It's off by default, since it costs a map
+    ///lookup on every call
+    pub fn set_count_calls(&mut self, on: bool) {
+        self.calls = if on { Some(self.calls.take().unwrap_or_default()) } else { None };
+    }
+
+    ///How many times each script function has been called since `set_count_calls(true)`, most called
+    ///first. Methods show up as `Class.method`. This is what decides which functions are hot enough
+    ///to be worth optimizing
+    pub fn call_counts(&self) -> Vec<(String, u64)> {
+        let mut counts: Vec<(String, u64)> = self.calls.iter().flatten().map(|(k, v)| (k.clone(), *v)).collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        counts
+    }
+
+    ///Builtins can't give back errors themselves, so this stops the script with `e` once the
+    ///builtin that raised it returns
+    pub fn raise(&mut self, e: String) {
+        self.raised = Some(e);
+    }
+
+    ///Stops the script the way `exit(code)` does. The error this gives back has to be passed along
+    ///like any other, nothing catches it
+    pub fn exit(&mut self, code: i32) -> String {
+        self.exit_code = Some(code);
+        format!("The script exited with code {}", code)
+    }
+
+    ///The code the script gave `exit`, if it called it
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit_code
+    }
+
+    ///Forgets that the script called `exit`, so more code can run in the same runtime afterwards
+    pub(crate) fn clear_exit(&mut self) {
+        self.exit_code = None;
+    }
+
+    ///Writes out anything the output, error, log, audit, and trace streams are still holding on to
+    pub fn flush(&mut self) {
+        self.stdout.flush().unwrap_or(());
+        self.stderr.flush().unwrap_or(());
+        self.audit.flush().unwrap_or(());
+        if let Some(sink) = &mut self.log_sink {
+            sink.flush().unwrap_or(());
+        }
+        self.trace.flush();
+    }
+
+    ///Reports an error through the error stream
+    pub fn report(&mut self, text: &str) {
+        writeln!(self.stderr, "{}", text).unwrap_or(());
+        self.stderr.flush().unwrap_or(());
+    }
+
+    ///Names the script being run and keeps its text, so errors can show the line they're about
+    pub fn set_source(&mut self, name: &str, text: &str) {
+        self.source = Some((name.to_owned(), text.to_owned()));
+    }
+
+    ///Turns an error into a `Diagnostic` that points into the script from `set_source`, with a
+    ///suggestion if it's about a name that doesn't exist. It points at `at` if there is one, like the
+    ///token from a parse error, otherwise at the statement that was running when the error happened
+    pub fn diagnose(&self, err: &str, at: Option<Span>) -> Diagnostic {
+        let mut diag = Diagnostic::new(err).suggest(self.heap.keys().chain(self.functions.keys()).map(String::as_str));
+        if let Some((name, text)) = &self.source {
+            if let Some(span) = at.or(self.at) {
+                diag = diag.locate(name, text, span);
+            }
+            diag = diag.suggest(diagnostics::words(text));
+        }
+        diag
+    }
+
+    ///Reports a crash with the line it's about underneath, if there's a source to find it in
+    fn report_crash(&mut self, err: &str, frame: &StackFrame) {
+        let diag = self.diagnose(err, None).suggest(frame.stack.keys().map(String::as_str));
+        let snippet = diag.snippet();
+        let sep = if snippet.is_empty() { "" } else { "\n" };
+        self.report(&format!("Interpreter crashed because: {}{}{}", err, sep, snippet));
+    }
+
+    ///Sets how deep function calls can nest before the script is stopped, defaults to 1000.
+    ///Each call takes a fair amount of stack, so the thread running the script needs room for this many
+    ///(the CLI gives the interpreter its own thread with a big stack)
+    pub fn set_max_depth(&mut self, depth: usize) {
+        self.max_depth = depth;
+    }
+
+    ///In strict mode objects can only get new properties inside of their constructor
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    ///Records every nondeterministic input the script reads (stdin, command output) to the given file
+    pub fn record_to(&mut self, path: &str) -> std::io::Result<()> {
+        self.trace = trace::Trace::record(path)?;
+        Ok(())
+    }
+
+    ///Feeds the inputs saved by `record_to` back to the script instead of reading them live
+    pub fn replay_from(&mut self, path: &str) -> std::io::Result<()> {
+        self.trace = trace::Trace::replay(path)?;
+        Ok(())
+    }
+
+    ///When dry run is on, side-effecting builtins only report what they would have done
+    pub fn set_dry_run(&mut self, dry_run: bool) {
+        self.dry_run = dry_run;
+    }
+
+    ///Controls whether scripts can use `exec` and `shell` to run other programs, on by default. Runtimes
+    ///made with a profile that doesn't allow other programs can't turn them on
+    pub fn set_allow_exec(&mut self, allow: bool) {
+        self.allow_exec = allow && self.profile.allows_processes();
+        if self.allow_exec {
+            builtins::add_exec(&mut self.functions);
+        } else {
+            builtins::remove_exec(&mut self.functions);
+        }
+    }
+
+    ///Sets where dry run reports get written, defaults to stderr
+    pub fn set_audit_sink(&mut self, sink: Box<dyn Write + Send + Sync>) {
+        self.audit = sink;
+        self.shared_audit = None;
+    }
+
+    ///Called by builtins before they touch anything outside the script. Returns false if the
+    ///action should be skipped, in which case it has been logged to the audit sink instead
+    pub fn side_effect(&mut self, action: &str) -> bool {
+        if self.dry_run {
+            writeln!(self.audit, "[dry-run] {}", action).unwrap_or(());
+            false
+        } else {
+            true
+        }
+    }
+
+    ///Defines every function and class at the top level of the tree before anything runs, so
+    ///they can be used above the place they're defined. Returns the rest of the statements to be run.
+    ///If something is defined more than once the last definition wins everywhere. Constant parts of
+    ///the tree are marked first, so they're only worked out once no matter how often they run.
+    ///Values kept from earlier runs are dropped, since a tree that isn't marked yet gets new ids and
+    ///the old ones would pile up in something like the REPL that runs tree after tree
+    fn hoist(&mut self, tree: &ExprNode, frame: &mut StackFrame) -> Result<ExprNode, String> {
+        self.constants.clear();
+        let tree = &constants::mark(tree);
+        if let ExprNode::Block(v) = tree {
+            let mut rest = vec![];
+            for node in v.iter() {
+                match node {
+                    ExprNode::Func(n, p, b) => {
+                        self.def_func(n, p, b)?;
+                    }
+                    ExprNode::Class(name, body) => {
+                        self.define_class(name, body, frame)?;
+                    }
+                    _ => rest.push(node.clone()),
+                }
+            }
+            Ok(ExprNode::Block(rest))
+        } else {
+            Ok(tree.clone())
+        }
+    }
+
+    ///Saves a function or class definition to the heap, replacing whatever was there before. That
+    ///includes builtins, so scripts that already had their own `max` keep working. Replacing one
+    ///that isn't a prelude helper gets a warning in a file
+    fn define(&mut self, name: &str, val: Value) {
+        if self.warn_redefinitions && self.heap.contains_key(name) {
+            self.report(&format!("Warning: {} is defined more than once, the last definition will be used", name));
+        }
+        if self.functions.remove(name).is_some() && self.warn_redefinitions && !builtins::in_prelude(name) {
+            self.report(&format!("Warning: {} replaces the builtin with the same name", name));
         }
+        self.heap.insert(name.to_owned(), val);
     }
 
     ///Matches the provided node and dispatches functions to handle it
     fn walk_tree(&mut self, node: &ExprNode, frame: &mut StackFrame) -> Result<Value, String> {
+        let _guard = crash::NodeGuard(node);
+        let _access = self.enter();
+        self.nodes += 1;
+        if let Some(fuel) = &mut self.fuel {
+            if *fuel == 0 {
+                return Err("Ran out of fuel for this tick".to_owned());
+            }
+            *fuel -= 1;
+        }
+        self.check_interrupt()?;
         // println!(
         //     "Walking tree: \n    Current node: {:?}\n     Current stack: {:?}",
         //     node, frame.stack
@@ -164,49 +678,101 @@ impl Runtime {
         let res: Value;
         match node {
             ExprNode::Block(v) => {
+                let outer = self.at;
                 let mut ret = Value::Null;
                 for e in v.iter() {
                     match e {
+                        ExprNode::At(span) => self.at = Some(*span),
                         /*When we run into a ReturnVal, it needs special treatment so we know to stop executing the
                          *current block once we get whatever the value is
                          **/
                         ExprNode::ReturnVal(v) => {
                             ret = self.walk_tree(v, frame)?;
+                            self.returning = true;
                             break;
                         }
                         _ => {
-                            self.walk_tree(e, frame)?;
+                            let val = self.walk_tree(e, frame)?;
+                            if !self.watches.is_empty() {
+                                self.check_watches(frame);
+                            }
+                            if self.returning {
+                                //if the returning flag has been set somewhere inside of this statement (like in an if block),
+                                //then pass the value up and stop executing this block
+                                ret = val;
+                                break;
+                            }
                         }
                     }
-                    if self.returning {
-                        //if the returning flag has been set, then break out of the loop and stop executing this block
-                        //This is for return statements that don't return anything
-                        break;
-                    }
                 }
+                //an error leaves this pointing at the statement it happened in
+                self.at = outer;
                 return Ok(ret);
             }
-            ExprNode::Operation(o, l, r) => res = self.do_operation(&**o, &**l, &**r, frame)?,
-            ExprNode::Call(ex, n) => res = self.do_call(&**ex, &*n, frame)?,
+            ExprNode::Operation(o, l, r) => res = self.do_operation(o, l, r, frame)?,
+            ExprNode::Call(ex, n) => res = self.do_call(ex, n, frame)?,
             ExprNode::MethodCall(n, args) => res = self.do_method(n, args, frame)?,
-            ExprNode::StrLiteral(s) => res = Value::EmString(*s.clone()),
+            ExprNode::CallExpr(callee, args) => {
+                let func = self.walk_tree(callee, frame)?;
+                let args = args.iter().map(|a| self.walk_tree(a, frame)).collect::<Result<Vec<Value>, String>>()?;
+                res = self.call_function(&func, args)?
+            }
+            ExprNode::StrLiteral(s) => res = Value::EmString(s.as_str().into()),
+            ExprNode::BytesLiteral(b) => res = Value::Bytes(Arc::new((**b).clone())),
             ExprNode::NumLiteral(n) => res = Value::Float(**n),
             ExprNode::BoolLiteral(b) => res = Value::EmBool(*b),
-            ExprNode::Name(n) => res = frame.get_var_copy(n),
+            ExprNode::NullLiteral => res = Value::Null,
+            ExprNode::Name(n) => {
+                //functions and classes can be used as values, as long as a variable isn't hiding them
+                res = match (frame.get_var_copy(n), self.heap.get(&**n)) {
+                    (Value::Null, Some(val)) => val.clone(),
+                    (Value::Null, None) if **n == "log" => Value::Native(Arc::new(logging::Log)),
+                    (v, _) => v,
+                }
+            }
             ExprNode::Func(n, p, b) => res = self.def_func(n, p, b)?, //don't need the stackframe here because functions are stored on the heap
-            ExprNode::Statement(e) => res = self.walk_tree(&**e, frame)?,
-            ExprNode::Loop(ty, con, block) => res = self.do_loop(&**ty, &**con, &**block, frame)?,
+            ExprNode::Statement(e) => res = self.walk_tree(e, frame)?,
+            ExprNode::Loop(ty, con, block) => res = self.do_loop(ty, con, block, frame)?,
             ExprNode::IfStatement(con, body, branch) => {
                 res = self.do_if(con, body, branch, frame)?
             }
             ExprNode::Array(v) => res = self.create_array(v, frame)?,
+            ExprNode::Tuple(v) => {
+                res = Value::Tuple(Arc::new(v.iter().map(|e| self.walk_tree(e, frame)).collect::<Result<_, _>>()?))
+            }
+            ExprNode::Comprehension(item, name, source, condition) => {
+                res = self.comprehension(item, name, source, condition, frame)?
+            }
+            ExprNode::ForIn(name, source, block) => res = self.for_in(name, source, block, frame)?,
             ExprNode::Index(ident, index) => res = self.index_array(ident, index, frame)?,
+            ExprNode::Constant(inner, id) => res = self.constant(inner, *id, frame)?,
             ExprNode::New(name, args) => res = self.do_init(name, args, frame)?,
-            ExprNode::Class(name, body) => res = self.define_class(&**name, &**body, frame)?,
-            _ => res = Value::Null,
+            ExprNode::Class(name, body) => res = self.define_class(name, body, frame)?,
+            ExprNode::Delete(target) => res = self.delete_prop(target, frame)?,
+            ExprNode::Unary(op, operand) => res = self.do_unary(op, operand, frame)?,
+            //a generator function's frame already has its arguments, the iterator takes it from here
+            ExprNode::Generator(body) => res = Value::Native(Arc::new(generator::start(body, std::mem::take(frame)))),
+            ExprNode::Async(body) => res = self.spawn_task(generator::Generator::new(body, std::mem::take(frame))),
+            //outside of an async function there's nothing to pause, so other tasks run until this one is done
+            ExprNode::Await(v) => {
+                let val = self.walk_tree(v, frame)?;
+                res = self.wait_for(val)?
+            }
+            ExprNode::Yield(_) => return Err("yield can only be used inside a function".to_owned()),
+            //blocks take care of returns themselves, this is a return that's the whole body of something
+            ExprNode::ReturnVal(v) => {
+                res = self.walk_tree(v, frame)?;
+                self.returning = true;
+            }
+            ExprNode::ElseStatement(body) => res = self.walk_tree(body, frame)?,
+            //generators run statements one at a time instead of as a block
+            ExprNode::At(span) => {
+                self.at = Some(*span);
+                res = Value::Null
+            }
+            //loops run these themselves, and nothing should leave the others in a tree
+            ExprNode::ForLoopDec(..) | ExprNode::Illegal(_) | ExprNode::EOF => return Err(internal_error(node)),
         }
-        //Reset the returning flag, since we're returning whatever value we got anyways
-        self.returning = false;
         Ok(res)
     }
 
@@ -226,8 +792,8 @@ impl Runtime {
                 //     "Condition is currently: {:?}",
                 //     self.walk_tree(&condition, frame)
                 // );
-                while self.walk_tree(&condition, frame)? == Value::EmBool(true) {
-                    ret = self.walk_tree(&block, frame)?;
+                while self.walk_tree(condition, frame)?.is_truthy() {
+                    ret = self.walk_tree(block, frame)?;
                     if self.returning {
                         break;
                     }
@@ -236,34 +802,25 @@ impl Runtime {
             }
             "for" => {
                 let mut ret = Value::Null;
-                if let ExprNode::ForLoopDec(dec, con, inc) = condition {
-                    if let ExprNode::Illegal(_) = **dec {
-                        while self.walk_tree(&con, frame)? == Value::EmBool(true) {
-                            //walk the tree to execute the loop body
-                            ret = self.walk_tree(&block, frame)?;
-                            if self.returning {
-                                break;
-                            }
-                            //perform the incrementation
-                            self.walk_tree(&inc, frame)?;
-                        }
-                    } else {
-                        self.walk_tree(&dec, frame)?;
-                        while self.walk_tree(&con, frame)? == Value::EmBool(true) {
-                            //walk the tree to execute the loop body
-                            ret = self.walk_tree(&block, frame)?;
-                            if self.returning {
-                                break;
-                            }
-                            //perform the incrementation
-                            self.walk_tree(&inc, frame)?;
-                        }
+                let (dec, con, inc) = match condition {
+                    ExprNode::ForLoopDec(dec, con, inc) => (dec, con, inc),
+                    other => return Err(internal_error(other)),
+                };
+                //a declaration that was left out is null, so running it does nothing
+                self.walk_tree(dec, frame)?;
+                while self.walk_tree(con, frame)?.is_truthy() {
+                    //walk the tree to execute the loop body
+                    ret = self.walk_tree(block, frame)?;
+                    if self.returning {
+                        break;
                     }
+                    //perform the incrementation
+                    self.walk_tree(inc, frame)?;
                 }
 
                 Ok(ret)
             }
-            _ => Ok(Value::Null),
+            other => Err(format!("Internal error: the parser made a {} loop, which doesn't exist", other)),
         }
     }
 
@@ -274,20 +831,11 @@ impl Runtime {
         params: &[ExprNode],
         body: &ExprNode,
     ) -> Result<Value, String> {
+        let f = make_func(name, params, body)?;
         if let Expression::Ident(n) = name {
-            let mut args = vec![];
-            params.iter().for_each(|e| {
-                if let ExprNode::Name(n) = e {
-                    args.push(Value::Name(n.to_string()));
-                }
-            });
-            let f = Value::Function(name.clone(), args, body.clone());
-            self.heap.insert(n.to_owned(), RefCell::new(f.clone()));
-            Ok(f)
-        } else {
-            Err(format!("Expected identifier, found {:?}", name))
-            //If we don't get a name for the funciton, we should exit since things will break
+            self.define(n, f.clone());
         }
+        Ok(f)
     }
 
     ///Performs arithmatic and boolean operations and returns their results
@@ -301,11 +849,16 @@ impl Runtime {
         match opr {
             Expression::Equal => match left {
                 ExprNode::Name(n) => {
-                    let v = self.walk_tree(&right, frame)?;
+                    let v = self.walk_tree(right, frame)?;
                     // println!("Assigning variable: {:?}", v);
                     frame.set_var(n.to_string(), v.clone());
                     Ok(v)
                 }
+                ExprNode::Tuple(names) => {
+                    let v = self.walk_tree(right, frame)?;
+                    unpack(names, &v, frame)?;
+                    Ok(v)
+                }
                 ExprNode::Index(n, i) => {
                     let name = if let ExprNode::Name(s) = *n.clone() {
                         *s
@@ -314,15 +867,33 @@ impl Runtime {
                     };
                     let index = self.walk_tree(i, frame)?;
                     let val = self.walk_tree(right, frame)?;
-                    frame.update_array_index(&name, index, val.clone());
+                    if let Value::EmString(prop) = &index {
+                        return self.set_member(&name, prop, val, frame);
+                    }
+                    frame.update_array_index(&name, index, val.clone())?;
 
                     Ok(val)
                 }
                 ExprNode::Operation(o, l, r) => {
                     match **o {
                         Expression::Lbracket => {
+                            //work back from the last index to the variable being assigned to
+                            let mut indices = vec![];
+                            let mut node = (&**l, &**r);
+                            let name = loop {
+                                match self.walk_tree(node.1, frame)? {
+                                    Value::Float(f) => indices.push(f),
+                                    other => return Err(format!("Expected a number to index with, found {}", other)),
+                                }
+                                match node.0 {
+                                    ExprNode::Operation(o, l, r) if **o == Expression::Lbracket => node = (l, r),
+                                    ExprNode::Name(n) => break n,
+                                    other => return Err(format!("Can't assign to an index of {:?}", other)),
+                                }
+                            };
+                            indices.reverse();
                             let val = self.walk_tree(right, frame)?;
-                            frame.update_nested_array(l, r, Some(val.clone()), true);
+                            frame.update_nested_array(name, &indices, val.clone())?;
                             Ok(val)
                         }
                         Expression::Operator(op) => {
@@ -334,19 +905,13 @@ impl Runtime {
                                         return Err(format!("Expected name, got {:?}", l));
                                     };
                                     let val = self.walk_tree(right, frame)?;
-
-                                    if let Some(Value::Object(e)) = frame.get_var_mut(&name.to_string()){
-                                        let prop = if let ExprNode::Name(n) = &**r {
-                                            n
-                                        }else {
-                                            return Err(format!("Unexpected symbol {:?}", r));
-                                        };
-
-                                        e.set_prop(*prop.clone(), Box::new(val.clone()));
-                                        Ok(val)
+                                    let prop = if let ExprNode::Name(n) = &**r {
+                                        n
                                     }else {
-                                        Err(format!("Unexpected {:?}", name))
-                                    }
+                                        return Err(format!("Unexpected symbol {:?}", r));
+                                    };
+
+                                    self.set_member(&name, prop, val, frame)
                                 }
                                 _ => Err(format!("Unexpected operator {}", op))
                             }
@@ -360,7 +925,7 @@ impl Runtime {
             Expression::Operator(o) => {
                 if *o == '.' {
                     // let val = self.walk_tree(&left, frame)?;
-                    return if let Value::Object(obj) = self.walk_tree(&left, frame)? {
+                    return if let Value::Object(obj) = self.walk_tree(left, frame)? {
                         if let Some(v) = obj.get_prop(&right.inner()) {
                             Ok(v.clone())
                         }else {
@@ -371,10 +936,12 @@ impl Runtime {
                     }
 
                 }
-                let l_p = self.walk_tree(&left, frame)?;
-                let r_p = self.walk_tree(&right, frame)?;
+                let l_p = self.walk_tree(left, frame)?;
+                let r_p = self.walk_tree(right, frame)?;
 
-               
+                if let (Value::Bytes(l), '+', Value::Bytes(r)) = (&l_p, *o, &r_p) {
+                    return Ok(Value::Bytes(Arc::new([l.as_slice(), r.as_slice()].concat())));
+                }
 
                 let f = match l_p {
                     Value::Float(f) => f,
@@ -382,13 +949,13 @@ impl Runtime {
                         if let Value::Float(f) = frame.get_var(&n) {
                             *f
                         } else {
-                            0.0 as f32
+                            0.0
                         }
                     }
                     Value::EmString(s) => {
-                        return Ok(Value::EmString(format!("{}{}", s, r_p)))
+                        return Ok(Value::EmString(format!("{}{}", s, r_p).into()))
                     },
-                    _ => 0.0 as f32,
+                    _ => 0.0,
                 };
 
                 let r = match r_p {
@@ -397,40 +964,79 @@ impl Runtime {
                         if let Value::Float(f) = frame.get_var(&n) {
                             *f
                         } else {
-                            0.0 as f32
+                            0.0
                         }
                     }
-                    _ => 0.0 as f32,
+                    _ => 0.0,
                 };
 
-                if *o == '+' {
-                    Ok(Value::Float(f + r))
+                let res = if *o == '+' {
+                    f + r
                 } else if *o == '-' {
-                    Ok(Value::Float(f - r))
+                    f - r
                 } else if *o == '*' {
-                    Ok(Value::Float(f * r))
+                    f * r
                 } else if *o == '/' {
-                    Ok(Value::Float(f / r))
+                    f / r
                 } else {
-                    Err(format!("Invalid Operator: {}", o))
+                    return Err(format!("Invalid Operator: {}", o));
+                };
+                Ok(Value::Float(self.arithmetic(f, *o, r, res)?))
+            }
+            Expression::BoolOp(op) if op == "&&" || op == "||" => {
+                //the right side only runs if it can still change the answer
+                let l_p = self.walk_tree(left, frame)?.is_truthy();
+                if l_p == (op == "||") {
+                    Ok(Value::EmBool(l_p))
+                } else {
+                    Ok(Value::EmBool(self.walk_tree(right, frame)?.is_truthy()))
                 }
             }
             Expression::BoolOp(op) => {
-                let l_p = self.walk_tree(&left, frame)?;
-                let r_p = self.walk_tree(&right, frame)?;
+                let l_p = self.walk_tree(left, frame)?;
+                let r_p = self.walk_tree(right, frame)?;
                 match op.as_str() {
-                    "==" => Ok(Value::EmBool(l_p == r_p)),
-                    "!=" => Ok(Value::EmBool(l_p != r_p)),
-                    ">=" => Ok(Value::EmBool(l_p >= r_p)),
-                    "<=" => Ok(Value::EmBool(l_p <= r_p)),
-                    "<" => Ok(Value::EmBool(l_p < r_p)),
-                    ">" => Ok(Value::EmBool(l_p > r_p)),
-                    _ => Err(format!("Invalid Operator: {}", op)),
+                    "==" => Ok(Value::EmBool(self.equals(&l_p, &r_p)?)),
+                    "!=" => Ok(Value::EmBool(!self.equals(&l_p, &r_p)?)),
+                    _ => Ok(Value::EmBool(compare::compare(op, &l_p, &r_p)?)),
                 }
             }
 
+            Expression::DotDot => match (self.walk_tree(left, frame)?, self.walk_tree(right, frame)?) {
+                (Value::Float(start), Value::Float(end)) => Ok(Value::Range(start, end, 1.0)),
+                (l, r) => Err(format!("A range needs numbers on both sides, got {} and {}", l, r)),
+            },
             Expression::Lbracket => Ok(self.index_array(left, right, frame)?),
-            _ => Ok(Value::Null),
+            other => Err(format!("Internal error: the parser made an operation out of {:?}", other)),
+        }
+    }
+
+    fn do_unary(&mut self, op: &Expression, operand: &ExprNode, frame: &mut StackFrame) -> Result<Value, String> {
+        let val = self.walk_tree(operand, frame)?;
+        match (op, val) {
+            (Expression::Operator('-'), Value::Float(f)) => Ok(Value::Float(-f)),
+            (Expression::Operator('-'), v) => Err(format!("Can't negate {}", v)),
+            (Expression::Operator('!'), v) => Ok(Value::EmBool(!v.is_truthy())),
+            (o, _) => Err(format!("Invalid Operator: {}", o)),
+        }
+    }
+
+    ///Assigns to a property of an object stored in the current frame, for both `obj.prop` and `obj["prop"]`
+    fn set_member(
+        &mut self,
+        name: &str,
+        prop: &str,
+        val: Value,
+        frame: &mut StackFrame,
+    ) -> Result<Value, String> {
+        if let Some(Value::Object(e)) = frame.get_var_mut(name) {
+            if self.strict && self.constructing == 0 && e.get_prop(prop).is_none() {
+                return Err(format!("Can't add property {} to {} in strict mode", prop, name));
+            }
+            Arc::make_mut(e).set_prop(prop, val.clone());
+            Ok(val)
+        } else {
+            Err(format!("Unexpected {:?}", name))
         }
     }
 
@@ -445,7 +1051,7 @@ impl Runtime {
                         ExprNode::Call(n, args) => 
                             self.do_call(n, args, frame)?,
                         
-                        _ => self.walk_tree(&value, frame)?,
+                        _ => self.walk_tree(value, frame)?,
                         };
             match s.as_str() {
                 "return" => {
@@ -461,6 +1067,29 @@ impl Runtime {
         Ok(Value::Null)
     }
 
+    ///Runs the body of a function, method, or constructor. This is where a return stops, and
+    ///where the call depth gets checked so runaway recursion is an error instead of a crash
+    fn call_body(&mut self, name: &str, body: &ExprNode, func_frame: &mut StackFrame) -> Result<Value, String> {
+        if self.depth >= self.max_depth {
+            return Err(format!("Maximum call depth of {} exceeded", self.max_depth));
+        }
+        self.depth += 1;
+        if let Some(calls) = &mut self.calls {
+            match calls.get_mut(name) {
+                Some(n) => *n += 1,
+                None => {
+                    calls.insert(name.to_owned(), 1);
+                }
+            }
+        }
+        CALL_STACK.with(|c| c.borrow_mut().push(name.to_owned()));
+        let res = self.walk_tree(body, func_frame);
+        CALL_STACK.with(|c| c.borrow_mut().pop());
+        self.depth -= 1;
+        self.returning = false;
+        res
+    }
+
     ///Executes a keyword or function call
     fn do_call(
         &mut self,
@@ -469,20 +1098,33 @@ impl Runtime {
         frame: &mut StackFrame,
     ) -> Result<Value, String> {
         match name {
-            Expression::Key(_) => self.keyword(name, &args[0], frame),
+            Expression::Key(_) => match args.first() {
+                Some(arg) => self.keyword(name, arg, frame),
+                None => Err(format!("Expected a value after {}", name)),
+            },
             Expression::Ident(n) => {
                 //check if there is a built-in function to use
-                if self.functions.contains_key(n) {
+                if let Some(builtin) = self.functions.get(n).cloned() {
                     let tmp = args.iter()
-                    .map(|e| self.walk_tree(e, frame).unwrap())
-                    .collect();
-                    let func = self.functions.get(n).unwrap();
-                    return Ok(func(tmp))
+                    .map(|e| self.walk_tree(e, frame))
+                    .collect::<Result<Vec<Value>, String>>()?;
+                    builtin.sig.check(n, &tmp)?;
+                    let val = (builtin.func)(self, tmp);
+                    return match self.raised.take() {
+                        Some(e) => Err(e),
+                        None => Ok(val),
+                    };
+                }
+
+                //a variable holding a function can be called like one
+                if let func @ Value::Function(..) = frame.get_var_copy(n) {
+                    let args = args.iter().map(|a| self.walk_tree(a, frame)).collect::<Result<Vec<Value>, String>>()?;
+                    return self.call_function(&func, args);
                 }
 
-                if let Some(func) = self.heap.get(n) {
-                    //I'd really like to not have to borrow here
-                    match &*func.clone().borrow() {
+                //cloned so the heap isn't borrowed while the function runs
+                if let Some(func) = self.heap.get(n).cloned() {
+                    match &func {
                         Value::Function(_, params, body) => {
                             if params.len() != args.len() {
                                 Err(format!(
@@ -495,7 +1137,7 @@ impl Runtime {
                                 let mut func_frame = StackFrame::new();
                                 for (i, e) in args.iter().enumerate() {
                                     if let Value::Name(arg) = &params[i] {
-                                        let val = self.walk_tree(&e, frame)?;
+                                        let val = self.walk_tree(e, frame)?;
                                         match val {
                                             Value::Name(n) => {
                                                 let tmp = frame.get_var(&n).clone();
@@ -506,7 +1148,7 @@ impl Runtime {
                                         }
                                     }
                                 }
-                                self.walk_tree(&body, &mut func_frame)
+                                self.call_body(n, body, &mut func_frame)
                                 //this shouldn't be necessary since Rust will destroy the old
                                 //stack frame anyways when it goes out of  scope
                                 // params.iter().for_each(|e| {
@@ -516,7 +1158,7 @@ impl Runtime {
                                 // });
                             }
                         }
-                        _ => Err(format!("Expected function, found {}", func.borrow())),
+                        _ => Err(format!("Expected function, found {}", func)),
                     }
                 } else {
                     Err(format!("Couldn't find identifier {}", n))
@@ -526,17 +1168,149 @@ impl Runtime {
         }
     }
 
+    ///Calls a function value with arguments that have already been evaluated, like when a builtin
+    ///is handed a function to run
+    pub(crate) fn call_function(&mut self, func: &Value, args: Vec<Value>) -> Result<Value, String> {
+        match func {
+            Value::Function(n, params, body) => {
+                if params.len() != args.len() {
+                    return Err(format!("Expected {} arguments for {}, got {}", params.len(), ident_name(n), args.len()));
+                }
+                let mut func_frame = StackFrame::new();
+                for (param, val) in params.iter().zip(args) {
+                    if let Value::Name(arg) = param {
+                        func_frame.set_var(arg.to_string(), val);
+                    }
+                }
+                self.call_body(&ident_name(n), body, &mut func_frame)
+            }
+            other => Err(format!("{} is not a function", other)),
+        }
+    }
+
+    ///Calls a builtin or a function defined in `frame` by name, the same as calling it from a script
+    pub fn call(&mut self, name: &str, args: Vec<Value>, frame: &StackFrame) -> Result<Value, String> {
+        if let Some(builtin) = self.functions.get(name).cloned() {
+            builtin.sig.check(name, &args)?;
+            let val = (builtin.func)(self, args);
+            return match self.raised.take() {
+                Some(e) => Err(e),
+                None => Ok(val),
+            };
+        }
+        let func = match (frame.lookup(name), self.heap.get(name)) {
+            (Some(func @ Value::Function(..)), _) => func.clone(),
+            (_, Some(func)) => func.clone(),
+            _ => return Err(format!("Couldn't find identifier {}", name)),
+        };
+        self.call_function(&func, args)
+    }
+
+    ///Gives back every item in something that can be looped over. Arrays and tuples give their elements, strings
+    ///give their characters, bytes give each byte as a number, sets give their items in the order they were added, and objects give
+    ///the names of their properties. Iterators and objects
+    ///with a `~next` method give everything they have left. Everything that consumes a sequence
+    ///should go through here (or `iterator::to_iter` to go lazily) so they all accept the same things
+    pub(crate) fn iterate(&mut self, val: Value) -> Result<Vec<Value>, String> {
+        match val {
+            Value::EmArray(v) | Value::Tuple(v) => Ok(Arc::unwrap_or_clone(v)),
+            Value::EmString(s) => Ok(s.chars().map(|c| Value::EmString(c.to_string().into())).collect()),
+            Value::Bytes(b) => Ok(b.iter().map(|b| Value::Float(*b as f64)).collect()),
+            Value::Native(_) => iterator::to_iter(self, val)?.collect(self),
+            Value::Object(ref e) if self.find_method(e, "~next").is_some() => iterator::to_iter(self, val)?.collect(self),
+            Value::Object(e) => Ok(e.members.keys().map(|k| Value::EmString(k.as_str().into())).collect()),
+            Value::Range(start, end, step) => Ok(range_values(start, end, step).map(Value::Float).collect()),
+            Value::EmSet(s) => Ok(s.iter().map(MapKey::to_value).collect()),
+            other => Err(format!("Can't loop over {}", other)),
+        }
+    }
+
+    ///Looks for a method on the object itself first, then on its class
+    fn find_method(&self, obj: &EmObject, name: &str) -> Option<Value> {
+        match obj.members.get(name) {
+            Some(v) => Some((**v).clone()),
+            None => self.current_class(obj)?.get_prop(name).cloned(),
+        }
+    }
+
+    ///Asks an object for its next item with its `~next` method, which gives back null once there
+    ///aren't any more. Objects are values, so the method can't change the caller's copy of `self`.
+    ///Instead whatever `self` ends up as inside the method replaces `obj`, ready for the next call
+    pub(crate) fn next_of(&mut self, obj: &mut Arc<EmObject>) -> Result<Option<Value>, String> {
+        let (params, body) = match self.find_method(obj, "~next") {
+            Some(Value::Function(_, params, body)) => (params, body),
+            _ => return Err(format!("{} has no ~next method", obj)),
+        };
+        if params.len() != 1 {
+            return Err(format!("~next should only take self, but it takes {} arguments", params.len()));
+        }
+        let mut func_frame = StackFrame::new();
+        func_frame.set_var(String::from("self"), Value::Object(obj.clone()));
+        let name = format!("{}.~next", obj.get_prop("~name").unwrap_or(&Value::Null));
+        let val = self.call_body(&name, &body, &mut func_frame)?;
+        if let Some(Value::Object(updated)) = func_frame.lookup("self") {
+            *obj = updated.clone();
+        }
+        Ok(if val == Value::Null { None } else { Some(val) })
+    }
+
+    ///Finds the newest definition of an object's class, so objects made before their class was
+    ///redefined still get the new methods
+    fn current_class(&self, obj: &EmObject) -> Option<Arc<EmObject>> {
+        let class = obj.class.as_ref()?;
+        if let Some(Value::EmString(name)) = class.get_prop("~name") {
+            if let Some(Value::Class(c)) = self.heap.get(&**name).cloned() {
+                return Some(c);
+            }
+        }
+        Some(class.clone())
+    }
+
     fn do_method(&mut self, method: &ExprNode, args: &Vec<ExprNode>, frame: &mut StackFrame) -> Result<Value, String> {
         if let ExprNode::Operation(_, name, member) = method {
-            if let Value::Object(e) = self.walk_tree(&**name, frame)?{
-                let func = e.get_prop(&*member.inner());
+            if let (ExprNode::Name(var), true) = (&**name, builtins::writes_back(&member.inner())) {
+                if let Some(val) = self.method_in_place(var, &member.inner(), args, frame)? {
+                    return Ok(val);
+                }
+            }
+            let target = self.walk_tree(name, frame)?;
+            if let Value::Native(n) = &target {
+                let args = args.iter().map(|a| self.walk_tree(a, frame)).collect::<Result<Vec<Value>, String>>()?;
+                let res = n.call_method(self, &member.inner(), args);
+                //native methods can call builtins that raise instead of giving back an error
+                return match self.raised.take() {
+                    Some(e) => Err(e),
+                    None => res,
+                };
+            }
+            if !matches!(target, Value::Object(_)) {
+                let method = member.inner();
+                if let Some(builtin) = builtins::primitive_method(&target, &method) {
+                    let mut tmp = vec![target];
+                    for a in args.iter() {
+                        tmp.push(self.walk_tree(a, frame)?);
+                    }
+                    builtin.sig.check(&method, &tmp[1..])?;
+                    let val = (builtin.func)(self, tmp);
+                    return match self.raised.take() {
+                        Some(e) => Err(e),
+                        None => Ok(val),
+                    };
+                }
+            }
+            if let Value::Object(e) = target {
+                let prop = member.inner();
+                let func = self.find_method(&e, &prop);
                 match func {
+                    Some(Value::Function(n, p, _)) if p.is_empty() => {
+                        Err(format!("{} can't be called as a method since it doesn't take self", ident_name(&n)))
+                    }
                     Some(Value::Function(n, p, body)) => {
                         if args.len() != p.len() - 1 {
                             Err(format!(
                                 "Method {} for {} takes {} arguments, found {}",
                                 n,
-                                e.get_prop("~name").unwrap(),
+                                e.get_prop("~name").unwrap_or(&Value::Null),
                                 p.len(),
                                 args.len()
                             ))
@@ -545,7 +1319,7 @@ impl Runtime {
                             func_frame.set_var(String::from("self"), Value::Object(e.clone()));
                             for (i, e) in args.iter().enumerate() {
                                 if let Value::Name(arg) = &p[i+1] {
-                                    let val = self.walk_tree(&e, frame)?;
+                                    let val = self.walk_tree(e, frame)?;
                                     match val {
                                         Value::Name(n) => {
                                             let tmp = frame.get_var(&n).clone();
@@ -556,7 +1330,8 @@ impl Runtime {
                                     }
                                 }
                             }
-                            self.walk_tree(body, &mut func_frame)
+                            let name = format!("{}.{}", e.get_prop("~name").unwrap_or(&Value::Null), prop);
+                            self.call_body(&name, &body, &mut func_frame)
                         }
                     }
                     _ => {
@@ -564,7 +1339,7 @@ impl Runtime {
                     }
                 }
             }else {
-                Err(format!("Expected object, got {:?}", self.walk_tree(name, frame)?))
+                Err(format!("{} has no method {}", target, member.inner()))
             }
         } else {
             Err(format!("Unexpected expression {:?}", method))
@@ -578,7 +1353,7 @@ impl Runtime {
         branches: &ExprNode,
         frame: &mut StackFrame,
     ) -> Result<Value, String> {
-        if self.walk_tree(condition, frame)? == Value::EmBool(true) {
+        if self.walk_tree(condition, frame)?.is_truthy() {
             self.walk_tree(body, frame)
         } else if let ExprNode::IfStatement(con, body, branch) = branches {
             self.do_if(con, body, branch, frame)
@@ -591,57 +1366,60 @@ impl Runtime {
 
     fn do_init(
         &mut self,
-        name: &Expression,
+        class: &ExprNode,
         init_args: &Vec<ExprNode>,
         frame: &mut StackFrame,
     ) -> Result<Value, String> {
-        if let Expression::Ident(n) = name{
-            let class = match self.heap.get(n) {
-                Some(val) => {
-                    if let Value::Object(e) = val.borrow().clone(){
-                        e
-                    }else {
-                        return Err(format!("Expected class, got {}", val.borrow()));
-                    }
-                },
-                None => return Err(format!("Class {} is not defined", name)),
+        let class = match (class, self.walk_tree(class, frame)?) {
+            (_, Value::Class(c)) => c,
+            (ExprNode::Name(n), Value::Null) => return Err(format!("Class {} is not defined", n)),
+            (_, other) => return Err(format!("Expected class, got {}", other)),
+        };
+        let mut args = vec![];
+        for e in init_args.iter() {
+            match self.walk_tree(e, frame)? {
+                //I'd really like to not have to copy here
+                Value::Name(n) => args.push(frame.get_var(&n).clone()),
+                val => args.push(val),
+            }
+        }
+        self.instantiate(class, args)
+    }
 
-            };
+    ///Makes a new object from a class and runs its constructor with the given arguments
+    pub(crate) fn instantiate(&mut self, class: Arc<EmObject>, args: Vec<Value>) -> Result<Value, String> {
+        let instance = EmObject::instance_of(class.clone());
         if let Some(Value::Function(_, params, body)) = class.get_prop("~init") {
-            if init_args.len() != params.len() - 1 {
+            let name = class.get_prop("~name").unwrap_or(&Value::Null);
+            if params.is_empty() {
+                Err(format!("The constructor for {} has to take self", name))
+            } else if args.len() != params.len() - 1 {
                 Err(format!(
-                    "Contrsuctor for {} takes {} arguments, found {}",
-                    class.get_prop("~name").unwrap(),
-                    params.len(),
-                    init_args.len()
+                    "Constructor for {} takes {} arguments, found {}",
+                    name,
+                    params.len() - 1,
+                    args.len()
                 ))
             } else {
                 let mut func_frame = StackFrame::new();
-                func_frame.set_var(String::from("self"), Value::Object(class.clone()));
-                for (i, e) in init_args.iter().enumerate() {
-                    if let Value::Name(arg) = &params[i+1] {
-                        let val = self.walk_tree(&e, frame)?;
-                        match val {
-                            Value::Name(n) => {
-                                let tmp = frame.get_var(&n).clone();
-                                func_frame.set_var(arg.to_string(), tmp);
-                                //I'd really like to not have to copy here
-                            }
-                            _ => func_frame.set_var(arg.to_string(), val),
-                        }
+                func_frame.set_var(String::from("self"), Value::Object(Arc::new(instance)));
+                for (param, val) in params[1..].iter().zip(args) {
+                    if let Value::Name(arg) = param {
+                        func_frame.set_var(arg.to_string(), val);
                     }
                 }
-                self.walk_tree(body, &mut func_frame)?;
-                
+                self.constructing += 1;
+                let name = format!("{}.~init", class.get_prop("~name").unwrap_or(&Value::Null));
+                let res = self.call_body(&name, body, &mut func_frame);
+                self.constructing -= 1;
+                res?;
+
                 //should figure out a way to get ownership from a stackframe
                 Ok(func_frame.get_var("self").clone())
             }
         } else {
-            Ok(Value::Object(class))
+            Ok(Value::Object(Arc::new(instance)))
         }
-    }else {
-        Err(format!("Expected object, found {:?}", name))
-    }
     }
 
     ///Defines an array and saves it to the current stackframe
@@ -652,10 +1430,62 @@ impl Runtime {
     ) -> Result<Value, String> {
         let mut tmp = vec![];
         for val in raw.iter() {
-            tmp.push(Box::new(self.walk_tree(val, frame)?));
+            tmp.push(self.walk_tree(val, frame)?);
         }
 
-        Ok(Value::EmArray(tmp))
+        Ok(Value::EmArray(Arc::new(tmp)))
+    }
+
+    ///Builds an array out of everything in `source` that passes `condition`. The variable only exists
+    ///inside the comprehension, so whatever had that name before gets it back afterwards
+    fn comprehension(
+        &mut self,
+        item: &ExprNode,
+        name: &str,
+        source: &ExprNode,
+        condition: &ExprNode,
+        frame: &mut StackFrame,
+    ) -> Result<Value, String> {
+        let items = self.walk_tree(source, frame)?;
+        let items = self.iterate(items)?;
+        let shadowed = frame.stack.remove(name);
+        let mut out = vec![];
+        let mut res = Ok(());
+        for val in items {
+            frame.set(name, val);
+            let keep = self.walk_tree(condition, frame).map(|c| c.is_truthy());
+            res = keep.and_then(|keep| {
+                if keep {
+                    out.push(self.walk_tree(item, frame)?);
+                }
+                Ok(())
+            });
+            if res.is_err() {
+                break;
+            }
+        }
+        frame.stack.remove(name);
+        if let Some(v) = shadowed {
+            frame.set(name, v);
+        }
+        res.map(|_| Value::EmArray(Arc::new(out)))
+    }
+
+    ///Runs a block once for every item in something iterable. Ranges and iterators are stepped
+    ///through instead of being turned into an array first, so `for i in 0..1000000` doesn't build a
+    ///huge array and an iterator that never ends can still be broken out of with `return`
+    fn for_in(&mut self, name: &str, source: &ExprNode, block: &ExprNode, frame: &mut StackFrame) -> Result<Value, String> {
+        let source = self.walk_tree(source, frame)?;
+        let items = iterator::to_iter(self, source)?;
+        let mut ret = Value::Null;
+        while let Some(val) = items.next(self)? {
+            frame.set(name, val);
+            ret = self.walk_tree(block, frame)?;
+            if self.returning {
+                break;
+            }
+        }
+        Ok(ret)
     }
 
     ///Returns the value at a given array index
@@ -666,15 +1496,63 @@ impl Runtime {
         frame: &mut StackFrame,
     ) -> Result<Value, String> {
         let array = self.walk_tree(ident, frame)?;
-        if let Value::Float(f) = self.walk_tree(index, frame)? {
-            Ok(array.index(f as usize)?.clone())
-        } else {
-            Err(format!("Index was not a numeber"))
+        match self.walk_tree(index, frame)? {
+            //objects are looked up by key, so anything that can be a key works
+            key if matches!(array, Value::Object(_)) => {
+                Ok(array.index_key(&types::MapKey::from_value(&key)?.to_string())?.clone())
+            }
+            Value::Float(f) => array.index(f),
+            Value::Range(start, end, step) => slice(&array, start, end, step),
+            Value::EmString(key) => Ok(array.index_key(&key)?.clone()),
+            _ => Err(format!("Index was not a number or string")),
+        }
+    }
+
+    ///Calls a method like `push` on the value in `var` and puts what it gives back there. The value is
+    ///taken out of the variable first, so it's the only reference and gets changed without a copy.
+    ///Gives back None if `var` doesn't hold something with that method
+    fn method_in_place(&mut self, var: &str, method: &str, args: &[ExprNode], frame: &mut StackFrame) -> Result<Option<Value>, String> {
+        let builtin = match frame.get_var_mut(var) {
+            Some(Value::Object(_)) | None => return Ok(None),
+            Some(val) => match builtins::primitive_method(val, method) {
+                Some(builtin) => builtin,
+                None => return Ok(None),
+            },
+        };
+        let mut tmp = vec![Value::Null];
+        for a in args.iter() {
+            tmp.push(self.walk_tree(a, frame)?);
+        }
+        builtin.sig.check(method, &tmp[1..])?;
+        if let Some(slot) = frame.get_var_mut(var) {
+            tmp[0] = std::mem::replace(slot, Value::Null);
+        }
+        let val = (builtin.func)(self, tmp);
+        frame.set_var(var.to_owned(), val.clone());
+        match self.raised.take() {
+            Some(e) => Err(e),
+            None => Ok(Some(val)),
         }
     }
 
+    ///Removes a property from an object stored in the current frame, returning the removed value
+    fn delete_prop(&mut self, target: &ExprNode, frame: &mut StackFrame) -> Result<Value, String> {
+        if let ExprNode::Operation(o, l, r) = target {
+            if let (Expression::Operator('.'), ExprNode::Name(name), ExprNode::Name(prop)) = (&**o, &**l, &**r) {
+                return match frame.get_var_mut(name) {
+                    Some(Value::Object(e)) => match Arc::make_mut(e).remove_prop(prop) {
+                        Some(v) => Ok(*v),
+                        None => Err(format!("{} has no property {}", name, prop)),
+                    },
+                    _ => Err(format!("{} is not an object", name)),
+                };
+            }
+        }
+        Err(format!("Expected a property to delete, found {:?}", target))
+    }
+
     fn define_class(&mut self, name: &Expression, body: &ExprNode, frame: &mut StackFrame) -> Result<Value, String> {
-        let mut members = HashMap::new();
+        let mut members = EmObject::new(OrderedMap::new());
         let class = if let Expression::Ident(s) = name{
             s
         }else {
@@ -682,11 +1560,16 @@ impl Runtime {
         }; 
 
         //the name property will be the name of the class for now, this might change in the future
-        members.insert("~name".to_string(), Box::new(Value::EmString(class.clone())));
+        members.set_prop("~name", Value::EmString(class.as_str().into()));
 
         if let ExprNode::Block(v) = body {
             for node in v {
-                let val = self.walk_tree(node, frame)?;
+                //methods only live on the class, so they shouldn't be put on the heap like other functions
+                let val = match node {
+                    ExprNode::At(_) => continue,
+                    ExprNode::Func(n, p, b) => make_func(n, p, b)?,
+                    _ => self.walk_tree(node, frame)?,
+                };
                 match &val {
                     Value::Function(n, _, _) => {
                         let fn_name = if let Expression::Ident(s) =  n{
@@ -694,7 +1577,7 @@ impl Runtime {
                         }else {
                             return Err("Expected identifier".to_owned());
                         };
-                        members.insert(fn_name.clone(), Box::new(val.clone()));
+                        members.set_prop(fn_name, val.clone());
                     }
                     er => {
                         return Err(format!("Unexpected {:?} in class definition", er));
@@ -703,13 +1586,121 @@ impl Runtime {
             }
         }
 
-        let tmp = Value::Object(EmObject {members: members});
-        self.heap.insert(class.clone(), RefCell::new(tmp.clone()));
+        let tmp = Value::Class(Arc::new(members));
+        self.define(class, tmp.clone());
 
         Ok(tmp)
     }
 }
 
+///For nodes the parser should never have let through. There's no line number to give, so the error
+///shows the node itself instead
+fn internal_error(node: &ExprNode) -> String {
+    format!("Internal error: the parser left {:?} in the tree where it can't be run, this is a bug", node)
+}
+
+fn ident_name(e: &Expression) -> String {
+    match e {
+        Expression::Ident(s) => s.clone(),
+        other => format!("{:?}", other),
+    }
+}
+
+thread_local! {
+    //names of the script functions being run on this thread, so a crash can say where it happened
+    static CALL_STACK: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+///The script functions that are running right now, outermost first. Meant for crash reports, where
+///the runtime itself might not be reachable anymore
+pub fn call_stack() -> Vec<String> {
+    CALL_STACK.with(|c| c.borrow().clone())
+}
+
+///Turns an index from a script into a position in something `len` long, counting back from the end
+///if it's negative
+fn array_index(f: f64, len: usize) -> Result<usize, String> {
+    if f.fract() != 0.0 || f.is_nan() {
+        return Err(format!("Array index must be a whole number, got {}", f));
+    }
+    let i = if f < 0.0 { len as f64 + f } else { f };
+    if i < 0.0 || i >= len as f64 {
+        Err(format!("Index {} out of bounds for length {}", f, len))
+    } else {
+        Ok(i as usize)
+    }
+}
+
+///How many numbers a range counts through, which is none if the step goes the wrong way
+pub(crate) fn range_len(start: f64, end: f64, step: f64) -> usize {
+    ((end - start) / step).ceil().max(0.0) as usize
+}
+
+///Each number is worked out from the start instead of added up, so float error doesn't build up
+pub(crate) fn range_values(start: f64, end: f64, step: f64) -> impl Iterator<Item = f64> {
+    (0..range_len(start, end, step)).map(move |i| start + i as f64 * step)
+}
+
+///Assigns each item of a tuple or array to the name in the same place, like `(a, b) = (1, 2)`.
+///Names can be tuples themselves to unpack nested items, and the counts have to match
+fn unpack(names: &[ExprNode], val: &Value, frame: &mut StackFrame) -> Result<(), String> {
+    let items = match val {
+        Value::Tuple(items) | Value::EmArray(items) => items,
+        other => return Err(format!("Can't unpack {}, only tuples and arrays can be unpacked", other)),
+    };
+    if items.len() != names.len() {
+        return Err(format!("Can't unpack {} items into {} names", items.len(), names.len()));
+    }
+    for (name, item) in names.iter().zip(items.iter()) {
+        match name {
+            ExprNode::Name(n) => frame.set_var(n.to_string(), item.clone()),
+            ExprNode::Tuple(inner) => unpack(inner, item, frame)?,
+            other => return Err(format!("Can't unpack into {}", crate::parser::to_source(other).trim())),
+        }
+    }
+    Ok(())
+}
+
+///Indexing with a range picks out every index the range counts through. Either end can be negative
+///to count back from the end, so `a[1..-1]` is everything but the first and last items. Strings and
+///bytes give back the same type, everything else an array
+fn slice(target: &Value, start: f64, end: f64, step: f64) -> Result<Value, String> {
+    let len = match target {
+        Value::EmString(s) => s.chars().count(),
+        Value::Bytes(b) => b.len(),
+        Value::EmArray(a) => a.len(),
+        Value::Range(start, end, step) => range_len(*start, *end, *step),
+        other => return Err(format!("Type {} can't be sliced", other)),
+    } as f64;
+    let from_end = |i: f64| if i < 0.0 { i + len } else { i };
+    let items = range_values(from_end(start), from_end(end), step)
+        .map(|i| target.index(i))
+        .collect::<Result<Vec<Value>, String>>()?;
+    match target {
+        Value::EmString(_) => Ok(Value::EmString(items.iter().map(|c| format!("{}", c)).collect::<String>().into())),
+        Value::Bytes(_) => Ok(Value::Bytes(Arc::new(
+            items.iter().filter_map(|b| if let Value::Float(f) = b { Some(*f as u8) } else { None }).collect(),
+        ))),
+        _ => Ok(Value::EmArray(Arc::new(items))),
+    }
+}
+
+///Builds a function value without saving it anywhere
+fn make_func(name: &Expression, params: &[ExprNode], body: &ExprNode) -> Result<Value, String> {
+    if let Expression::Ident(_) = name {
+        let mut args = vec![];
+        params.iter().for_each(|e| {
+            if let ExprNode::Name(n) = e {
+                args.push(Value::Name(n.to_string()));
+            }
+        });
+        Ok(Value::Function(name.clone(), args, body.clone()))
+    } else {
+        Err(format!("Expected identifier, found {:?}", name))
+        //If we don't get a name for the funciton, we should exit since things will break
+    }
+}
+
 ///Keeps track of local variables for functions. Currently only created when a function is called
 impl Default for Runtime {
     fn default() -> Self {
@@ -724,12 +1715,24 @@ impl Default for StackFrame {
 }
 
 impl StackFrame {
+    #[cfg_attr(feature = "debug-alloc", track_caller)]
     pub fn new() -> StackFrame {
+        #[cfg(feature = "debug-alloc")]
+        crate::alloc_stats::record(crate::alloc_stats::Kind::Map, std::panic::Location::caller());
         StackFrame {
             stack: HashMap::new(),
         }
     }
 
+    ///The value of a variable in this frame, if it has one
+    pub fn lookup(&self, name: &str) -> Option<&Value> {
+        self.stack.get(name)
+    }
+
+    pub fn set(&mut self, name: &str, v: Value) {
+        self.set_var(name.to_owned(), v);
+    }
+
     fn set_var(&mut self, name: String, v: Value) {
         self.stack.insert(name, v);
     }
@@ -750,71 +1753,29 @@ impl StackFrame {
         }
     }
 
-    fn update_array_index(&mut self, name: &str, index: Value, val: Value) {
+    fn update_array_index(&mut self, name: &str, index: Value, val: Value) -> Result<(), String> {
         let var = self
             .stack
             .get_mut(name)
-            .expect(format!("Unable to find variable {}", name).as_str());
+            .ok_or_else(|| format!("Couldn't find identifier {}", name))?;
 
-        if let Value::Float(f) = index {
-            match var {
-                Value::EmArray(v) => {
-                    v[f as usize] = Box::new(val);
-                }
-                _ => panic!("Expected array, found {}", var),
-            }
+        match index {
+            Value::Float(f) => *var.index_mut(f)? = val,
+            other => return Err(format!("Expected a number to index with, found {}", other)),
         }
+        Ok(())
     }
 
-    fn update_nested_array(
-        &mut self,
-        ident: &ExprNode,
-        index: &ExprNode,
-        val: Option<Value>,
-        first: bool,
-    ) -> Option<&mut Box<Value>> {
-        match ident {
-            ExprNode::Operation(o, l, r) => {
-                if **o != Expression::Lbracket {
-                    panic!("Found operation when assigning to array: {:?}", ident);
-                } else {
-                    let var = self.update_nested_array(l, r, None, false)?;
-                    let i = match index {
-                        ExprNode::NumLiteral(f) => **f as usize,
-                        _ => panic!("Expected number literal, found {:?}", index),
-                    };
-                    match &mut **var {
-                        Value::EmArray(v) => {
-                            if first {
-                                v[i] = Box::new(val.unwrap());
-                                None
-                            } else {
-                                v.get_mut(i)
-                            }
-                        }
-                        n => panic!("Expected array, found {}", n),
-                    }
-                }
-            }
-            ExprNode::Name(n) => {
-                let i = match index {
-                    ExprNode::NumLiteral(f) => **f as usize,
-                    _ => panic!("Expected number literal, found {:?}", index),
-                };
-
-                let var = self
-                    .stack
-                    .get_mut(&**n)
-                    .expect(format!("Unable to find variable {}", n).as_str());
-
-                match var {
-                    Value::EmArray(v) => v.get_mut(i),
-                    _ => panic!("Expected array, found {}", var),
-                }
-            }
-
-            _ => panic!("Unexpected node: {:?}", ident),
+    fn update_nested_array(&mut self, name: &str, indices: &[f64], val: Value) -> Result<(), String> {
+        let mut slot = self
+            .stack
+            .get_mut(name)
+            .ok_or_else(|| format!("Couldn't find identifier {}", name))?;
+        for i in indices {
+            slot = slot.index_mut(*i)?;
         }
+        *slot = val;
+        Ok(())
     }
 
     fn get_var_copy(&self, name: &str) -> Value {
@@ -0,0 +1,128 @@
+use super::bytes::{base64_decode, base64_encode};
+use super::{Runtime, Value};
+use std::cmp::Ordering;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+///Something implemented in rust that scripts can hold on to and call methods on, like a file.
+///Natives are shared when copied, and can end up on another thread along with the runtime, so any
+///state they keep needs a thread safe kind of interior mutability like a `Mutex`
+pub trait NativeObject: Send + Sync {
+    ///The name `type()` gives back for this object
+    fn type_name(&self) -> &str;
+
+    fn call_method(&self, rt: &mut Runtime, name: &str, args: Vec<Value>) -> Result<Value, String>;
+
+    fn display(&self) -> String {
+        format!("<{}>", self.type_name())
+    }
+
+    ///Lets rust code get the concrete type back out of a `Value::Native`
+    fn as_any(&self) -> Option<&dyn std::any::Any> {
+        None
+    }
+}
+
+impl fmt::Debug for dyn NativeObject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.display())
+    }
+}
+
+//two natives are only equal if they are the same object
+impl PartialEq for dyn NativeObject {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self as *const Self as *const u8, other as *const Self as *const u8)
+    }
+}
+
+impl PartialOrd for dyn NativeObject {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        if self == other {
+            Some(Ordering::Equal)
+        } else {
+            None
+        }
+    }
+}
+
+///Made by the `open` builtin. Every method goes to the file system again, so the handle
+///never goes stale if something else changes the file
+pub struct FileHandle {
+    path: PathBuf,
+}
+
+impl FileHandle {
+    pub fn new(path: &str) -> FileHandle {
+        FileHandle { path: PathBuf::from(path) }
+    }
+
+    ///Goes through the trace like `read` does, with the bytes saved as base64 since a trace is text
+    pub(crate) fn read_bytes(&self, rt: &mut Runtime) -> Result<Vec<u8>, String> {
+        let res = rt.trace.fallible("file_bytes", || {
+            fs::read(&self.path)
+                .map(|b| base64_encode(&b))
+                .map_err(|e| format!("Couldn't read {}: {}", self.path.display(), e))
+        });
+        base64_decode(&res?)
+    }
+
+    ///Bytes are written exactly as they are, anything else is written as the text it prints as
+    pub(crate) fn write(&self, rt: &mut Runtime, text: Option<&Value>, append: bool) -> Result<Value, String> {
+        let text = match text {
+            Some(Value::Bytes(b)) => b.to_vec(),
+            Some(t) => format!("{}", t).into_bytes(),
+            None => return Err("Expected text to write".to_owned()),
+        };
+        let verb = if append { "append to" } else { "write" };
+        //a dry run gives back the same thing a write that worked would, so scripts don't take another path
+        if !rt.side_effect(&format!("{} {}", verb, self.path.display())) {
+            return Ok(Value::EmBool(true));
+        }
+        let res = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .open(&self.path)
+            .and_then(|mut f| f.write_all(&text));
+        match res {
+            Ok(_) => Ok(Value::EmBool(true)),
+            Err(e) => Err(format!("Couldn't {} {}: {}", verb, self.path.display(), e)),
+        }
+    }
+}
+
+impl NativeObject for FileHandle {
+    fn type_name(&self) -> &str {
+        "file"
+    }
+
+    fn call_method(&self, rt: &mut Runtime, name: &str, args: Vec<Value>) -> Result<Value, String> {
+        match name {
+            //what's in a file is an input to the script, so reads go through the trace
+            "read" => rt
+                .trace
+                .fallible("file", || {
+                    fs::read_to_string(&self.path).map_err(|e| format!("Couldn't read {}: {}", self.path.display(), e))
+                })
+                .map(|s| Value::EmString(s.into())),
+            "read_bytes" => self.read_bytes(rt).map(|b| Value::Bytes(Arc::new(b))),
+            "write" => self.write(rt, args.first(), false),
+            "append" => self.write(rt, args.first(), true),
+            "exists" => {
+                let exists = rt.trace.input("file_exists", || Some(self.path.exists().to_string()));
+                Ok(Value::EmBool(exists.as_deref() == Some("true")))
+            }
+            "path" => Ok(Value::EmString(self.path.display().to_string().into())),
+            _ => Err(format!("file has no method {}", name)),
+        }
+    }
+
+    fn display(&self) -> String {
+        format!("<file {}>", self.path.display())
+    }
+}
@@ -0,0 +1,59 @@
+use super::Runtime;
+use std::fmt;
+use std::str::FromStr;
+
+///What happens when arithmetic comes out to something that isn't a finite number, like dividing by
+///zero or a result too big for a float. Every number is a float, so there's no integer overflow
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumericPolicy {
+    ///Infinity and NaN come out like they do anywhere else floats are used, this is the default
+    Ieee,
+    ///Results too big in either direction become the biggest finite number with the same sign.
+    ///NaN has no sign to go by, so making one is still an error
+    Saturate,
+    ///Any result that isn't a finite number is an error scripts can catch
+    Error,
+}
+
+impl NumericPolicy {
+    pub const NAMES: &'static [&'static str] = &["ieee", "saturate", "error"];
+}
+
+impl FromStr for NumericPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<NumericPolicy, String> {
+        match s {
+            "ieee" => Ok(NumericPolicy::Ieee),
+            "saturate" => Ok(NumericPolicy::Saturate),
+            "error" => Ok(NumericPolicy::Error),
+            other => Err(format!("{} isn't a numeric policy, expected one of {}", other, NumericPolicy::NAMES.join(", "))),
+        }
+    }
+}
+
+impl fmt::Display for NumericPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", NumericPolicy::NAMES[*self as usize])
+    }
+}
+
+impl Runtime {
+    ///Sets what dividing by zero and results too big for a float turn into, defaults to `NumericPolicy::Ieee`
+    pub fn set_numeric_policy(&mut self, policy: NumericPolicy) {
+        self.numeric = policy;
+    }
+
+    ///Applies the numeric policy to the result of `l op r`
+    pub(crate) fn arithmetic(&self, l: f64, op: char, r: f64, res: f64) -> Result<f64, String> {
+        if res.is_finite() || self.numeric == NumericPolicy::Ieee {
+            return Ok(res);
+        }
+        match self.numeric {
+            NumericPolicy::Saturate if !res.is_nan() => Ok(if res > 0.0 { f64::MAX } else { f64::MIN }),
+            _ if op == '/' && r == 0.0 => Err(format!("Can't divide {} by zero", l)),
+            _ if res.is_nan() => Err(format!("{} {} {} isn't a number", l, op, r)),
+            _ => Err(format!("{} {} {} is too big for a number", l, op, r)),
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use super::Runtime;
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+///How much a script can print, see `Runtime::set_output_limit`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputLimit {
+    Bytes(usize),
+    Lines(usize),
+}
+
+impl fmt::Display for OutputLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputLimit::Bytes(n) => write!(f, "{} bytes", n),
+            OutputLimit::Lines(n) => write!(f, "{} lines", n),
+        }
+    }
+}
+
+///How much has been printed against the limit
+pub(crate) struct Budget {
+    limit: OutputLimit,
+    used: usize,
+    //once something didn't fit nothing else does, even if it's small enough
+    over: bool,
+}
+
+impl Budget {
+    fn new(limit: OutputLimit) -> Budget {
+        Budget { limit, used: 0, over: false }
+    }
+
+    ///Splits off as much of `text` as still fits, and says whether any of it didn't
+    fn fit<'a>(&mut self, text: &'a str) -> (&'a str, bool) {
+        let end = match self.limit {
+            _ if self.over => 0,
+            OutputLimit::Bytes(max) => {
+                let mut end = text.len().min(max - self.used);
+                while !text.is_char_boundary(end) {
+                    end -= 1;
+                }
+                self.used += end;
+                end
+            }
+            //everything up to and including the last newline that's allowed
+            OutputLimit::Lines(max) => match text.match_indices('\n').nth(max - self.used) {
+                _ if self.used == max => 0,
+                Some((i, _)) => {
+                    self.used = max;
+                    i + 1
+                }
+                None => {
+                    self.used += text.matches('\n').count();
+                    text.len()
+                }
+            },
+        };
+        let cut = end < text.len();
+        self.over |= cut;
+        (&text[..end], cut)
+    }
+}
+
+///A sink that threads write to along with the runtime that started them, so their output ends up
+///in the same place
+#[derive(Clone)]
+pub(crate) struct Shared(Arc<Mutex<Box<dyn Write + Send + Sync>>>);
+
+impl Shared {
+    ///Swaps `sink` for a shared one that writes to what it was
+    fn wrap(sink: &mut Box<dyn Write + Send + Sync>) -> Shared {
+        let shared = Shared(Arc::new(Mutex::new(std::mem::replace(sink, Box::new(io::sink())))));
+        *sink = Box::new(shared.clone());
+        shared
+    }
+}
+
+impl Write for Shared {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).flush()
+    }
+}
+
+impl Runtime {
+    ///Stops scripts from printing more than `limit`, counting from now. Past it printing is an error,
+    ///or with `set_truncate_output` a marker gets printed once and everything after it is dropped.
+    ///`None` takes the limit away again
+    pub fn set_output_limit(&mut self, limit: Option<OutputLimit>) {
+        self.output_budget = limit.map(|l| Arc::new(Mutex::new(Budget::new(l))));
+    }
+
+    ///Whether going over the output limit cuts the output off instead of stopping the script,
+    ///defaults to false
+    pub fn set_truncate_output(&mut self, truncate: bool) {
+        self.truncate_output = truncate;
+    }
+
+    ///Writes script output to the output sink, as much of it as the output limit allows
+    pub(crate) fn print(&mut self, text: &str) -> Result<(), String> {
+        let (fits, over, first, limit) = match &self.output_budget {
+            Some(budget) => {
+                let mut budget = budget.lock().unwrap_or_else(|e| e.into_inner());
+                let was_over = budget.over;
+                let (fits, over) = budget.fit(text);
+                (fits, over, !was_over, Some(budget.limit))
+            }
+            None => (text, false, false, None),
+        };
+        self.stdout.write_all(fits.as_bytes()).unwrap_or(());
+        let res = match limit {
+            Some(limit) if over && !self.truncate_output => Err(format!("Went over the output limit of {}", limit)),
+            Some(limit) if over && first => {
+                let marker = format!("\n[output truncated, the limit is {}]\n", limit);
+                self.stdout.write_all(marker.as_bytes()).unwrap_or(());
+                Ok(())
+            }
+            _ => Ok(()),
+        };
+        self.stdout.flush().unwrap_or(());
+        res
+    }
+}
+
+///What a thread needs to write to the same places as the runtime that started it, with its output
+///counting against the same limit
+pub(crate) struct ThreadOutput {
+    stdout: Shared,
+    stderr: Shared,
+    audit: Shared,
+    log: Option<Shared>,
+    budget: Option<Arc<Mutex<Budget>>>,
+    truncate: bool,
+}
+
+//a sink that's already been shared gets handed out again instead of being wrapped a second time
+fn share(shared: &mut Option<Shared>, sink: &mut Box<dyn Write + Send + Sync>) -> Shared {
+    shared.get_or_insert_with(|| Shared::wrap(sink)).clone()
+}
+
+impl Runtime {
+    pub(crate) fn thread_output(&mut self) -> ThreadOutput {
+        let log = match &mut self.log_sink {
+            Some(sink) => Some(share(&mut self.shared_log, sink)),
+            None => None,
+        };
+        ThreadOutput {
+            stdout: share(&mut self.shared_stdout, &mut self.stdout),
+            stderr: share(&mut self.shared_stderr, &mut self.stderr),
+            audit: share(&mut self.shared_audit, &mut self.audit),
+            log,
+            budget: self.output_budget.clone(),
+            truncate: self.truncate_output,
+        }
+    }
+
+    ///Used on a thread's runtime with what `thread_output` gave back
+    pub(crate) fn use_output(&mut self, output: ThreadOutput) {
+        self.stdout = Box::new(output.stdout.clone());
+        self.stderr = Box::new(output.stderr.clone());
+        self.audit = Box::new(output.audit.clone());
+        self.log_sink = output.log.clone().map(|l| Box::new(l) as Box<dyn Write + Send + Sync>);
+        //a thread that starts its own threads hands them the same sinks instead of wrapping them again
+        self.shared_stdout = Some(output.stdout);
+        self.shared_stderr = Some(output.stderr);
+        self.shared_audit = Some(output.audit);
+        self.shared_log = output.log;
+        self.output_budget = output.budget;
+        self.truncate_output = output.truncate;
+    }
+}
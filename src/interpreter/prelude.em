@@ -0,0 +1,109 @@
+// Builtins written in EmeraldScript itself. Every function here gets loaded into every runtime
+// and can be called just like the ones written in rust. Arrays are values, so helpers that
+// build one give back a new array instead of changing the one they were given
+
+// The items that f gives back something true for
+fn filter(items, f) {
+    out = [];
+    for (i = 0; i < len(items); i++) {
+        if f(items[i]) {
+            out.push(items[i]);
+        }
+    }
+    return out;
+}
+
+// Combines every item into one value, starting from start
+fn reduce(items, f, start) {
+    acc = start;
+    for (i = 0; i < len(items); i++) {
+        acc = f(acc, items[i]);
+    }
+    return acc;
+}
+
+fn any(items, f) {
+    for (i = 0; i < len(items); i++) {
+        if f(items[i]) {
+            return true;
+        }
+    }
+    return false;
+}
+
+fn all(items, f) {
+    for (i = 0; i < len(items); i++) {
+        if !f(items[i]) {
+            return false;
+        }
+    }
+    return true;
+}
+
+// The first item that f gives back something true for, or null if there isn't one
+fn find(items, f) {
+    for (i = 0; i < len(items); i++) {
+        if f(items[i]) {
+            return items[i];
+        }
+    }
+    return null;
+}
+
+fn reverse(items) {
+    out = [];
+    for (i = len(items) - 1; i >= 0; i--) {
+        out.push(items[i]);
+    }
+    return out;
+}
+
+fn max(items) {
+    best = items[0];
+    for (i = 1; i < len(items); i++) {
+        if items[i] > best {
+            best = items[i];
+        }
+    }
+    return best;
+}
+
+fn min(items) {
+    best = items[0];
+    for (i = 1; i < len(items); i++) {
+        if items[i] < best {
+            best = items[i];
+        }
+    }
+    return best;
+}
+
+// An array of n copies of value
+fn fill(n, value) {
+    out = [];
+    for (i = 0; i < n; i++) {
+        out.push(value);
+    }
+    return out;
+}
+
+// A rows by cols array of arrays, every cell starting as value
+fn grid(rows, cols, value) {
+    return fill(rows, fill(cols, value));
+}
+
+// Swaps the rows and columns of an array of arrays
+fn transpose(rows) {
+    out = [];
+    if len(rows) == 0 {
+        return out;
+    }
+    for (c = 0; c < len(rows[0]); c++) {
+        col = [];
+        for (r = 0; r < len(rows); r++) {
+            col.push(rows[r][c]);
+        }
+        out.push(col);
+    }
+    return out;
+}
@@ -0,0 +1,89 @@
+use super::Runtime;
+use std::cell::Cell;
+
+///Which builtins that reach outside the script a runtime has. Scripts can still print and read
+///input, but those go through the streams the host gives the runtime
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+    ///Nothing that touches the disk, the network, or other programs
+    Pure,
+    ///Files, but no network or other programs
+    FileIo,
+    ///The network, but no files or other programs
+    Network,
+    ///Everything, which is what `Runtime::new` gives
+    Full,
+}
+
+const FILES: &[&str] = &["open", "read_file_bytes", "write_file_bytes"];
+//nothing talks to the network yet, builtins that do go here
+const NETWORK: &[&str] = &[];
+const PROCESSES: &[&str] = &["exec", "shell"];
+
+impl Profile {
+    fn allows_files(self) -> bool {
+        matches!(self, Profile::FileIo | Profile::Full)
+    }
+
+    fn allows_network(self) -> bool {
+        matches!(self, Profile::Network | Profile::Full)
+    }
+
+    pub(crate) fn allows_processes(self) -> bool {
+        self == Profile::Full
+    }
+}
+
+thread_local! {
+    //the profile of the runtime walking a tree on this thread and whether it can run other programs,
+    //so runtimes made to call `~display` don't get more than that. Nothing walking means neither
+    static ACCESS: Cell<(Profile, bool)> = const { Cell::new((Profile::Pure, false)) };
+}
+
+///Lives for as long as a runtime is working on a node, and puts back whatever was walking before
+pub(crate) struct AccessGuard((Profile, bool));
+
+impl Drop for AccessGuard {
+    fn drop(&mut self) {
+        ACCESS.with(|a| a.set(self.0));
+    }
+}
+
+impl Runtime {
+    ///Creates a runtime with only the builtins `profile` allows. They're never registered at all, so
+    ///nothing a script does can get them back, and `set_allow_exec(true)` only works with `Profile::Full`.
+    ///Threads and `~display` methods run in runtimes with the same profile
+    pub fn with_profile(profile: Profile) -> Runtime {
+        let mut rt = Runtime::new();
+        rt.profile = profile;
+        rt.allow_exec = profile.allows_processes();
+        let groups = [
+            (FILES, profile.allows_files()),
+            (NETWORK, profile.allows_network()),
+            (PROCESSES, profile.allows_processes()),
+        ];
+        for (names, _) in groups.iter().filter(|(_, allowed)| !allowed) {
+            for name in names.iter() {
+                rt.functions.remove(*name);
+            }
+        }
+        rt
+    }
+
+    pub fn profile(&self) -> Profile {
+        self.profile
+    }
+
+    pub(crate) fn enter(&self) -> AccessGuard {
+        AccessGuard(ACCESS.with(|a| a.replace((self.profile, self.allow_exec))))
+    }
+
+    ///A runtime that can't do anything the one walking a tree on this thread can't, for running
+    ///code that has no runtime of its own to go with it, like a `~display` method
+    pub(crate) fn confined() -> Runtime {
+        let (profile, exec) = ACCESS.with(Cell::get);
+        let mut rt = Runtime::with_profile(profile);
+        rt.set_allow_exec(exec);
+        rt
+    }
+}
@@ -0,0 +1,362 @@
+use super::editor::{Completions, LineEditor};
+use super::{builtins, eval_tree, Runtime, Signature, StackFrame, Value};
+use crate::lexer::{self, Expression};
+use crate::parser::{self, ExprNode};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+const DEFAULT_SESSION: &str = "session.em";
+
+const HELP: &str = ":env          list the global variables
+:heap         list the functions and classes
+:type <expr>  show the type of an expression
+:load <file>  run a file in this session
+:watch [expr] print expr whenever it changes, or list what's watched
+:unwatch      stop watching everything
+:reset        forget everything defined so far, then run the rc file again
+:save [file]  write the session out as a script, session.em by default
+:load-session [file]
+              start over from a script made by :save
+:quit         leave the repl";
+
+///What `repl_set(name, value)` can change, usually from the rc file
+#[derive(Debug, Clone)]
+struct Options {
+    prompt: String,
+    //whether the value of each line gets shown, print still works either way
+    auto_print: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            prompt: "> ".to_owned(),
+            auto_print: true,
+        }
+    }
+}
+
+///An interactive session. Lines that start with `:` are commands for the REPL itself, everything
+///else gets run as code in the same global frame
+pub struct Repl {
+    runtime: Runtime,
+    frame: StackFrame,
+    options: Arc<Mutex<Options>>,
+    //run again whenever the session starts over
+    rc: Option<PathBuf>,
+    //all the code run so far, one piece after the other, so errors in functions from earlier lines
+    //can point at them too
+    typed: String,
+}
+
+impl Default for Repl {
+    fn default() -> Self {
+        Repl::new()
+    }
+}
+
+impl Repl {
+    pub fn new() -> Repl {
+        let options = Arc::new(Mutex::new(Options::default()));
+        let mut runtime = Runtime::new();
+        let set = options.clone();
+        runtime.register_fn(
+            "repl_set",
+            Signature::new(2, Some(2), &["string"]),
+            Arc::new(move |rt, args| {
+                let mut options = set.lock().unwrap_or_else(|e| e.into_inner());
+                match (format!("{}", args[0]).as_str(), &args[1]) {
+                    ("prompt", val) => options.prompt = format!("{}", val),
+                    ("auto_print", val) => options.auto_print = val.is_truthy(),
+                    (other, _) => rt.raise(format!("{} isn't a repl option, try prompt or auto_print", other)),
+                }
+                Value::Null
+            }),
+        );
+        Repl {
+            runtime,
+            frame: StackFrame::new(),
+            options,
+            rc: None,
+            typed: String::new(),
+        }
+    }
+
+    ///`~/.emeraldscript/replrc.em`, unless `EM_REPLRC` points somewhere else
+    pub fn default_rc_path() -> Option<PathBuf> {
+        if let Some(path) = env::var_os("EM_REPLRC") {
+            return Some(path.into());
+        }
+        let home = env::var_os("HOME").or_else(|| env::var_os("USERPROFILE"))?;
+        Some(Path::new(&home).join(".emeraldscript").join("replrc.em"))
+    }
+
+    ///Runs a file of helpers and `repl_set` calls in the session, and again each time it's `:reset`
+    pub fn load_rc(&mut self, path: &Path) -> Result<(), String> {
+        let data = fs::read_to_string(path).map_err(|e| format!("Couldn't read {}: {}", path.display(), e))?;
+        self.rc = Some(path.to_owned());
+        self.evaluate(&data).map(|_| ()).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    ///What gets shown before each line of input
+    pub fn prompt(&self) -> String {
+        self.options.lock().unwrap_or_else(|e| e.into_inner()).prompt.clone()
+    }
+
+    ///What the name at the end of `line` could be. After a `.` that's the properties and methods of
+    ///the object before it, as long as that's a plain chain of names like `a.b.`, since anything
+    ///else would have to be run to find out. Otherwise it's globals, functions, classes and builtins
+    pub fn complete(&self, line: &str) -> Completions {
+        let is_name = |c: char| c.is_alphanumeric() || c == '_';
+        let start = line.trim_end_matches(is_name).len();
+        let word = &line[start..];
+        let mut names: Vec<String> = match line[..start].strip_suffix('.') {
+            Some(target) => {
+                let path = &target[target.trim_end_matches(|c: char| is_name(c) || c == '.').len()..];
+                self.members(path)
+            }
+            None => self
+                .frame
+                .stack
+                .keys()
+                .chain(self.runtime.heap.keys())
+                .cloned()
+                .chain(self.runtime.builtin_names())
+                .collect(),
+        };
+        names.retain(|n| n.starts_with(word) && !n.starts_with('~'));
+        names.sort();
+        names.dedup();
+        (start, names)
+    }
+
+    ///The properties and methods of whatever `a.b.c` is, if it's an object or class
+    fn members(&self, path: &str) -> Vec<String> {
+        let mut parts = path.split('.');
+        let first = parts.next().unwrap_or_default();
+        let mut val = match self.frame.stack.get(first).or_else(|| self.runtime.heap.get(first)) {
+            Some(v) => v.clone(),
+            None => return vec![],
+        };
+        for part in parts {
+            val = match &val {
+                Value::Object(o) | Value::Class(o) => match o.get_prop(part) {
+                    Some(v) => v.clone(),
+                    None => return vec![],
+                },
+                _ => return vec![],
+            };
+        }
+        match &val {
+            Value::Object(o) | Value::Class(o) => {
+                let mut names: Vec<String> = o.members.keys().cloned().collect();
+                if let Some(class) = self.runtime.current_class(o) {
+                    names.extend(class.members.keys().cloned());
+                }
+                names
+            }
+            _ => vec![],
+        }
+    }
+
+    ///Forgets everything, then runs the rc file again if there is one
+    fn restart(&mut self) -> Result<(), String> {
+        let rc = self.rc.take();
+        *self = Repl::new();
+        match rc {
+            Some(path) => self.load_rc(&path),
+            None => Ok(()),
+        }
+    }
+
+    ///Reads lines from stdin until it runs out or gets `:quit`. At a terminal, tab completes names
+    ///and the arrow keys go back through earlier lines
+    pub fn start(&mut self) {
+        let mut editor = LineEditor::new();
+        while let Ok(Some(line)) = editor.read_line(&self.prompt(), |line| self.complete(line)) {
+            if matches!(line.trim(), ":quit" | ":q") {
+                break;
+            }
+            self.runtime.at = None;
+            match self.eval(&line) {
+                Ok(Some(out)) => println!("{}", out),
+                Ok(None) => {}
+                //exit ends the session like :quit
+                Err(_) if self.runtime.exit_code().is_some() => break,
+                Err(e) => {
+                    let diag = self.runtime.diagnose(&e, None).suggest(self.frame.stack.keys().map(String::as_str));
+                    self.runtime.report(&diag.to_string());
+                }
+            }
+        }
+    }
+
+    ///Runs one line of input and gives back what should be shown for it, if anything
+    pub fn eval(&mut self, line: &str) -> Result<Option<String>, String> {
+        let line = line.trim();
+        let command = match line.strip_prefix(':') {
+            Some(c) => c,
+            None => {
+                let val = self.evaluate(line)?;
+                let auto_print = self.options.lock().unwrap_or_else(|e| e.into_inner()).auto_print;
+                return Ok(match val {
+                    Value::Null => None,
+                    _ if !auto_print => None,
+                    val => Some(format!("{}", val)),
+                });
+            }
+        };
+        let (name, arg) = match command.find(char::is_whitespace) {
+            Some(i) => (&command[..i], command[i..].trim()),
+            None => (command, ""),
+        };
+
+        match name {
+            "env" => {
+                let mut vars: Vec<String> = self.frame.stack.iter().map(|(k, v)| format!("{} = {}", k, v)).collect();
+                vars.sort();
+                Ok(Some(vars.join("\n")))
+            }
+            "heap" => {
+                let mut defs: Vec<String> = self.runtime.heap.iter().map(|(k, v)| describe(k, v)).collect();
+                defs.sort();
+                Ok(Some(defs.join("\n")))
+            }
+            "type" => {
+                let val = self.evaluate(arg)?;
+                Ok(Some(builtins::type_name(&val)))
+            }
+            "load" => {
+                let data = fs::read_to_string(arg).map_err(|e| format!("Couldn't read {}: {}", arg, e))?;
+                self.evaluate(&data)?;
+                Ok(None)
+            }
+            "save" => {
+                let path = if arg.is_empty() { DEFAULT_SESSION } else { arg };
+                fs::write(path, self.session_source()).map_err(|e| format!("Couldn't write {}: {}", path, e))?;
+                Ok(Some(format!("Saved session to {}", path)))
+            }
+            "load-session" => {
+                let path = if arg.is_empty() { DEFAULT_SESSION } else { arg };
+                let data = fs::read_to_string(path).map_err(|e| format!("Couldn't read {}: {}", path, e))?;
+                self.restart()?;
+                self.evaluate(&data)?;
+                Ok(None)
+            }
+            "watch" if arg.is_empty() => Ok(Some(self.runtime.watches().join("\n"))),
+            "watch" => {
+                self.runtime.add_watch(arg)?;
+                Ok(None)
+            }
+            "unwatch" => {
+                self.runtime.clear_watches();
+                Ok(None)
+            }
+            "reset" => {
+                self.restart()?;
+                Ok(None)
+            }
+            "help" => Ok(Some(HELP.to_owned())),
+            _ => Err(format!("Unknown command :{}, try :help", name)),
+        }
+    }
+
+    ///Writes every class, function and global out as code that rebuilds them when it's run.
+    ///Values that can't be written as a literal (objects and natives) are left as a comment
+    fn session_source(&self) -> String {
+        let mut heap: Vec<(&String, Value)> = self.runtime.heap.iter().map(|(k, v)| (k, v.clone())).collect();
+        //classes go first so nothing below them has to worry about order
+        heap.sort_by_key(|(k, v)| (!matches!(v, Value::Class(_)), (*k).clone()));
+        let mut vars: Vec<(&String, &Value)> = self.frame.stack.iter().collect();
+        vars.sort_by_key(|(k, _)| (*k).clone());
+
+        let mut out = String::from("//saved repl session\n");
+        for (name, val) in heap {
+            let node = match val {
+                Value::Function(n, params, body) => ExprNode::Func(Box::new(n), params.iter().map(param_node).collect(), Box::new(body)),
+                Value::Class(c) => {
+                    let methods = c
+                        .members
+                        .iter()
+                        .filter_map(|(_, m)| match &**m {
+                            Value::Function(n, params, body) => {
+                                Some(ExprNode::Func(Box::new(n.clone()), params.iter().map(param_node).collect(), Box::new(body.clone())))
+                            }
+                            _ => None,
+                        })
+                        .collect();
+                    ExprNode::Class(Box::new(Expression::Ident(name.clone())), Box::new(ExprNode::Block(methods)))
+                }
+                _ => continue,
+            };
+            out.push_str(&parser::to_source(&node));
+            out.push('\n');
+        }
+        for (name, val) in vars {
+            match self.literal(val) {
+                Some(node) => out.push_str(&format!("{} = {};\n", name, parser::to_source(&node))),
+                None => out.push_str(&format!("//couldn't save {}, {} values can't be written out\n", name, builtins::type_name(val))),
+            }
+        }
+        out
+    }
+
+    ///The literal that evaluates back to `val`, if there is one
+    fn literal(&self, val: &Value) -> Option<ExprNode> {
+        Some(match val {
+            Value::Null => ExprNode::NullLiteral,
+            Value::Float(n) if !n.is_finite() => return None,
+            Value::Float(n) if *n < 0.0 => ExprNode::Unary(Box::new(Expression::Operator('-')), Box::new(ExprNode::NumLiteral(Box::new(-n)))),
+            Value::Float(n) => ExprNode::NumLiteral(Box::new(*n)),
+            //there's no escaping quotes inside a string literal
+            Value::EmString(s) if s.contains('"') => return None,
+            Value::EmString(s) => ExprNode::StrLiteral(Box::new(s.to_string())),
+            Value::Bytes(b) => ExprNode::BytesLiteral(Box::new(b.to_vec())),
+            Value::EmBool(b) => ExprNode::BoolLiteral(*b),
+            Value::EmArray(items) => ExprNode::Array(items.iter().map(|i| self.literal(i)).collect::<Option<Vec<ExprNode>>>()?),
+            Value::Tuple(items) => ExprNode::Tuple(items.iter().map(|i| self.literal(i)).collect::<Option<Vec<ExprNode>>>()?),
+            //functions and classes are saved by name, so they have to still be on the heap
+            Value::Function(Expression::Ident(n), _, _) if self.runtime.heap.contains_key(n) => ExprNode::Name(Box::new(n.clone())),
+            Value::Class(c) => match c.get_prop("~name") {
+                Some(Value::EmString(n)) if self.runtime.heap.contains_key(&**n) => ExprNode::Name(Box::new(n.to_string())),
+                _ => return None,
+            },
+            _ => return None,
+        })
+    }
+
+    fn evaluate(&mut self, code: &str) -> Result<Value, String> {
+        //the lexer only finishes a token when something comes after it
+        let code = format!("{}\n", code);
+        let (tokens, mut spans) = lexer::run_spanned(&code);
+        for span in spans.iter_mut() {
+            span.start += self.typed.len();
+            span.end += self.typed.len();
+        }
+        self.typed.push_str(&code);
+        self.runtime.set_source("<repl>", &self.typed);
+        let tree = parser::parse_spanned(&tokens, &spans).map_err(|(e, at)| {
+            self.runtime.at = at;
+            e
+        })?;
+        eval_tree(&tree, &mut self.runtime, &mut self.frame)
+    }
+}
+
+fn param_node(param: &Value) -> ExprNode {
+    ExprNode::Name(Box::new(format!("{}", param)))
+}
+
+///One line for `:heap`, like `fn add(a, b)` or `class Point`
+fn describe(name: &str, val: &Value) -> String {
+    match val {
+        Value::Function(_, params, _) => {
+            let params: Vec<String> = params.iter().map(|p| format!("{}", p)).collect();
+            format!("fn {}({})", name, params.join(", "))
+        }
+        Value::Class(_) => format!("class {}", name),
+        other => format!("{} = {}", name, other),
+    }
+}
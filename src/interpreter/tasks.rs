@@ -0,0 +1,245 @@
+use super::generator::{Generator, Step};
+use super::native::NativeObject;
+use super::{Runtime, Value};
+use std::any::Any;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+enum State {
+    Ready,
+    Sleeping(Instant),
+    Waiting(Task),
+    Done(Result<Value, String>),
+}
+
+struct TaskData {
+    //taken out while the task is running, so a task can't be stepped from inside itself
+    body: Option<Generator>,
+    state: State,
+    awaited: bool,
+}
+
+///Something running on the scheduler. Calling an async function, `spawn`, and `sleep_async` all give
+///one back, and `await` waits for it to finish
+#[derive(Clone)]
+pub struct Task(Arc<Mutex<TaskData>>);
+
+impl Task {
+    fn new(body: Option<Generator>, state: State) -> Task {
+        Task(Arc::new(Mutex::new(TaskData { body, state, awaited: false })))
+    }
+
+    //a task only runs on the thread its runtime is on, so the lock is never held by anything else
+    fn data(&self) -> MutexGuard<'_, TaskData> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn result(&self) -> Option<Result<Value, String>> {
+        match &self.data().state {
+            State::Done(res) => Some(res.clone()),
+            _ => None,
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        matches!(self.data().state, State::Done(_))
+    }
+
+    fn from_value(val: &Value) -> Option<Task> {
+        match val {
+            Value::Native(n) => n.as_any()?.downcast_ref::<Task>().cloned(),
+            _ => None,
+        }
+    }
+
+    fn fire_if_due(&self, now: Instant) {
+        let mut data = self.data();
+        if matches!(data.state, State::Sleeping(at) if at <= now) {
+            data.state = State::Done(Ok(Value::Null));
+        }
+    }
+
+    ///Whether the scheduler can step this task right now
+    fn runnable(&self) -> bool {
+        let (running, waiting) = {
+            let data = self.data();
+            match &data.state {
+                State::Ready => (true, None),
+                State::Waiting(other) => (true, Some(other.clone())),
+                _ => (false, None),
+            }
+        };
+        //the lock has to be let go first, a task waiting on itself would lock it twice
+        running && self.data().body.is_some() && waiting.is_none_or(|t| t.is_done())
+    }
+}
+
+impl NativeObject for Task {
+    fn type_name(&self) -> &str {
+        "task"
+    }
+
+    fn call_method(&self, _rt: &mut Runtime, name: &str, _args: Vec<Value>) -> Result<Value, String> {
+        match name {
+            "done" => Ok(Value::EmBool(self.is_done())),
+            _ => Err(format!("task has no method {}", name)),
+        }
+    }
+
+    fn as_any(&self) -> Option<&dyn Any> {
+        Some(self)
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct Scheduler {
+    //every task that hasn't finished, in the order they get a turn
+    queue: VecDeque<Task>,
+    failed: Vec<Task>,
+}
+
+impl Runtime {
+    ///Starts a task for an async function's body. It doesn't run until something waits
+    pub(crate) fn spawn_task(&mut self, body: Generator) -> Value {
+        self.schedule(Task::new(Some(body), State::Ready))
+    }
+
+    ///A task that finishes after `ms` milliseconds, for `sleep_async`
+    pub(crate) fn timer(&mut self, ms: f64) -> Result<Value, String> {
+        let at = Duration::try_from_secs_f64(ms.max(0.0) / 1000.0)
+            .ok()
+            .and_then(|d| Instant::now().checked_add(d))
+            .ok_or_else(|| format!("Can't sleep for {} milliseconds", ms))?;
+        Ok(self.schedule(Task::new(None, State::Sleeping(at))))
+    }
+
+    ///Wraps a value that isn't a task yet in one that's already finished
+    pub(crate) fn as_task(&mut self, val: Value) -> Value {
+        match Task::from_value(&val) {
+            Some(_) => val,
+            None => Value::Native(Arc::new(Task::new(None, State::Done(Ok(val))))),
+        }
+    }
+
+    fn schedule(&mut self, task: Task) -> Value {
+        self.tasks.queue.push_back(task.clone());
+        Value::Native(Arc::new(task))
+    }
+
+    ///Runs other tasks until the one given finishes and gives back what it came out to. Anything that
+    ///isn't a task is already finished, so it comes straight back
+    pub(crate) fn wait_for(&mut self, val: Value) -> Result<Value, String> {
+        let task = match Task::from_value(&val) {
+            Some(t) => t,
+            None => return Ok(val),
+        };
+        task.data().awaited = true;
+        self.run_until(Some(&task))?;
+        task.result().unwrap_or(Ok(Value::Null))
+    }
+
+    ///Runs every task until they've all finished. Scripts run this after `main`, anything else
+    ///running scripts that use tasks has to call it to give them all a chance to finish
+    pub fn run_tasks(&mut self) -> Result<(), String> {
+        self.run_until(None)?;
+        //nothing waited for these, so their errors would get lost otherwise
+        for task in std::mem::take(&mut self.tasks.failed) {
+            let data = task.data();
+            if let (false, State::Done(Err(e))) = (data.awaited, &data.state) {
+                return Err(e.clone());
+            }
+        }
+        Ok(())
+    }
+
+    ///Steps tasks until the one given finishes, or until they all have
+    fn run_until(&mut self, target: Option<&Task>) -> Result<(), String> {
+        let done = |queue: &VecDeque<Task>| target.map_or(queue.is_empty(), Task::is_done);
+        while !done(&self.tasks.queue) {
+            //a task called exit, none of the others get to finish
+            if let Some(code) = self.exit_code {
+                return Err(self.exit(code));
+            }
+            let now = Instant::now();
+            self.tasks.queue.iter().for_each(|t| t.fire_if_due(now));
+            let mut next = None;
+            //finished tasks drop out, and the one that gets a turn goes to the back of the line
+            let mut i = 0;
+            while i < self.tasks.queue.len() {
+                let task = self.tasks.queue[i].clone();
+                if task.runnable() {
+                    self.tasks.queue.remove(i);
+                    self.tasks.queue.push_back(task.clone());
+                    next = Some(task);
+                    break;
+                }
+                if task.is_done() {
+                    self.tasks.queue.remove(i);
+                    if let Some(Err(_)) = task.result() {
+                        self.tasks.failed.push(task);
+                    }
+                } else {
+                    i += 1;
+                }
+            }
+            match next {
+                Some(task) => self.step(&task),
+                None => {
+                    let wake = self.tasks.queue.iter().filter_map(|t| match t.data().state {
+                        State::Sleeping(at) => Some(at),
+                        _ => None,
+                    });
+                    match wake.min() {
+                        Some(at) => std::thread::sleep(at.saturating_duration_since(Instant::now())),
+                        None if done(&self.tasks.queue) => break,
+                        None => return Err("Every task left is waiting on another one, so none of them can finish".to_owned()),
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    ///Runs a task up to its next await
+    fn step(&mut self, task: &Task) {
+        let waiting = match &task.data().state {
+            State::Waiting(other) => Some(other.clone()),
+            _ => None,
+        };
+        let waited = waiting.and_then(|t| t.result());
+        let body = task.data().body.take();
+        let mut body = match body {
+            Some(b) => b,
+            None => return,
+        };
+        let state = match waited {
+            Some(Err(e)) => State::Done(Err(e)),
+            other => {
+                if let Some(Ok(v)) = other {
+                    body.give(v);
+                }
+                loop {
+                    match body.resume(self) {
+                        Ok(Step::Await(v)) => match Task::from_value(&v) {
+                            Some(other) => {
+                                other.data().awaited = true;
+                                break State::Waiting(other);
+                            }
+                            None => body.give(v),
+                        },
+                        //async bodies don't yield, but a turn is all a yield could mean here
+                        Ok(Step::Yield(_)) => break State::Ready,
+                        Ok(Step::Done(v)) => break State::Done(Ok(v)),
+                        Err(e) => break State::Done(Err(e)),
+                    }
+                }
+            }
+        };
+        let mut data = task.data();
+        if !matches!(state, State::Done(_)) {
+            data.body = Some(body);
+        }
+        data.state = state;
+    }
+}
@@ -0,0 +1,29 @@
+fn half(n) {
+    if n == 0 {
+        return [n][1];
+    }
+    return n / 2;
+}
+
+fn test_passes() {
+    assert(half(4) == 2, "half of 4");
+    assert_eq(half(10), 5);
+    assert_throws(fn_that_throws);
+}
+
+fn test_fails() {
+    assert_eq(half(3), 1);
+    println("not reached");
+}
+
+fn test_not_throwing() {
+    assert_throws(fn_that_works);
+}
+
+fn fn_that_throws() {
+    half(0);
+}
+
+fn fn_that_works() {
+    half(1);
+}
@@ -0,0 +1,16 @@
+//every instance should get its own data, and none of it should end up on the class
+class Counter {
+    fn ~init(self, start) {
+        self.count = start;
+    }
+
+    fn get(self) {
+        return self.count;
+    }
+}
+
+a = new Counter(1);
+b = new Counter(5);
+a.count = 2;
+a_count = a.get();
+b_count = b.get();
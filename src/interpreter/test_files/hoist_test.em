@@ -0,0 +1,19 @@
+//functions and classes can be used before the place they're defined
+x = double(4);
+p = new Pair(1, 2);
+sum = p.total();
+
+fn double(n) {
+    return n * 2;
+}
+
+class Pair {
+    fn ~init(self, a, b) {
+        self.a = a;
+        self.b = b;
+    }
+
+    fn total(self) {
+        return self.a + self.b;
+    }
+}
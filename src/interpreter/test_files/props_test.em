@@ -0,0 +1,24 @@
+//objects can gain and lose properties after they're created
+class Pt {
+    fn ~init(self, x) {
+        self.x = x;
+    }
+}
+
+p = new Pt(1);
+p.y = 2;
+removed = p.y;
+delete p.y;
+
+//property names can be computed too
+key = "x";
+from_bracket = p[key];
+p["z"] = 3;
+
+suffix = "z";
+from_expr = p["" + suffix];
+names = props(p);
+has_x = has_prop(p, "x");
+has_method = has_prop(p, "~init");
+has_w = has_prop(p, "w");
+without_x = remove_prop(p, "x");
@@ -0,0 +1,46 @@
+//plain, mutual, and method recursion, with early returns from inside of if blocks
+fn fact(n) {
+    if n <= 1 {
+        return 1;
+    }
+    return n * fact(n - 1);
+}
+
+fn is_even(n) {
+    if n == 0 {
+        return true;
+    }
+    return is_odd(n - 1);
+}
+
+class Tree {
+    fn ~init(self, depth) {
+        self.depth = depth;
+    }
+
+    fn count(self, n) {
+        if n == 0 {
+            return 1;
+        }
+        below = self.count(n - 1);
+        return below + 1;
+    }
+}
+
+fact_res = fact(5);
+even_res = is_even(6);
+odd_res = is_even(7);
+t = new Tree(3);
+count_res = t.count(4);
+
+//defined after it's used by is_even
+fn is_odd(n) {
+    if n == 0 {
+        return false;
+    }
+    return is_even(n - 1);
+}
+
+fn forever(n) {
+    return forever(n + 1);
+}
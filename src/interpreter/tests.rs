@@ -8,9 +8,9 @@ use std::collections::HashMap;
 #[test]
 fn generate_literals() {
     let dummy_string = ExprNode::StrLiteral(Box::new("Test".to_owned()));
-    let dummy_number = ExprNode::NumLiteral(Box::new(69.0 as f32));
+    let dummy_number = ExprNode::NumLiteral(Box::new(69.0 as f64));
 
-    let expected_string = Value::EmString("Test".to_owned());
+    let expected_string = Value::EmString("Test".into());
     let expected_number = Value::Float(69.0);
 
     let mut r = Runtime::new();
@@ -36,14 +36,14 @@ fn assign_vars() {
         Box::new(ExprNode::StrLiteral(Box::new("this is a test".to_owned()))),
     );
 
-    let expected = Value::EmString("this is a test".to_owned());
+    let expected = Value::EmString("this is a test".into());
 
     let mut r = Runtime::new();
     let mut stack = StackFrame {
         stack: HashMap::new(),
     };
     r.walk_tree(&op, &mut stack).expect("Unable to walk tree");
-    assert_eq!(stack.get_var(&"test"), &expected);
+    assert_eq!(stack.get_var("test"), &expected);
 }
 
 #[test]
@@ -52,7 +52,7 @@ fn looping() {
     let condition = ExprNode::Operation(
         Box::new(Expression::BoolOp("<".to_owned())),
         Box::new(ExprNode::Name(Box::new("i".to_owned()))),
-        Box::new(ExprNode::NumLiteral(Box::new(10 as f32))),
+        Box::new(ExprNode::NumLiteral(Box::new(10 as f64))),
     );
     let block = ExprNode::Block(vec![ExprNode::Operation(
         Box::new(Expression::Equal),
@@ -60,13 +60,13 @@ fn looping() {
         Box::new(ExprNode::Operation(
             Box::new(Expression::Operator('+')),
             Box::new(ExprNode::Name(Box::new("i".to_owned()))),
-            Box::new(ExprNode::NumLiteral(Box::new(1.0 as f32))),
+            Box::new(ExprNode::NumLiteral(Box::new(1.0 as f64))),
         )),
     )]);
     // let loop_test = ExprNode::Loop(Box::new(ty), Box::new(condition), Box::new(block));
     let mut r = Runtime::new();
     let mut stack = StackFrame::new();
-    stack.set_var(String::from("i"), Value::Float(0.0 as f32));
+    stack.set_var(String::from("i"), Value::Float(0.0 as f64));
     r.do_loop(&ty, &condition, &block, &mut stack)
         .expect("Error executing loop");
 
@@ -100,8 +100,8 @@ fn if_elif_else() {
         }
 
         if let Value::EmArray(v) = frame.get_var("res") {
-            for val in v {
-                match **val {
+            for val in v.iter() {
+                match *val {
                     Value::EmBool(b) => return assert!(b),
                     _ => {}
                 }
@@ -111,3 +111,1780 @@ fn if_elif_else() {
         assert!(false);
     }
 }
+
+//a writer we can still read from after handing it to the runtime
+#[derive(Clone, Default)]
+struct Sink(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+impl Sink {
+    fn contents(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+    }
+}
+
+impl std::io::Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn dry_run_skips_side_effects() {
+    let sink = Sink::default();
+    let mut r = Runtime::new();
+    assert!(r.side_effect("write to out.txt"));
+
+    r.set_dry_run(true);
+    r.set_audit_sink(Box::new(sink.clone()));
+    assert!(!r.side_effect("write to out.txt"));
+    assert_eq!(sink.contents(), "[dry-run] write to out.txt\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn exec_and_shell() {
+    let mut runtime = Runtime::new();
+    let mut frame = StackFrame::new();
+    let script = "out = exec(\"echo\", [\"hi\"]); sh = shell(\"exit 3\");";
+    repl_run(parser::parse(lexer::run(script)).unwrap(), &mut runtime, &mut frame).unwrap();
+
+    if let Value::Object(o) = frame.get_var("out") {
+        assert_eq!(o.get_prop("stdout"), Some(&Value::EmString("hi\n".into())));
+        assert_eq!(o.get_prop("status"), Some(&Value::Float(0.0)));
+    } else {
+        panic!("exec didn't return an object");
+    }
+    if let Value::Object(o) = frame.get_var("sh") {
+        assert_eq!(o.get_prop("status"), Some(&Value::Float(3.0)));
+    } else {
+        panic!("shell didn't return an object");
+    }
+
+    let script = "out = exec(\"no-such-command-here\");";
+    let res = repl_run(parser::parse(lexer::run(script)).unwrap(), &mut runtime, &mut frame);
+    assert!(res.unwrap_err().starts_with("Couldn't run no-such-command-here: "));
+
+    runtime.set_allow_exec(false);
+    let script = "out = exec(\"echo\", [\"hi\"]);";
+    assert!(repl_run(parser::parse(lexer::run(script)).unwrap(), &mut runtime, &mut frame).is_err());
+}
+
+//golden test, objects have to print their members in the order they were added every time
+#[test]
+fn object_member_order() {
+    let mut members = OrderedMap::new();
+    for key in &["zeta", "alpha", "mid", "beta", "omega", "gamma"] {
+        members.insert(key.to_string(), Box::new(Value::EmString((*key).into())));
+    }
+    members.insert("alpha".to_owned(), Box::new(Value::Float(1.0)));
+    members.remove("mid");
+    let obj = Value::Object(Arc::new(EmObject::new(members)));
+
+    let expected = "{\"zeta\": EmString(\"zeta\"), \"alpha\": Float(1.0), \"beta\": EmString(\"beta\"), \"omega\": EmString(\"omega\"), \"gamma\": EmString(\"gamma\")}";
+    for _ in 0..10 {
+        assert_eq!(format!("{}", obj.clone()), expected);
+    }
+    if let Value::Object(e) = obj {
+        assert_eq!(format!("{}", e), expected);
+    }
+}
+
+#[test]
+fn add_and_delete_props() {
+    let dummy = parser::parse(lexer::run(include_str!("test_files/props_test.em"))).unwrap();
+    let mut runtime = Runtime::new();
+    let mut frame = StackFrame::new();
+    repl_run(dummy.clone(), &mut runtime, &mut frame).expect("Unable to perform run");
+
+    assert_eq!(*frame.get_var("removed"), Value::Float(2.0));
+    assert_eq!(*frame.get_var("from_bracket"), Value::Float(1.0));
+    assert_eq!(*frame.get_var("from_expr"), Value::Float(3.0));
+    assert_eq!(format!("{}", frame.get_var("names")), "[\"x\", \"z\"]");
+    assert_eq!(*frame.get_var("has_x"), Value::EmBool(true));
+    assert_eq!(*frame.get_var("has_method"), Value::EmBool(true));
+    assert_eq!(*frame.get_var("has_w"), Value::EmBool(false));
+    if let Value::Object(o) = frame.get_var("without_x") {
+        assert_eq!(o.get_prop("x"), None);
+    } else {
+        panic!("remove_prop should return an object");
+    }
+    if let Value::Object(p) = frame.get_var("p") {
+        assert_eq!(p.get_prop("x"), Some(&Value::Float(1.0)));
+        assert_eq!(p.get_prop("y"), None);
+        assert_eq!(p.get_prop("z"), Some(&Value::Float(3.0)));
+        //methods come from the class instead of being copied into every instance
+        assert!(p.members.get("~init").is_none());
+        assert!(p.get_prop("~init").is_some());
+    } else {
+        panic!("p should be an object");
+    }
+
+    //the constructor can still add x, but adding y afterwards isn't allowed
+    let mut runtime = Runtime::new();
+    runtime.set_strict(true);
+    let err = repl_run(dummy, &mut runtime, &mut StackFrame::new()).unwrap_err();
+    assert!(err.contains("strict mode"));
+}
+
+#[test]
+fn format_strings() {
+    let mut runtime = Runtime::new();
+    let mut frame = StackFrame::new();
+    let script = "half = 5 / 2;
+    quarter = 5 / 4;
+    sum = 7 / 2;
+    a = format(\"{} + {} = {:.2}\", 1, half, sum);
+    b = format(\"[{:5}|{:<5}|{:^7.1}]\", 42, \"ab\", quarter);
+    c = format(\"{{}} {}\", true);";
+    repl_run(parser::parse(lexer::run(script)).unwrap(), &mut runtime, &mut frame).unwrap();
+
+    assert_eq!(*frame.get_var("a"), Value::EmString("1 + 2.5 = 3.50".into()));
+    assert_eq!(*frame.get_var("b"), Value::EmString("[   42|ab   |  1.2  ]".into()));
+    assert_eq!(*frame.get_var("c"), Value::EmString("{} true".into()));
+
+    let run = |code: &str| repl_run(parser::parse(lexer::run(code)).unwrap(), &mut Runtime::new(), &mut StackFrame::new());
+    assert_eq!(run("format(\"{} {}\", 1)"), Err("Placeholder 2 in the format string has no argument to fill it".to_owned()));
+    assert_eq!(run("format(\"{}\")"), Err("Placeholder 1 in the format string has no argument to fill it".to_owned()));
+    assert_eq!(run("printf(\"{:x}\", 1)"), Err("Placeholder 1 in the format string has an invalid spec {:x}".to_owned()));
+    assert_eq!(run("format(\"{} {\", 1)"), Err("Placeholder 2 in the format string is missing its }".to_owned()));
+    assert_eq!(run("format(\"a } b\")"), Err("The } at 2 in the format string doesn't close anything, use }} for a literal one".to_owned()));
+}
+
+#[test]
+fn print_to_sink() {
+    let sink = Sink::default();
+    let mut runtime = Runtime::new();
+    runtime.set_stdout(Box::new(sink.clone()));
+    let script = "println(1, \"two\", true); print(\"a\"); set_print_separator(\", \"); println(\"b\", \"c\");";
+    repl_run(parser::parse(lexer::run(script)).unwrap(), &mut runtime, &mut StackFrame::new()).unwrap();
+
+    assert_eq!(sink.contents(), "1 two true\nab, c\n");
+}
+
+#[test]
+fn read_from_stdin() {
+    let out = Sink::default();
+    let mut runtime = Runtime::new();
+    runtime.set_stdout(Box::new(out.clone()));
+    runtime.set_stdin(Box::new(std::io::Cursor::new("first\nsecond\n")));
+    let mut frame = StackFrame::new();
+    let script = "a = readln(\"> \"); b = readln(); c = readln();";
+    repl_run(parser::parse(lexer::run(script)).unwrap(), &mut runtime, &mut frame).unwrap();
+
+    assert_eq!(*frame.get_var("a"), Value::EmString("first".into()));
+    assert_eq!(*frame.get_var("b"), Value::EmString("second".into()));
+    assert_eq!(*frame.get_var("c"), Value::Null);
+    assert_eq!(out.contents(), "> ");
+}
+
+#[test]
+fn independent_instances() {
+    let dummy = parser::parse(lexer::run(include_str!("test_files/class_test.em"))).unwrap();
+    let mut runtime = Runtime::new();
+    let mut frame = StackFrame::new();
+    repl_run(dummy, &mut runtime, &mut frame).expect("Unable to perform run");
+
+    assert_eq!(*frame.get_var("a_count"), Value::Float(2.0));
+    assert_eq!(*frame.get_var("b_count"), Value::Float(5.0));
+
+    //instances start out empty instead of as a copy of the class
+    if let Value::Object(a) = frame.get_var("a") {
+        assert_eq!(a.members.keys().collect::<Vec<_>>(), vec!["count"]);
+        assert_eq!(a.get_prop("~name"), Some(&Value::EmString("Counter".into())));
+    } else {
+        panic!("a should be an object");
+    }
+    let class = runtime.heap["Counter"].clone();
+    if let Value::Class(c) = class {
+        assert!(c.get_prop("count").is_none());
+    } else {
+        panic!("Counter should be a class");
+    }
+}
+
+#[test]
+fn use_before_definition() {
+    let dummy = parser::parse(lexer::run(include_str!("test_files/hoist_test.em"))).unwrap();
+    let mut runtime = Runtime::new();
+    let mut frame = StackFrame::new();
+    repl_run(dummy, &mut runtime, &mut frame).expect("Unable to perform run");
+
+    assert_eq!(*frame.get_var("x"), Value::Float(8.0));
+    assert_eq!(*frame.get_var("sum"), Value::Float(3.0));
+    //methods shouldn't leak out as global functions
+    assert!(!runtime.heap.contains_key("total"));
+}
+
+#[test]
+fn recursion() {
+    let dummy = parser::parse(lexer::run(include_str!("test_files/recursion_test.em"))).unwrap();
+    let mut runtime = Runtime::new();
+    //tests run on threads with small stacks
+    runtime.set_max_depth(20);
+    let mut frame = StackFrame::new();
+    repl_run(dummy, &mut runtime, &mut frame).expect("Unable to perform run");
+
+    assert_eq!(*frame.get_var("fact_res"), Value::Float(120.0));
+    assert_eq!(*frame.get_var("even_res"), Value::EmBool(true));
+    assert_eq!(*frame.get_var("odd_res"), Value::EmBool(false));
+    assert_eq!(*frame.get_var("count_res"), Value::Float(5.0));
+
+    let call = parser::parse(lexer::run("forever(0);")).unwrap();
+    let err = repl_run(call, &mut runtime, &mut frame).unwrap_err();
+    assert_eq!(err, "Maximum call depth of 20 exceeded");
+}
+
+#[test]
+fn redefinition() {
+    //in the REPL, redefining a class updates objects that already exist
+    let mut runtime = Runtime::new();
+    let mut frame = StackFrame::new();
+    let first = "class A { fn get(self) { return 1; } } a = new A(); x = a.get();";
+    let second = "class A { fn get(self) { return 2; } } y = a.get();";
+    repl_run(parser::parse(lexer::run(first)).unwrap(), &mut runtime, &mut frame).unwrap();
+    repl_run(parser::parse(lexer::run(second)).unwrap(), &mut runtime, &mut frame).unwrap();
+    assert_eq!(*frame.get_var("x"), Value::Float(1.0));
+    assert_eq!(*frame.get_var("y"), Value::Float(2.0));
+
+    //in a file the last definition is used everywhere, with a warning
+    let out = Sink::default();
+    let err = Sink::default();
+    let mut runtime = Runtime::new();
+    runtime.set_stdout(Box::new(out.clone()));
+    runtime.set_stderr(Box::new(err.clone()));
+    let script = "fn f() { return 1; } fn main(args) { println(f()); } fn f() { return 2; }";
+    run_with(runtime, parser::parse(lexer::run(script)).unwrap(), vec![]);
+    assert_eq!(out.contents(), "2\n");
+    assert_eq!(err.contents(), "Warning: f is defined more than once, the last definition will be used\n");
+
+    //builtins get replaced too
+    let out = Sink::default();
+    let err = Sink::default();
+    let mut runtime = Runtime::new();
+    runtime.set_stdout(Box::new(out.clone()));
+    runtime.set_stderr(Box::new(err.clone()));
+    let script = "fn len(x) { return 42; } fn main(args) { println(len([1, 2])); }";
+    run_with(runtime, parser::parse(lexer::run(script)).unwrap(), vec![]);
+    assert_eq!(out.contents(), "42\n");
+    assert_eq!(err.contents(), "Warning: len replaces the builtin with the same name\n");
+}
+
+#[test]
+fn runtime_types() {
+    let mut runtime = Runtime::new();
+    let mut frame = StackFrame::new();
+    let script = "class A { fn get(self) {} } class B { fn get(self) {} }
+    a = new A();
+    types = [type(1), type(\"s\"), type(true), type(a), type(null)];
+    is_a = is_instance(a, \"A\");
+    is_b = is_instance(a, \"B\");
+    not_obj = is_instance(1, \"A\");";
+    repl_run(parser::parse(lexer::run(script)).unwrap(), &mut runtime, &mut frame).unwrap();
+
+    let expected = ["float", "string", "bool", "object", "null"];
+    assert_eq!(
+        *frame.get_var("types"),
+        Value::EmArray(Arc::new(expected.iter().map(|t| Value::EmString((*t).into())).collect()))
+    );
+    assert_eq!(*frame.get_var("is_a"), Value::EmBool(true));
+    assert_eq!(*frame.get_var("is_b"), Value::EmBool(false));
+    assert_eq!(*frame.get_var("not_obj"), Value::EmBool(false));
+}
+
+#[test]
+fn conversions() {
+    let mut runtime = Runtime::new();
+    let mut frame = StackFrame::new();
+    let script = "n = to_number(\" 12 \");
+    bad = to_number(\"12px\");
+    i = to_int(\"-7.9\");
+    s = to_string(3);
+    b = [to_bool(0), to_bool(\"x\"), to_bool(null)];
+    p = parse_float(\"12.5px\");
+    none = parse_float(\"px\");";
+    repl_run(parser::parse(lexer::run(script)).unwrap(), &mut runtime, &mut frame).unwrap();
+
+    assert_eq!(*frame.get_var("n"), Value::Float(12.0));
+    assert_eq!(*frame.get_var("bad"), Value::Null);
+    assert_eq!(*frame.get_var("i"), Value::Float(-7.0));
+    assert_eq!(*frame.get_var("s"), Value::EmString("3".into()));
+    assert_eq!(
+        *frame.get_var("b"),
+        Value::EmArray(Arc::new(vec![Value::EmBool(false), Value::EmBool(true), Value::EmBool(false)]))
+    );
+    assert_eq!(*frame.get_var("p"), Value::Float(12.5));
+    assert_eq!(*frame.get_var("none"), Value::Null);
+}
+
+#[test]
+fn classes_as_values() {
+    let mut runtime = Runtime::new();
+    let mut frame = StackFrame::new();
+    let script = "class A { fn ~init(self, v) { self.v = v; } }
+    class B { fn ~init(self) { self.v = 2; } }
+    classes = [A, B];
+    b = new (classes[1])();
+    a = make(A, [1]);
+    bv = b.v;
+    av = a.v;
+    check = is_instance(b, B);
+    kind = type(A);";
+    repl_run(parser::parse(lexer::run(script)).unwrap(), &mut runtime, &mut frame).unwrap();
+
+    assert_eq!(*frame.get_var("bv"), Value::Float(2.0));
+    assert_eq!(*frame.get_var("av"), Value::Float(1.0));
+    assert_eq!(*frame.get_var("check"), Value::EmBool(true));
+    assert_eq!(*frame.get_var("kind"), Value::EmString("class".into()));
+}
+
+#[test]
+fn native_file() {
+    let path = std::env::temp_dir().join("em_native_file_test.txt");
+    let _ = std::fs::remove_file(&path);
+    let mut runtime = Runtime::new();
+    let mut frame = StackFrame::new();
+    frame.set_var("path".to_owned(), Value::EmString(path.display().to_string().into()));
+    let script = "f = open(path);
+    before = f.exists();
+    f.write(\"a\");
+    f.append(\"b\");
+    text = f.read();
+    kind = type(f);";
+    repl_run(parser::parse(lexer::run(script)).unwrap(), &mut runtime, &mut frame).unwrap();
+
+    assert_eq!(*frame.get_var("before"), Value::EmBool(false));
+    assert_eq!(*frame.get_var("text"), Value::EmString("ab".into()));
+    assert_eq!(*frame.get_var("kind"), Value::EmString("file".into()));
+
+    //writes are side effects, so a dry run leaves the file alone
+    runtime.set_dry_run(true);
+    runtime.set_audit_sink(Box::new(Sink::default()));
+    repl_run(parser::parse(lexer::run("wrote = f.write(\"c\");")).unwrap(), &mut runtime, &mut frame).unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "ab");
+    assert_eq!(*frame.get_var("wrote"), Value::EmBool(true));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn replay_file_reads() {
+    let dir = std::env::temp_dir();
+    let path = dir.join(format!("em_replay_file_{}.txt", std::process::id()));
+    let trace = dir.join(format!("em_replay_file_{}.trace", std::process::id()));
+    let trace = trace.to_str().unwrap();
+    std::fs::write(&path, "one\ntwo").unwrap();
+    let script = "f = open(path); there = f.exists(); text = f.read(); raw = f.read_bytes(); whole = read_file_bytes(path);
+    open(path + \".gone\").read();";
+    let run = |runtime: &mut Runtime| {
+        let mut frame = StackFrame::new();
+        frame.set_var("path".to_owned(), Value::EmString(path.display().to_string().into()));
+        let err = repl_run(parser::parse(lexer::run(script)).unwrap(), runtime, &mut frame).unwrap_err();
+        runtime.flush();
+        let vars: Vec<String> = ["there", "text", "raw", "whole"].iter().map(|v| format!("{}", frame.get_var(v))).collect();
+        (vars, err)
+    };
+    let mut recording = Runtime::new();
+    recording.record_to(trace).unwrap();
+    let recorded = run(&mut recording);
+    assert_eq!(recorded.0, vec!["true", "one\ntwo", "b\"one\\x0atwo\"", "b\"one\\x0atwo\""]);
+    assert!(recorded.1.starts_with("Couldn't read"));
+
+    //the file is gone, but the replay still sees what was in it
+    std::fs::remove_file(&path).unwrap();
+    let mut replaying = Runtime::new();
+    replaying.replay_from(trace).unwrap();
+    assert_eq!(run(&mut replaying), recorded);
+    std::fs::remove_file(trace).unwrap();
+}
+
+#[test]
+fn truthiness() {
+    let mut runtime = Runtime::new();
+    let mut frame = StackFrame::new();
+    let script = "a = 0; b = 0; c = 0;
+    if 1 { a = 1; }
+    if \"\" { b = 1; } else { b = 2; }
+    items = [1, 2];
+    if items { c = 1; }";
+    repl_run(parser::parse(lexer::run(script)).unwrap(), &mut runtime, &mut frame).unwrap();
+
+    assert_eq!(*frame.get_var("a"), Value::Float(1.0));
+    assert_eq!(*frame.get_var("b"), Value::Float(2.0));
+    assert_eq!(*frame.get_var("c"), Value::Float(1.0));
+}
+
+#[test]
+fn operator_precedence() {
+    let mut runtime = Runtime::new();
+    let mut frame = StackFrame::new();
+    let script = "class Pt { fn ~init(self, x) { self.x = x; } fn get(self) { return self.x; } }
+    p = new Pt(3);
+    a = 1 + 2 * 3 - 4 / 2;
+    b = -(1 + 2) * 2;
+    c = 1 < 2 && !(2 < 1) || false;
+    d = [[1, 2], [3, 4]][1][0] + 2.5;
+    e = p.get() * 2 + 1;
+    f = false && missing();";
+    repl_run(parser::parse(lexer::run(script)).unwrap(), &mut runtime, &mut frame).unwrap();
+
+    assert_eq!(*frame.get_var("a"), Value::Float(5.0));
+    assert_eq!(*frame.get_var("b"), Value::Float(-6.0));
+    assert_eq!(*frame.get_var("c"), Value::EmBool(true));
+    assert_eq!(*frame.get_var("d"), Value::Float(5.5));
+    assert_eq!(*frame.get_var("e"), Value::Float(7.0));
+    assert_eq!(*frame.get_var("f"), Value::EmBool(false));
+}
+
+#[test]
+fn map_keys() {
+    use crate::interpreter::types::MapKey;
+    use std::collections::HashSet;
+
+    let keys: HashSet<MapKey> = [Value::Float(0.0), Value::Float(-0.0), Value::Float(1.0), Value::EmString("1".into())]
+        .iter()
+        .map(|v| MapKey::from_value(v).unwrap())
+        .collect();
+    assert_eq!(keys.len(), 3);
+    assert_eq!(MapKey::from_value(&Value::EmBool(true)).unwrap().to_value(), Value::EmBool(true));
+    assert!(MapKey::from_value(&Value::Float(f64::NAN)).is_err());
+    assert!(MapKey::from_value(&Value::EmArray(Arc::new(vec![Value::Null]))).is_err());
+
+    //objects can be indexed by any key, but not by something unhashable
+    let mut runtime = Runtime::new();
+    let mut frame = StackFrame::new();
+    let script = "class A { fn ~init(self) { self[\"1\"] = 2; } } a = new A(); one = a[1];";
+    repl_run(parser::parse(lexer::run(script)).unwrap(), &mut runtime, &mut frame).unwrap();
+    assert_eq!(*frame.get_var("one"), Value::Float(2.0));
+    let bad = repl_run(parser::parse(lexer::run("a[a];")).unwrap(), &mut runtime, &mut frame);
+    assert!(bad.unwrap_err().ends_with("can't be used as a key, only strings, bytes, numbers, bools, and arrays or tuples of those can"));
+}
+
+#[test]
+fn array_index_validation() {
+    let mut runtime = Runtime::new();
+    let mut frame = StackFrame::new();
+    let setup = "arr = [1, 2, 3]; grid = [[1, 2], [3, 4]]; ok = arr[2]; last = arr[-1]; grid[-1][-2] = 5;";
+    repl_run(parser::parse(lexer::run(setup)).unwrap(), &mut runtime, &mut frame).unwrap();
+    assert_eq!(*frame.get_var("ok"), Value::Float(3.0));
+    assert_eq!(*frame.get_var("last"), Value::Float(3.0));
+    assert_eq!(format!("{}", frame.get_var("grid")), "[[1, 2], [5, 4]]");
+
+    let mut run = |script: &str| repl_run(parser::parse(lexer::run(script)).unwrap(), &mut runtime, &mut frame).unwrap_err();
+    assert_eq!(run("x = arr[1.7];"), "Array index must be a whole number, got 1.7");
+    assert_eq!(run("x = arr[3];"), "Index 3 out of bounds for length 3");
+    assert_eq!(run("x = arr[0 - 4];"), "Index -4 out of bounds for length 3");
+    assert_eq!(run("x = \"abc\"[3];"), "Index 3 out of bounds for length 3");
+    assert_eq!(run("s = \"abc\"; s[0] = \"x\";"), "Strings can't be changed by index, build a new one instead");
+    assert_eq!(run("arr[0.5] = 1;"), "Array index must be a whole number, got 0.5");
+    assert_eq!(run("grid[0][1.5] = 1;"), "Array index must be a whole number, got 1.5");
+}
+
+#[test]
+fn bad_index_assignment() {
+    let mut runtime = Runtime::new();
+    let mut frame = StackFrame::new();
+    let setup = "arr = [1]; grid = [[1, 2], [3, 4]]; n = 5;";
+    repl_run(parser::parse(lexer::run(setup)).unwrap(), &mut runtime, &mut frame).unwrap();
+
+    let mut run = |script: &str| repl_run(parser::parse(lexer::run(script)).unwrap(), &mut runtime, &mut frame).unwrap_err();
+    assert!(run("arr[1] = 2;").starts_with("Index 1 out of bounds"));
+    assert!(run("grid[5][0] = 1;").starts_with("Index 5 out of bounds"));
+    assert!(run("grid[0][2] = 1;").starts_with("Index 2 out of bounds"));
+    assert_eq!(run("n[0] = 1;"), "Type 5 isn't indexable");
+    assert_eq!(run("n[0][0] = 1;"), "Type 5 isn't indexable");
+    assert_eq!(run("missing[0][0] = 1;"), "Couldn't find identifier missing");
+    //a script can still catch them
+    let caught = "fn bad() { g = [[1]]; g[0][9] = 1; } assert_throws(bad);";
+    assert!(repl_run(parser::parse(lexer::run(caught)).unwrap(), &mut runtime, &mut frame).is_ok());
+    assert_eq!(*frame.get_var("grid"), Value::EmArray(Arc::new(vec![
+        Value::EmArray(Arc::new(vec![Value::Float(1.0), Value::Float(2.0)])),
+        Value::EmArray(Arc::new(vec![Value::Float(3.0), Value::Float(4.0)])),
+    ])));
+}
+
+#[test]
+fn lengths() {
+    let err = Sink::default();
+    let mut runtime = Runtime::new();
+    runtime.set_stderr(Box::new(err.clone()));
+    let mut frame = StackFrame::new();
+    let script = "class P { fn ~init(self) { self.a = 1; self.b = 2; } }
+    s = \"héllo\";
+    arr = [1, 2, 3];
+    lens = [len(s), len(arr), len(new P()), s.len(), arr.len()];";
+    repl_run(parser::parse(lexer::run(script)).unwrap(), &mut runtime, &mut frame).unwrap();
+
+    let expected = [5.0, 3.0, 2.0, 5.0, 3.0];
+    assert_eq!(
+        *frame.get_var("lens"),
+        Value::EmArray(Arc::new(expected.iter().map(|n| Value::Float(*n)).collect()))
+    );
+    assert_eq!(
+        repl_run(parser::parse(lexer::run("bad = len(5);")).unwrap(), &mut runtime, &mut frame),
+        Err("Argument 1 of len should be an array, a tuple, a string, bytes, an object, a range, or a set, got a float".to_owned())
+    );
+}
+
+#[test]
+fn iterables() {
+    let mut runtime = Runtime::new();
+    let mut frame = StackFrame::new();
+    let script = "fn double(n) { return n * 2; }
+    total = sum([1, 2, 3]);
+    sorted = join(sort([3, 1, 2]), \",\");
+    letters = join(sort(\"cab\"));
+    doubled = join(map([1, 2], double), \" \");";
+    repl_run(parser::parse(lexer::run(script)).unwrap(), &mut runtime, &mut frame).unwrap();
+
+    assert_eq!(*frame.get_var("total"), Value::Float(6.0));
+    assert_eq!(*frame.get_var("sorted"), Value::EmString("1,2,3".into()));
+    assert_eq!(*frame.get_var("letters"), Value::EmString("abc".into()));
+    assert_eq!(*frame.get_var("doubled"), Value::EmString("2 4".into()));
+}
+
+#[test]
+fn primitive_methods() {
+    let mut runtime = Runtime::new();
+    let mut frame = StackFrame::new();
+    let script = "s = \" Hello World \";
+    words = s.trim().lower().split(\" \");
+    shout = s.upper();
+    arr = [3, 1, 2];
+    sorted = arr.sort().join(\",\");
+    has = arr.contains(2);
+    down = (3.7).floor();
+    up = (0 - 3.2).ceil();";
+    repl_run(parser::parse(lexer::run(script)).unwrap(), &mut runtime, &mut frame).unwrap();
+
+    assert_eq!(
+        *frame.get_var("words"),
+        Value::EmArray(Arc::new(vec![Value::EmString("hello".into()), Value::EmString("world".into())]))
+    );
+    assert_eq!(*frame.get_var("shout"), Value::EmString(" HELLO WORLD ".into()));
+    assert_eq!(*frame.get_var("sorted"), Value::EmString("1,2,3".into()));
+    assert_eq!(*frame.get_var("has"), Value::EmBool(true));
+    assert_eq!(*frame.get_var("down"), Value::Float(3.0));
+    assert_eq!(*frame.get_var("up"), Value::Float(-3.0));
+
+    let err = repl_run(parser::parse(lexer::run("x = arr.nope();")).unwrap(), &mut runtime, &mut frame).unwrap_err();
+    assert_eq!(err, "[3, 1, 2] has no method nope");
+
+    //methods check their arguments the way builtins do, not counting the value they're called on
+    let run = |src: &str, runtime: &mut Runtime, frame: &mut StackFrame| repl_run(parser::parse(lexer::run(src)).unwrap(), runtime, frame);
+    assert_eq!(run("x = \"abc\".contains();", &mut runtime, &mut frame), Err("contains takes 1 argument, got 0".to_owned()));
+    assert_eq!(run("x = s.upper(1);", &mut runtime, &mut frame), Err("upper takes 0 arguments, got 1".to_owned()));
+    assert_eq!(run("x = arr.map(1);", &mut runtime, &mut frame), Err("Argument 1 of map should be a function, got a float".to_owned()));
+    assert_eq!(run("arr.push();", &mut runtime, &mut frame), Err("push takes 1 argument, got 0".to_owned()));
+    assert_eq!(format!("{}", frame.get_var("arr")), "[3, 1, 2]");
+}
+
+#[test]
+fn call_chains() {
+    let mut runtime = Runtime::new();
+    let mut frame = StackFrame::new();
+    let script = "class User { fn ~init(self) { self.name = \"ann\"; } }
+    fn get_user() { return new User(); }
+    fn double(n) { return n * 2; }
+    fn pick() { return double; }
+    name = get_user().name.upper();
+    first = [3, 1, 2].sort().map(double)[0];
+    fns = [double];
+    a = fns[0](4);
+    b = pick()(5);
+    f = double;
+    c = f(6);";
+    repl_run(parser::parse(lexer::run(script)).unwrap(), &mut runtime, &mut frame).unwrap();
+
+    assert_eq!(*frame.get_var("name"), Value::EmString("ANN".into()));
+    assert_eq!(*frame.get_var("first"), Value::Float(2.0));
+    assert_eq!(*frame.get_var("a"), Value::Float(8.0));
+    assert_eq!(*frame.get_var("b"), Value::Float(10.0));
+    assert_eq!(*frame.get_var("c"), Value::Float(12.0));
+}
+
+#[test]
+fn split_text() {
+    let mut runtime = Runtime::new();
+    let mut frame = StackFrame::new();
+    frame.set_var("text".to_owned(), Value::EmString("one\r\ntwo\n".into()));
+    frame.set_var("accent".to_owned(), Value::EmString("e\u{301}!".into()));
+    let script = "rows = lines(text); letters = accent.chars().len();";
+    repl_run(parser::parse(lexer::run(script)).unwrap(), &mut runtime, &mut frame).unwrap();
+
+    assert_eq!(
+        *frame.get_var("rows"),
+        Value::EmArray(Arc::new(vec![Value::EmString("one".into()), Value::EmString("two".into())]))
+    );
+    assert_eq!(*frame.get_var("letters"), Value::Float(3.0));
+
+    #[cfg(feature = "graphemes")]
+    {
+        repl_run(parser::parse(lexer::run("g = graphemes(accent).len();")).unwrap(), &mut runtime, &mut frame).unwrap();
+        assert_eq!(*frame.get_var("g"), Value::Float(2.0));
+    }
+}
+
+#[test]
+fn repl_commands() {
+    use crate::interpreter::repl::Repl;
+
+    let path = std::env::temp_dir().join("em_repl_load_test.em");
+    std::fs::write(&path, "fn double(n) { return n * 2; }\nclass Pt {}").unwrap();
+
+    let mut repl = Repl::new();
+    assert_eq!(repl.eval("x = 1 + 1").unwrap(), Some("2".to_owned()));
+    assert_eq!(repl.eval(&format!(":load {}", path.display())).unwrap(), None);
+    assert_eq!(repl.eval(":env").unwrap(), Some("x = 2".to_owned()));
+    assert_eq!(repl.eval(":heap").unwrap(), Some("class Pt\nfn double(n)".to_owned()));
+    assert_eq!(repl.eval(":type double(x)").unwrap(), Some("float".to_owned()));
+    assert_eq!(repl.eval(":reset").unwrap(), None);
+    assert_eq!(repl.eval(":env").unwrap(), Some("".to_owned()));
+    assert!(repl.eval(":nope").is_err());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn repl_rc_file() {
+    use crate::interpreter::repl::Repl;
+
+    let path = std::env::temp_dir().join("em_replrc_test.em");
+    std::fs::write(&path, "fn sq(n) { return n * n; }\nrepl_set(\"prompt\", \"em> \");\nrepl_set(\"auto_print\", false);").unwrap();
+
+    let mut repl = Repl::new();
+    assert_eq!(repl.prompt(), "> ");
+    repl.load_rc(&path).unwrap();
+    assert_eq!(repl.prompt(), "em> ");
+    assert_eq!(repl.eval("x = sq(3)").unwrap(), None);
+    assert_eq!(repl.eval(":env").unwrap(), Some("x = 9".to_owned()));
+    //starting over runs the rc file again, so the helpers and options are still there
+    repl.eval(":reset").unwrap();
+    assert_eq!(repl.eval(":heap").unwrap(), Some("fn sq(n)".to_owned()));
+    assert_eq!(repl.prompt(), "em> ");
+    repl.eval("repl_set(\"auto_print\", true)").unwrap();
+    assert_eq!(repl.eval("sq(4)").unwrap(), Some("16".to_owned()));
+    assert_eq!(repl.eval("repl_set(\"colour\", 1)"), Err("colour isn't a repl option, try prompt or auto_print".to_owned()));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn repl_completion() {
+    use crate::interpreter::repl::Repl;
+
+    let mut repl = Repl::new();
+    repl.eval("class Pt { fn ~init(self, x) { self.x = x; } fn len_sq(self) { return self.x * self.x; } }").unwrap();
+    repl.eval("fn plot() { }").unwrap();
+    repl.eval("point = new Pt(2)").unwrap();
+    repl.eval("class Config { fn ~init(self) { self.inner = new Pt(1); self.name = \"a\"; } }").unwrap();
+    repl.eval("config = new Config()").unwrap();
+    assert_eq!(repl.complete("x = po"), (4, vec!["point".to_owned()]));
+    assert_eq!(repl.complete("pl"), (0, vec!["plot".to_owned()]));
+    assert_eq!(repl.complete("P"), (0, vec!["Pt".to_owned()]));
+    //builtins are in there too
+    assert!(repl.complete("prin").1.contains(&"println".to_owned()));
+    assert_eq!(repl.complete("point."), (6, vec!["len_sq".to_owned(), "x".to_owned()]));
+    assert_eq!(repl.complete("f(config.inner.l"), (15, vec!["len_sq".to_owned()]));
+    assert_eq!(repl.complete("config.n"), (7, vec!["name".to_owned()]));
+    //calls would have to be run to know what they give back
+    assert_eq!(repl.complete("plot().x"), (7, vec![]));
+}
+
+#[test]
+fn call_stack_names() {
+    let mut runtime = Runtime::new();
+    runtime.functions.insert(
+        "where".to_owned(),
+        builtins::BuiltinFn::new(builtins::Signature::ANY, Arc::new(|_, _| Value::EmString(call_stack().join(" > ").into()))),
+    );
+    let mut frame = StackFrame::new();
+    let script = "class A { fn get(self) { return outer(); } }
+    fn outer() { return where(); }
+    a = new A();
+    inside = a.get();";
+    repl_run(parser::parse(lexer::run(script)).unwrap(), &mut runtime, &mut frame).unwrap();
+
+    assert_eq!(*frame.get_var("inside"), Value::EmString("A.get > outer".into()));
+    assert!(call_stack().is_empty());
+}
+
+#[test]
+fn repl_sessions() {
+    use crate::interpreter::repl::Repl;
+
+    let path = std::env::temp_dir().join("em_repl_session_test.em");
+    let mut repl = Repl::new();
+    repl.eval("class Pt { fn ~init(self, x) { self.x = x; } fn get(self) { return self.x; } }").unwrap();
+    repl.eval("fn double(n) { if n < 0 { return 0 - n * 2; } else { return n * 2; } }").unwrap();
+    repl.eval("nums = [1, -2.5, \"three\", [true, null]]").unwrap();
+    repl.eval("f = double").unwrap();
+    repl.eval("p = new Pt(4)").unwrap();
+    repl.eval(&format!(":save {}", path.display())).unwrap();
+    let env = repl.eval(":env").unwrap();
+    let heap = repl.eval(":heap").unwrap();
+
+    let mut restored = Repl::new();
+    restored.eval("leftover = 1").unwrap();
+    assert_eq!(restored.eval(&format!(":load-session {}", path.display())).unwrap(), None);
+    assert_eq!(restored.eval(":heap").unwrap(), heap);
+    //objects can't be written out, so p is the only thing missing
+    assert_ne!(restored.eval(":env").unwrap(), env);
+    assert_eq!(restored.eval(":env").unwrap(), Some("f = fn double(n) { ... }\nnums = [1, -2.5, \"three\", [true, null]]".to_owned()));
+    assert_eq!(restored.eval("f(-3)").unwrap(), Some("6".to_owned()));
+    assert_eq!(restored.eval("new Pt(7).get()").unwrap(), Some("7".to_owned()));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn script_tests() {
+    let out = Sink::default();
+    let mut runtime = Runtime::new();
+    runtime.set_stdout(Box::new(out.clone()));
+    let tree = parser::parse(lexer::run(include_str!("test_files/assert_test.em"))).unwrap();
+
+    assert_eq!(run_tests(runtime, tree), 2);
+    assert_eq!(
+        out.contents(),
+        "test test_fails ... FAILED: Assertion failed: 1.5 != 1\n\
+        test test_not_throwing ... FAILED: Assertion failed: expected fn fn_that_works() { ... } to throw\n\
+        test test_passes ... ok\n\
+        1 passed, 2 failed\n"
+    );
+}
+
+#[test]
+fn crash_dump() {
+    let mut runtime = Runtime::new();
+    runtime.functions.insert("boom".to_owned(), builtins::BuiltinFn::new(builtins::Signature::ANY, Arc::new(|_, _| panic!("boom"))));
+    let tree = parser::parse(lexer::run("fn inner() { x = boom(); } fn main(args) { inner(); }")).unwrap();
+    let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        run_with(runtime, tree, vec![])
+    }));
+    assert!(res.is_err());
+
+    let path = std::env::temp_dir().join("em_crash_dump_test.txt");
+    crash::write_dump(path.to_str().unwrap(), "the source", "the panic").unwrap();
+    let dump = std::fs::read_to_string(&path).unwrap();
+    assert!(dump.contains("== call stack ==\nmain > inner\n"));
+    assert!(dump.contains("== node being run ==\nCall(Ident(\"boom\"), [])\n"));
+    assert!(dump.contains("heap: fn inner/0, fn main/1\n"));
+    assert!(dump.contains("== source ==\nthe source\n"));
+    //the dump takes what it needs, so a second crash starts clean
+    assert!(crash::take().node.is_none());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn counts_nodes() {
+    let tree = parser::parse(lexer::run("fn main(args) { x = 1 + 2; }")).unwrap();
+    let mut runtime = Runtime::new();
+    run_on(&mut runtime, tree.clone(), vec![]);
+    let once = runtime.nodes_evaluated();
+    assert!(once > 0);
+    run_on(&mut runtime, tree, vec![]);
+    assert_eq!(runtime.nodes_evaluated(), once * 2);
+}
+
+#[test]
+fn prelude() {
+    let mut runtime = Runtime::new();
+    let mut frame = StackFrame::new();
+    let script = "fn even(n) { return n - to_int(n / 2) * 2 == 0; }
+    fn add(a, b) { return a + b; }
+    r = range(0, 6);
+    evens = filter(r, even);
+    total = reduce(r, add, 0);
+    back = reverse(push(r.to_array(), 6));
+    first = find(r, even);
+    nothing = find([], even);
+    most = max([3, 9, 2]);";
+    repl_run(parser::parse(lexer::run(script)).unwrap(), &mut runtime, &mut frame).unwrap();
+
+    assert_eq!(format!("{}", frame.get_var("r")), "0..6");
+    assert_eq!(format!("{}", frame.get_var("evens")), "[0, 2, 4]");
+    assert_eq!(*frame.get_var("total"), Value::Float(15.0));
+    assert_eq!(format!("{}", frame.get_var("back")), "[6, 5, 4, 3, 2, 1, 0]");
+    assert_eq!(*frame.get_var("first"), Value::Float(0.0));
+    assert_eq!(*frame.get_var("nothing"), Value::Null);
+    assert_eq!(*frame.get_var("most"), Value::Float(9.0));
+    //errors inside the prelude stop the script like any other function
+    assert!(repl_run(parser::parse(lexer::run("reduce([1], add)")).unwrap(), &mut runtime, &mut frame).is_err());
+
+    //a script's own function wins over a prelude helper with the same name
+    let script = "fn max(a, b) { if a > b { return a; } return b; } bigger = max(1, 2);";
+    repl_run(parser::parse(lexer::run(script)).unwrap(), &mut runtime, &mut frame).unwrap();
+    assert_eq!(*frame.get_var("bigger"), Value::Float(2.0));
+    let out = Sink::default();
+    let mut runtime = Runtime::new();
+    runtime.set_stdout(Box::new(out.clone()));
+    let script = "fn main(args) { println(min(4, 3)); } fn min(a, b) { return a - b; }";
+    assert_eq!(run_with(runtime, parser::parse(lexer::run(script)).unwrap(), vec![]), 0);
+    assert_eq!(out.contents(), "1\n");
+}
+
+#[test]
+fn push_in_place() {
+    let mut runtime = Runtime::new();
+    let mut frame = StackFrame::new();
+    let script = "a = [1]; b = a; b.push(2); c = b.push(3); n = 5; pushed = n.push(1);";
+    let res = repl_run(parser::parse(lexer::run(script)).unwrap(), &mut runtime, &mut frame);
+    assert_eq!(res, Err("5 has no method push".to_owned()));
+    assert_eq!(format!("{}", frame.get_var("a")), "[1]");
+    assert_eq!(format!("{}", frame.get_var("b")), "[1, 2, 3]");
+    assert_eq!(format!("{}", frame.get_var("c")), "[1, 2, 3]");
+    let script = "items = []; for (i = 0; i < 1000; i++) { items.push(i); } back = reverse(items);";
+    repl_run(parser::parse(lexer::run(script)).unwrap(), &mut runtime, &mut frame).unwrap();
+    match frame.get_var("back") {
+        Value::EmArray(a) => assert_eq!((a.len(), &a[0]), (1000, &Value::Float(999.0))),
+        _ => panic!("expected an array"),
+    }
+}
+
+#[test]
+fn copy_on_write() {
+    let mut runtime = Runtime::new();
+    let mut frame = StackFrame::new();
+    let script = "fn id(v) { return v; }
+    a = [1, [2, 3]];
+    b = id(a);
+    c = a;
+    c[0] = 9;
+    c[1][0] = 8;";
+    repl_run(parser::parse(lexer::run(script)).unwrap(), &mut runtime, &mut frame).unwrap();
+
+    //passing and returning doesn't copy anything
+    match (frame.get_var("a"), frame.get_var("b")) {
+        (Value::EmArray(a), Value::EmArray(b)) => assert!(Arc::ptr_eq(a, b)),
+        _ => panic!("expected arrays"),
+    }
+    //but changing a copy doesn't change the original
+    assert_eq!(format!("{}", frame.get_var("a")), "[1, [2, 3]]");
+    assert_eq!(format!("{}", frame.get_var("c")), "[9, [8, 3]]");
+}
+
+#[test]
+fn call_counts() {
+    let tree = parser::parse(lexer::run(
+        "class A { fn get(self) { return 1; } }
+        fn fib(n) { if n < 2 { return n; } return fib(n - 1) + fib(n - 2); }
+        fn main(args) { a = new A(); a.get(); fib(5); }",
+    ))
+    .unwrap();
+    //nothing gets counted unless it's asked for
+    let mut runtime = Runtime::new();
+    run_on(&mut runtime, tree.clone(), vec![]);
+    assert_eq!(runtime.call_counts(), vec![]);
+
+    let mut runtime = Runtime::new();
+    runtime.set_count_calls(true);
+    run_on(&mut runtime, tree, vec![]);
+    assert_eq!(
+        runtime.call_counts(),
+        vec![("fib".to_owned(), 15), ("A.get".to_owned(), 1), ("main".to_owned(), 1)]
+    );
+}
+
+#[test]
+fn builtin_signatures() {
+    let mut runtime = Runtime::new();
+    let mut frame = StackFrame::new();
+    let mut run = |code: &str| repl_run(parser::parse(lexer::run(code)).unwrap(), &mut runtime, &mut frame);
+
+    assert_eq!(run("len()"), Err("len takes 1 argument, got 0".to_owned()));
+    assert_eq!(run("join([1], \",\", 3)"), Err("join takes 1 to 2 arguments, got 3".to_owned()));
+    assert_eq!(run("format()"), Err("format takes at least 1 argument, got 0".to_owned()));
+    assert_eq!(run("map([1], 2)"), Err("Argument 2 of map should be a function, got a float".to_owned()));
+    assert_eq!(run("has_prop(1, \"x\")"), Err("Argument 1 of has_prop should be an object or a class, got a float".to_owned()));
+    //prelude functions get their arity from their parameters
+    assert_eq!(run("filter([1])"), Err("filter takes 2 arguments, got 1".to_owned()));
+    assert_eq!(run("println(join([1, 2], \"-\"))"), Ok("null".to_owned()));
+}
+
+#[test]
+fn engine() {
+    let mut engine = crate::Engine::new();
+    engine.set_global("base", Value::Float(10.0));
+    engine.eval("fn add(a, b) { return a + b }").unwrap();
+    assert_eq!(engine.call("add", vec![Value::Float(1.0), Value::Float(2.0)]), Ok(Value::Float(3.0)));
+    assert_eq!(engine.call("len", vec![Value::EmString("four".into())]), Ok(Value::Float(4.0)));
+    assert_eq!(engine.call("len", vec![]), Err("len takes 1 argument, got 0".to_owned()));
+    assert_eq!(engine.call("nope", vec![]), Err("Couldn't find identifier nope".to_owned()));
+    assert_eq!(engine.eval("x = add(base, 0)"), Ok(Value::Float(10.0)));
+    assert_eq!(engine.get_global("x"), Some(&Value::Float(10.0)));
+}
+
+#[test]
+fn ranges() {
+    let mut runtime = Runtime::new();
+    let mut frame = StackFrame::new();
+    let mut run = |code: &str| repl_run(parser::parse(lexer::run(&format!("{}\n", code))).unwrap(), &mut runtime, &mut frame);
+
+    assert_eq!(run("r = 2..5"), Ok("2..5".to_owned()));
+    assert_eq!(run("[len(r), r[0], r[-1], r.contains(5)]"), Ok("[3, 2, 4, false]".to_owned()));
+    assert_eq!(run("range(10, 0, -4).to_array()"), Ok("[10, 6, 2]".to_owned()));
+    assert_eq!(run("total = 0; for i in range(4) { total += i; } total"), Ok("6".to_owned()));
+    assert_eq!(run("a = [1, 2, 3, 4]; [a[1..3], a[-2..4], \"hello\"[1..-1]]"), Ok("[[2, 3], [3, 4], \"ell\"]".to_owned()));
+    assert_eq!(run("a[2..9]"), Err("Index 4 out of bounds for length 4".to_owned()));
+    assert_eq!(run("1..\"b\""), Err("A range needs numbers on both sides, got 1 and b".to_owned()));
+    assert_eq!(run("r[0] = 1"), Err("Ranges can't be changed by index, use to_array() to get an array".to_owned()));
+}
+
+#[test]
+fn iterators() {
+    let err = Sink::default();
+    let mut runtime = Runtime::new();
+    runtime.set_stderr(Box::new(err.clone()));
+    let mut frame = StackFrame::new();
+    let mut run = |code: &str| repl_run(parser::parse(lexer::run(&format!("{}\n", code))).unwrap(), &mut runtime, &mut frame);
+
+    run("class Evens { fn ~init(self) { self.n = 0; } fn ~next(self) { self.n += 2; return self.n; } }").unwrap();
+    assert_eq!(run("take(new Evens(), 3).to_array()"), Ok("[2, 4, 6]".to_owned()));
+    //iterators share their position, so one that's been used is used up
+    assert_eq!(run("it = iter(1..4); [it.~next(), to_array(it), it.~next()]"), Ok("[1, [2, 3], null]".to_owned()));
+    run("fn first_over(limit) { for n in new Evens() { if n > limit { return n; } } }").unwrap();
+    assert_eq!(run("first_over(7)"), Ok("8".to_owned()));
+    assert_eq!(run("skip([1], -1)"), Err("skip needs a whole number that isn't negative, got -1".to_owned()));
+    //a bad step stops the script right there instead of handing null to whatever comes next
+    assert_eq!(run("range(0, 10, 0).to_array()"), Err("A range's step can't be 0".to_owned()));
+    assert_eq!(run("class Bad { fn ~next(self, x) { return x; } } for x in new Bad() { }"), Err("~next should only take self, but it takes 2 arguments".to_owned()));
+    assert_eq!(run("for x in open(\"f\") { }"), Err("file has no method ~next".to_owned()));
+    assert_eq!(err.contents(), "");
+}
+
+#[test]
+fn generators() {
+    let out = Sink::default();
+    let mut runtime = Runtime::new();
+    runtime.set_stdout(Box::new(out.clone()));
+    let mut frame = StackFrame::new();
+    let mut run = |code: &str| repl_run(parser::parse(lexer::run(&format!("{}\n", code))).unwrap(), &mut runtime, &mut frame);
+
+    run("fn naturals() { n = 0; while true { println(\"making\", n); yield n; n += 1; } }").unwrap();
+    //nothing runs until an item is asked for, and only as much as it takes
+    assert_eq!(run("g = naturals(); g.take(2).to_array()"), Ok("[0, 1]".to_owned()));
+    assert_eq!(out.contents(), "making 0\nmaking 1\n");
+    assert_eq!(run("g.~next()"), Ok("2".to_owned()));
+    run("fn evens(items) { for x in items { if x > 4 { return; } elif x > 1 { yield x; } } yield \"never\"; }").unwrap();
+    assert_eq!(run("to_array(evens([1, 2, 4, 5, 6]))"), Ok("[2, 4]".to_owned()));
+    assert_eq!(run("fn f() { for (i = 0; i < 3; i++) { yield i * i; } } [x for x in f()]"), Ok("[0, 1, 4]".to_owned()));
+    assert_eq!(run("yield 1;"), Err("yield can only be used inside a function".to_owned()));
+    assert!(parser::parse(lexer::run("fn bad() { println(yield 1); }\n")).is_err());
+}
+
+#[test]
+fn tasks() {
+    let out = Sink::default();
+    let mut runtime = Runtime::new();
+    runtime.set_stdout(Box::new(out.clone()));
+    let mut frame = StackFrame::new();
+    let mut run = |code: &str| repl_run(parser::parse(lexer::run(&format!("{}\n", code))).unwrap(), &mut runtime, &mut frame);
+
+    run("fn tick(name, times, ms) { for (i = 0; i < times; i++) { await sleep_async(ms); println(name, i); } return name; }").unwrap();
+    //calling an async function starts it, but nothing runs until something waits
+    assert_eq!(run("slow = tick(\"slow\", 2, 30); fast = spawn(tick, \"fast\", 3, 12); fast.done()"), Ok("false".to_owned()));
+    assert_eq!(run("[await slow, fast.done(), await fast]"), Ok("[\"slow\", true, \"fast\"]".to_owned()));
+    assert_eq!(out.contents(), "fast 0\nfast 1\nslow 0\nfast 2\nslow 1\n");
+    run("fn fails() { await sleep_async(1); return [][0]; } fn waits(t) { return await t; }").unwrap();
+    assert_eq!(run("await waits(fails())"), Err("Index 0 out of bounds for length 0".to_owned()));
+}
+
+#[test]
+fn expressions() {
+    let mut engine = crate::Engine::new();
+    engine.eval("fn secret() { return 1; }").unwrap();
+    let runtime = engine.runtime();
+    let mut bindings = Bindings::new();
+    bindings.set("price", Value::Float(20.0));
+    bindings.set("tags", Value::EmArray(Arc::new(vec![Value::EmString("a".into()), Value::EmString("sale".into())])));
+    bindings.register_fn(
+        "max",
+        Signature::new(2, Some(2), &["float", "float"]),
+        Arc::new(|_, args| match (&args[0], &args[1]) {
+            (Value::Float(a), Value::Float(b)) => Value::Float(a.max(*b)),
+            _ => Value::Null,
+        }),
+    );
+    assert_eq!(runtime.eval_expr("max(price * 0.5, 15) + 1", &bindings), Ok(Value::Float(16.0)));
+    assert_eq!(runtime.eval_expr("tags.contains(\"sale\") && price > 10", &bindings), Ok(Value::EmBool(true)));
+    assert_eq!(runtime.eval_expr("[t for t in tags if t != \"a\"]", &bindings).map(|v| v.to_string()), Ok("[\"sale\"]".to_owned()));
+    //nothing outside of the bindings is reachable, builtins included
+    assert_eq!(runtime.eval_expr("cost * 2", &bindings), Err("Unknown name cost".to_owned()));
+    assert_eq!(runtime.eval_expr("println(price)", &bindings), Err("Unknown function println".to_owned()));
+    assert_eq!(runtime.eval_expr("secret()", &bindings), Err("Unknown function secret".to_owned()));
+    assert_eq!(runtime.eval_expr("price = 3", &bindings), Err("Expressions can't assign to anything".to_owned()));
+    assert!(runtime.eval_expr("price; price", &bindings).is_err());
+    assert!(runtime.eval_expr("while true { }", &bindings).is_err());
+    //and the runtime gets its builtins back afterwards
+    assert!(runtime.builtin_names().contains(&"println".to_owned()));
+}
+
+#[test]
+fn threads() {
+    let mut runtime = Runtime::new();
+    let mut frame = StackFrame::new();
+    let mut run = |code: &str| repl_run(parser::parse(lexer::run(&format!("{}\n", code))).unwrap(), &mut runtime, &mut frame);
+
+    run("fn double(x) { return x * 2; } fn worker(jobs, out) { n = recv(jobs); while n != null { send(out, double(n)); n = recv(jobs); } return \"done\"; }").unwrap();
+    run("jobs = channel(); out = channel(); t = thread_spawn(worker, jobs, out); for i in 0..3 { send(jobs, i); } send(jobs, null);").unwrap();
+    //the thread's copy of the channel is the same queue, and it can call functions defined out here
+    assert_eq!(run("[recv(out), recv(out), recv(out), t.join()]"), Ok("[0, 2, 4, \"done\"]".to_owned()));
+    assert_eq!(run("t.join()"), Err("This thread has already been joined".to_owned()));
+    assert_eq!(run("thread_spawn(double, iter([1]))"), Err("Can't send <iterator> to another thread".to_owned()));
+    assert_eq!(run("fn fails() { return [][1]; } thread_spawn(fails).join()"), Err("Index 1 out of bounds for length 0".to_owned()));
+
+    //threads print where the runtime that started them does, and share its output limit
+    let out = Sink::default();
+    let mut runtime = Runtime::new();
+    runtime.set_stdout(Box::new(out.clone()));
+    runtime.set_output_limit(Some(OutputLimit::Lines(3)));
+    let mut frame = StackFrame::new();
+    let code = "fn chatty(n) { for i in 0..n { println(i); } } println(\"main\"); thread_spawn(chatty, 1).join(); thread_spawn(chatty, 5).join()\n";
+    let res = repl_run(parser::parse(lexer::run(code)).unwrap(), &mut runtime, &mut frame);
+    assert_eq!(res, Err("Went over the output limit of 3 lines".to_owned()));
+    assert_eq!(out.contents(), "main\n0\n0\n");
+
+    //and they're set up the same way, down to the builtins registered from rust
+    let (out, audit) = (Sink::default(), Sink::default());
+    let mut runtime = Runtime::new();
+    runtime.set_stdout(Box::new(out.clone()));
+    runtime.set_audit_sink(Box::new(audit.clone()));
+    runtime.set_dry_run(true);
+    runtime.set_numeric_policy(NumericPolicy::Error);
+    runtime.set_print_separator(", ");
+    runtime.register_fn("twice", Signature::new(1, Some(1), &["float"]), Arc::new(|_, args| match args[0] {
+        Value::Float(f) => Value::Float(f * 2.0),
+        _ => Value::Null,
+    }));
+    let mut frame = StackFrame::new();
+    let code = "fn work() { println(1, 2); open(\"out.txt\").write(\"x\"); return twice(4); }
+    fn divide() { return 1 / 0; }
+    done = thread_spawn(work).join(); thread_spawn(divide).join()\n";
+    let res = repl_run(parser::parse(lexer::run(code)).unwrap(), &mut runtime, &mut frame);
+    assert_eq!(res, Err("Can't divide 1 by zero".to_owned()));
+    assert_eq!(*frame.get_var("done"), Value::Float(8.0));
+    assert_eq!(out.contents(), "1, 2\n");
+    assert_eq!(audit.contents(), "[dry-run] write out.txt\n");
+}
+
+#[test]
+fn compiled_programs() {
+    let program = crate::Program::compile("fn tax(x) { return x * rate; } total = price * qty; total + total * rate").unwrap();
+    let mut runtime = Runtime::new();
+    let records = [(2.0, 3.0), (10.0, 1.0)];
+    let mut results = vec![];
+    for (price, qty) in records.iter() {
+        let mut bindings = Bindings::new();
+        bindings.set("price", Value::Float(*price));
+        bindings.set("qty", Value::Float(*qty));
+        bindings.set("rate", Value::Float(0.5));
+        results.push(program.run_with(&mut runtime, &bindings));
+    }
+    assert_eq!(results, vec![Ok(Value::Float(9.0)), Ok(Value::Float(15.0))]);
+    //globals don't leak from one run into the next
+    let program = crate::Program::compile("before = last; last = 1; before").unwrap();
+    assert_eq!(program.run_with(&mut runtime, &Bindings::new()), Ok(Value::Null));
+    assert_eq!(program.run_with(&mut runtime, &Bindings::new()), Ok(Value::Null));
+    let mut bindings = Bindings::new();
+    bindings.register_fn("twice", Signature::new(1, Some(1), &["float"]), Arc::new(|_, args| match args[0] {
+        Value::Float(f) => Value::Float(f * 2.0),
+        _ => Value::Null,
+    }));
+    let program = crate::Program::compile("twice(21)").unwrap();
+    assert_eq!(program.run_with(&mut runtime, &bindings), Ok(Value::Float(42.0)));
+    assert!(!runtime.builtin_names().contains(&"twice".to_owned()));
+}
+
+#[test]
+fn config_mode() {
+    let mut runtime = Runtime::new();
+    let config = runtime
+        .eval_config(
+            "name = \"demo\";
+            server.port = 8000 + 80;
+            server.hosts = [\"a\", \"b\"];
+            debug = !false && server.port > 1024;
+            backup = server;
+            backup.port = server.port + 1;",
+        )
+        .unwrap();
+    let get = |path: &[&str]| path.iter().fold(config.clone(), |v, key| v.index_key(key).unwrap().clone()).to_string();
+    assert_eq!(get(&["name"]), "demo");
+    assert_eq!(get(&["server", "hosts"]), "[\"a\", \"b\"]");
+    assert_eq!(get(&["debug"]), "true");
+    //copies are separate, so changing one doesn't change the other
+    assert_eq!((get(&["server", "port"]), get(&["backup", "port"])), ("8080".to_owned(), "8081".to_owned()));
+    assert_eq!(runtime.eval_config("x = open(\"f\");"), Err("Config files can't use function calls".to_owned()));
+    assert_eq!(runtime.eval_config("x = [i for i in 0..3];"), Err("Config files can't use loops".to_owned()));
+    assert_eq!(runtime.eval_config("x = y;"), Err("y has to be set before it's used".to_owned()));
+    assert_eq!(runtime.eval_config("x = 1; x.y = 2;"), Err("x is already set to 1, so it can't have keys of its own".to_owned()));
+    assert!(runtime.eval_config("while true { }").unwrap_err().starts_with("Config files can only set keys"));
+}
+
+#[test]
+fn fixed_step_harness() {
+    let out = Sink::default();
+    let mut runtime = Runtime::new();
+    runtime.set_stdout(Box::new(out.clone()));
+    let mut engine = crate::Engine::with_runtime(runtime);
+    engine
+        .eval(
+            "fn on_init() { return 0; }
+            fn on_update(x, dt) { return x + dt; }
+            fn log(x) { println(\"at\", x); }
+            fn on_shutdown(x) { println(\"done\", x); }
+            on(\"update\", log);",
+        )
+        .unwrap();
+    let rt = engine.runtime();
+    rt.set_fixed_step(Some(0.5));
+    //a frame that's too short for a whole step runs nothing and carries over
+    assert_eq!(rt.tick(0.25), Ok(0));
+    assert_eq!(rt.tick(1.25), Ok(3));
+    assert_eq!(*rt.state(), Value::Float(1.5));
+    rt.shutdown().unwrap();
+    assert_eq!(out.contents(), "at 0.5\nat 1\nat 1.5\ndone 1.5\n");
+
+    //a tick that runs out of fuel is an error instead of a hang, and the next one gets fuel again
+    engine.eval("fn on_update(x, dt) { while dt > 0 { } }").unwrap();
+    let rt = engine.runtime();
+    rt.set_fixed_step(None);
+    rt.set_tick_fuel(Some(1000));
+    assert_eq!(rt.tick(1.0), Err("Ran out of fuel for this tick".to_owned()));
+    assert_eq!(rt.tick(0.0), Ok(1));
+}
+
+#[test]
+fn runtime_is_send_and_sync() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Runtime>();
+    assert_send_sync::<crate::Engine>();
+    //everything a host hands to a runtime or gets back from one can go to another thread with it
+    assert_send_sync::<Value>();
+    assert_send_sync::<StackFrame>();
+    assert_send_sync::<Bindings>();
+    assert_send_sync::<crate::Program>();
+    assert_send_sync::<Builtin>();
+
+    //a runtime built on one thread can be shared with others behind a mutex
+    let mut engine = crate::Engine::new();
+    let seen = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+    let log = seen.clone();
+    engine.register_fn(
+        "record",
+        Signature::ANY,
+        std::sync::Arc::new(move |_rt: &mut Runtime, args: Vec<Value>| {
+            log.lock().unwrap().extend(args);
+            Value::Null
+        }),
+    );
+    engine.eval("fn add(a, b) { record(a + b); return a + b; }").unwrap();
+    let shared = std::sync::Arc::new(std::sync::Mutex::new(engine));
+    let handles: Vec<_> = (0..4)
+        .map(|i| {
+            let engine = shared.clone();
+            std::thread::spawn(move || engine.lock().unwrap().call("add", vec![Value::Float(i as f64), Value::Float(1.0)]))
+        })
+        .collect();
+    let mut results: Vec<Value> = handles.into_iter().map(|h| h.join().unwrap().unwrap()).collect();
+    results.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    assert_eq!(results, (1..5).map(|i| Value::Float(i as f64)).collect::<Vec<_>>());
+    assert_eq!(seen.lock().unwrap().len(), 4);
+}
+
+#[test]
+fn constant_subtrees() {
+    let code = "i = 0; rows = []; while i < 3 { row = [0, 0, 0]; row[i] = 1; rows = push(rows, row); i = i + 1; } rows\n";
+    let tree = parser::parse(lexer::run(code)).unwrap();
+    let marked = constants::mark(&tree);
+    //marking prints the same and marking twice doesn't change anything
+    assert_eq!(parser::to_source(&marked), parser::to_source(&tree));
+    assert_eq!(constants::mark(&marked), marked);
+
+    let mut runtime = Runtime::new();
+    let mut frame = StackFrame::new();
+    let res = repl_run(tree, &mut runtime, &mut frame);
+    //changing a copy of a cached array doesn't change the next one
+    assert_eq!(res, Ok("[[1, 0, 0], [0, 1, 0], [0, 0, 1]]".to_owned()));
+    //just `[]` and `[0, 0, 0]`, no matter how many times the loop ran
+    assert_eq!(runtime.constants.len(), 2);
+    //running more code on the same runtime, like the REPL does, doesn't keep the old ones around
+    for _ in 0..10 {
+        eval_tree(&parser::parse(lexer::run(code)).unwrap(), &mut runtime, &mut frame).unwrap();
+    }
+    assert_eq!(runtime.constants.len(), 2);
+}
+
+#[test]
+fn structured_logging() {
+    let (out, logs) = (Sink::default(), Sink::default());
+    let mut runtime = Runtime::new();
+    runtime.set_stderr(Box::new(out.clone()));
+    runtime.set_log_sink(Box::new(logs.clone()));
+    runtime.set_log_level("info".parse().unwrap());
+    let mut frame = StackFrame::new();
+    let code = "log.debug(\"hidden\"); log.info(\"shown\"); log.error(\"failed\", 5)\n";
+    let res = repl_run(parser::parse(lexer::run(code)).unwrap(), &mut runtime, &mut frame);
+    assert_eq!(res, Err("Log fields have to be an object, found 5".to_owned()));
+    assert_eq!(logs.contents(), "[info] shown\n");
+    assert_eq!(out.contents(), "");
+    assert_eq!("verbose".parse::<LogLevel>(), Err("verbose isn't a log level, expected one of debug, info, warn, error".to_owned()));
+
+    //a script's own `log` wins over the builtin one
+    let res = repl_run(parser::parse(lexer::run("log = 3; log\n")).unwrap(), &mut runtime, &mut frame);
+    assert_eq!(res, Ok("3".to_owned()));
+}
+
+#[test]
+fn sandbox_profiles() {
+    let has = |rt: &Runtime, name: &str| rt.builtin_names().contains(&name.to_owned());
+    let mut pure = Runtime::with_profile(Profile::Pure);
+    //turning exec back on doesn't get around the profile
+    pure.set_allow_exec(true);
+    assert!(!has(&pure, "open") && !has(&pure, "exec") && !has(&pure, "shell"));
+    assert!(has(&pure, "println") && has(&pure, "map"));
+    let mut frame = StackFrame::new();
+    let res = repl_run(parser::parse(lexer::run("open(\"secrets.txt\")\n")).unwrap(), &mut pure, &mut frame);
+    assert!(res.is_err());
+
+    let files = Runtime::with_profile(Profile::FileIo);
+    assert!(has(&files, "open") && !has(&files, "exec"));
+    let network = Runtime::with_profile(Profile::Network);
+    assert!(!has(&network, "open") && !has(&network, "exec"));
+    let full = Runtime::with_profile(Profile::Full);
+    assert_eq!(full.builtin_names(), Runtime::new().builtin_names());
+    assert_eq!(full.profile(), Profile::Full);
+}
+
+#[test]
+fn sandbox_reaches_display_and_threads() {
+    let marker = std::env::temp_dir().join(format!("em_sandbox_{}", std::process::id()));
+    let marker = marker.to_str().unwrap().to_owned();
+    let script = format!(
+        "class Evil {{ fn ~display(self) {{ shell(\"echo escaped > {0}\"); open(\"{0}\").write(\"escaped\"); return \"evil\"; }} }}
+        println(new Evil())
+        fn sneaky() {{ shell(\"echo escaped > {0}\"); }}
+        thread_spawn(sneaky).join()\n",
+        marker
+    );
+    let run = |mut rt: Runtime| {
+        let mut frame = StackFrame::new();
+        repl_run(parser::parse(lexer::run(&script)).unwrap(), &mut rt, &mut frame)
+    };
+    assert!(run(Runtime::with_profile(Profile::Pure)).is_err());
+    let mut no_exec = Runtime::new();
+    no_exec.set_allow_exec(false);
+    no_exec.set_dry_run(true);
+    assert!(run(no_exec).is_err());
+    assert!(!std::path::Path::new(&marker).exists());
+}
+
+#[test]
+fn compiled_programs_round_trip() {
+    let path = std::env::temp_dir().join("em_compiled_test.emc");
+    let path = path.to_str().unwrap();
+    let src = "fn sq(x) { return x * x; } rows = [[1, 2], [3, 4]]; sq(rows[1][0]) + n";
+    crate::Program::compile_to_file(src, path).unwrap();
+    let loaded = crate::Program::load_compiled(path).unwrap();
+    let mut bindings = Bindings::new();
+    bindings.set("n", Value::Float(1.0));
+    let expected = crate::Program::compile(src).unwrap().run_with(&mut Runtime::new(), &bindings);
+    assert_eq!(loaded.run_with(&mut Runtime::new(), &bindings), expected);
+    assert_eq!(expected, Ok(Value::Float(10.0)));
+    let data = std::fs::read_to_string(path).unwrap();
+    std::fs::remove_file(path).unwrap();
+    //constant ids aren't saved, loading marks the tree again
+    assert!(!data.contains("Constant"));
+    let old = data.replacen(env!("CARGO_PKG_VERSION"), "0.0.0", 1);
+    let err = crate::Program::from_compiled(&old).unwrap_err();
+    assert!(err.contains("compiled by version 0.0.0"), "{}", err);
+    assert!(crate::Program::from_compiled("not json").is_err());
+    //files saved before the nodes last changed shape don't have a format
+    let unformatted = data.replacen(",\"format\":2", "", 1);
+    assert_ne!(unformatted, data);
+    assert!(crate::Program::from_compiled(&unformatted).unwrap_err().contains("older build"));
+}
+
+#[test]
+fn compile_cache() {
+    let dir = std::env::temp_dir().join("em_compile_cache_test");
+    std::fs::remove_dir_all(&dir).unwrap_or(());
+    let src = "x = 6; x * 7";
+    let run = |program: crate::Program| program.run_with(&mut Runtime::new(), &Bindings::new());
+    assert_eq!(run(crate::Program::cached(src, &dir).unwrap()), Ok(Value::Float(42.0)));
+    let entries: Vec<_> = std::fs::read_dir(&dir).unwrap().map(|e| e.unwrap().path()).collect();
+    assert_eq!(entries.len(), 1);
+    //the second run reads the entry instead of parsing, so changing its tree shows up
+    let entry = entries[0].to_str().unwrap();
+    let data = std::fs::read_to_string(entry).unwrap();
+    std::fs::write(entry, data.replace("7.0", "8.0")).unwrap();
+    assert_eq!(run(crate::Program::cached(src, &dir).unwrap()), Ok(Value::Float(48.0)));
+    //but an entry for some other script isn't trusted just because of its name
+    crate::Program::compile_to_file("1", entry).unwrap();
+    assert_eq!(run(crate::Program::cached(src, &dir).unwrap()), Ok(Value::Float(42.0)));
+    //an entry that can't be loaded gets replaced
+    std::fs::write(entry, "garbage").unwrap();
+    assert_eq!(run(crate::Program::cached(src, &dir).unwrap()), Ok(Value::Float(42.0)));
+    assert!(crate::Program::load_compiled(entry).is_ok());
+    assert!(crate::Program::cached("x = ;", &dir).is_err());
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn illegal_nodes_are_errors() {
+    let mut runtime = Runtime::new();
+    for node in [ExprNode::Illegal(None), ExprNode::EOF] {
+        let tree = ExprNode::Block(vec![ExprNode::Statement(Box::new(node))]);
+        let err = runtime.eval_bound(&tree, &Bindings::new()).unwrap_err();
+        assert!(err.starts_with("Internal error: the parser left"), "{}", err);
+    }
+    //parts that were left out are filled in by the parser instead of being Illegal
+    let program = crate::Program::compile("i = 0; for (; i < 2; i += 1) { } [[x for x in 0..3], [x for x in 0..3 if null], i]").unwrap();
+    let out = program.run_with(&mut runtime, &Bindings::new()).unwrap();
+    assert_eq!(out.to_string(), "[[0, 1, 2], [], 2]");
+    let err = |src: &str| crate::Program::compile(src).unwrap_err();
+    assert_eq!(err("fn f(a, 3) { }"), "Expected a parameter name, found Number(3.0)");
+    assert_eq!(err("fn f(a) return a;"), "Expected the body of f to start with {, found Some(Key(\"return\"))");
+}
+
+#[test]
+fn script_errors_dont_panic() {
+    let run = |src: &str| crate::Program::compile(src).and_then(|p| p.run_with(&mut Runtime::new(), &Bindings::new()));
+    let class = "class A { fn ~init(self) { } fn m() { return 1; } } ";
+    assert_eq!(run(&format!("{}new A().m()", class)), Err("m can't be called as a method since it doesn't take self".to_owned()));
+    assert_eq!(run("class B { fn ~init() { } } new B()"), Err("The constructor for B has to take self".to_owned()));
+    assert_eq!(run("sleep_async(1e30)"), Err("Can't sleep for 1000000000000000000000000000000 milliseconds".to_owned()));
+    //errors in functions handed to builtins come out of the builtin
+    assert_eq!(run("fn f(x) { return [][x]; } map([0], f)"), Err("Index 0 out of bounds for length 0".to_owned()));
+}
+
+#[test]
+fn script_arguments() {
+    //arguments get to main exactly as they were given, quotes and commas included, and never run
+    let out = Sink::default();
+    let mut runtime = Runtime::new();
+    runtime.set_stdout(Box::new(out.clone()));
+    let args = vec![Value::EmString("x\", println(\"ran\"), \"y".into()), Value::EmString("q\"uote, b".into())];
+    let src = "fn main(args) { println(len(args)); for a in args { println(a); } }\n";
+    assert_eq!(crate::run_with(runtime, src.to_owned(), args, false), 0);
+    assert_eq!(out.contents(), "2\nx\", println(\"ran\"), \"y\nq\"uote, b\n");
+}
+
+#[test]
+fn output_limits() {
+    let run = |limit, truncate, src: &str| {
+        let out = Sink::default();
+        let mut runtime = Runtime::new();
+        runtime.set_stdout(Box::new(out.clone()));
+        runtime.set_output_limit(Some(limit));
+        runtime.set_truncate_output(truncate);
+        let res = crate::Program::compile(src).and_then(|p| p.run_with(&mut runtime, &Bindings::new()));
+        (res, out.contents())
+    };
+    let flood = "for (i = 0; i < 100; i += 1) { println(i); }";
+    assert_eq!(
+        run(OutputLimit::Lines(3), false, flood),
+        (Err("Went over the output limit of 3 lines".to_owned()), "0\n1\n2\n".to_owned())
+    );
+    assert_eq!(
+        run(OutputLimit::Lines(2), true, &format!("{} 5", flood)),
+        (Ok(Value::Float(5.0)), "0\n1\n\n[output truncated, the limit is 2 lines]\n".to_owned())
+    );
+    //bytes are cut where a whole character still fits
+    assert_eq!(run(OutputLimit::Bytes(4), true, "print(\"abcé\"); print(\"z\");").1, "abc\n[output truncated, the limit is 4 bytes]\n");
+    //scripts can catch it like any other error
+    let (res, _) = run(OutputLimit::Bytes(0), false, "fn f() { print(\"x\"); } assert_throws(f)");
+    assert_eq!(res, Ok(Value::Null));
+}
+
+#[test]
+fn watch_expressions() {
+    let err = Sink::default();
+    let mut runtime = Runtime::new();
+    runtime.set_stderr(Box::new(err.clone()));
+    runtime.add_watch("p.hp").unwrap();
+    runtime.add_watch("total * 2").unwrap();
+    assert!(runtime.add_watch("total = 3").is_err());
+    let src = "class P { fn ~init(self) { self.hp = 10; } }
+        fn f(x) { y = x * 2; return x; }
+        p = new P(); total = 0;
+        for (i = 0; i < 3; i += 1) { total += f(i); }
+        p.hp = 4; p.hp = 4;";
+    crate::Program::compile(src).and_then(|p| p.run_with(&mut runtime, &Bindings::new())).unwrap();
+    //each change shows up once, and nothing is printed inside f where p and total can't be seen
+    assert_eq!(
+        err.contents(),
+        "[watch] p.hp = 10\n[watch] total * 2 = 0\n[watch] total * 2 = 2\n[watch] total * 2 = 6\n[watch] p.hp = 4\n"
+    );
+    assert_eq!(runtime.watches(), vec!["p.hp", "total * 2"]);
+}
+
+#[test]
+fn engine_reload_keeps_globals() {
+    let out = Sink::default();
+    let mut runtime = Runtime::new();
+    runtime.set_stdout(Box::new(out.clone()));
+    let mut engine = crate::Engine::with_runtime(runtime);
+    let src = "if runs == null { runs = 0; } runs += 1; fn main(args) { println(args[0]); }";
+    engine.reload(src, vec![Value::EmString("first".into())]).unwrap();
+    engine.reload(&src.replace("println(args[0])", "println(args[0], \"again\")"), vec![Value::EmString("second".into())]).unwrap();
+    assert_eq!(engine.get_global("runs"), Some(&Value::Float(2.0)));
+    assert_eq!(out.contents(), "first\nsecond again\n");
+    //scripts without a main just run their top level
+    assert_eq!(crate::Engine::new().reload("x = 1;", vec![]), Ok(Value::Null));
+}
+
+#[test]
+fn numbers_are_doubles() {
+    let run = |src: &str| crate::Program::compile(src).and_then(|p| p.run_with(&mut Runtime::new(), &Bindings::new()));
+    //f32 couldn't tell these apart
+    assert_eq!(run("16777216 + 1 == 16777217"), Ok(Value::EmBool(true)));
+    assert_eq!(run("1700000000123 + 1"), Ok(Value::Float(1700000000124.0)));
+    assert_eq!(run("to_string(1700000000123)"), Ok(Value::EmString("1700000000123".into())));
+}
+
+#[test]
+fn numeric_policies() {
+    let run = |policy, src: &str| {
+        let mut runtime = Runtime::new();
+        runtime.set_numeric_policy(policy);
+        crate::Program::compile(src).and_then(|p| p.run_with(&mut runtime, &Bindings::new()))
+    };
+    let nan = run(NumericPolicy::Ieee, "0 / 0").unwrap();
+    assert!(matches!(nan, Value::Float(f) if f.is_nan()));
+    assert_eq!(run(NumericPolicy::Ieee, "[(0 / 0).is_nan(), (1 / 0).is_finite(), (-1 / 0) < 0]").unwrap().to_string(), "[true, false, true]");
+    assert_eq!(run(NumericPolicy::Saturate, "1 / 0"), Ok(Value::Float(f64::MAX)));
+    assert_eq!(run(NumericPolicy::Saturate, "0 - 1e308 * 10"), Ok(Value::Float(f64::MIN)));
+    assert_eq!(run(NumericPolicy::Saturate, "0 / 0"), Err("Can't divide 0 by zero".to_owned()));
+    assert_eq!(run(NumericPolicy::Error, "x = 5; x /= 0;"), Err("Can't divide 5 by zero".to_owned()));
+    let err = run(NumericPolicy::Error, "1e308 * 10").unwrap_err();
+    assert!(err.ends_with("* 10 is too big for a number"), "{}", err);
+    //scripts can catch it
+    assert_eq!(run(NumericPolicy::Error, "fn f() { return 1 / 0; } assert_throws(f)"), Ok(Value::Null));
+    assert_eq!("saturate".parse(), Ok(NumericPolicy::Saturate));
+}
+
+#[test]
+fn number_formatting() {
+    let run = |src: &str| crate::Program::compile(src).and_then(|p| p.run_with(&mut Runtime::new(), &Bindings::new())).map(|v| v.to_string());
+    assert_eq!(run("[is_nan(0 / 0), is_nan(1), is_finite(1 / 0), is_finite(2)]"), Ok("[true, false, false, true]".to_owned()));
+    assert_eq!(run("[round_to(3.14159, 2), round_to(1234.5, -2), round_to(0.1 + 0.2, 10), (1 / 3).round_to(3)]"), Ok("[3.14, 1200, 0.3, 0.333]".to_owned()));
+    assert_eq!(run("[to_fixed(3.14159, 2), to_fixed(2, 3), (0.1 + 0.2).to_fixed(1), to_fixed(0 / 0, 2)]"), Ok(r#"["3.14", "2.000", "0.3", "NaN"]"#.to_owned()));
+    assert_eq!(run("to_fixed(1, 1.5)"), Err("Expected a whole number of places from 0 to 100, found 1.5".to_owned()));
+    assert_eq!(run("to_fixed(1, -1)"), Err("Expected a whole number of places from 0 to 100, found -1".to_owned()));
+}
+
+#[test]
+fn equality_and_ordering() {
+    let run = |src: &str| crate::Program::compile(src).and_then(|p| p.run_with(&mut Runtime::new(), &Bindings::new())).map(|v| v.to_string());
+    assert_eq!(run("[[1, [2, \"a\"]] == [1, [2, \"a\"]], [1, 2] == [1, 2, 3], [1] != [2]]"), Ok("[true, false, true]".to_owned()));
+    let points = "class P { fn ~init(self, x, y) { self.x = x; self.y = y; } } class Q { fn ~init(self, x, y) { self.x = x; self.y = y; } }";
+    assert_eq!(run(&format!("{} [new P(1, 2) == new P(1, 2), new P(1, 2) == new P(2, 1), new P(1, 2) == new Q(1, 2)]", points)), Ok("[true, false, false]".to_owned()));
+    //~eq decides no matter which side the object is on, and inside arrays too
+    let loose = "class Loose { fn ~init(self, v) { self.v = v; } fn ~eq(self, other) { return self.v == other || self.v == other.v; } }";
+    assert_eq!(run(&format!("{} [new Loose(1) == 1, 1 == new Loose(1), [new Loose(2)] == [new Loose(2)], [new Loose(3)].contains(3)]", loose)), Ok("[true, true, true, true]".to_owned()));
+    assert_eq!(run("[\"a\" < \"b\", [1, 2] < [1, 3], [1] < [1, 0], false < true, (0 / 0) < 1]"), Ok("[true, true, true, true, false]".to_owned()));
+    assert_eq!(run("1 < \"2\""), Err("Can't compare float and string".to_owned()));
+    assert_eq!(run("null >= 0"), Err("Can't compare null and float".to_owned()));
+    assert_eq!(run("class A { } new A() < new A()"), Err("Can't compare object and object".to_owned()));
+    assert_eq!(run("[1, 2] < [1, \"b\"]"), Err("Can't compare float and string".to_owned()));
+    assert_eq!(run("sort([1, \"a\"])"), Err("Can't compare string and float".to_owned()));
+}
+
+#[test]
+fn hashable_values() {
+    use crate::interpreter::types::MapKey;
+    let pair = Value::EmArray(Arc::new(vec![Value::Float(1.0), Value::EmString("a".into())]));
+    let key = MapKey::from_value(&pair).unwrap();
+    assert_eq!(key, MapKey::from_value(&pair.clone()).unwrap());
+    assert_eq!(key.to_value(), pair);
+    assert!(MapKey::from_value(&Value::EmArray(Arc::new(vec![Value::Null]))).is_err());
+
+    let run = |src: &str| crate::Program::compile(src).and_then(|p| p.run_with(&mut Runtime::new(), &Bindings::new())).map(|v| v.to_string());
+    assert_eq!(run("s = set([3, 1, 3, [1, 2], \"a\", [1, 2]]); [s, len(s), type(s)]"), Ok("[set([3, 1, [1, 2], \"a\"]), 4, \"set\"]".to_owned()));
+    assert_eq!(run("s = set(\"hello\").add(\"z\"); [s.has(\"l\"), s.has([\"l\"]), s.has(\"z\"), to_array(s)]"), Ok("[true, false, true, [\"h\", \"e\", \"l\", \"o\", \"z\"]]".to_owned()));
+    //adding changes the set in the variable, but not other copies of it
+    assert_eq!(run("a = set(); b = a; a.add(0); [a, b, b == set([]), set([1, 2]) == set([2, 1])]"), Ok("[set([0]), set([]), true, true]".to_owned()));
+    assert_eq!(run("a = set([1]); c = a.add(2); [a, c]"), Ok("[set([1, 2]), set([1, 2])]".to_owned()));
+    //an item that can't go in a set doesn't cost the set its items
+    let mut runtime = Runtime::new();
+    let mut frame = StackFrame::new();
+    let res = repl_run(parser::parse(lexer::run("a = set([1]); a.add(null);")).unwrap(), &mut runtime, &mut frame);
+    assert_eq!(res, Err("null can't be used as a key".to_owned()));
+    assert_eq!(frame.get_var("a").to_string(), "set([1])");
+    assert_eq!(run("total = 0; for x in set([1, 1, 2]) { total += x; } total"), Ok("3".to_owned()));
+}
+
+#[test]
+fn set_operations() {
+    let run = |src: &str| crate::Program::compile(src).and_then(|p| p.run_with(&mut Runtime::new(), &Bindings::new())).map(|v| v.to_string());
+    let sets = "a = set([1, 2, 3]); b = set([3, 4, 2]);";
+    assert_eq!(run(&format!("{} [a.union(b), a.intersect(b), a.difference(b), b.difference(a)]", sets)), Ok("[set([1, 2, 3, 4]), set([2, 3]), set([1]), set([4])]".to_owned()));
+    //the other side can be anything set() takes
+    assert_eq!(run(&format!("{} [a.union([5, 5]), a.intersect(2..9), a.difference(\"x\")]", sets)), Ok("[set([1, 2, 3, 5]), set([2, 3]), set([1, 2, 3])]".to_owned()));
+    assert_eq!(run(&format!("{} c = b.remove(2).remove(9); a.remove(1); [a, b, c, c.has(2)]", sets)), Ok("[set([2, 3]), set([3, 4]), set([3, 4]), false]".to_owned()));
+    assert_eq!(run("words = [\"b\", \"a\", \"b\", \"c\", \"a\"]; len(set(words))"), Ok("3".to_owned()));
+    assert_eq!(run("set([1]).union([null])"), Err("null can't be used as a key".to_owned()));
+}
+
+#[test]
+fn tuples() {
+    let run = |src: &str| crate::Program::compile(src).and_then(|p| p.run_with(&mut Runtime::new(), &Bindings::new())).map(|v| v.to_string());
+    assert_eq!(run("t = (1, \"a\", true); [t, t[1], t[-1], len(t), type(t), (5,)]"), Ok("[(1, \"a\", true), \"a\", true, 3, \"tuple\", (5,)]".to_owned()));
+    assert_eq!(run("fn div(a, b) { return (a / b, a - b); } let (q, d) = div(6, 3); [q, d]"), Ok("[2, 3]".to_owned()));
+    assert_eq!(run("(a, (b, c)) = (1, [2, 3]); [a, b, c]"), Ok("[1, 2, 3]".to_owned()));
+    //tuples aren't arrays, even with the same items
+    assert_eq!(run("[(1, 2) == (1, 2), (1, 2) == [1, 2], (1, 2) < (1, 3), set([(1, 2), (1, 2)]).len(), (0, 1).contains(1)]"), Ok("[true, false, true, 1, true]".to_owned()));
+    assert_eq!(run("t = (1, 2); t[0] = 5;"), Err("Tuples can't be changed, make a new one instead".to_owned()));
+    assert_eq!(run("(a, b) = (1, 2, 3);"), Err("Can't unpack 3 items into 2 names".to_owned()));
+    assert_eq!(run("(a, b) = 5;"), Err("Can't unpack 5, only tuples and arrays can be unpacked".to_owned()));
+    assert_eq!(run("(a, 1) = (1, 2);"), Err("Can't unpack into 1".to_owned()));
+}
+
+#[test]
+fn bytes() {
+    let run = |src: &str| crate::Program::compile(src).and_then(|p| p.run_with(&mut Runtime::new(), &Bindings::new())).map(|v| v.to_string());
+    assert_eq!(run("b = b\"hi\\xff\"; [b, b[0], b[-1], len(b), type(b), b[0..2], b + b\"!\"]"), Ok("[b\"hi\\xff\", 104, 255, 3, \"bytes\", b\"hi\", b\"hi\\xff!\"]".to_owned()));
+    assert_eq!(run("[bytes(\"é\"), bytes(\"é\", \"latin1\"), decode(b\"\\xe9\", \"latin1\"), \"ab\".to_bytes().decode(), bytes([0, 65]).to_array()]"), Ok("[b\"\\xc3\\xa9\", b\"\\xe9\", \"é\", \"ab\", [0, 65]]".to_owned()));
+    assert_eq!(run("[b\"a\" == b\"a\", b\"a\" < b\"b\", b\"\" || false, set([b\"a\", b\"a\"]).len()]"), Ok("[true, true, false, 1]".to_owned()));
+    assert_eq!(run("decode(b\"\\xff\");"), Err("The bytes aren't valid utf-8: invalid utf-8 sequence of 1 bytes from index 0".to_owned()));
+    assert_eq!(run("bytes([256]);"), Err("256 isn't a byte, bytes are whole numbers from 0 to 255".to_owned()));
+    assert_eq!(run("bytes(\"a\", \"utf-16\");"), Err("utf-16 isn't an encoding, expected one of utf-8, latin1, ascii".to_owned()));
+    assert_eq!(run("b = b\"a\"; b[0] = 1;"), Err("Bytes can't be changed by index, build new ones with + or bytes()".to_owned()));
+
+    let path = std::env::temp_dir().join("em_bytes_test.bin");
+    let mut frame = StackFrame::new();
+    frame.set_var("path".to_owned(), Value::EmString(path.display().to_string().into()));
+    let script = "write_file_bytes(path, b\"\\x00\\x01\\xfe\"); back = read_file_bytes(path); raw = open(path).read_bytes();";
+    repl_run(parser::parse(lexer::run(script)).unwrap(), &mut Runtime::new(), &mut frame).unwrap();
+    assert_eq!(std::fs::read(&path).unwrap(), vec![0, 1, 254]);
+    assert_eq!(*frame.get_var("back"), Value::Bytes(Arc::new(vec![0, 1, 254])));
+    assert_eq!(*frame.get_var("raw"), *frame.get_var("back"));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn text_encodings() {
+    let run = |src: &str| crate::Program::compile(src).and_then(|p| p.run_with(&mut Runtime::new(), &Bindings::new())).map(|v| v.to_string());
+    assert_eq!(
+        run("[base64_encode(\"\"), base64_encode(\"f\"), base64_encode(\"fo\"), base64_encode(\"foo\"), base64_encode(b\"\\xff\\xfe\")]"),
+        Ok("[\"\", \"Zg==\", \"Zm8=\", \"Zm9v\", \"//4=\"]".to_owned())
+    );
+    assert_eq!(run("[base64_decode(\"Zm9vYg==\").decode(), base64_decode(\"Zm9vYg\"), base64_decode(\"Zm9v YmFy\").decode()]"), Ok("[\"foob\", b\"foob\", \"foobar\"]".to_owned()));
+    assert_eq!(run("[hex_encode(\"Hi\"), hex_decode(\"00FF\"), url_encode(\"a b&c=é\"), url_decode(\"a+b%26c%3D%C3%A9\")]"), Ok("[\"4869\", b\"\\x00\\xff\", \"a%20b%26c%3D%C3%A9\", \"a b&c=é\"]".to_owned()));
+    assert_eq!(run("[utf8_valid(\"é\"), utf8_valid(b\"\\xc3\\xa9\"), utf8_valid(b\"\\xc3\")]"), Ok("[true, true, false]".to_owned()));
+    assert_eq!(run("base64_decode(\"Zm9v!\");"), Err("! at 4 isn't base64".to_owned()));
+    assert_eq!(run("base64_decode(\"Z\");"), Err("The base64 ends part way through a byte".to_owned()));
+    assert_eq!(run("hex_decode(\"abc\");"), Err("Hex needs two digits for every byte".to_owned()));
+    assert_eq!(run("url_decode(\"%4\");"), Err("The % at 0 isn't followed by two hex digits".to_owned()));
+}
+
+#[test]
+#[cfg(feature = "hashing")]
+fn hashing() {
+    let run = |src: &str| crate::Program::compile(src).and_then(|p| p.run_with(&mut Runtime::new(), &Bindings::new())).map(|v| v.to_string());
+    assert_eq!(
+        run("[md5(\"\"), md5(\"The quick brown fox jumps over the lazy dog\"), sha1(\"abc\"), crc32(\"123456789\")]"),
+        Ok("[\"d41d8cd98f00b204e9800998ecf8427e\", \"9e107d9d372bb6826bd81d3542a419d6\", \"a9993e364706816aba3e25717850c26c9cd0d89d\", \"cbf43926\"]".to_owned())
+    );
+    assert_eq!(
+        run("[sha256(b\"abc\"), hmac_sha256(\"key\", \"The quick brown fox jumps over the lazy dog\")]"),
+        Ok("[\"ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad\", \"f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8\"]".to_owned())
+    );
+    //long input spans more than one block
+    assert_eq!(run(&format!("sha1(\"{}\")", "a".repeat(100))), Ok("7f9000257a4918d7072655ea468540cdcbd42e0c".to_owned()));
+}
+
+#[test]
+fn exit_codes() {
+    let run = |src: &str| {
+        let (out, err) = (Sink::default(), Sink::default());
+        let mut runtime = Runtime::new();
+        runtime.set_stdout(Box::new(out.clone()));
+        runtime.set_stderr(Box::new(err.clone()));
+        let code = run_on(&mut runtime, parser::parse(lexer::run(src)).unwrap(), vec![]);
+        (code, out.contents(), err.contents())
+    };
+    assert_eq!(run("fn main(args) { println(1); }"), (0, "1\n".to_owned(), "".to_owned()));
+    //exit isn't an error, so nothing gets reported and assert_throws can't swallow it
+    assert_eq!(
+        run("fn stop() { exit(3); } fn main(args) { assert_throws(stop); println(\"after\"); }"),
+        (3, "".to_owned(), "".to_owned())
+    );
+    assert_eq!(run("exit(); fn main(args) { println(\"main\"); }").0, 0);
+    assert_eq!(run("async fn later() { exit(4); } fn main(args) { later(); }").0, 4);
+    //builtins that call back into the script don't get to carry on after an exit either
+    assert_eq!(
+        run("fn f(x) { exit(3); } fn main(args) { y = map([1, 2], f); println(\"after\", y); }"),
+        (3, "".to_owned(), "".to_owned())
+    );
+    let count = "class Count { fn ~init(self) { } fn ~next(self) { exit(5); } }";
+    assert_eq!(
+        run(&format!("{} fn main(args) {{ y = sort(new Count()); println(\"after\", y); }}", count)),
+        (5, "".to_owned(), "".to_owned())
+    );
+    let (code, _, err) = run("fn main(args) { x = [1][5]; }");
+    assert_eq!(code, 1);
+    assert!(err.starts_with("Interpreter crashed because:"), "{}", err);
+    let (code, _, err) = run("fn main(args) { exit(1.5); }");
+    assert_eq!(code, 1);
+    assert!(err.contains("Exit codes are whole numbers from 0 to 255, got 1.5"), "{}", err);
+}
+
+#[test]
+fn interrupts() {
+    let out = Sink::default();
+    let mut runtime = Runtime::new();
+    runtime.set_stdout(Box::new(out.clone()));
+    //stands in for someone pressing Ctrl-C partway through the loop
+    runtime.register_fn(
+        "press_ctrl_c",
+        Signature::ANY,
+        Arc::new(|_, _| {
+            unsafe { libc::raise(libc::SIGINT) };
+            Value::Null
+        }),
+    );
+    let script = "fn cleanup() { println(\"cleaning up\"); }
+    fn main(args) {
+        on_interrupt(cleanup);
+        for i in range(0, 100) { if i == 3 { press_ctrl_c(); } println(i); }
+        println(\"finished\");
+    }";
+    let code = run_on(&mut runtime, parser::parse(lexer::run(script)).unwrap(), vec![]);
+    assert_eq!(code, 130);
+    assert_eq!(out.contents(), "0\n1\n2\ncleaning up\n");
+
+    //Ctrl-C while waiting stops the wait instead of coming in after it's done
+    let handler = || unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        libc::sigaction(libc::SIGINT, std::ptr::null(), &mut action);
+        action.sa_sigaction
+    };
+    let before = handler();
+    let waits = [
+        "recv(channel());",
+        "fn forever() { recv(channel()); } thread_spawn(forever).join();",
+        "readln();",
+    ];
+    for wait in waits {
+        let out = Sink::default();
+        let mut runtime = Runtime::new();
+        runtime.set_stdout(Box::new(out.clone()));
+        //a read that keeps getting woken up by signals, like one waiting on a terminal
+        runtime.set_stdin(Box::new(Interrupted));
+        runtime.register_fn(
+            "ctrl_c_soon",
+            Signature::ANY,
+            Arc::new(|_, _| {
+                std::thread::spawn(|| {
+                    std::thread::sleep(std::time::Duration::from_millis(100));
+                    unsafe { libc::raise(libc::SIGINT) };
+                });
+                Value::Null
+            }),
+        );
+        let script = format!("fn cleanup() {{ println(\"cleaning up\"); }} fn main(args) {{ on_interrupt(cleanup); ctrl_c_soon(); {} println(\"after\"); }}", wait);
+        let code = run_on(&mut runtime, parser::parse(lexer::run(&script)).unwrap(), vec![]);
+        assert_eq!((code, out.contents()), (130, "cleaning up\n".to_owned()), "{}", wait);
+    }
+    //a runtime that's still catching Ctrl-C puts the old handler back when it goes away
+    let mut runtime = Runtime::new();
+    runtime.on_interrupt(Value::Null);
+    assert_ne!(handler(), before);
+    drop(runtime);
+    assert_eq!(handler(), before);
+}
+
+//stdin that never has anything, every read is woken up by a signal
+struct Interrupted;
+
+impl std::io::Read for Interrupted {
+    fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+        Err(std::io::ErrorKind::Interrupted.into())
+    }
+}
+
+impl std::io::BufRead for Interrupted {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        Err(std::io::ErrorKind::Interrupted.into())
+    }
+
+    fn consume(&mut self, _: usize) {}
+}
+
+#[test]
+fn diagnostics() {
+    let run = |src: &str, test: bool| {
+        let (out, err) = (Sink::default(), Sink::default());
+        let mut runtime = Runtime::new();
+        runtime.set_stdout(Box::new(out.clone()));
+        runtime.set_stderr(Box::new(err.clone()));
+        runtime.set_source("c.em", src);
+        if test {
+            crate::test_with(runtime, src.to_owned()).unwrap_or(0);
+        } else {
+            crate::run_with(runtime, src.to_owned(), vec![], false);
+        }
+        console::strip_ansi_codes(&(out.contents() + &err.contents())).into_owned()
+    };
+    let src = "fn total() {}\nfn main(args) {\n    print(totl());\n}\n";
+    assert_eq!(
+        run(src, false),
+        "Interpreter crashed because: Couldn't find identifier totl\n --> c.em:3:11\n  |\n3 |     print(totl());\n  |           ^^^^\n  = help: did you mean `totl` → `total`?\n"
+    );
+    let out = run("f(y);\nf(x y);\n", false);
+    assert!(out.starts_with("Expected \",\" or \")\", found Some(Ident(\"y\"))\n --> c.em:2:5\n"), "{}", out);
+    let out = run("fn test_it() {\n    assert(lenn([1]) == 1);\n}\n", true);
+    assert!(out.contains("test test_it ... FAILED: Couldn't find identifier lenn\n --> c.em:2:12\n"), "{}", out);
+    assert!(out.contains("did you mean `lenn` → `len`?"), "{}", out);
+    //the statement that was running is pointed at, not the first place the name shows up
+    let out = run("fn a() {\n    if false {\n        frob();\n    }\n}\nfn main(args) {\n    a();\n    frob(2);\n}\n", false);
+    assert!(out.contains(" --> c.em:8:5\n  |\n8 |     frob(2);\n  |     ^^^^\n"), "{}", out);
+    //errors that don't name anything underline the whole statement, and caught ones don't count
+    let out = run("fn bad() {\n    x = [][0];\n}\nfn main(args) {\n    assert_throws(bad); y = [1]\n        [5];\n}\n", false);
+    assert!(out.contains(" --> c.em:5:25\n  |\n5 |     assert_throws(bad); y = [1]\n  |                         ^^^^^^^\n"), "{}", out);
+    //without a source there's nothing to point at, but globals can still be suggested
+    let err = Sink::default();
+    let mut runtime = Runtime::new();
+    runtime.set_stderr(Box::new(err.clone()));
+    crate::run_with(runtime, src.to_owned(), vec![], false);
+    assert_eq!(err.contents(), "Interpreter crashed because: Couldn't find identifier totl\n  = help: did you mean `totl` → `total`?\n");
+}
+
+//a native whose method fails by raising, like one that hands its work to a builtin
+struct Raises;
+
+impl NativeObject for Raises {
+    fn type_name(&self) -> &str {
+        "raises"
+    }
+
+    fn call_method(&self, rt: &mut Runtime, _name: &str, _args: Vec<Value>) -> Result<Value, String> {
+        rt.raise("boom".to_owned());
+        Ok(Value::Null)
+    }
+}
+
+#[test]
+fn methods_that_raise() {
+    let run = |src: &str| {
+        let (out, err) = (Sink::default(), Sink::default());
+        let mut runtime = Runtime::new();
+        runtime.set_stdout(Box::new(out.clone()));
+        runtime.set_stderr(Box::new(err.clone()));
+        runtime.register_fn("raises", Signature::ANY, Arc::new(|_, _| Value::Native(Arc::new(Raises))));
+        let code = run_on(&mut runtime, parser::parse(lexer::run(src)).unwrap(), vec![]);
+        (code, out.contents(), err.contents())
+    };
+    //the error stops the script where it happened, instead of turning up at the next builtin
+    let (code, out, err) = run("fn main(args) { b = \"abc\".to_bytes(\"nope-enc\"); println(\"after\", b); x = 1 + 1; println(x); }");
+    assert_eq!((code, out.as_str()), (1, ""));
+    assert_eq!(err, "Interpreter crashed because: nope-enc isn't an encoding, expected one of utf-8, latin1, ascii\n");
+    let (code, out, err) = run("fn main(args) { r = raises(); r.go(); println(\"after\"); }");
+    assert_eq!((code, out.as_str(), err.as_str()), (1, "", "Interpreter crashed because: boom\n"));
+    let (code, out, _) = run("fn main(args) { for x in raises() { println(x); } println(\"after\"); }");
+    assert_eq!((code, out.as_str()), (1, ""));
+}
@@ -0,0 +1,304 @@
+use super::builtins::BuiltinFn;
+use super::native::NativeObject;
+use super::output::ThreadOutput;
+use super::trace::Trace;
+use super::{EmObject, LogLevel, NumericPolicy, OrderedMap, OrderedSet, Profile, Runtime, Value};
+use crate::lexer::Expression;
+use crate::parser::ExprNode;
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+//scripts can recurse deep, so threads get the same kind of room the main interpreter thread has
+const STACK_SIZE: usize = 64 * 1024 * 1024;
+
+///A deep copy of a value that can go to another thread. Values share their insides with `Arc`, so
+///sending them as they are would leave two runtimes changing the same objects
+pub(crate) enum Sendable {
+    Null,
+    Float(f64),
+    Str(String),
+    Bytes(Vec<u8>),
+    Bool(bool),
+    Array(Vec<Sendable>),
+    Tuple(Vec<Sendable>),
+    Range(f64, f64, f64),
+    Set(OrderedSet),
+    Name(String),
+    Function(Expression, Vec<Sendable>, ExprNode),
+    Object(SendObject),
+    Class(SendObject),
+    Channel(Channel),
+}
+
+pub(crate) struct SendObject {
+    members: Vec<(String, Sendable)>,
+    class: Option<Box<SendObject>>,
+}
+
+impl Sendable {
+    pub(crate) fn from_value(val: &Value) -> Result<Sendable, String> {
+        let all = |items: &[Value]| items.iter().map(Sendable::from_value).collect::<Result<Vec<_>, String>>();
+        Ok(match val {
+            Value::Null => Sendable::Null,
+            Value::Float(f) => Sendable::Float(*f),
+            Value::EmString(s) => Sendable::Str(s.to_string()),
+            Value::Bytes(b) => Sendable::Bytes(b.to_vec()),
+            Value::EmBool(b) => Sendable::Bool(*b),
+            Value::EmArray(items) => Sendable::Array(all(items)?),
+            Value::Tuple(items) => Sendable::Tuple(all(items)?),
+            Value::Range(a, b, c) => Sendable::Range(*a, *b, *c),
+            Value::EmSet(s) => Sendable::Set((**s).clone()),
+            Value::Name(n) => Sendable::Name(n.clone()),
+            Value::Function(name, params, body) => Sendable::Function(name.clone(), all(params)?, body.clone()),
+            Value::Object(obj) => Sendable::Object(SendObject::from_object(obj)?),
+            Value::Class(obj) => Sendable::Class(SendObject::from_object(obj)?),
+            Value::Native(n) => match n.as_any().and_then(|a| a.downcast_ref::<Channel>()) {
+                Some(ch) => Sendable::Channel(ch.clone()),
+                None => return Err(format!("Can't send {} to another thread", n.display())),
+            },
+        })
+    }
+
+    pub(crate) fn into_value(self) -> Value {
+        match self {
+            Sendable::Null => Value::Null,
+            Sendable::Float(f) => Value::Float(f),
+            Sendable::Str(s) => Value::EmString(s.into()),
+            Sendable::Bytes(b) => Value::Bytes(Arc::new(b)),
+            Sendable::Bool(b) => Value::EmBool(b),
+            Sendable::Array(items) => Value::EmArray(Arc::new(items.into_iter().map(Sendable::into_value).collect())),
+            Sendable::Tuple(items) => Value::Tuple(Arc::new(items.into_iter().map(Sendable::into_value).collect())),
+            Sendable::Range(a, b, c) => Value::Range(a, b, c),
+            Sendable::Set(s) => Value::EmSet(Arc::new(s)),
+            Sendable::Name(n) => Value::Name(n),
+            Sendable::Function(name, params, body) => {
+                Value::Function(name, params.into_iter().map(Sendable::into_value).collect(), body)
+            }
+            Sendable::Object(obj) => Value::Object(Arc::new(obj.into_object())),
+            Sendable::Class(obj) => Value::Class(Arc::new(obj.into_object())),
+            Sendable::Channel(ch) => Value::Native(Arc::new(ch)),
+        }
+    }
+}
+
+impl SendObject {
+    fn from_object(obj: &EmObject) -> Result<SendObject, String> {
+        Ok(SendObject {
+            members: obj
+                .members
+                .iter()
+                .map(|(k, v)| Ok((k.clone(), Sendable::from_value(v)?)))
+                .collect::<Result<Vec<_>, String>>()?,
+            class: match &obj.class {
+                Some(c) => Some(Box::new(SendObject::from_object(c)?)),
+                None => None,
+            },
+        })
+    }
+
+    fn into_object(self) -> EmObject {
+        let mut members = OrderedMap::new();
+        for (k, v) in self.members {
+            members.insert(k, Box::new(v.into_value()));
+        }
+        EmObject {
+            members,
+            class: self.class.map(|c| Arc::new(c.into_object())),
+        }
+    }
+}
+
+///Made by `channel()`. Every copy sends to and receives from the same queue, no matter which
+///thread it's on
+#[derive(Clone)]
+pub struct Channel {
+    tx: Sender<Sendable>,
+    rx: Arc<Mutex<Receiver<Sendable>>>,
+}
+
+impl Channel {
+    pub fn new() -> Channel {
+        let (tx, rx) = mpsc::channel();
+        Channel { tx, rx: Arc::new(Mutex::new(rx)) }
+    }
+
+    pub fn send(&self, val: &Value) -> Result<(), String> {
+        self.tx
+            .send(Sendable::from_value(val)?)
+            .map_err(|_| "The channel is closed".to_owned())
+    }
+
+    ///Waits for the next value anything sends. Gives up if `stopped` says the script was interrupted,
+    ///which gets checked every time the wait wakes up
+    pub fn recv(&self, stopped: impl Fn() -> bool) -> Result<Value, String> {
+        let rx = self.rx.lock().map_err(|_| "A thread crashed while using this channel".to_owned())?;
+        loop {
+            match rx.recv_timeout(POLL) {
+                Ok(val) => return Ok(val.into_value()),
+                Err(RecvTimeoutError::Timeout) if stopped() => return Err(INTERRUPTED.to_owned()),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return Err("The channel is closed".to_owned()),
+            }
+        }
+    }
+}
+
+impl Default for Channel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NativeObject for Channel {
+    fn type_name(&self) -> &str {
+        "channel"
+    }
+
+    fn call_method(&self, rt: &mut Runtime, name: &str, args: Vec<Value>) -> Result<Value, String> {
+        match name {
+            "send" => self.send(args.first().unwrap_or(&Value::Null)).map(|_| Value::Null),
+            "recv" => self.recv(|| rt.interrupt_pending()).or_else(|e| interrupted(rt, e)),
+            _ => Err(format!("channel has no method {}", name)),
+        }
+    }
+
+    fn as_any(&self) -> Option<&dyn Any> {
+        Some(self)
+    }
+}
+
+///Made by `thread_spawn`, `join()` waits for the thread and gives back what its function returned
+pub struct Thread(Mutex<Option<JoinHandle<Result<Sendable, String>>>>);
+
+impl NativeObject for Thread {
+    fn type_name(&self) -> &str {
+        "thread"
+    }
+
+    fn call_method(&self, rt: &mut Runtime, name: &str, _args: Vec<Value>) -> Result<Value, String> {
+        match name {
+            "join" => self.join(|| rt.interrupt_pending()).or_else(|e| interrupted(rt, e)),
+            _ => Err(format!("thread has no method {}", name)),
+        }
+    }
+}
+
+impl Thread {
+    ///Waits for the thread to finish, unless `stopped` says the script was interrupted
+    fn join(&self, stopped: impl Fn() -> bool) -> Result<Value, String> {
+        let mut handle = self.0.lock().map_err(|_| "The thread crashed".to_owned())?;
+        while handle.as_ref().is_some_and(|h| !h.is_finished()) {
+            if stopped() {
+                return Err(INTERRUPTED.to_owned());
+            }
+            std::thread::sleep(POLL);
+        }
+        match handle.take().map(JoinHandle::join) {
+            Some(Ok(res)) => res.map(Sendable::into_value),
+            Some(Err(_)) => Err("The thread crashed".to_owned()),
+            None => Err("This thread has already been joined".to_owned()),
+        }
+    }
+}
+
+//waiting gets woken up this often to see if Ctrl-C was pressed
+const POLL: Duration = Duration::from_millis(50);
+const INTERRUPTED: &str = "Interrupted while waiting";
+
+//a wait that gave up because of Ctrl-C runs the script's handler and stops it
+fn interrupted(rt: &mut Runtime, e: String) -> Result<Value, String> {
+    rt.check_interrupt()?;
+    Err(e)
+}
+
+///Everything about how a runtime is set up, so a thread's runtime works the same as the one that
+///started it. What the script has done so far, like its variables and tasks, isn't part of it.
+///Settings added to `Runtime` need to go here too
+pub(crate) struct Settings {
+    functions: HashMap<String, BuiltinFn>,
+    profile: Profile,
+    allow_exec: bool,
+    dry_run: bool,
+    strict: bool,
+    max_depth: usize,
+    warn_redefinitions: bool,
+    log_level: LogLevel,
+    numeric: NumericPolicy,
+    print_sep: String,
+    stop: Option<Arc<AtomicBool>>,
+    output: ThreadOutput,
+    trace: Trace,
+}
+
+impl Runtime {
+    pub(crate) fn settings(&mut self) -> Settings {
+        Settings {
+            functions: self.functions.clone(),
+            profile: self.profile,
+            allow_exec: self.allow_exec,
+            dry_run: self.dry_run,
+            strict: self.strict,
+            max_depth: self.max_depth,
+            warn_redefinitions: self.warn_redefinitions,
+            log_level: self.log_level,
+            numeric: self.numeric,
+            print_sep: self.print_sep.clone(),
+            stop: self.stop.clone(),
+            output: self.thread_output(),
+            trace: self.trace.share(),
+        }
+    }
+
+    pub(crate) fn with_settings(settings: Settings) -> Runtime {
+        let mut rt = Runtime::new();
+        rt.functions = settings.functions;
+        rt.profile = settings.profile;
+        rt.allow_exec = settings.allow_exec;
+        rt.dry_run = settings.dry_run;
+        rt.strict = settings.strict;
+        rt.max_depth = settings.max_depth;
+        rt.warn_redefinitions = settings.warn_redefinitions;
+        rt.log_level = settings.log_level;
+        rt.numeric = settings.numeric;
+        rt.print_sep = settings.print_sep;
+        rt.stop = settings.stop;
+        rt.use_output(settings.output);
+        rt.trace = settings.trace;
+        rt
+    }
+
+    ///Calls `func` on a new thread with its own runtime. The runtime gets copies of every function and
+    ///class this one has, and the same builtins and settings. It writes to the same sinks, shares the
+    ///output limit, and reads and records inputs through the same trace
+    pub(crate) fn spawn_thread(&mut self, func: &Value, args: &[Value]) -> Result<Value, String> {
+        let func = Sendable::from_value(func)?;
+        let args = args.iter().map(Sendable::from_value).collect::<Result<Vec<_>, String>>()?;
+        let heap = self
+            .heap
+            .iter()
+            .map(|(k, v)| Ok((k.clone(), Sendable::from_value(v)?)))
+            .collect::<Result<Vec<_>, String>>()?;
+        let settings = self.settings();
+        let handle = std::thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn(move || {
+                let mut rt = Runtime::with_settings(settings);
+                for (name, val) in heap {
+                    rt.define(&name, val.into_value());
+                }
+                let args = args.into_iter().map(Sendable::into_value).collect();
+                let res = rt.call_function(&func.into_value(), args)?;
+                //an async function gives back a task, which has to finish here before the thread does
+                let res = rt.wait_for(res)?;
+                rt.run_tasks()?;
+                Sendable::from_value(&res)
+            })
+            .map_err(|e| format!("Couldn't start a thread: {}", e))?;
+        Ok(Value::Native(Arc::new(Thread(Mutex::new(Some(handle))))))
+    }
+}
@@ -0,0 +1,128 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+///Keeps track of nondeterministic inputs so a run can be reproduced later.
+///A trace file has one entry per line in the form `kind value`, where a missing value means null
+pub enum Trace {
+    Off,
+    Record(Box<dyn Write + Send + Sync>),
+    Replay(VecDeque<(String, Option<String>)>),
+    ///One of the others, used by a runtime and the threads it started so they all read from and
+    ///write to the same trace
+    Shared(Arc<Mutex<Trace>>),
+}
+
+impl Trace {
+    pub fn record(path: &str) -> io::Result<Trace> {
+        Ok(Trace::Record(Box::new(File::create(path)?)))
+    }
+
+    pub fn replay(path: &str) -> io::Result<Trace> {
+        let data = std::fs::read_to_string(path)?;
+        let entries = data
+            .lines()
+            .map(|l| match l.find(' ') {
+                Some(i) => (l[..i].to_owned(), Some(unescape(&l[i + 1..]))),
+                None => (l.to_owned(), None),
+            })
+            .collect();
+        Ok(Trace::Replay(entries))
+    }
+
+    ///Gives back a trace that uses this one, turning it into a shared one first if it isn't already
+    pub fn share(&mut self) -> Trace {
+        if let Trace::Off = self {
+            return Trace::Off;
+        }
+        if !matches!(self, Trace::Shared(_)) {
+            let inner = std::mem::replace(self, Trace::Off);
+            *self = Trace::Shared(Arc::new(Mutex::new(inner)));
+        }
+        match self {
+            Trace::Shared(t) => Trace::Shared(t.clone()),
+            _ => Trace::Off,
+        }
+    }
+
+    pub fn flush(&mut self) {
+        match self {
+            Trace::Record(out) => out.flush().unwrap_or(()),
+            Trace::Shared(t) => t.lock().unwrap_or_else(|e| e.into_inner()).flush(),
+            _ => {}
+        }
+    }
+
+    ///Produces an input of the given kind, either by asking `live` for it or by taking it from the trace
+    pub fn input<F>(&mut self, kind: &str, live: F) -> Option<String>
+    where
+        F: FnOnce() -> Option<String>,
+    {
+        match self {
+            Trace::Off => live(),
+            Trace::Shared(t) => t.lock().unwrap_or_else(|e| e.into_inner()).input(kind, live),
+            Trace::Record(out) => {
+                let val = live();
+                match &val {
+                    Some(v) => writeln!(out, "{} {}", kind, escape(v)),
+                    None => writeln!(out, "{}", kind),
+                }
+                .unwrap_or(());
+                val
+            }
+            Trace::Replay(entries) => match entries.pop_front() {
+                Some((k, v)) if k == kind => v,
+                Some((k, _)) => {
+                    eprintln!("Replay diverged: expected {} input, trace has {}", kind, k);
+                    live()
+                }
+                None => {
+                    eprintln!("Replay trace ran out while reading {} input", kind);
+                    live()
+                }
+            },
+        }
+    }
+
+    ///Like `input`, for inputs that can fail, like reading a file. The error gets recorded instead of
+    ///the value, so a replay fails in the same place with the same message
+    pub fn fallible<F>(&mut self, kind: &str, live: F) -> Result<String, String>
+    where
+        F: FnOnce() -> Result<String, String>,
+    {
+        let val = self.input(kind, || {
+            Some(match live() {
+                Ok(v) => format!("+{}", v),
+                Err(e) => format!("-{}", e),
+            })
+        });
+        match val {
+            Some(v) if v.starts_with('+') => Ok(v[1..].to_owned()),
+            Some(v) if v.starts_with('-') => Err(v[1..].to_owned()),
+            _ => Err(format!("The replay trace has no {} input here", kind)),
+        }
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('\n', "\\n").replace('\r', "\\r")
+}
+
+fn unescape(s: &str) -> String {
+    let mut res = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('n') => res.push('\n'),
+                Some('r') => res.push('\r'),
+                Some(o) => res.push(o),
+                None => res.push('\\'),
+            }
+        } else {
+            res.push(c);
+        }
+    }
+    res
+}
@@ -1,11 +1,17 @@
 use crate::interpreter::Value;
-use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::iter::FromIterator;
+use std::sync::Arc;
 
+///Negative indices count back from the end, so `-1` is the last item. Anything past either end is
+///an error instead of wrapping around or giving back null
 pub trait Indexable<T> {
-    fn index<'a>(&'a self, index: usize) -> Result<&'a T, String>;
+    fn index(&self, index: f64) -> Result<T, String>;
 
-    fn index_mut<'a>(&'a mut self, index: usize) -> Result<&'a mut T, String>;
+    fn index_mut(&mut self, index: f64) -> Result<&mut T, String>;
+
+    ///Looks up a value by name instead of position, like `obj["prop"]`
+    fn index_key<'a>(&'a self, key: &str) -> Result<&'a T, String>;
 }
 
 pub trait Valuable {
@@ -13,26 +19,144 @@ pub trait Valuable {
     fn set_value(&mut self, val: Value);
 }
 
+///Property names are ordinary runtime strings, so they can be computed while the script runs
 pub trait Object {
-    fn get_prop(&self, prop: &'static str) -> Option<&dyn Valuable>;
+    fn get_prop(&self, prop: &str) -> Option<&dyn Valuable>;
     fn set_prop(&mut self, prop: &str, val: Box<dyn Valuable>);
 }
+
+///A map that always iterates in the order keys were first inserted. Anything in the language
+///that acts like a map should use this so printing and iterating never depends on hashing
+#[cfg_attr(not(feature = "debug-alloc"), derive(Clone))]
+pub struct OrderedMap<V> {
+    keys: Vec<String>,
+    map: HashMap<String, V>,
+}
+
+#[cfg(feature = "debug-alloc")]
+impl<V: Clone> Clone for OrderedMap<V> {
+    #[track_caller]
+    fn clone(&self) -> Self {
+        crate::alloc_stats::record(crate::alloc_stats::Kind::Map, std::panic::Location::caller());
+        OrderedMap {
+            keys: self.keys.clone(),
+            map: self.map.clone(),
+        }
+    }
+}
+
+impl<V> OrderedMap<V> {
+    #[cfg_attr(feature = "debug-alloc", track_caller)]
+    pub fn new() -> OrderedMap<V> {
+        #[cfg(feature = "debug-alloc")]
+        crate::alloc_stats::record(crate::alloc_stats::Kind::Map, std::panic::Location::caller());
+        OrderedMap {
+            keys: vec![],
+            map: HashMap::new(),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&V> {
+        self.map.get(key)
+    }
+
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut V> {
+        self.map.get_mut(key)
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.map.contains_key(key)
+    }
+
+    ///Replacing the value of an existing key keeps its original position
+    pub fn insert(&mut self, key: String, val: V) -> Option<V> {
+        if !self.map.contains_key(&key) {
+            self.keys.push(key.clone());
+        }
+        self.map.insert(key, val)
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<V> {
+        let val = self.map.remove(key)?;
+        self.keys.retain(|k| k != key);
+        Some(val)
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &String> {
+        self.keys.iter()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &V)> {
+        self.keys.iter().map(move |k| (k, &self.map[k]))
+    }
+}
+
+impl<V> Default for OrderedMap<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V: std::fmt::Debug> std::fmt::Debug for OrderedMap<V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+//two maps with the same entries are equal no matter what order they were built in
+impl<V: PartialEq> PartialEq for OrderedMap<V> {
+    fn eq(&self, other: &OrderedMap<V>) -> bool {
+        self.len() == other.len() && self.iter().all(|(k, v)| other.get(k) == Some(v))
+    }
+}
+
+///Instances only hold their own data, anything they don't have (like methods) is looked up on their class
 #[derive(Debug, Clone, PartialEq)]
 pub struct EmObject {
-    pub members: HashMap<String, Box<Value>>,
+    pub members: OrderedMap<Box<Value>>,
+    pub class: Option<Arc<EmObject>>,
 }
 
 impl EmObject {
+    pub fn new(members: OrderedMap<Box<Value>>) -> EmObject {
+        EmObject {
+            members,
+            class: None,
+        }
+    }
+
+    ///Creates an empty object that shares the methods of the provided class
+    pub fn instance_of(class: Arc<EmObject>) -> EmObject {
+        EmObject {
+            members: OrderedMap::new(),
+            class: Some(class),
+        }
+    }
+
     pub fn get_prop(&self, prop: &str) -> Option<&Value> {
         if let Some(val) = self.members.get(prop) {
             Some(&**val)
+        } else if let Some(class) = &self.class {
+            class.get_prop(prop)
         } else {
             None
         }
     }
 
-    pub fn set_prop(&mut self, prop: String, val: Box<Value>) {
-        self.members.insert(prop, val);
+    pub fn set_prop(&mut self, prop: &str, val: Value) {
+        self.members.insert(prop.to_owned(), Box::new(val));
+    }
+
+    pub fn remove_prop(&mut self, prop: &str) -> Option<Box<Value>> {
+        self.members.remove(prop)
     }
 }
 
@@ -41,14 +165,119 @@ impl std::fmt::Display for EmObject {
         if let Some(v) = self.get_prop("~name"){
             write!(f, "{}", v)
         }else {
-            write!(f, "{:?}", self)
+            //only the object's own members, in order, so the output is the same every run
+            write!(f, "{:?}", self.members)
+        }
+    }
+}
+
+///The values that can be used to look things up in a map or set. Strings, bytes, numbers, bools,
+///and arrays or tuples of those qualify. Changing an array makes a new one, so none of them can change after they're
+///used as a key, and equal values always hash the same
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum MapKey {
+    Str(String),
+    //stored as bits so it can be hashed, -0.0 is turned into 0.0 first since they're equal
+    Num(u64),
+    Bool(bool),
+    Bytes(Vec<u8>),
+    Array(Vec<MapKey>),
+    Tuple(Vec<MapKey>),
+}
+
+impl MapKey {
+    pub fn from_value(val: &Value) -> Result<MapKey, String> {
+        match val {
+            Value::EmString(s) => Ok(MapKey::Str(s.to_string())),
+            Value::Name(s) => Ok(MapKey::Str(s.clone())),
+            Value::Float(f) if f.is_nan() => Err("NaN can't be used as a key".to_owned()),
+            Value::Float(f) => Ok(MapKey::Num(if *f == 0.0 { 0.0f64 } else { *f }.to_bits())),
+            Value::EmBool(b) => Ok(MapKey::Bool(*b)),
+            Value::Bytes(b) => Ok(MapKey::Bytes(b.to_vec())),
+            Value::EmArray(items) => Ok(MapKey::Array(items.iter().map(MapKey::from_value).collect::<Result<_, _>>()?)),
+            Value::Tuple(items) => Ok(MapKey::Tuple(items.iter().map(MapKey::from_value).collect::<Result<_, _>>()?)),
+            Value::Null => Err("null can't be used as a key".to_owned()),
+            other => Err(format!("{} can't be used as a key, only strings, bytes, numbers, bools, and arrays or tuples of those can", other)),
+        }
+    }
+
+    pub fn to_value(&self) -> Value {
+        match self {
+            MapKey::Str(s) => Value::EmString(s.as_str().into()),
+            MapKey::Num(bits) => Value::Float(f64::from_bits(*bits)),
+            MapKey::Bool(b) => Value::EmBool(*b),
+            MapKey::Bytes(b) => Value::Bytes(Arc::new(b.clone())),
+            MapKey::Array(items) => Value::EmArray(Arc::new(items.iter().map(MapKey::to_value).collect())),
+            MapKey::Tuple(items) => Value::Tuple(Arc::new(items.iter().map(MapKey::to_value).collect())),
+        }
+    }
+}
+
+impl std::fmt::Display for MapKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_value())
+    }
+}
+
+///A set of keys that remembers the order they were added in, for the same reason as `OrderedMap`
+#[derive(Debug, Clone, Default)]
+pub struct OrderedSet {
+    keys: Vec<MapKey>,
+    set: HashSet<MapKey>,
+}
+
+impl OrderedSet {
+    pub fn new() -> OrderedSet {
+        OrderedSet::default()
+    }
+
+    ///Gives back false if the key was already there, which leaves it where it was
+    pub fn insert(&mut self, key: MapKey) -> bool {
+        if self.set.contains(&key) {
+            return false;
         }
+        self.keys.push(key.clone());
+        self.set.insert(key)
+    }
+
+    pub fn remove(&mut self, key: &MapKey) -> bool {
+        if !self.set.remove(key) {
+            return false;
+        }
+        self.keys.retain(|k| k != key);
+        true
+    }
+
+    pub fn contains(&self, key: &MapKey) -> bool {
+        self.set.contains(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &MapKey> {
+        self.keys.iter()
     }
 }
 
-//This is probably really bad but idk how else to compare them
-impl PartialOrd for EmObject {
-    fn partial_cmp(&self, other: &EmObject) -> Option<Ordering> {
-        Some(self.members.len().cmp(&other.members.len()))
+//like maps, the order things were added in doesn't matter for equality
+impl PartialEq for OrderedSet {
+    fn eq(&self, other: &OrderedSet) -> bool {
+        self.set == other.set
+    }
+}
+
+impl FromIterator<MapKey> for OrderedSet {
+    fn from_iter<I: IntoIterator<Item = MapKey>>(iter: I) -> OrderedSet {
+        let mut set = OrderedSet::new();
+        for key in iter {
+            set.insert(key);
+        }
+        set
     }
 }
@@ -0,0 +1,94 @@
+use super::{Runtime, StackFrame};
+use crate::lexer::{self, Expression};
+use crate::parser::{self, ExprNode};
+use std::io::Write;
+
+///An expression that gets looked at again after every statement
+pub(crate) struct Watch {
+    src: String,
+    node: ExprNode,
+    //what it showed last time, so it's only printed when that changes
+    last: Option<String>,
+}
+
+///Names the expression reads, not counting property names after a dot
+fn names<'a>(node: &'a ExprNode, out: &mut Vec<&'a str>) {
+    match node {
+        ExprNode::Name(n) => out.push(n.as_str()),
+        ExprNode::Operation(op, a, b) => {
+            names(a, out);
+            if **op != Expression::Operator('.') {
+                names(b, out);
+            }
+        }
+        ExprNode::Index(a, b) => {
+            names(a, out);
+            names(b, out);
+        }
+        ExprNode::Unary(_, a) => names(a, out),
+        ExprNode::Array(items) | ExprNode::Tuple(items) | ExprNode::Call(_, items) => items.iter().for_each(|i| names(i, out)),
+        ExprNode::MethodCall(target, args) | ExprNode::CallExpr(target, args) => {
+            names(target, out);
+            args.iter().for_each(|a| names(a, out));
+        }
+        _ => {}
+    }
+}
+
+fn assigns(node: &ExprNode) -> bool {
+    match node {
+        ExprNode::Operation(op, a, b) => {
+            matches!(**op, Expression::Equal | Expression::CompoundOp(_)) || assigns(a) || assigns(b)
+        }
+        ExprNode::Index(a, b) => assigns(a) || assigns(b),
+        ExprNode::Unary(_, a) => assigns(a),
+        ExprNode::Array(items) | ExprNode::Tuple(items) | ExprNode::Call(_, items) => items.iter().any(assigns),
+        ExprNode::MethodCall(target, args) | ExprNode::CallExpr(target, args) => assigns(target) || args.iter().any(assigns),
+        _ => false,
+    }
+}
+
+impl Runtime {
+    ///Looks at `src` again after every statement and writes `src = value` to the error stream each time
+    ///the value changes. It's only looked at where every name in it can be seen, so a watch on a
+    ///global stays quiet inside functions instead of flipping to null and back
+    pub fn add_watch(&mut self, src: &str) -> Result<(), String> {
+        let node = parser::parse_single(lexer::run(&format!("{}\n", src)))?;
+        if assigns(&node) {
+            return Err(format!("Watching {} would change something every time it's looked at", src.trim()));
+        }
+        self.watches.push(Watch { src: src.trim().to_owned(), node, last: None });
+        Ok(())
+    }
+
+    ///What's being watched, in the order it was added
+    pub fn watches(&self) -> Vec<String> {
+        self.watches.iter().map(|w| w.src.clone()).collect()
+    }
+
+    pub fn clear_watches(&mut self) {
+        self.watches.clear();
+    }
+
+    ///Prints the watches whose values changed since they were last looked at
+    pub(crate) fn check_watches(&mut self, frame: &mut StackFrame) {
+        //taken out while they run so a watch that calls a function doesn't check them all again
+        let mut watches = std::mem::take(&mut self.watches);
+        for watch in watches.iter_mut() {
+            let mut used = vec![];
+            names(&watch.node, &mut used);
+            if !used.iter().all(|n| frame.lookup(n).is_some() || self.heap.contains_key(*n)) {
+                continue;
+            }
+            let shown = match self.walk_tree(&watch.node, frame) {
+                Ok(val) => val.to_string(),
+                Err(e) => format!("<error: {}>", e),
+            };
+            if watch.last.as_ref() != Some(&shown) {
+                writeln!(self.stderr, "[watch] {} = {}", watch.src, shown).unwrap_or(());
+                watch.last = Some(shown);
+            }
+        }
+        self.watches = watches;
+    }
+}
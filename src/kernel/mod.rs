@@ -0,0 +1,524 @@
+//! A Jupyter kernel, so notebooks can run EmeraldScript. Jupyter starts it with a connection file
+//! saying which ports to listen on, then every cell runs in the same runtime so variables and
+//! functions stick around between cells like they do in the REPL. Output is streamed back a line
+//! at a time while a cell runs
+//!
+//! Only the parts of the messaging protocol a notebook needs are handled: kernel info, execute,
+//! is_complete, comm info, interrupt, and shutdown. Completion, inspection, and history get answered
+//! with nothing found, so clients that ask don't sit waiting. Interrupts come in on the control channel, which
+//! gets its own thread so they can stop a cell that's still running. Scripts can't ask for input yet
+
+mod zmtp;
+
+#[cfg(test)]
+mod tests;
+
+use crate::digest;
+use crate::lexer;
+use crate::Engine;
+use serde_json::{json, Value as Json};
+use std::io::{self, Write};
+use std::net::TcpListener;
+use std::sync::mpsc::{self, Sender};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+use zmtp::Socket;
+
+const DELIMITER: &[u8] = b"<IDS|MSG>";
+const PROTOCOL_VERSION: &str = "5.3";
+
+///The parts of the connection file we care about
+struct Connection {
+    ip: String,
+    key: String,
+    shell_port: u64,
+    iopub_port: u64,
+    stdin_port: u64,
+    control_port: u64,
+    hb_port: u64,
+}
+
+impl Connection {
+    fn load(path: &str) -> Result<Connection, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("Couldn't read {}: {}", path, e))?;
+        let json: Json = serde_json::from_str(&text).map_err(|e| format!("{} isn't valid JSON: {}", path, e))?;
+        let port = |name: &str| {
+            json[name]
+                .as_u64()
+                .ok_or_else(|| format!("The connection file is missing {}", name))
+        };
+        let transport = json["transport"].as_str().unwrap_or("tcp");
+        if transport != "tcp" {
+            return Err(format!("Only tcp connections are supported, got {}", transport));
+        }
+        let scheme = json["signature_scheme"].as_str().unwrap_or("hmac-sha256");
+        if scheme != "hmac-sha256" {
+            return Err(format!("Only hmac-sha256 signatures are supported, got {}", scheme));
+        }
+        Ok(Connection {
+            ip: json["ip"].as_str().unwrap_or("127.0.0.1").to_owned(),
+            key: json["key"].as_str().unwrap_or_default().to_owned(),
+            shell_port: port("shell_port")?,
+            iopub_port: port("iopub_port")?,
+            stdin_port: port("stdin_port")?,
+            control_port: port("control_port")?,
+            hb_port: port("hb_port")?,
+        })
+    }
+
+    fn bind(&self, port: u64) -> Result<TcpListener, String> {
+        let addr = format!("{}:{}", self.ip, port);
+        TcpListener::bind(&addr).map_err(|e| format!("Couldn't listen on {}: {}", addr, e))
+    }
+}
+
+///A request from Jupyter, already checked against its signature
+struct Message {
+    ids: Vec<Vec<u8>>,
+    header: Json,
+    content: Json,
+}
+
+impl Message {
+    fn parse(parts: Vec<Vec<u8>>, key: &[u8]) -> Result<Message, String> {
+        let split = parts
+            .iter()
+            .position(|p| p == DELIMITER)
+            .ok_or("Message is missing its delimiter")?;
+        let rest = &parts[split + 1..];
+        if rest.len() < 5 {
+            return Err("Message is missing some of its parts".to_owned());
+        }
+        if !key.is_empty() {
+            let parts: Vec<&[u8]> = rest[1..5].iter().map(|p| p.as_slice()).collect();
+            if !digest::verify(key, &parts, &rest[0]) {
+                return Err("Message has an invalid signature".to_owned());
+            }
+        }
+        let json = |p: &[u8]| serde_json::from_slice::<Json>(p).map_err(|e| format!("Message isn't valid JSON: {}", e));
+        Ok(Message {
+            ids: parts[..split].to_vec(),
+            header: json(&rest[1])?,
+            content: json(&rest[4])?,
+        })
+    }
+
+    fn msg_type(&self) -> &str {
+        self.header["msg_type"].as_str().unwrap_or_default()
+    }
+}
+
+///Builds and signs messages. Everything the kernel sends goes through here so the headers and
+///signatures are always right
+#[derive(Clone)]
+struct Session {
+    key: Arc<[u8]>,
+    id: Arc<str>,
+    sent: Arc<AtomicU64>,
+}
+
+impl Session {
+    fn new(key: &str) -> Session {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or_default();
+        Session {
+            key: key.as_bytes().into(),
+            id: format!("{:x}-{:x}", std::process::id(), nanos).into(),
+            sent: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    fn frames(&self, ids: &[Vec<u8>], msg_type: &str, parent: &Json, content: Json) -> Vec<Vec<u8>> {
+        let sent = self.sent.fetch_add(1, Ordering::Relaxed) + 1;
+        let header = json!({
+            "msg_id": format!("{}-{}", self.id, sent),
+            "session": &*self.id,
+            "username": "kernel",
+            "date": timestamp(),
+            "msg_type": msg_type,
+            "version": PROTOCOL_VERSION,
+        });
+        let body: Vec<Vec<u8>> = [header, parent.clone(), json!({}), content]
+            .iter()
+            .map(|j| j.to_string().into_bytes())
+            .collect();
+        let parts: Vec<&[u8]> = body.iter().map(|p| p.as_slice()).collect();
+        let mut frames = ids.to_vec();
+        frames.push(DELIMITER.to_vec());
+        frames.push(digest::hmac_sha256(&self.key, &parts).into_bytes());
+        frames.extend(body);
+        frames
+    }
+}
+
+///Sends messages to everyone listening on the IOPub channel, which is where output goes
+#[derive(Clone)]
+struct Publisher {
+    session: Session,
+    subscribers: Arc<Mutex<Vec<Socket>>>,
+    //the request that caused whatever's being published
+    parent: Arc<Mutex<Json>>,
+}
+
+impl Publisher {
+    fn publish(&self, msg_type: &str, content: Json) {
+        let topic = format!("kernel.{}.{}", self.session.id, msg_type).into_bytes();
+        let frames = self.session.frames(&[topic], msg_type, &self.parent.lock().unwrap_or_else(|e| e.into_inner()), content);
+        let mut subs = self.subscribers.lock().unwrap_or_else(|e| e.into_inner());
+        //anyone who disconnected just stops getting messages
+        subs.retain_mut(|s| s.send(&frames).is_ok());
+    }
+}
+
+///Where a script's output goes while a cell is running, it gets published a line at a time
+#[derive(Clone)]
+struct Stream {
+    name: &'static str,
+    publisher: Publisher,
+    buf: Arc<Mutex<Vec<u8>>>,
+}
+
+impl Stream {
+    fn send(&self, upto: usize) {
+        let text: Vec<u8> = self.buf.lock().unwrap_or_else(|e| e.into_inner()).drain(..upto).collect();
+        if !text.is_empty() {
+            let text = String::from_utf8_lossy(&text);
+            self.publisher.publish("stream", json!({ "name": self.name, "text": text }));
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let line_end = {
+            let mut buf = self.buf.lock().unwrap_or_else(|e| e.into_inner());
+            buf.extend_from_slice(data);
+            buf.iter().rposition(|b| *b == b'\n')
+        };
+        if let Some(end) = line_end {
+            self.send(end + 1);
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let len = self.buf.lock().unwrap_or_else(|e| e.into_inner()).len();
+        self.send(len);
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Channel {
+    Shell,
+    Control,
+    Stdin,
+}
+
+type Request = (Channel, Socket, Vec<Vec<u8>>);
+
+struct Kernel {
+    engine: Engine,
+    publisher: Publisher,
+    stdout: Stream,
+    stderr: Stream,
+    //stops the cell that's running, set from the control thread
+    stop: Arc<AtomicBool>,
+    execution_count: u64,
+}
+
+impl Kernel {
+    fn new(publisher: Publisher) -> Kernel {
+        let stream = |name| Stream {
+            name,
+            publisher: publisher.clone(),
+            buf: Arc::new(Mutex::new(vec![])),
+        };
+        let (stdout, stderr) = (stream("stdout"), stream("stderr"));
+        let mut engine = Engine::new();
+        let stop = engine.runtime().stop_flag();
+        engine.runtime().set_stdout(Box::new(stdout.clone()));
+        engine.runtime().set_stderr(Box::new(stderr.clone()));
+        Kernel {
+            engine,
+            publisher,
+            stdout,
+            stderr,
+            stop,
+            execution_count: 0,
+        }
+    }
+
+    ///Handles one request, giving back false once the kernel should shut down
+    fn handle(&mut self, channel: Channel, reply_to: &mut Socket, msg: Message) -> bool {
+        if channel == Channel::Stdin {
+            return true;
+        }
+        *self.publisher.parent.lock().unwrap_or_else(|e| e.into_inner()) = msg.header.clone();
+        self.publisher.publish("status", json!({ "execution_state": "busy" }));
+        let (reply, keep_going) = match msg.msg_type() {
+            "kernel_info_request" => (Some(kernel_info()), true),
+            "execute_request" => (Some(self.execute(&msg.content)), true),
+            "is_complete_request" => (Some(is_complete(msg.content["code"].as_str().unwrap_or_default())), true),
+            "comm_info_request" => (Some(json!({ "status": "ok", "comms": {} })), true),
+            "complete_request" => {
+                let cursor = &msg.content["cursor_pos"];
+                (Some(json!({ "status": "ok", "matches": [], "cursor_start": cursor, "cursor_end": cursor, "metadata": {} })), true)
+            }
+            "inspect_request" => (Some(json!({ "status": "ok", "found": false, "data": {}, "metadata": {} })), true),
+            "history_request" => (Some(json!({ "status": "ok", "history": [] })), true),
+            "shutdown_request" => (Some(json!({ "status": "ok", "restart": msg.content["restart"] })), false),
+            _ => (None, true),
+        };
+        if let Some(content) = reply {
+            let reply_type = msg.msg_type().replace("_request", "_reply");
+            let frames = self.publisher.session.frames(&msg.ids, &reply_type, &msg.header, content);
+            //if the client is gone there's nobody left to tell
+            reply_to.send(&frames).ok();
+        }
+        self.publisher.publish("status", json!({ "execution_state": "idle" }));
+        keep_going
+    }
+
+    fn execute(&mut self, content: &Json) -> Json {
+        let code = content["code"].as_str().unwrap_or_default();
+        let silent = content["silent"].as_bool().unwrap_or(false);
+        if !silent {
+            self.execution_count += 1;
+            self.publisher.publish("execute_input", json!({ "code": code, "execution_count": self.execution_count }));
+        }
+        //an interrupt that came in between cells isn't meant for this one, and neither is an exit
+        self.stop.store(false, Ordering::SeqCst);
+        self.engine.runtime().clear_exit();
+        let res = self.engine.eval(code);
+        self.stdout.flush().ok();
+        self.stderr.flush().ok();
+        match res {
+            Ok(val) => {
+                if !silent && val != crate::interpreter::Value::Null {
+                    self.publisher.publish(
+                        "execute_result",
+                        json!({
+                            "execution_count": self.execution_count,
+                            "data": { "text/plain": format!("{}", val) },
+                            "metadata": {},
+                        }),
+                    );
+                }
+                json!({
+                    "status": "ok",
+                    "execution_count": self.execution_count,
+                    "payload": [],
+                    "user_expressions": {},
+                })
+            }
+            Err(e) => {
+                let error = json!({ "ename": "Error", "evalue": e, "traceback": [e] });
+                if !silent {
+                    self.publisher.publish("error", error.clone());
+                }
+                let mut reply = error;
+                reply["status"] = json!("error");
+                reply["execution_count"] = json!(self.execution_count);
+                reply
+            }
+        }
+    }
+}
+
+fn kernel_info() -> Json {
+    json!({
+        "status": "ok",
+        "protocol_version": PROTOCOL_VERSION,
+        "implementation": "gem",
+        "implementation_version": crate::version(),
+        "language_info": {
+            "name": "emeraldscript",
+            "version": crate::version(),
+            "mimetype": "text/x-emeraldscript",
+            "file_extension": ".em",
+        },
+        "banner": format!("EmeraldScript {}", crate::version()),
+    })
+}
+
+///A cell is incomplete if it still has brackets, a string, or a block comment open, so the console
+///can keep reading lines. Brackets in comments don't count
+fn is_complete(code: &str) -> Json {
+    let (open, close) = lexer::BLOCK_COMMENT;
+    let mut depth = 0i32;
+    let mut comments = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut rest = code;
+    while let Some(c) = rest.chars().next() {
+        let mut len = c.len_utf8();
+        if comments > 0 {
+            if rest.starts_with(open) {
+                comments += 1;
+                len = open.len();
+            } else if rest.starts_with(close) {
+                comments -= 1;
+                len = close.len();
+            }
+        } else if escaped {
+            escaped = false;
+        } else if in_string {
+            match c {
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+        } else if rest.starts_with(open) {
+            comments = 1;
+            len = open.len();
+        } else if lexer::LINE_COMMENTS.iter().any(|s| rest.starts_with(s)) {
+            len = rest.find('\n').unwrap_or(rest.len());
+        } else {
+            match c {
+                '"' => in_string = true,
+                '{' | '(' | '[' => depth += 1,
+                '}' | ')' | ']' => depth -= 1,
+                _ => {}
+            }
+        }
+        rest = &rest[len..];
+    }
+    if depth > 0 || in_string || comments > 0 {
+        json!({ "status": "incomplete", "indent": "    " })
+    } else {
+        json!({ "status": "complete" })
+    }
+}
+
+///The current time in ISO 8601, which is what message headers want
+fn timestamp() -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = now.as_secs() as i64;
+    let (days, rem) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+    //turns days since 1970 into a calendar date
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:06}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60,
+        now.subsec_micros()
+    )
+}
+
+///Accepts connections in the background, handing every message that comes in to the kernel
+fn listen(listener: TcpListener, kind: &'static str, channel: Channel, requests: Sender<Request>) {
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let requests = requests.clone();
+            thread::spawn(move || {
+                let mut sock = match Socket::handshake(stream, kind, true) {
+                    Ok(s) => s,
+                    Err(_) => return,
+                };
+                while let (Ok(parts), Ok(reply_to)) = (sock.recv(), sock.try_clone()) {
+                    if requests.send((channel, reply_to, parts)).is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+    });
+}
+
+///Interrupts get answered as soon as they come in, since the kernel could be busy running the cell
+///they're meant to stop. Everything else on the control channel waits its turn with the shell
+fn control(requests: mpsc::Receiver<Request>, forward: Sender<Request>, session: Session, stop: Arc<AtomicBool>) {
+    thread::spawn(move || {
+        for (channel, mut reply_to, parts) in requests {
+            match Message::parse(parts.clone(), &session.key) {
+                Ok(msg) if msg.msg_type() == "interrupt_request" => {
+                    stop.store(true, Ordering::SeqCst);
+                    let frames = session.frames(&msg.ids, "interrupt_reply", &msg.header, json!({ "status": "ok" }));
+                    reply_to.send(&frames).ok();
+                }
+                _ => {
+                    if forward.send((channel, reply_to, parts)).is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    });
+}
+
+///Heartbeats just get echoed back so Jupyter knows the kernel is still alive
+fn heartbeat(listener: TcpListener) {
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            thread::spawn(move || {
+                if let Ok(mut sock) = Socket::handshake(stream, "REP", true) {
+                    while let Ok(parts) = sock.recv() {
+                        if sock.send(&parts).is_err() {
+                            return;
+                        }
+                    }
+                }
+            });
+        }
+    });
+}
+
+fn subscribers(listener: TcpListener) -> Arc<Mutex<Vec<Socket>>> {
+    let subs = Arc::new(Mutex::new(vec![]));
+    let list = subs.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            //subscriptions are ignored, everyone gets everything
+            if let Ok(sock) = Socket::handshake(stream, "PUB", true) {
+                list.lock().unwrap_or_else(|e| e.into_inner()).push(sock);
+            }
+        }
+    });
+    subs
+}
+
+///Runs a kernel for the connection file Jupyter gave us until it's told to shut down
+pub fn run(connection_file: &str) -> Result<(), String> {
+    let conn = Connection::load(connection_file)?;
+    let (tx, rx) = mpsc::channel();
+    listen(conn.bind(conn.shell_port)?, "ROUTER", Channel::Shell, tx.clone());
+    let session = Session::new(&conn.key);
+    let (control_tx, control_rx) = mpsc::channel();
+    listen(conn.bind(conn.control_port)?, "ROUTER", Channel::Control, control_tx);
+    listen(conn.bind(conn.stdin_port)?, "ROUTER", Channel::Stdin, tx.clone());
+    heartbeat(conn.bind(conn.hb_port)?);
+    let publisher = Publisher {
+        session: session.clone(),
+        subscribers: subscribers(conn.bind(conn.iopub_port)?),
+        parent: Arc::new(Mutex::new(json!({}))),
+    };
+
+    let mut kernel = Kernel::new(publisher);
+    control(control_rx, tx, session, kernel.stop.clone());
+    kernel.publisher.publish("status", json!({ "execution_state": "starting" }));
+    for (channel, mut reply_to, parts) in rx {
+        match Message::parse(parts, conn.key.as_bytes()) {
+            Ok(msg) => {
+                if !kernel.handle(channel, &mut reply_to, msg) {
+                    break;
+                }
+            }
+            Err(e) => eprintln!("Ignoring a message: {}", e),
+        }
+    }
+    Ok(())
+}
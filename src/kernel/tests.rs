@@ -0,0 +1,178 @@
+use super::*;
+use std::net::TcpStream;
+use std::time::Duration;
+
+#[test]
+fn hmac_sha256() {
+    //RFC 4231 test case 2
+    assert_eq!(
+        digest::hmac_sha256(b"Jefe", &[b"what do ya want ", b"for nothing?"]),
+        "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+    );
+    //keys longer than a block get hashed first (RFC 4231 test case 6)
+    assert_eq!(
+        digest::hmac_sha256(&[0xaa; 131], &[b"Test Using Larger Than Block-Size Key - Hash Key First"]),
+        "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"
+    );
+    let signature = digest::hmac_sha256(b"key", &[b"{}"]);
+    assert!(digest::verify(b"key", &[b"{}"], signature.as_bytes()));
+    assert!(!digest::verify(b"other", &[b"{}"], signature.as_bytes()));
+    assert!(!digest::verify(b"key", &[b"{}"], &signature.as_bytes()[1..]));
+}
+
+#[test]
+fn incomplete_cells() {
+    assert_eq!(is_complete("x = 1")["status"], "complete");
+    assert_eq!(is_complete("fn f() {")["status"], "incomplete");
+    assert_eq!(is_complete("s = \"{\"")["status"], "complete");
+    assert_eq!(is_complete("x = 1 // {")["status"], "complete");
+    assert_eq!(is_complete("x = 1 # (\nfn f() {")["status"], "incomplete");
+    assert_eq!(is_complete("/* { /* } */ [ */ x")["status"], "complete");
+    assert_eq!(is_complete("/* still going")["status"], "incomplete");
+}
+
+#[test]
+fn interrupts_stay_in_their_runtime() {
+    let (mut a, mut b) = (Engine::new(), Engine::new());
+    let stop = a.runtime().stop_flag();
+    b.runtime().stop_flag();
+    stop.store(true, Ordering::SeqCst);
+    assert_eq!(b.eval("1").map(|v| v.to_string()), Ok("1".to_owned()));
+    assert_eq!(a.eval("1"), Err("Interrupted".to_owned()));
+}
+
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+fn connect(port: u16, kind: &str) -> Socket {
+    for _ in 0..100 {
+        if let Ok(stream) = TcpStream::connect(("127.0.0.1", port)) {
+            stream.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+            return Socket::handshake(stream, kind, false).unwrap();
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    panic!("The kernel never started listening on {}", port);
+}
+
+struct Client {
+    key: String,
+    shell: Socket,
+    iopub: Socket,
+    sent: u32,
+}
+
+impl Client {
+    fn frames(&mut self, msg_type: &str, content: Json) -> (String, Vec<Vec<u8>>) {
+        self.sent += 1;
+        let id = format!("test-{}", self.sent);
+        let header = json!({ "msg_id": id, "session": "test", "username": "test", "msg_type": msg_type, "version": "5.3" });
+        let body: Vec<Vec<u8>> = [header, json!({}), json!({}), content].iter().map(|j| j.to_string().into_bytes()).collect();
+        let parts: Vec<&[u8]> = body.iter().map(|p| p.as_slice()).collect();
+        let mut frames = vec![DELIMITER.to_vec(), digest::hmac_sha256(self.key.as_bytes(), &parts).into_bytes()];
+        frames.extend(body);
+        (id, frames)
+    }
+
+    fn reply(&self, sock: &mut Socket, msg_type: &str) -> Json {
+        let reply = Message::parse(sock.recv().unwrap(), self.key.as_bytes()).unwrap();
+        assert_eq!(reply.msg_type(), msg_type.replace("_request", "_reply"));
+        reply.content
+    }
+
+    fn request(&mut self, msg_type: &str, content: Json) -> (String, Json) {
+        let (id, frames) = self.frames(msg_type, content);
+        self.shell.send(&frames).unwrap();
+        let mut shell = self.shell.try_clone().unwrap();
+        (id, self.reply(&mut shell, msg_type))
+    }
+
+    ///Everything published because of a request, up to the kernel going idle again
+    fn published(&mut self, id: &str) -> Vec<(String, Json)> {
+        let mut msgs = vec![];
+        loop {
+            let parts = self.iopub.recv().unwrap();
+            let parent: Json = serde_json::from_slice(&parts[parts.len() - 3]).unwrap();
+            let msg = Message::parse(parts, self.key.as_bytes()).unwrap();
+            if parent["msg_id"] != id {
+                continue;
+            }
+            if msg.msg_type() == "status" && msg.content["execution_state"] == "idle" {
+                return msgs;
+            }
+            msgs.push((msg.msg_type().to_owned(), msg.content));
+        }
+    }
+}
+
+#[test]
+fn notebook_session() {
+    let ports: Vec<u16> = (0..5).map(|_| free_port()).collect();
+    let file = std::env::temp_dir().join(format!("gem-kernel-{}.json", std::process::id()));
+    let conn = json!({
+        "ip": "127.0.0.1", "transport": "tcp", "signature_scheme": "hmac-sha256", "key": "secret",
+        "shell_port": ports[0], "iopub_port": ports[1], "stdin_port": ports[2], "control_port": ports[3], "hb_port": ports[4],
+    });
+    std::fs::write(&file, conn.to_string()).unwrap();
+    let path = file.to_str().unwrap().to_owned();
+    let kernel = thread::spawn(move || run(&path));
+
+    let mut hb = connect(ports[4], "REQ");
+    hb.send(&[vec![], b"ping".to_vec()]).unwrap();
+    assert_eq!(hb.recv().unwrap(), vec![vec![], b"ping".to_vec()]);
+
+    let iopub = connect(ports[1], "SUB");
+    let mut client = Client { key: "secret".to_owned(), shell: connect(ports[0], "DEALER"), iopub, sent: 0 };
+    //give the kernel a moment to add the subscriber before anything gets published
+    thread::sleep(Duration::from_millis(200));
+
+    let (_, info) = client.request("kernel_info_request", json!({}));
+    assert_eq!(info["language_info"]["file_extension"], ".em");
+
+    let (id, reply) = client.request("execute_request", json!({ "code": "x = 20\nprintln(\"hi\")\nx + 1", "silent": false }));
+    assert_eq!(reply["status"], "ok");
+    assert_eq!(reply["execution_count"], 1);
+    let published = client.published(&id);
+    assert!(published.contains(&("stream".to_owned(), json!({ "name": "stdout", "text": "hi\n" }))));
+    let result = published.iter().find(|(t, _)| t == "execute_result").unwrap();
+    assert_eq!(result.1["data"]["text/plain"], "21");
+
+    //variables stick around between cells, and errors come back as errors
+    let (id, reply) = client.request("execute_request", json!({ "code": "len(x, x)", "silent": false }));
+    assert_eq!(reply["status"], "error");
+    assert_eq!(reply["evalue"], "len takes 1 argument, got 2");
+    assert!(client.published(&id).iter().any(|(t, _)| t == "error"));
+
+    //a cell that never finishes gets stopped by an interrupt on the control channel
+    let (_, frames) = client.frames("execute_request", json!({ "code": "println(\"running\")\nwhile true {}", "silent": false }));
+    client.shell.send(&frames).unwrap();
+    //the output only shows up once the cell has started
+    while !client.iopub.recv().unwrap().iter().any(|p| p.windows(9).any(|w| w == b"running\\n")) {}
+    let mut control = connect(ports[3], "DEALER");
+    let (_, frames) = client.frames("interrupt_request", json!({}));
+    control.send(&frames).unwrap();
+    assert_eq!(client.reply(&mut control, "interrupt_request")["status"], "ok");
+    let mut shell = client.shell.try_clone().unwrap();
+    let reply = client.reply(&mut shell, "execute_request");
+    assert_eq!(reply["evalue"], "Interrupted");
+    let (_, reply) = client.request("execute_request", json!({ "code": "x", "silent": false }));
+    assert_eq!(reply["status"], "ok");
+
+    //a cell that exits doesn't leave the next one thinking it should stop too
+    let (_, reply) = client.request("execute_request", json!({ "code": "exit(3)", "silent": false }));
+    assert_eq!(reply["status"], "error");
+    let (_, reply) = client.request("execute_request", json!({ "code": "fn bad() { len(1, 1) }\nassert_throws(bad)\n\"caught\"", "silent": false }));
+    assert_eq!(reply["status"], "ok");
+
+    let (_, reply) = client.request("complete_request", json!({ "code": "pri", "cursor_pos": 3 }));
+    assert_eq!((reply["status"].as_str(), &reply["cursor_end"]), (Some("ok"), &json!(3)));
+    let (_, reply) = client.request("inspect_request", json!({ "code": "x", "cursor_pos": 1, "detail_level": 0 }));
+    assert_eq!(reply["found"], false);
+    let (_, reply) = client.request("history_request", json!({ "hist_access_type": "tail", "n": 10 }));
+    assert_eq!(reply["history"], json!([]));
+
+    client.request("shutdown_request", json!({ "restart": false }));
+    assert_eq!(kernel.join().unwrap(), Ok(()));
+    std::fs::remove_file(file).ok();
+}
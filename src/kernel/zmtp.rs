@@ -0,0 +1,115 @@
+//! Just enough of ZeroMQ's wire protocol (ZMTP 3.0 with no security) for Jupyter to talk to us.
+//! Every TCP connection is its own `Socket`, so routing replies is just writing back to the
+//! connection the request came in on
+
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+
+const MORE: u8 = 0x01;
+const LONG: u8 = 0x02;
+const COMMAND: u8 = 0x04;
+
+pub struct Socket {
+    stream: TcpStream,
+}
+
+fn bad(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_owned())
+}
+
+impl Socket {
+    ///Does the greeting and handshake on a new connection. `kind` is the zmq socket type we're
+    ///pretending to be, like ROUTER or PUB
+    pub fn handshake(mut stream: TcpStream, kind: &str, as_server: bool) -> io::Result<Socket> {
+        let mut greeting = [0u8; 64];
+        greeting[0] = 0xff;
+        greeting[9] = 0x7f;
+        greeting[10] = 3;
+        greeting[12..16].copy_from_slice(b"NULL");
+        greeting[32] = as_server as u8;
+        stream.write_all(&greeting)?;
+
+        let mut peer = [0u8; 64];
+        stream.read_exact(&mut peer)?;
+        if peer[0] != 0xff || peer[9] & 1 == 0 || peer[10] < 3 {
+            return Err(bad("Peer doesn't speak ZMTP 3"));
+        }
+        if &peer[12..17] != b"NULL\0" {
+            return Err(bad("Only the NULL security mechanism is supported"));
+        }
+
+        let mut sock = Socket { stream };
+        let mut ready = vec![5];
+        ready.extend_from_slice(b"READY");
+        ready.push(11);
+        ready.extend_from_slice(b"Socket-Type");
+        ready.extend_from_slice(&(kind.len() as u32).to_be_bytes());
+        ready.extend_from_slice(kind.as_bytes());
+        let mut out = vec![];
+        frame(&mut out, &ready, COMMAND);
+        sock.stream.write_all(&out)?;
+
+        let (body, flags) = sock.read_frame()?;
+        if flags & COMMAND == 0 || !body.starts_with(b"\x05READY") {
+            return Err(bad("Expected a READY command"));
+        }
+        Ok(sock)
+    }
+
+    pub fn try_clone(&self) -> io::Result<Socket> {
+        Ok(Socket {
+            stream: self.stream.try_clone()?,
+        })
+    }
+
+    fn read_frame(&mut self) -> io::Result<(Vec<u8>, u8)> {
+        let mut flags = [0u8];
+        self.stream.read_exact(&mut flags)?;
+        let len = if flags[0] & LONG != 0 {
+            let mut len = [0u8; 8];
+            self.stream.read_exact(&mut len)?;
+            u64::from_be_bytes(len) as usize
+        } else {
+            let mut len = [0u8];
+            self.stream.read_exact(&mut len)?;
+            len[0] as usize
+        };
+        let mut body = vec![0u8; len];
+        self.stream.read_exact(&mut body)?;
+        Ok((body, flags[0]))
+    }
+
+    ///Reads one whole message, skipping over any commands the peer sends in between
+    pub fn recv(&mut self) -> io::Result<Vec<Vec<u8>>> {
+        let mut parts = vec![];
+        loop {
+            let (body, flags) = self.read_frame()?;
+            if flags & COMMAND != 0 {
+                continue;
+            }
+            parts.push(body);
+            if flags & MORE == 0 {
+                return Ok(parts);
+            }
+        }
+    }
+
+    pub fn send(&mut self, parts: &[Vec<u8>]) -> io::Result<()> {
+        let mut out = vec![];
+        for (i, part) in parts.iter().enumerate() {
+            frame(&mut out, part, if i + 1 < parts.len() { MORE } else { 0 });
+        }
+        self.stream.write_all(&out)
+    }
+}
+
+fn frame(out: &mut Vec<u8>, body: &[u8], flags: u8) {
+    if body.len() > 255 {
+        out.push(flags | LONG);
+        out.extend_from_slice(&(body.len() as u64).to_be_bytes());
+    } else {
+        out.push(flags);
+        out.push(body.len() as u8);
+    }
+    out.extend_from_slice(body);
+}
@@ -5,8 +5,7 @@ extern crate regex;
 mod tests;
 
 use regex::Regex;
-use std::iter::Peekable;
-use std::process;
+use serde::{Deserialize, Serialize};
 use std::str::Chars;
 
 // Enums are more idomatic and make the resulting Vec much easier to understand
@@ -14,11 +13,13 @@ use std::str::Chars;
 // this should suffice
 
 ///Describes the different tokens that can be generated from the source code
-#[derive(Debug, Clone, PartialEq, PartialOrd)]
+#[derive(Debug, Clone, PartialEq, PartialOrd, Serialize, Deserialize)]
 pub enum Expression {
     Ident(String),
-    Number(f32),
+    Number(f64),
     Word(String),
+    ///A byte string like `b"GIF89a\x00"`
+    Bytes(Vec<u8>),
     Key(String),
     Operator(char),
     CompoundOp(String),
@@ -32,6 +33,9 @@ pub enum Expression {
     Rbracket,
     Semicolon,
     Comma,
+    DotDot,
+    ///Something that can't be a token, with why. The parser stops with this as the error when it gets to it
+    Illegal(String),
     EOF,
 }
 
@@ -41,6 +45,7 @@ impl std::fmt::Display for Expression {
             Expression::Ident(name) => write!(f, "Identifier: {}", name),
             Expression::Number(n) => write!(f, "Number: {}", n),
             Expression::Word(n) => write!(f, "String: {}", n),
+            Expression::Bytes(b) => write!(f, "Bytes: {:?}", b),
             Expression::Key(n) => write!(f, "Keyword: {}", n),
             Expression::Operator(n) => write!(f, "Operator: {}", n),
             Expression::BoolOp(n) => write!(f, "Operator: {}", n),
@@ -53,23 +58,92 @@ impl std::fmt::Display for Expression {
             Expression::Lbrace => write!(f, "Symbol: {{"),
             Expression::Semicolon => write!(f, "Symbol: ;"),
             Expression::Comma => write!(f, "Symbol: ,"),
+            Expression::DotDot => write!(f, "Operator: .."),
+            Expression::Illegal(why) => write!(f, "Illegal: {}", why),
             _ => write!(f, "{}", self),
         }
     }
 }
 
+///Every word the lexer turns into a keyword instead of an identifier. Anything that needs to know
+///the keywords (like the syntax highlighting generator) should read them from here
+pub const KEYWORDS: &[&str] = &[
+    "fn", "new", "class", "delete", "return", "yield", "await", "true", "false", "null", "while", "for", "if", "else", "elif", "let",
+];
+
+///Every operator the lexer understands, longest first so they can be matched greedily
+pub const OPERATORS: &[&str] = &[
+    "==", "!=", "<=", ">=", "&&", "||", "+=", "-=", "*=", "/=", "++", "--", "..", "=", "+", "-", "*", "/", "<", ">", "!", ".",
+];
+
+///What starts a comment that runs to the end of the line
+pub const LINE_COMMENTS: &[&str] = &["//", "#"];
+
+///What opens and closes a block comment, these can be nested
+pub const BLOCK_COMMENT: (&str, &str) = ("/*", "*/");
+
 pub fn run(data: &str) -> Vec<Expression> {
+    Lexer::new().tokenize(data).0
+}
+
+///Same as `run`, along with where each token is in `data`
+pub fn run_spanned(data: &str) -> (Vec<Expression>, Vec<Span>) {
     Lexer::new().tokenize(data)
 }
 
+///Where a token is in the source, as byte offsets
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Serialize, Deserialize)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+///The characters left to lex, which knows how far into the source it is
+struct Source<'a> {
+    rest: Chars<'a>,
+    next: Option<char>,
+    len: usize,
+}
+
+impl<'a> Source<'a> {
+    fn new(data: &'a str) -> Source<'a> {
+        Source {
+            rest: data.chars(),
+            next: data.chars().next(),
+            len: data.len(),
+        }
+    }
+
+    fn peek(&self) -> Option<&char> {
+        self.next.as_ref()
+    }
+
+    ///The byte offset of the next character
+    fn offset(&self) -> usize {
+        self.len - self.rest.as_str().len()
+    }
+}
+
+impl Iterator for Source<'_> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.rest.next();
+        self.next = self.rest.clone().next();
+        c
+    }
+}
+
 ///Describes the current state of the lexer
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone, Copy)]
 enum State {
     Nothing,
     EmString,
+    EmBytes,
     EmName,
     EmNumber,
     Comment,
+    BlockComment,
 }
 
 ///Handles the tokenization of the source code
@@ -80,6 +154,11 @@ struct Lexer {
     valid_chars: Regex,
     valid_symb: Regex,
     check: bool,
+    //how many block comments we're inside of, since they can be nested
+    comment_depth: usize,
+    //where the token being built up started, and how long the last name or number was
+    start: usize,
+    len: usize,
 }
 
 impl Lexer {
@@ -90,18 +169,29 @@ impl Lexer {
             token: String::new(),
             valid_num: Regex::new(r"\d*").unwrap(),
             valid_chars: Regex::new(r"\D+[[:word:]]*").unwrap(),
-            valid_symb: Regex::new(r"[\{\}\(\)=;.\*\+\-/#!,\t\n\[\]]").unwrap(),
+            valid_symb: Regex::new(r"[\{\}\(\)=;.\*\+\-/#!,<>&|\t\n\[\]]").unwrap(),
             check: false,
+            comment_depth: 0,
+            start: 0,
+            len: 0,
         }
     }
 
-    ///Loops through the characters in the provided string can outputs a vec of expressions
-    pub fn tokenize(&mut self, data: &str) -> Vec<Expression> {
+    ///Loops through the characters in the provided string can outputs a vec of expressions, and
+    ///where each of them is
+    pub fn tokenize(&mut self, data: &str) -> (Vec<Expression>, Vec<Span>) {
         let mut result = vec![];
+        let mut spans = vec![];
 
-        let mut ch = data.chars().peekable();
+        let mut ch = Source::new(data);
 
-        while let Some(c) = ch.next() {
+        loop {
+            let at = ch.offset();
+            let c = match ch.next() {
+                Some(c) => c,
+                None => break,
+            };
+            let was = self.current_state;
             // println!(
             //     "Current char: {:?}\nNext char: {:?}\nCurrent token: {}",
             //     c,
@@ -115,28 +205,50 @@ impl Lexer {
                         self.token.clear();
                     }
                 }
+                State::BlockComment => {
+                    if c == '/' && ch.peek() == Some(&'*') {
+                        ch.next();
+                        self.comment_depth += 1;
+                    } else if c == '*' && ch.peek() == Some(&'/') {
+                        ch.next();
+                        self.comment_depth -= 1;
+                        if self.comment_depth == 0 {
+                            self.current_state = State::Nothing;
+                        }
+                    }
+                }
                 State::EmString => {
                     if c == '"' {
                         result.push(Expression::Word(self.token.clone()));
+                        spans.push(Span { start: self.start, end: ch.offset() });
                         self.token.clear();
                         self.current_state = State::Nothing;
                     } else {
                         self.token.push(c);
                     }
                 }
+                State::EmBytes => {
+                    if let Some(r) = self.bytes_handle(c, &mut ch) {
+                        result.push(r);
+                        spans.push(Span { start: self.start, end: ch.offset() });
+                    }
+                }
                 State::EmNumber => {
                     if let Some(r) = self.num_handle(c, &mut ch) {
                         result.push(r);
+                        spans.push(Span { start: self.start, end: self.start + self.len });
                     }
                 }
                 State::EmName => {
                     if let Some(r) = self.name_handle(c) {
                         result.push(r);
+                        spans.push(Span { start: self.start, end: self.start + self.len });
                     }
                 }
                 State::Nothing => {
                     if let Some(r) = self.nothing_handle(c, &mut ch) {
                         result.push(r);
+                        spans.push(Span { start: at, end: ch.offset() });
                     }
                 }
             }
@@ -146,35 +258,38 @@ impl Lexer {
             if self.check {
                 if let Some(r) = self.nothing_handle(c, &mut ch) {
                     result.push(r);
+                    spans.push(Span { start: at, end: ch.offset() });
                 }
                 self.check = false;
             }
+            //strings, names, and numbers start on the character that moved the lexer out of the nothing state
+            let building = matches!(self.current_state, State::EmString | State::EmBytes | State::EmName | State::EmNumber);
+            if building && self.current_state != was {
+                self.start = at;
+            }
             // println!("Current result: {:?}", result);
         }
 
-        result //return the result
+        (result, spans) //return the result
     }
 
     ///Handles generation of number literals
-    fn num_handle(&mut self, c: char, iter: &mut Peekable<Chars<'_>>) -> Option<Expression> {
+    fn num_handle(&mut self, c: char, iter: &mut Source<'_>) -> Option<Expression> {
         let result: Option<Expression>;
+        //a dot followed by a digit is a decimal point instead of the dot operator
+        if c == '.' && !self.token.contains('.') && iter.peek().is_some_and(|n| n.is_ascii_digit()) {
+            self.token.push(c);
+            return None;
+        }
         if c.is_whitespace() || self.valid_symb.is_match(&c.to_string()) {
             self.current_state = State::Nothing;
 
-            if !c.is_whitespace() && c.is_numeric() || c == '.' {
+            //a second dot means this is the start of a range like `1..10`, not a decimal point
+            if !c.is_whitespace() && c.is_numeric() || c == '.' && iter.peek() != Some(&'.') {
                 //the current char could be part of the thing we're accumulating
                 self.token.push(c);
             }
-            result = Some(Expression::Number(
-                self.token.parse::<f32>().unwrap_or_else(|e| {
-                    println!(
-                        "Got this er!ror message ({}) when parsing this: {}",
-                        e, self.token
-                    );
-
-                    process::exit(-1);
-                }),
-            ));
+            result = Some(self.number());
             self.token.clear();
             self.check = true;
         } else {
@@ -183,15 +298,7 @@ impl Lexer {
                     if c.is_numeric() || c == '.' {
                         self.token.push(c);
                     }
-                    let tmp = Some(Expression::Number(
-                        self.token.parse::<f32>().unwrap_or_else(|e| {
-                            println!(
-                                "Got this error message ({}) when parsing this: {}",
-                                e, self.token
-                            );
-                            process::exit(-1);
-                        }),
-                    ));
+                    let tmp = Some(self.number());
                     self.token.clear();
                     self.current_state = State::Nothing;
                     return tmp;
@@ -203,6 +310,43 @@ impl Lexer {
         result
     }
 
+    ///Turns the number that's been built up into a token, or an `Illegal` one saying why it couldn't
+    fn number(&mut self) -> Expression {
+        self.len = self.token.len();
+        match self.token.parse::<f64>() {
+            Ok(n) => Expression::Number(n),
+            Err(e) => Expression::Illegal(format!("Couldn't read {} as a number: {}", self.token, e)),
+        }
+    }
+
+    ///Builds up a byte string. Unlike normal strings these have escapes, since most bytes aren't
+    ///something that can be typed: `\xNN` for any byte, and `\\` and `\"` for the two that would
+    ///otherwise end the literal or start an escape
+    fn bytes_handle(&mut self, c: char, iter: &mut Source<'_>) -> Option<Expression> {
+        match c {
+            '"' => {
+                self.current_state = State::Nothing;
+                let bytes = std::mem::take(&mut self.token);
+                Some(match unescape_bytes(&bytes) {
+                    Ok(b) => Expression::Bytes(b),
+                    Err(why) => Expression::Illegal(why),
+                })
+            }
+            //kept as they are so the closing quote check above doesn't see an escaped one
+            '\\' => {
+                self.token.push(c);
+                if let Some(next) = iter.next() {
+                    self.token.push(next);
+                }
+                None
+            }
+            _ => {
+                self.token.push(c);
+                None
+            }
+        }
+    }
+
     ///Handles the generation of identifiers and keywords
     fn name_handle(&mut self, c: char) -> Option<Expression> {
         let result: Option<Expression>;
@@ -212,28 +356,9 @@ impl Lexer {
                 //current char could be part of the thing we're accumulating
                 self.token.push(c);
             }
+            self.len = self.token.len();
             match self.token.as_str() {
-                "fn" | "new" | "class" => {
-                    result = Some(Expression::Key(self.token.to_string()));
-                    self.token.clear();
-                }
-                // "print" | "println" => {
-                //     result = Some(Expression::Key(self.token.to_string()));
-                //     self.token.clear();
-                // }
-                "return" => {
-                    result = Some(Expression::Key(self.token.to_string()));
-                    self.token.clear();
-                }
-                "true" | "false" | "null" => {
-                    result = Some(Expression::Key(self.token.to_string()));
-                    self.token.clear();
-                }
-                "while" | "for" => {
-                    result = Some(Expression::Key(self.token.to_string()));
-                    self.token.clear();
-                }
-                "if" | "else" | "elif" => {
+                k if KEYWORDS.contains(&k) => {
                     result = Some(Expression::Key(self.token.to_string()));
                     self.token.clear();
                 }
@@ -252,7 +377,7 @@ impl Lexer {
     }
 
     ///The default state of the lexer, handles symbols and decides when to change states
-    fn nothing_handle(&mut self, c: char, ch: &mut Peekable<Chars<'_>>) -> Option<Expression> {
+    fn nothing_handle(&mut self, c: char, ch: &mut Source<'_>) -> Option<Expression> {
         // println!(
         //     "This is what it looks like when you call char.to_string(): {:?}",
         //     c.to_string()
@@ -264,6 +389,12 @@ impl Lexer {
                 self.token.clear();
                 None
             }
+            'b' if ch.peek() == Some(&'"') => {
+                ch.next();
+                self.current_state = State::EmBytes;
+                self.token.clear();
+                None
+            }
             ',' => Some(Expression::Comma),
             '{' => Some(Expression::Lbrace),
             '}' => Some(Expression::Rbrace),
@@ -330,9 +461,15 @@ impl Lexer {
                     None
                 }
             }
-            '.' => Some(Expression::Operator(c)),
+            '.' => {
+                if ch.peek() == Some(&'.') {
+                    ch.next();
+                    Some(Expression::DotDot)
+                } else {
+                    Some(Expression::Operator(c))
+                }
+            }
             '#' => {
-                ch.next();
                 self.current_state = State::Comment;
                 None
             } 
@@ -344,6 +481,12 @@ impl Lexer {
                             self.current_state = State::Comment;
                             None
                         }
+                        '*' => {
+                            ch.next();
+                            self.current_state = State::BlockComment;
+                            self.comment_depth = 1;
+                            None
+                        }
                         '=' => {
                             ch.next();
                             Some(Expression::CompoundOp("/=".to_owned()))
@@ -361,13 +504,21 @@ impl Lexer {
                         ch.next();
                         Some(Expression::BoolOp("!=".to_owned()))
                     } else {
-                        None
-                        //if we need other operators to do with the bang they would go here
+                        Some(Expression::Operator(c))
                     }
                 } else {
                     None
                 }
             }
+            '&' | '|' => {
+                //only the doubled versions mean anything for now
+                if ch.peek() == Some(&c) {
+                    ch.next();
+                    Some(Expression::BoolOp(format!("{}{}", c, c)))
+                } else {
+                    None
+                }
+            }
 
             '<' => {
                 if let Some(sym) = ch.peek() {
@@ -411,3 +562,41 @@ impl Lexer {
         }
     }
 }
+
+///Writes bytes the way a byte string literal would have them, so `b"..."` around it reads back the same
+pub fn escape_bytes(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for b in bytes {
+        match b {
+            b'\\' => out.push_str("\\\\"),
+            b'"' => out.push_str("\\\""),
+            0x20..=0x7e => out.push(*b as char),
+            _ => out.push_str(&format!("\\x{:02x}", b)),
+        }
+    }
+    out
+}
+
+fn unescape_bytes(src: &str) -> Result<Vec<u8>, String> {
+    let mut out = vec![];
+    let mut chars = src.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('x') => {
+                    let hex: String = chars.by_ref().take(2).collect();
+                    match u8::from_str_radix(&hex, 16) {
+                        Ok(b) if hex.len() == 2 => out.push(b),
+                        _ => return Err(format!("\\x{} isn't a byte, \\x needs two hex digits after it", hex)),
+                    }
+                }
+                Some(e @ '\\') | Some(e @ '"') => out.push(e as u8),
+                Some(e) => return Err(format!("Unknown escape \\{} in a byte string", e)),
+                None => return Err("A byte string can't end with \\".to_owned()),
+            },
+            c if c.is_ascii() => out.push(c as u8),
+            c => return Err(format!("Byte strings can only have ASCII characters, use \\x escapes for {}", c)),
+        }
+    }
+    Ok(out)
+}
@@ -18,3 +18,87 @@ fn token_generation() {
 
     assert_eq!(expected, lexer::run(dummy));
 }
+
+#[test]
+fn comments() {
+    let dummy = "a = 1;// line
+    /* block /* nested */ still a comment */ b// right after a name
+    # hash comment
+    = 2;";
+    let expected = vec![
+        Expression::Ident("a".to_owned()),
+        Expression::Equal,
+        Expression::Number(1.0),
+        Expression::Semicolon,
+        Expression::Ident("b".to_owned()),
+        Expression::Equal,
+        Expression::Number(2.0),
+        Expression::Semicolon,
+    ];
+
+    assert_eq!(expected, lexer::run(dummy));
+}
+
+//the tables other tools read have to match what the lexer actually does
+#[test]
+fn keyword_and_operator_tables() {
+    for k in lexer::KEYWORDS {
+        assert_eq!(lexer::run(&format!("{} ", k)), vec![Expression::Key(k.to_string())]);
+    }
+    for op in lexer::OPERATORS {
+        let tokens = lexer::run(&format!("a {} b ", op));
+        let text = match &tokens[1] {
+            Expression::Operator(c) => c.to_string(),
+            Expression::BoolOp(s) | Expression::CompoundOp(s) => s.clone(),
+            Expression::Equal => "=".to_owned(),
+            Expression::DotDot => "..".to_owned(),
+            other => panic!("{} lexed as {:?}", op, other),
+        };
+        assert_eq!((tokens.len(), text.as_str()), (3, *op));
+    }
+}
+
+#[test]
+fn ranges() {
+    let num = Expression::Number;
+    assert_eq!(lexer::run("1..10 "), vec![num(1.0), Expression::DotDot, num(10.0)]);
+    assert_eq!(lexer::run("0.5..n "), vec![num(0.5), Expression::DotDot, Expression::Ident("n".to_owned())]);
+    assert_eq!(lexer::run("a.b "), vec![Expression::Ident("a".to_owned()), Expression::Operator('.'), Expression::Ident("b".to_owned())]);
+}
+
+#[test]
+fn bad_numbers() {
+    //these used to end the whole process, now the parser reports them like any other error
+    let tokens = lexer::run("x = 1.2.3;\n");
+    assert!(tokens.iter().any(|t| matches!(t, Expression::Illegal(_))), "{:?}", tokens);
+    let err = crate::parser::parse(tokens).unwrap_err();
+    assert!(err.starts_with("Couldn't read 1.2. as a number"), "{}", err);
+}
+
+#[test]
+fn shebang_line() {
+    //# starts a line comment, so a script can start with one and be run as an executable
+    let src = "fn main(args) { println(args); }\n";
+    assert_eq!(lexer::run(&format!("#!/usr/bin/env gem-bin\n{}", src)), lexer::run(src));
+}
+
+#[test]
+fn byte_strings() {
+    assert_eq!(lexer::run("b\"hi\\x00\\xff\" "), vec![Expression::Bytes(vec![b'h', b'i', 0, 255])]);
+    //a b on its own is still a name
+    assert_eq!(lexer::run("b + 1 "), vec![Expression::Ident("b".to_owned()), Expression::Operator('+'), Expression::Number(1.0)]);
+    assert!(matches!(&lexer::run("b\"\\xzz\" ")[0], Expression::Illegal(_)));
+    assert_eq!(lexer::escape_bytes(&[b'a', b'"', 10]), "a\\\"\\x0a");
+}
+
+#[test]
+fn spans() {
+    let src = "let x = b\"a\\\"\" + \"hi\";\n  foo(1.5..2)  ==y;// done";
+    let (tokens, spans) = lexer::run_spanned(src);
+    assert_eq!(tokens, lexer::run(src));
+    let texts: Vec<&str> = spans.iter().map(|s| &src[s.start..s.end]).collect();
+    assert_eq!(
+        texts,
+        vec!["let", "x", "=", "b\"a\\\"\"", "+", "\"hi\"", ";", "foo", "(", "1.5", "..", "2", ")", "==", "y", ";"]
+    );
+}
@@ -1,5 +1,18 @@
 #![feature(test)]
 
+#[cfg(feature = "debug-alloc")]
+pub mod alloc_stats;
+pub mod conformance;
+pub mod diagnostics;
+mod digest;
+mod engine;
+pub mod ffi;
+pub mod highlight;
+pub mod kernel;
+pub mod lint;
+pub mod literate;
+pub mod playground;
+mod program;
 pub mod interpreter;
 pub mod lexer;
 pub mod parser;
@@ -7,24 +20,114 @@ pub mod parser;
 #[cfg(test)]
 mod bench;
 
-///Runs the lexer, parser, and interpreter on the provided string
-pub fn run(data: String, args: &str, debug: bool) {
-    let tokens = lexer::run(&data);
+pub use engine::Engine;
+pub use program::Program;
+
+///Runs the lexer, parser, and interpreter on the provided string, giving back the code the process
+///should exit with. That's 1 if the script doesn't parse or crashes, or whatever it gave `exit`
+pub fn run(data: String, args: Vec<interpreter::Value>, debug: bool) -> i32 {
+    run_with(interpreter::Runtime::new(), data, args, debug)
+}
+
+///Same as `run`, but executes the script on a runtime that has already been configured
+pub fn run_with(mut runtime: interpreter::Runtime, data: String, args: Vec<interpreter::Value>, debug: bool) -> i32 {
+    let (tokens, spans) = lexer::run_spanned(&data);
     if debug {
-        println!("Generated tokens: {:?}", tokens);
+        runtime.print(&format!("Generated tokens: {:?}\n", tokens)).unwrap_or(());
     }
-    match parser::parse(tokens) {
+    match parser::parse_spanned(&tokens, &spans) {
         Ok(ast) => {
-            let args = parser::read_line(None, &mut lexer::run(&format!("[{}]", args)).iter().peekable(), &vec![&lexer::Expression::Semicolon]).unwrap();
-
             if debug {
-                println!("{:?}", &ast);
-                println!("{:?}", args);
+                runtime.print(&format!("{:?}\n{:?}\n", ast, args)).unwrap_or(());
             }
 
-            interpreter::run(ast, args)
+            interpreter::run_with(runtime, ast, args)
+        }
+        Err((e, at)) => {
+            let diag = runtime.diagnose(&e, at);
+            runtime.report(&diag.to_string());
+            1
+        }
+    }
+}
+
+///Timing results from `bench`
+#[derive(Debug, Clone)]
+pub struct BenchReport {
+    pub runs: u32,
+    pub fastest: std::time::Duration,
+    pub mean: std::time::Duration,
+    ///Nodes walked in one run of the script
+    pub nodes: u64,
+    ///The most called functions in one run, most called first
+    pub hottest: Vec<(String, u64)>,
+}
+
+impl BenchReport {
+    pub fn nodes_per_second(&self) -> f64 {
+        self.nodes as f64 / self.mean.as_secs_f64()
+    }
+}
+
+impl std::fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} runs, mean {:?}, fastest {:?}\n{} nodes per run, {:.0} nodes/s",
+            self.runs,
+            self.mean,
+            self.fastest,
+            self.nodes,
+            self.nodes_per_second()
+        )?;
+        if !self.hottest.is_empty() {
+            let hot: Vec<String> = self.hottest.iter().map(|(n, c)| format!("{} ({} calls)", n, c)).collect();
+            write!(f, "\nhottest functions: {}", hot.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+///Parses the script once and then runs it `runs` times on fresh runtimes, with its output thrown
+///away so printing doesn't get timed
+pub fn bench(data: &str, args: Vec<interpreter::Value>, runs: u32) -> Result<BenchReport, String> {
+    let ast = parser::parse(lexer::run(data))?;
+    let runs = runs.max(1);
+    let mut total = std::time::Duration::ZERO;
+    let mut fastest = std::time::Duration::MAX;
+    let mut nodes = 0;
+    let mut hottest = vec![];
+    for _ in 0..runs {
+        let mut runtime = interpreter::Runtime::new();
+        runtime.set_stdout(Box::new(std::io::sink()));
+        runtime.set_count_calls(true);
+        let start = std::time::Instant::now();
+        interpreter::run_on(&mut runtime, ast.clone(), args.clone());
+        let took = start.elapsed();
+        total += took;
+        fastest = fastest.min(took);
+        nodes = runtime.nodes_evaluated();
+        hottest = runtime.call_counts().into_iter().take(5).collect();
+    }
+    Ok(BenchReport {
+        runs,
+        fastest,
+        mean: total / runs,
+        nodes,
+        hottest,
+    })
+}
+
+///Runs every `test_` function in the script and gives back how many of them failed
+pub fn test_with(mut runtime: interpreter::Runtime, data: String) -> Result<usize, String> {
+    let (tokens, spans) = lexer::run_spanned(&data);
+    match parser::parse_spanned(&tokens, &spans) {
+        Ok(ast) => Ok(interpreter::run_tests(runtime, ast)),
+        Err((e, at)) => {
+            let diag = runtime.diagnose(&e, at);
+            runtime.report(&diag.to_string());
+            Err(e)
         }
-        Err(e) => println!("{}", e),
     }
 }
 
@@ -0,0 +1,269 @@
+//! Looks over a parsed script for code that's probably a mistake, without running any of it. Each
+//! function is looked at on its own, the same way it runs, since functions can't see globals or
+//! the variables of the function they were defined in
+
+#[cfg(test)]
+mod tests;
+
+use crate::interpreter::Runtime;
+use crate::lexer::Expression;
+use crate::parser::ExprNode;
+use std::collections::HashSet;
+use std::fmt;
+
+///Which kind of mistake a warning is about
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rule {
+    UnusedVariable,
+    UnreachableCode,
+    ShadowedParameter,
+    UndefinedFunction,
+    AssignmentInCondition,
+}
+
+impl Rule {
+    pub const NAMES: &'static [&'static str] = &[
+        "unused-variable",
+        "unreachable-code",
+        "shadowed-parameter",
+        "undefined-function",
+        "assignment-in-condition",
+    ];
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Rule::NAMES[*self as usize])
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Warning {
+    pub rule: Rule,
+    pub message: String,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} [{}]", self.message, self.rule)
+    }
+}
+
+///Checks a script against the builtins a new runtime has
+pub fn check(tree: &ExprNode) -> Vec<Warning> {
+    check_with(tree, &Runtime::new())
+}
+
+///Same as `check`, but calls to anything `rt` has as a builtin are fine, like functions a host registered
+pub fn check_with(tree: &ExprNode, rt: &Runtime) -> Vec<Warning> {
+    let mut functions: HashSet<String> = rt.builtin_names().into_iter().collect();
+    definitions(tree, &mut functions);
+    let mut lint = Lint { functions, warnings: vec![] };
+    //globals that are never read aren't a problem, whatever ran the script can still read them once it's done
+    lint.scope(tree, "at the top level", &[]);
+    lint.warnings
+}
+
+///Names of every function and class the script defines, anywhere in it. Methods are left out
+///since they can only be called on an object
+fn definitions(node: &ExprNode, out: &mut HashSet<String>) {
+    match node {
+        ExprNode::Func(name, _, body) => {
+            out.insert(ident(name));
+            definitions(body, out);
+        }
+        ExprNode::Class(name, _) => {
+            out.insert(ident(name));
+        }
+        other => children(other).into_iter().for_each(|n| definitions(n, out)),
+    }
+}
+
+fn ident(name: &Expression) -> String {
+    match name {
+        Expression::Ident(n) => n.clone(),
+        other => format!("{:?}", other),
+    }
+}
+
+fn children(node: &ExprNode) -> Vec<&ExprNode> {
+    match node {
+        ExprNode::Operation(_, a, b) | ExprNode::Index(a, b) | ExprNode::Loop(_, a, b) | ExprNode::ForIn(_, a, b) => vec![a, b],
+        ExprNode::ForLoopDec(a, b, c) | ExprNode::IfStatement(a, b, c) | ExprNode::Comprehension(a, _, b, c) => vec![a, b, c],
+        ExprNode::Call(_, items) | ExprNode::Block(items) | ExprNode::Array(items) | ExprNode::Tuple(items) => items.iter().collect(),
+        ExprNode::MethodCall(a, items) | ExprNode::CallExpr(a, items) | ExprNode::New(a, items) => {
+            std::iter::once(&**a).chain(items.iter()).collect()
+        }
+        ExprNode::Func(_, _, a)
+        | ExprNode::Class(_, a)
+        | ExprNode::Delete(a)
+        | ExprNode::Unary(_, a)
+        | ExprNode::Statement(a)
+        | ExprNode::ReturnVal(a)
+        | ExprNode::ElseStatement(a)
+        | ExprNode::Yield(a)
+        | ExprNode::Generator(a)
+        | ExprNode::Await(a)
+        | ExprNode::Async(a)
+        | ExprNode::Constant(a, _) => vec![a],
+        _ => vec![],
+    }
+}
+
+struct Lint {
+    functions: HashSet<String>,
+    warnings: Vec<Warning>,
+}
+
+///What one function (or the top level) sets and reads
+struct Scope<'a> {
+    place: &'a str,
+    params: &'a [String],
+    //in the order they were first set, so warnings come out in the order of the code
+    assigned: Vec<String>,
+    read: HashSet<String>,
+}
+
+impl Lint {
+    fn warn(&mut self, rule: Rule, message: String) {
+        self.warnings.push(Warning { rule, message });
+    }
+
+    ///Walks a scope and gives back the variables it set but never read
+    fn scope(&mut self, body: &ExprNode, place: &str, params: &[String]) -> Vec<String> {
+        let mut scope = Scope { place, params, assigned: vec![], read: HashSet::new() };
+        self.walk(body, &mut scope);
+        let read = scope.read;
+        scope.assigned.into_iter().filter(|n| !read.contains(n)).collect()
+    }
+
+    fn function(&mut self, place: String, params: &[ExprNode], body: &ExprNode) {
+        let params: Vec<String> = params.iter().map(|p| p.inner()).collect();
+        for (i, p) in params.iter().enumerate() {
+            if params[..i].contains(p) {
+                self.warn(Rule::ShadowedParameter, format!("{} has more than one parameter named {}", place, p));
+            }
+        }
+        let place = format!("in {}", place);
+        for name in self.scope(body, &place, &params) {
+            if !params.contains(&name) && !name.starts_with('_') {
+                self.warn(Rule::UnusedVariable, format!("{} is set {} but never used", name, place));
+            }
+        }
+    }
+
+    fn condition(&mut self, kind: &str, con: &ExprNode, scope: &Scope) {
+        if let ExprNode::Operation(op, target, _) = con {
+            if **op == Expression::Equal {
+                self.warn(
+                    Rule::AssignmentInCondition,
+                    format!("The {} condition {} sets {} instead of comparing it, did you mean ==?", kind, scope.place, target.inner()),
+                );
+            }
+        }
+    }
+
+    fn loop_variable(&mut self, name: &str, scope: &Scope) {
+        if scope.params.iter().any(|p| p == name) {
+            self.warn(Rule::ShadowedParameter, format!("The loop variable {} {} replaces the parameter with the same name", name, scope.place));
+        }
+    }
+
+    fn assign(&mut self, name: &str, scope: &mut Scope) {
+        if !scope.assigned.iter().any(|a| a == name) {
+            scope.assigned.push(name.to_owned());
+        }
+    }
+
+    ///Every name in something like `(a, (b, c)) = t` gets set
+    fn unpack(&mut self, names: &[ExprNode], scope: &mut Scope) {
+        for name in names {
+            match name {
+                ExprNode::Name(n) => self.assign(n, scope),
+                ExprNode::Tuple(inner) => self.unpack(inner, scope),
+                other => self.walk(other, scope),
+            }
+        }
+    }
+
+    fn walk(&mut self, node: &ExprNode, scope: &mut Scope) {
+        match node {
+            ExprNode::Name(n) => {
+                scope.read.insert(n.to_string());
+            }
+            ExprNode::Operation(op, target, value) => match (&**op, &**target) {
+                (Expression::Equal, ExprNode::Name(n)) => {
+                    self.walk(value, scope);
+                    self.assign(n, scope);
+                }
+                (Expression::Equal, ExprNode::Tuple(names)) => {
+                    self.walk(value, scope);
+                    self.unpack(names, scope);
+                }
+                (Expression::CompoundOp(_), ExprNode::Name(n)) => {
+                    scope.read.insert(n.to_string());
+                    self.walk(value, scope);
+                    self.assign(n, scope);
+                }
+                //the name after a dot is a property, not a variable
+                (Expression::Operator('.'), _) => self.walk(target, scope),
+                _ => {
+                    self.walk(target, scope);
+                    self.walk(value, scope);
+                }
+            },
+            ExprNode::Call(name, args) => {
+                if let Expression::Ident(n) = &**name {
+                    let known = self.functions.contains(n) || scope.params.contains(n) || scope.assigned.contains(n);
+                    if !known {
+                        self.warn(Rule::UndefinedFunction, format!("{} is called {} but there's no function with that name", n, scope.place));
+                    }
+                    scope.read.insert(n.clone());
+                }
+                args.iter().for_each(|a| self.walk(a, scope));
+            }
+            ExprNode::Block(items) => {
+                for (i, item) in items.iter().enumerate() {
+                    self.walk(item, scope);
+                    if matches!(item, ExprNode::ReturnVal(_)) && i + 1 < items.len() {
+                        self.warn(Rule::UnreachableCode, format!("The code after a return {} can never run", scope.place));
+                        break;
+                    }
+                }
+            }
+            //functions and methods are their own scopes
+            ExprNode::Func(name, params, body) => self.function(ident(name), params, body),
+            ExprNode::Class(name, body) => {
+                if let ExprNode::Block(items) = &**body {
+                    for item in items.iter() {
+                        match item {
+                            ExprNode::Func(method, params, body) => self.function(format!("{}.{}", ident(name), ident(method)), params, body),
+                            other => self.walk(other, scope),
+                        }
+                    }
+                }
+            }
+            ExprNode::IfStatement(con, _, _) => {
+                self.condition("if", con, scope);
+                children(node).into_iter().for_each(|n| self.walk(n, scope));
+            }
+            ExprNode::Loop(kind, con, _) => {
+                let con = match &**con {
+                    ExprNode::ForLoopDec(_, con, _) => con,
+                    con => con,
+                };
+                self.condition(kind, con, scope);
+                children(node).into_iter().for_each(|n| self.walk(n, scope));
+            }
+            ExprNode::ForIn(name, _, _) => {
+                self.loop_variable(name, scope);
+                children(node).into_iter().for_each(|n| self.walk(n, scope));
+            }
+            ExprNode::Comprehension(_, name, _, _) => {
+                self.loop_variable(name, scope);
+                children(node).into_iter().for_each(|n| self.walk(n, scope));
+            }
+            other => children(other).into_iter().for_each(|n| self.walk(n, scope)),
+        }
+    }
+}
@@ -0,0 +1,41 @@
+use super::*;
+use crate::{lexer, parser};
+
+fn lint(src: &str) -> Vec<String> {
+    let tree = parser::parse(lexer::run(&format!("{}\n", src))).unwrap();
+    check(&tree).iter().map(|w| w.to_string()).collect()
+}
+
+#[test]
+fn finds_likely_mistakes() {
+    let src = "fn f(a, b, a) { x = 1; y = 2; for b in [1] { println(b); } return y; println(a); }
+        class A { fn m(self) { if self.n = 3 { undefined_thing(); } } }
+        fn main(args) { total = 0; while total = 1 { total += 1; } print(f(1, 2, 3)); }";
+    assert_eq!(
+        lint(src),
+        vec![
+            "f has more than one parameter named a [shadowed-parameter]",
+            "The loop variable b in f replaces the parameter with the same name [shadowed-parameter]",
+            "The code after a return in f can never run [unreachable-code]",
+            "x is set in f but never used [unused-variable]",
+            "The if condition in A.m sets self.n instead of comparing it, did you mean ==? [assignment-in-condition]",
+            "undefined_thing is called in A.m but there's no function with that name [undefined-function]",
+            "The while condition in main sets total instead of comparing it, did you mean ==? [assignment-in-condition]",
+        ]
+    );
+}
+
+#[test]
+fn clean_code_has_no_warnings() {
+    let src = "fn add(a, b) { return a + b; }
+        fn main(args) {
+            list = [x * 2 for x in args if x > 1];
+            handler = add;
+            n = 0;
+            for (i = 0; i < 3; i += 1) { n += handler(i, 1); }
+            p = new Point(); p.x = n; list[0] = p.x;
+            _ignored = 5;
+        }
+        class Point { fn ~init(self) { self.x = 0; } }";
+    assert_eq!(lint(src), Vec::<String>::new());
+}
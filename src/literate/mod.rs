@@ -0,0 +1,181 @@
+//! Literate scripts, markdown documents where the fenced emerald blocks get run in order. Every
+//! block shares one runtime, so later blocks can use whatever earlier ones defined
+
+#[cfg(test)]
+mod tests;
+
+use crate::interpreter::Runtime;
+use crate::Engine;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+///Info strings that mark a fenced block as code to run
+pub const LANGUAGES: &[&str] = &["emerald", "em", "emeraldscript"];
+
+///Info string of the blocks `render` puts output in
+const OUTPUT: &str = "output";
+
+struct Fence {
+    marker: char,
+    len: usize,
+    info: String,
+}
+
+impl Fence {
+    fn open(line: &str) -> Option<Fence> {
+        let line = line.trim_start();
+        let marker = line.chars().next().filter(|c| *c == '`' || *c == '~')?;
+        let len = line.chars().take_while(|c| *c == marker).count();
+        let info = line[len..].trim();
+        if len < 3 || (marker == '`' && info.contains('`')) {
+            return None;
+        }
+        let info = info.split_whitespace().next().unwrap_or_default().to_lowercase();
+        Some(Fence { marker, len, info })
+    }
+
+    fn closes(&self, line: &str) -> bool {
+        let line = line.trim();
+        line.len() >= self.len && line.chars().all(|c| c == self.marker)
+    }
+}
+
+///A fenced block, `start` and `end` are the lines its fences are on. A block that never gets
+///closed runs to the end of the document
+struct Block {
+    info: String,
+    start: usize,
+    end: usize,
+}
+
+impl Block {
+    fn runs(&self) -> bool {
+        LANGUAGES.contains(&self.info.as_str())
+    }
+
+    fn code(&self, lines: &[&str]) -> String {
+        lines[self.start + 1..self.end.min(lines.len())].join("\n")
+    }
+}
+
+fn blocks(lines: &[&str]) -> Vec<Block> {
+    let mut out = vec![];
+    let mut i = 0;
+    while i < lines.len() {
+        if let Some(fence) = Fence::open(lines[i]) {
+            let start = i;
+            i += 1;
+            while i < lines.len() && !fence.closes(lines[i]) {
+                i += 1;
+            }
+            out.push(Block { info: fence.info, start, end: i });
+        }
+        i += 1;
+    }
+    out
+}
+
+//blocks are counted instead of going by line number, since rendering moves the lines around
+fn failure(number: usize, e: &str) -> String {
+    format!("Error in block {}: {}", number + 1, e)
+}
+
+///Runs every emerald block in a document, with output going wherever the runtime sends it.
+///A block that fails gets reported but doesn't stop the ones after it, gives back how many failed
+pub fn run(doc: &str, runtime: Runtime) -> usize {
+    let lines: Vec<&str> = doc.lines().collect();
+    let mut engine = Engine::with_runtime(runtime);
+    let mut failed = 0;
+    for (number, block) in blocks(&lines).iter().filter(|b| b.runs()).enumerate() {
+        if let Err(e) = engine.eval(&block.code(&lines)) {
+            engine.runtime().report(&failure(number, &e));
+            failed += 1;
+        }
+    }
+    failed
+}
+
+#[derive(Clone, Default)]
+struct Captured(Arc<Mutex<Vec<u8>>>);
+
+impl Captured {
+    fn take(&self) -> String {
+        String::from_utf8_lossy(&self.0.lock().unwrap_or_else(|e| e.into_inner()).split_off(0)).into_owned()
+    }
+}
+
+impl Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+///Runs a document and gives it back with each block's output in an `output` block beneath it,
+///along with how many blocks failed. Output blocks from an earlier render get replaced, so a
+///rendered document can be rendered again
+pub fn render(doc: &str, mut runtime: Runtime) -> (String, usize) {
+    let captured = Captured::default();
+    runtime.set_stdout(Box::new(captured.clone()));
+    runtime.set_stderr(Box::new(captured.clone()));
+    let mut engine = Engine::with_runtime(runtime);
+
+    let lines: Vec<&str> = doc.lines().collect();
+    let blocks = blocks(&lines);
+    let mut out = String::new();
+    let mut copied = 0;
+    let mut failed = 0;
+    let mut number = 0;
+    for (n, block) in blocks.iter().enumerate() {
+        if !block.runs() || block.start < copied {
+            continue;
+        }
+        number += 1;
+        let end = (block.end + 1).min(lines.len());
+        for line in &lines[copied..end] {
+            out.push_str(line);
+            out.push('\n');
+        }
+        copied = end;
+
+        let mut text = String::new();
+        let res = engine.eval(&block.code(&lines));
+        text.push_str(&captured.take());
+        if let Err(e) = res {
+            if !text.is_empty() && !text.ends_with('\n') {
+                text.push('\n');
+            }
+            text.push_str(&failure(number - 1, &e));
+            failed += 1;
+        }
+        if !text.is_empty() {
+            out.push_str(&output_block(&text));
+        }
+
+        //an output block left by the last render, with nothing but blank lines before it
+        if let Some(stale) = blocks.get(n + 1) {
+            if stale.info == OUTPUT && lines[copied..stale.start].iter().all(|l| l.trim().is_empty()) {
+                copied = (stale.end + 1).min(lines.len());
+            }
+        }
+    }
+    for line in &lines[copied..] {
+        out.push_str(line);
+        out.push('\n');
+    }
+    (out, failed)
+}
+
+fn output_block(text: &str) -> String {
+    //the fence has to be longer than any run of backticks in the output itself
+    let mut fence = String::from("```");
+    while text.contains(&fence) {
+        fence.push('`');
+    }
+    let newline = if text.ends_with('\n') { "" } else { "\n" };
+    format!("\n{}{}\n{}{}{}\n", fence, OUTPUT, text, newline, fence)
+}
@@ -0,0 +1,59 @@
+use super::*;
+
+const DOC: &str = "# Tutorial
+
+Variables stick around between blocks
+
+```emerald
+x = 20;
+println(\"x is \" + x);
+```
+
+Other code blocks are left alone
+
+```python
+print('not run')
+```
+
+~~~em
+println(x + 1);
+len(x, x);
+~~~
+";
+
+#[test]
+fn finds_blocks() {
+    let lines: Vec<&str> = DOC.lines().collect();
+    let found: Vec<(String, usize, usize)> = blocks(&lines).into_iter().map(|b| (b.info, b.start, b.end)).collect();
+    assert_eq!(
+        found,
+        vec![("emerald".to_owned(), 4, 7), ("python".to_owned(), 11, 13), ("em".to_owned(), 15, 18)]
+    );
+    //an unclosed block runs to the end
+    assert_eq!(blocks(&["```emerald", "x = 1;"])[0].end, 2);
+}
+
+#[test]
+fn render_puts_output_under_blocks() {
+    let (rendered, failed) = render(DOC, Runtime::new());
+    assert_eq!(failed, 1);
+    assert!(rendered.contains("println(\"x is \" + x);\n```\n\n```output\nx is 20\n```\n\nOther code blocks"));
+    assert!(rendered.contains(
+        "~~~\n\n```output\n21\nError in block 2: len takes 1 argument, got 2\n```\n"
+    ));
+    assert!(!rendered.contains("not run\n```output"));
+
+    //rendering again replaces the old output instead of adding more
+    let (again, _) = render(&rendered, Runtime::new());
+    assert_eq!(again, rendered);
+}
+
+#[test]
+fn run_reports_failures() {
+    let captured = Captured::default();
+    let mut runtime = Runtime::new();
+    runtime.set_stdout(Box::new(captured.clone()));
+    runtime.set_stderr(Box::new(captured.clone()));
+    assert_eq!(run(DOC, runtime), 1);
+    assert_eq!(captured.take(), "x is 20\n21\nError in block 2: len takes 1 argument, got 2\n");
+}
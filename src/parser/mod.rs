@@ -1,7 +1,12 @@
 #[cfg(test)]
 mod tests;
+mod source;
+
+pub use source::to_source;
 
 use super::lexer::*;
+use serde::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
 use std::iter::Peekable;
 use std::slice::Iter;
 
@@ -10,19 +15,25 @@ use std::slice::Iter;
 //making the nodes hold the actual values instead of the Expressions might be worth it to make
 //interpreting easier
 ///Enum with variants for each type of statement or literal in the lang
-#[derive(PartialEq, Debug, Clone, PartialOrd)]
+#[derive(PartialEq, Debug, PartialOrd, Serialize, Deserialize)]
+#[cfg_attr(not(feature = "debug-alloc"), derive(Clone))]
 pub enum ExprNode {
     Operation(Box<Expression>, Box<ExprNode>, Box<ExprNode>), //Operator, Left side, Right side
     StrLiteral(Box<String>),
-    NumLiteral(Box<f32>),
+    BytesLiteral(Box<Vec<u8>>),
+    NumLiteral(Box<f64>),
     BoolLiteral(bool),
+    NullLiteral, //also stands in for parts that were left out, like a for loop's declaration or a missing else
     Name(Box<String>),
     Call(Box<Expression>, Vec<ExprNode>), //name, args
     MethodCall(Box<ExprNode>, Vec<ExprNode>),
+    CallExpr(Box<ExprNode>, Vec<ExprNode>), //anything that gives back a function, args
     Block(Vec<ExprNode>),
     Func(Box<Expression>, Vec<ExprNode>, Box<ExprNode>), //Name, params, function body
     Class(Box<Expression>, Box<ExprNode>), //name, body
-    New(Box<Expression>, Vec<ExprNode>), //name params
+    New(Box<ExprNode>, Vec<ExprNode>), //class, params
+    Delete(Box<ExprNode>),               //property to remove
+    Unary(Box<Expression>, Box<ExprNode>), //operator, operand
     Loop(Box<String>, Box<ExprNode>, Box<ExprNode>),     //loop keyword, condition, block
     ForLoopDec(Box<ExprNode>, Box<ExprNode>, Box<ExprNode>), //declaration, condition, incrementation
     Statement(Box<ExprNode>),
@@ -30,62 +41,251 @@ pub enum ExprNode {
     IfStatement(Box<ExprNode>, Box<ExprNode>, Box<ExprNode>), //condition, body, branch
     ElseStatement(Box<ExprNode>),                             //body
     Array(Vec<ExprNode>),
+    Tuple(Vec<ExprNode>), //also the left side of an assignment that unpacks one
+    Comprehension(Box<ExprNode>, Box<String>, Box<ExprNode>, Box<ExprNode>), //item, variable, source, condition
+    ForIn(Box<String>, Box<ExprNode>, Box<ExprNode>), //variable, source, block
+    Yield(Box<ExprNode>),
+    Generator(Box<ExprNode>), //body of a function that yields, calling it gives back an iterator instead of running it
+    Await(Box<ExprNode>),
+    Async(Box<ExprNode>), //body of a function that awaits, calling it starts a task instead of running it
     Index(Box<ExprNode>, Box<ExprNode>), //array identifier, inedex
+    //ids only mean something to the process that marked them, so these never get saved
+    #[serde(skip)]
+    Constant(Box<ExprNode>, usize), //a subtree that always comes out the same, marked by the interpreter so it's only worked out once
+    At(Span), //where the statement after it is in the source, only left in blocks by `parse_spanned`
+    //never made by the parser, the interpreter treats finding one as a bug in the parser
     Illegal(Option<Expression>),
     EOF,
 }
 
 impl ExprNode {
-    ///Returns the inner value of a node as a string if possible
+    ///Returns the inner value of a node as a string. Anything that isn't a literal or a name comes
+    ///back as its source, which won't match any property name
     pub fn inner(&self) -> String{
         match self {
             ExprNode::StrLiteral(l) => l.to_string(),
             ExprNode::NumLiteral(l) => l.to_string(),
             ExprNode::BoolLiteral(l) => l.to_string(),
             ExprNode::Name(l) => l.to_string(),
-            _ => panic!("Can't unwrap {:?}", self)
+            other => to_source(other).trim().to_owned(),
+        }
+    }
+}
 
+///How far the parser goes before giving up, so a generated or hostile script gets an error instead
+///of overflowing the stack or filling up memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    ///How many groups, arrays, blocks, and prefix operators can be inside each other
+    pub max_depth: usize,
+    ///The longest a string literal can be, in bytes
+    pub max_literal: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            max_depth: 256,
+            max_literal: 1 << 20,
         }
     }
 }
 
+thread_local! {
+    //the parser is all free functions, so the limits for the parse in progress are kept here
+    static LIMITS: Cell<Limits> = Cell::new(Limits::default());
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+    //errors so far when the parser is recovering from them instead of stopping at the first one
+    static DIAGNOSTICS: RefCell<Option<Vec<String>>> = const { RefCell::new(None) };
+    //where each token is, when statements are being marked with where they are
+    static SPANS: RefCell<Option<Vec<Span>>> = const { RefCell::new(None) };
+}
+
+fn with_limits<T>(limits: Limits, f: impl FnOnce() -> T) -> T {
+    let old = LIMITS.with(|l| l.replace(limits));
+    let depth = DEPTH.with(|d| d.replace(0));
+    let res = f();
+    LIMITS.with(|l| l.set(old));
+    DEPTH.with(|d| d.set(depth));
+    res
+}
+
+///Runs `f` `levels` deeper, unless that goes past the depth limit
+fn nested<T>(levels: usize, f: impl FnOnce() -> Result<T, String>) -> Result<T, String> {
+    let depth = DEPTH.with(Cell::get) + levels;
+    let max = LIMITS.with(Cell::get).max_depth;
+    if depth > max {
+        return Err(format!("Nested more than {} levels deep, split it up into smaller pieces", max));
+    }
+    DEPTH.with(|d| d.set(depth));
+    let res = f();
+    DEPTH.with(|d| d.set(depth - levels));
+    res
+}
+
 ///Starts the parser
 pub fn parse(tokens: Vec<Expression>) -> Result<ExprNode, String> {
-    //let root = vec!();
-    let iter = tokens.iter();
+    parse_with_limits(tokens, Limits::default())
+}
 
-    make_block(&mut iter.peekable())
+///Same as `parse`, with different limits on nesting and literal sizes
+pub fn parse_with_limits(tokens: Vec<Expression>, limits: Limits) -> Result<ExprNode, String> {
+    with_limits(limits, || make_block(&mut tokens.iter().peekable()))
+}
+
+///Same as `parse`, given where each token is from `lexer::run_spanned`. Every statement is put
+///after an `ExprNode::At` saying where it is, so the interpreter knows where it was when something
+///goes wrong, and an error comes with where the token it's about is
+pub fn parse_spanned(tokens: &[Expression], spans: &[Span]) -> Result<ExprNode, (String, Option<Span>)> {
+    let old = SPANS.with(|s| s.replace(Some(spans.to_vec())));
+    let mut iter = tokens.iter().peekable();
+    let res = with_limits(Limits::default(), || make_block(&mut iter));
+    SPANS.with(|s| s.replace(old));
+    res.map_err(|e| {
+        //the parser has usually read the token it's complaining about, but not always
+        let read = tokens.len() - iter.len();
+        let named = |i: usize| tokens.get(i).is_some_and(|t| e.contains(&format!("{:?}", t)));
+        let at = match read {
+            0 => 0,
+            _ if !named(read - 1) && named(read) => read,
+            _ => read - 1,
+        };
+        let span = spans.get(at).or_else(|| spans.last()).copied();
+        (e, span)
+    })
+}
+
+///Same as `parse`, but a statement that doesn't parse gets skipped up to where the next one starts,
+///so every error in the file gets reported at once instead of just the first
+pub fn parse_all(tokens: Vec<Expression>) -> Result<ExprNode, Vec<String>> {
+    let old = DIAGNOSTICS.with(|d| d.replace(Some(vec![])));
+    let res = parse(tokens);
+    let mut errs = DIAGNOSTICS.with(|d| d.replace(old)).unwrap_or_default();
+    match res {
+        Ok(tree) if errs.is_empty() => Ok(tree),
+        Ok(_) => Err(errs),
+        Err(e) => {
+            errs.push(e);
+            Err(errs)
+        }
+    }
+}
 
-    // node
+///Parses a single expression, like the array of arguments passed to a script
+pub fn parse_expression(tokens: Vec<Expression>) -> Result<ExprNode, String> {
+    with_limits(Limits::default(), || expression(&mut tokens.iter().peekable(), 0))
+}
+
+///Parses exactly one expression, anything after it is an error
+pub fn parse_single(tokens: Vec<Expression>) -> Result<ExprNode, String> {
+    let mut iter = tokens.iter().peekable();
+    let node = with_limits(Limits::default(), || expression(&mut iter, 0))?;
+    match iter.find(|t| !matches!(t, Expression::Semicolon | Expression::EOF)) {
+        None => Ok(node),
+        Some(t) => Err(format!("Expected a single expression, found {:?} after it", t)),
+    }
 }
 
 ///Loops through expressions to generate all of the nodes in a block of code
 fn make_block(iter: &mut Peekable<Iter<Expression>>) -> Result<ExprNode, String> {
+    nested(1, || block_items(iter))
+}
+
+fn block_items(iter: &mut Peekable<Iter<Expression>>) -> Result<ExprNode, String> {
     let mut root = vec![];
 
-    while let Some(t) = iter.next() {
+    loop {
+        let left = iter.len();
+        //kept so an error can look back at the last token the statement read
+        let start = iter.clone();
+        let t = match iter.peek() {
+            Some(t) => *t,
+            None => break,
+        };
         match t {
-            Expression::EOF => break,
-            Expression::Rbrace => {
-                // iter.next();
+            Expression::EOF | Expression::Rbrace => {
+                iter.next();
                 break;
             }
-            Expression::Key(s) => {
-                root.push(key_word(iter, Some(t), &s)?);
-            }
-            Expression::Ident(_) => {
-                root.push(expr(iter, Some(t))?);
+            Expression::Semicolon => {
+                iter.next();
             }
             Expression::Lbrace => {
+                iter.next();
                 root.push(make_block(iter)?);
             }
-            _ => {}//root.push(expr(iter, Some(t))?)} //root.push(read_line(Some(&t), iter)?),
+            //keywords like true and new start an expression, the rest start a statement
+            Expression::Key(s) if !matches!(s.as_str(), "true" | "false" | "null" | "new") => {
+                let t = iter.next();
+                match key_word(iter, t, s) {
+                    Ok(node) => statement(&mut root, node, left, iter.len()),
+                    Err(e) => recover(iter, e, last_read(start, left - iter.len()))?,
+                }
+            }
+            _ => match expression(iter, 0) {
+                Ok(node) => statement(&mut root, node, left, iter.len()),
+                Err(e) => recover(iter, e, last_read(start, left - iter.len()))?,
+            },
         }
     }
 
     Ok(ExprNode::Block(root))
 }
 
+///Adds a statement to a block, after where it is if `parse_spanned` wants to know. The statement
+///started with `before` tokens left and ended with `after` left
+fn statement(root: &mut Vec<ExprNode>, node: ExprNode, before: usize, after: usize) {
+    SPANS.with(|s| {
+        if let Some(spans) = &*s.borrow() {
+            let (first, last) = (spans.len() - before, spans.len() - after);
+            if let (Some(a), Some(b)) = (spans.get(first), spans.get(last.max(first + 1) - 1)) {
+                root.push(ExprNode::At(Span { start: a.start, end: b.end }));
+            }
+        }
+    });
+    root.push(node);
+}
+
+///The last of the `read` tokens after `start`
+fn last_read<'a>(mut start: Peekable<Iter<'a, Expression>>, read: usize) -> Option<&'a Expression> {
+    start.nth(read.checked_sub(1)?)
+}
+
+///When `parse_all` is collecting errors, keeps this one and skips to the end of the statement it was in:
+///past the next `;` or block that closes, or up to the `}` that ends the block around it. If the
+///`;` was the last token the statement read, like in `x = ;`, the next statement starts right away.
+///Otherwise it's just handed back
+fn recover(iter: &mut Peekable<Iter<Expression>>, err: String, last: Option<&Expression>) -> Result<(), String> {
+    let err = DIAGNOSTICS.with(|d| match &mut *d.borrow_mut() {
+        Some(errs) => {
+            errs.push(err);
+            None
+        }
+        None => Some(err),
+    });
+    if let Some(err) = err {
+        return Err(err);
+    }
+    if last == Some(&Expression::Semicolon) {
+        return Ok(());
+    }
+    let mut depth = 0;
+    while let Some(t) = iter.peek() {
+        match t {
+            Expression::EOF => break,
+            Expression::Rbrace if depth == 0 => break,
+            Expression::Lbrace => depth += 1,
+            Expression::Rbrace => depth -= 1,
+            _ => {}
+        }
+        let t = iter.next();
+        if depth == 0 && matches!(t, Some(Expression::Semicolon) | Some(Expression::Rbrace)) {
+            break;
+        }
+    }
+    Ok(())
+}
+
 ///Handles all the different keywords
 fn key_word(
     iter: &mut Peekable<Iter<'_, Expression>>,
@@ -93,19 +293,30 @@ fn key_word(
     word: &str,
 ) -> Result<ExprNode, String> {
     match word.trim() {
+        //the old print keywords are just calls to the builtins now
         "print" | "println" => Ok(ExprNode::Call(
-            Box::new(Expression::Key(word.to_owned())),
-            vec![read_line(None, iter, &vec![])?],
+            Box::new(Expression::Ident(word.to_owned())),
+            vec![expression(iter, 0)?],
         )),
         "fn" => def_func(iter, cur),
         "class" => define_class(iter), //get the name of the class and collect the block that should follow
         "new" => new_object(iter), //call to a function that passes in the class name and the args for the constructor
-        "return" => Ok(ExprNode::ReturnVal(Box::new(expr(iter, cur)?))),
+        "delete" => Ok(ExprNode::Delete(Box::new(expression(iter, 0)?))),
+        "return" => match iter.peek() {
+            Some(Expression::Semicolon) | Some(Expression::Rbrace) | None => Ok(ExprNode::ReturnVal(Box::new(ExprNode::NullLiteral))),
+            _ => Ok(ExprNode::ReturnVal(Box::new(expression(iter, 0)?))),
+        },
+        "yield" => match iter.peek() {
+            Some(Expression::Semicolon) | Some(Expression::Rbrace) | None => Ok(ExprNode::Yield(Box::new(ExprNode::NullLiteral))),
+            _ => Ok(ExprNode::Yield(Box::new(expression(iter, 0)?))),
+        },
+        "await" => Ok(ExprNode::Await(Box::new(expression(iter, PREFIX_POWER)?))),
         "true" => Ok(ExprNode::BoolLiteral(true)),
         "false" => Ok(ExprNode::BoolLiteral(false)),
-        "null" => Ok(ExprNode::Illegal(None)),
+        "null" => Ok(ExprNode::NullLiteral),
         "while" => {
-            let con = read_line(None, iter, &vec![&Expression::Rbrace])?;
+            let con = expression(iter, 0)?;
+            expect(iter, &Expression::Lbrace)?;
             let body = make_block(iter)?;
             Ok(ExprNode::Loop(
                 Box::new("while".to_string()),
@@ -113,12 +324,20 @@ fn key_word(
                 Box::new(body),
             ))
         }
-        "for" => Ok(ExprNode::Loop(
-            Box::new("for".to_string()),
-            Box::new(make_for_loop(iter)?),
-            Box::new(make_block(iter)?),
-        )),
+        "for" => match iter.peek() {
+            Some(Expression::Ident(_)) => make_for_in(iter),
+            _ => Ok(ExprNode::Loop(
+                Box::new("for".to_string()),
+                Box::new(make_for_loop(iter)?),
+                Box::new(make_block(iter)?),
+            )),
+        },
         "if" => make_if(iter),
+        //`let` doesn't change what an assignment does, it's there so unpacking reads like it does elsewhere
+        "let" => match expression(iter, 0)? {
+            ExprNode::Operation(op, target, value) if *op == Expression::Equal => Ok(ExprNode::Operation(op, target, value)),
+            other => Err(format!("Expected an assignment after let, found {}", to_source(&other).trim())),
+        },
         _ => Err(format!("Unknown keyword {}", word)),
     }
 }
@@ -128,252 +347,304 @@ fn def_func(
     iter: &mut Peekable<Iter<'_, Expression>>,
     _cur: Option<&Expression>,
 ) -> Result<ExprNode, String> {
-    let mut name: Expression = Expression::Ident("broken".to_owned());
+    let name = match iter.next() {
+        Some(Expression::Ident(n)) => n.clone(),
+        n => return Err(format!("Expected indentifier found {:?}", n)),
+    };
     let mut params = vec![];
-    let mut body: ExprNode = ExprNode::Illegal(None);
-
-    if let Some(n) = iter.next() {
-        match n {
-            Expression::Ident(_) => name = n.clone(),
-            _ => return Err(format!("Expected indentifier found {:?}", n)),
-        }
-    }
 
     while let Some(p) = iter.next() {
         match p {
-            Expression::Lparen => continue,
+            Expression::Lparen | Expression::Comma => continue,
             Expression::Rparen => break,
             Expression::Ident(i) => params.push(ExprNode::Name(Box::new(i.to_string()))),
-            _ => {}
+            other => return Err(format!("Expected a parameter name, found {:?}", other)),
         }
     }
 
-    if let Some(b) = iter.next() {
-        if let Expression::Lbrace = b {
-            body = make_block(iter)?;
-        }
+    let mut body = match iter.next() {
+        Some(Expression::Lbrace) => make_block(iter)?,
+        other => return Err(format!("Expected the body of {} to start with {{, found {:?}", name, other)),
+    };
+
+    if contains_yield(&body) {
+        body = ExprNode::Generator(Box::new(body));
+    } else if contains_await(&body) {
+        body = ExprNode::Async(Box::new(lift_awaits(body)));
     }
 
-    Ok(ExprNode::Func(Box::new(name), params, Box::new(body)))
+    Ok(ExprNode::Func(Box::new(Expression::Ident(name)), params, Box::new(body)))
 }
 
-///Reads to the end of the current line, stopping at the first semicolon or lbrace, or the specified deliminator
-pub fn read_line<'a>(
-    prev: Option<&Vec<Expression>>,
-    iter: &mut Peekable<Iter<Expression>>,
-    delim: &Vec<&Expression>,
-) -> Result<ExprNode, String> {
-    //iterate through the next set of expressions until we get to a ';'
-    let mut accum = if let Some(v) = prev {
-        v.clone()
-    } else {
-        Vec::new()
-    };
+///Whether a function body yields anywhere, not counting the functions and classes defined inside it
+pub(crate) fn contains_yield(node: &ExprNode) -> bool {
+    contains(node, &|n| matches!(n, ExprNode::Yield(_)))
+}
 
-    
-    for exp in iter.take_while(|e| !(delim.contains(e)|| Expression::Lbrace == **e)) {
-        match exp {
-            // Expression::Lbracket => {
-            //     return make_array(iter);
-            // }
-            Expression::Operator(op) => {
-                return Ok(if op == &'.' {
-                    let tmp = ExprNode::Operation(
-                    Box::new(exp.clone()),
-                    Box::new(expr(&mut accum.iter().peekable(), None)?),
-                    Box::new(make_node(iter.next().unwrap())));
-                    
-                        match iter.peek() {
-                            Some(Expression::Operator(_)) => {
-                                let operator = iter.next().unwrap();
-                                ExprNode::Operation(
-                                    Box::new(operator.clone()),
-                                    Box::new(tmp),
-                                    Box::new(read_line(None, iter, delim)?))
-                            }
-                            Some(Expression::Equal) => {
-                                //if there is an equal sign after the dot operator, then use the dot operation as the left side of it
-                                iter.next();
-                                ExprNode::Operation(
-                                    Box::new(Expression::Equal),
-                                    Box::new(tmp),
-                                    Box::new(read_line(None, iter, &vec![&Expression::Semicolon])?))
-                            },
-                            Some(Expression::Lparen) => {
-                                iter.next();
-                                ExprNode::MethodCall(Box::new(tmp), find_params(iter)?)
-                            }
-                            _ => tmp,
-                        }
-
-                }else{
-                ExprNode::Operation(
-                    Box::new(exp.clone()),
-                    Box::new(expr(&mut accum.iter().peekable(), None)?),
-                    Box::new(read_line(None, iter, delim)?),
-                )
-                });
+pub(crate) fn contains_await(node: &ExprNode) -> bool {
+    contains(node, &|n| matches!(n, ExprNode::Await(_)))
+}
+
+///Tasks can only pause between statements, so an await buried in an expression is pulled out into a
+///statement of its own just before, and the expression uses a temporary instead. Loop conditions
+///run more than once, so awaits in those stay where they are
+fn lift_awaits(body: ExprNode) -> ExprNode {
+    let mut count = 0;
+    match body {
+        ExprNode::Block(v) => ExprNode::Block(lift_block(v, &mut count)),
+        other => other,
+    }
+}
+
+fn lift_block(statements: Vec<ExprNode>, count: &mut usize) -> Vec<ExprNode> {
+    let mut out = vec![];
+    for mut s in statements {
+        match &mut s {
+            ExprNode::Block(v) => *v = lift_block(std::mem::take(v), count),
+            ExprNode::IfStatement(con, body, branch) => {
+                lift_expr(con, &mut out, count);
+                lift_in_place(body, count);
+                //an elif's awaits can't go before the whole if, so it becomes an else with an if inside
+                let mut branches = lift_block(vec![std::mem::replace(&mut **branch, ExprNode::NullLiteral)], count);
+                **branch = match branches.len() {
+                    1 => branches.remove(0),
+                    _ => ExprNode::ElseStatement(Box::new(ExprNode::Block(branches))),
+                };
             }
-            Expression::BoolOp(_) => {
-                return Ok(ExprNode::Operation(
-                    Box::new(exp.clone()),
-                    Box::new(expr(&mut accum.iter().peekable(), None)?),
-                    Box::new(read_line(None, iter, delim)?),
-                ))
+            ExprNode::ElseStatement(body) => lift_in_place(body, count),
+            ExprNode::Loop(_, con, body) => {
+                if let ExprNode::ForLoopDec(dec, _, _) = &mut **con {
+                    lift_expr(dec, &mut out, count);
+                }
+                lift_in_place(body, count);
             }
-            Expression::CompoundOp(_) => {
-                let left = expr(&mut accum.iter().peekable(), None)?;
-                return make_compound_op(left, exp, iter);
+            ExprNode::ForIn(_, source, body) => {
+                lift_expr(source, &mut out, count);
+                lift_in_place(body, count);
             }
-            Expression::Equal => {
-                return Ok(ExprNode::Operation(
-                    Box::new(exp.clone()),
-                    Box::new(expr(&mut accum.iter().peekable(), None)?),
-                    Box::new(read_line(None, iter, delim)?),
-                ))
+            //these are already statements an await can pause at
+            ExprNode::Await(v) | ExprNode::ReturnVal(v) => match &mut **v {
+                ExprNode::Await(inner) => lift_expr(inner, &mut out, count),
+                other => lift_expr(other, &mut out, count),
+            },
+            ExprNode::Operation(op, target, value) if **op == Expression::Equal && matches!(**target, ExprNode::Name(_)) => {
+                match &mut **value {
+                    ExprNode::Await(inner) => lift_expr(inner, &mut out, count),
+                    other => lift_expr(other, &mut out, count),
+                }
             }
-            _ => accum.push(exp.clone()),
+            other => lift_expr(other, &mut out, count),
         }
+        out.push(s);
     }
+    out
+}
 
-    Ok(expr(&mut accum.iter().peekable(), None)?)
+fn lift_in_place(body: &mut ExprNode, count: &mut usize) {
+    if let ExprNode::Block(v) = body {
+        *v = lift_block(std::mem::take(v), count);
+    }
 }
 
-fn expr(
-    iter: &mut Peekable<Iter<'_, Expression>>,
-    cur: Option<&Expression>,
-) -> Result<ExprNode, String> {
-    let t = iter.next();
-    let mut node: ExprNode = ExprNode::Illegal(None);
-
-    if let Some(next) = iter.peek() {
-        match next {
-            Expression::Operator(_) => match cur {
-                Some(Expression::Lparen) => {
-                    return expr(iter, cur);
-                }
-                Some(_) => return expr(iter, t),
-                None => {}
-            },
-            Expression::BoolOp(_) => return expr(iter, t),
-            _ => {}
+fn lift_expr(node: &mut ExprNode, out: &mut Vec<ExprNode>, count: &mut usize) {
+    let mut lift = |n: &mut ExprNode| lift_expr(n, out, count);
+    match node {
+        ExprNode::Await(inner) => {
+            lift(inner);
+            let name = ExprNode::Name(Box::new(format!("~await{}", count)));
+            *count += 1;
+            let awaited = std::mem::replace(node, name.clone());
+            out.push(ExprNode::Operation(Box::new(Expression::Equal), Box::new(name), Box::new(awaited)));
+        }
+        ExprNode::Operation(_, a, b) | ExprNode::Index(a, b) => {
+            lift(a);
+            lift(b);
+        }
+        ExprNode::MethodCall(a, args) | ExprNode::CallExpr(a, args) | ExprNode::New(a, args) => {
+            lift(a);
+            args.iter_mut().for_each(lift);
         }
+        ExprNode::Call(_, args) | ExprNode::Array(args) | ExprNode::Tuple(args) => args.iter_mut().for_each(lift),
+        ExprNode::Delete(a) | ExprNode::Unary(_, a) | ExprNode::Statement(a) => lift(a),
+        _ => {}
     }
-    if let Some(exp) = t {
-        // println!(
-        //     "The expresion was: {:?}, current Expression was {:?}",
-        //     &t, cur
-        // );
-        match exp {
-            Expression::Equal => {
-                if let Some(Expression::Ident(name)) = cur {
-                    node = ExprNode::Operation(
-                        Box::new(t.unwrap().clone()),
-                        Box::new(ExprNode::Name(Box::new(name.to_string()))),
-                        Box::new(read_line(None, iter, &vec![&Expression::Semicolon])?),
-                    )
-                }
-            }
-            Expression::Operator(op) => {
-                if op == &'.' {
-                    let tmp = ExprNode::Operation(
-                    Box::new(t.unwrap().clone()),
-                    Box::new(make_node(cur.unwrap())),
-                    Box::new(make_node(iter.next().unwrap())));
-                    node = 
-                        match iter.peek() {
-                            Some(Expression::Operator(_)) => {
-                                let operator = iter.next().unwrap();
-                                ExprNode::Operation(
-                                    Box::new(operator.clone()),
-                                    Box::new(tmp),
-                                    Box::new(read_line(None, iter, &vec![&Expression::Semicolon])?))
-                            }
-                            Some(Expression::Equal) => {
-                                //if there is an equal sign after the dot operator, then use the dot operation as the left side of it
-                                iter.next();
-                                ExprNode::Operation(
-                                    Box::new(Expression::Equal),
-                                    Box::new(tmp),
-                                    Box::new(read_line(None, iter, &vec![&Expression::Semicolon])?))
-                            },
-                            Some(Expression::Lparen) => {
-                                iter.next();
-                                ExprNode::MethodCall(Box::new(tmp), find_params(iter)?)
-                            }
-                            _ => tmp,
-                        }
-
-                }else{
-                node = ExprNode::Operation(
-                    Box::new(t.unwrap().clone()),
-                    Box::new(make_node(cur.unwrap())),
-                    Box::new(read_line(None, iter, &vec![&Expression::Semicolon])?),
-                )
-                }
-            }
-            Expression::CompoundOp(_) => {
-                node = make_compound_op(make_node(cur.unwrap()), t.unwrap(), iter)?;
-            }
-            Expression::BoolOp(_) => {
-                node = ExprNode::Operation(
-                    Box::new(t.unwrap().clone()),
-                    Box::new(make_node(cur.unwrap())),
-                    Box::new(expr(iter, t)?),
-                )
-            }
-            Expression::Word(s) => node = ExprNode::StrLiteral(Box::new(s.to_string())),
-            Expression::Number(n) => node = ExprNode::NumLiteral(Box::new(*n)),
-            Expression::Key(w) => node = key_word(iter, cur, w)?,
-            Expression::Ident(i) => {
-                // println!("Next exp: {:?}", iter.peek());
-                node = match iter.peek() {
-                    Some(Expression::Lparen) => expr(iter, t)?,
-                    Some(Expression::Lbracket) => index_array(t.unwrap(), iter)?,
-                    Some(Expression::Operator(_)) => expr(iter, t)?,
-                    Some(Expression::Equal) => expr(iter, t)?,
-                    _ => ExprNode::Name(Box::new(i.to_string())),
-                }
+}
+
+fn contains(node: &ExprNode, is: &dyn Fn(&ExprNode) -> bool) -> bool {
+    let any = |nodes: &[ExprNode]| nodes.iter().any(|n| contains(n, is));
+    match node {
+        n if is(n) => true,
+        ExprNode::Operation(_, a, b) | ExprNode::Loop(_, a, b) | ExprNode::ForIn(_, a, b) | ExprNode::Index(a, b) => {
+            contains(a, is) || contains(b, is)
+        }
+        ExprNode::ForLoopDec(a, b, c) | ExprNode::IfStatement(a, b, c) | ExprNode::Comprehension(a, _, b, c) => {
+            contains(a, is) || contains(b, is) || contains(c, is)
+        }
+        ExprNode::MethodCall(a, args) | ExprNode::CallExpr(a, args) | ExprNode::New(a, args) => contains(a, is) || any(args),
+        ExprNode::Call(_, args) | ExprNode::Block(args) | ExprNode::Array(args) | ExprNode::Tuple(args) => any(args),
+        ExprNode::Delete(a)
+        | ExprNode::Unary(_, a)
+        | ExprNode::Statement(a)
+        | ExprNode::ReturnVal(a)
+        | ExprNode::ElseStatement(a)
+        | ExprNode::Yield(a)
+        | ExprNode::Await(a) => contains(a, is),
+        _ => false,
+    }
+}
+
+///Skips the next token, as long as it's the one we expected
+fn expect(iter: &mut Peekable<Iter<'_, Expression>>, exp: &Expression) -> Result<(), String> {
+    match iter.next() {
+        Some(e) if e == exp => Ok(()),
+        other => Err(format!("Expected {:?}, found {:?}", exp, other)),
+    }
+}
+
+//operators that bind tighter have higher numbers. Each infix operator has a left and right power,
+//the side with the higher number is the one that groups first when the same operator repeats
+const PREFIX_POWER: u8 = 17;
+
+fn infix_power(exp: &Expression) -> Option<(u8, u8)> {
+    match exp {
+        Expression::Equal | Expression::CompoundOp(_) => Some((2, 1)),
+        Expression::BoolOp(op) => match op.as_str() {
+            "||" => Some((3, 4)),
+            "&&" => Some((5, 6)),
+            "==" | "!=" => Some((7, 8)),
+            _ => Some((9, 10)),
+        },
+        //looser than arithmetic so `0..n - 1` doesn't need parens
+        Expression::DotDot => Some((11, 12)),
+        Expression::Operator('+') | Expression::Operator('-') => Some((13, 14)),
+        Expression::Operator('*') | Expression::Operator('/') => Some((15, 16)),
+        _ => None,
+    }
+}
+
+///Parses an expression, only taking operators that bind at least as tightly as `min_power`.
+///Calls, indexing, and member access bind tighter than anything else
+fn expression(iter: &mut Peekable<Iter<'_, Expression>>, min_power: u8) -> Result<ExprNode, String> {
+    nested(1, || operations(iter, min_power))
+}
+
+fn operations(iter: &mut Peekable<Iter<'_, Expression>>, min_power: u8) -> Result<ExprNode, String> {
+    let mut left = prefix(iter)?;
+
+    while let Some(&op) = iter.peek() {
+        match op {
+            Expression::Lparen | Expression::Lbracket | Expression::Operator('.') => {
+                iter.next();
+                left = postfix(left, op, iter)?;
+                continue;
             }
-            Expression::Lparen => {
-                // println!("This is what cur =  {:?}", cur);
-                node = if let Some(Expression::Ident(_)) = cur {
-                    ExprNode::Call(Box::new(cur.unwrap().clone()), find_params(iter)?)
-                //if there was an identifier last before the '(', it should be a function call
-                } else {
-                    //Otherwise it should be a statement
-                    ExprNode::Statement(Box::new(expr(iter, cur)?))
-                }
+            Expression::CompoundOp(c) if c == "++" || c == "--" => {
+                iter.next();
+                left = make_compound_op(left, op, iter)?;
+                continue;
             }
-            Expression::Rparen => {
-                node = if let Some(Expression::Semicolon) = iter.peek() {
-                    make_node(cur.unwrap())
-                } else {
-                    let tmp = iter.next();
-                    expr(iter, tmp)?
-                }
+            _ => {}
+        }
+
+        let (left_power, right_power) = match infix_power(op) {
+            Some(p) if p.0 >= min_power => p,
+            _ => break,
+        };
+        iter.next();
+        left = match op {
+            Expression::CompoundOp(_) => make_compound_op(left, op, iter)?,
+            _ => ExprNode::Operation(
+                Box::new(op.clone()),
+                Box::new(left),
+                Box::new(expression(iter, right_power)?),
+            ),
+        };
+        //an assignment can't be the left side of anything
+        if left_power == 2 {
+            break;
+        }
+    }
+
+    Ok(left)
+}
+
+///Parses the start of an expression, a literal, a name, a group in parens, or a prefix operator
+fn prefix(iter: &mut Peekable<Iter<'_, Expression>>) -> Result<ExprNode, String> {
+    match iter.next() {
+        Some(Expression::Number(n)) => Ok(ExprNode::NumLiteral(Box::new(*n))),
+        Some(Expression::Word(s)) => {
+            let max = LIMITS.with(Cell::get).max_literal;
+            if s.len() > max {
+                return Err(format!("String literal is {} bytes long, the most it can be is {}", s.len(), max));
             }
-            Expression::Lbrace => {
-                node = make_block(iter)?;
+            Ok(ExprNode::StrLiteral(Box::new(s.to_string())))
+        }
+        Some(Expression::Bytes(b)) => {
+            let max = LIMITS.with(Cell::get).max_literal;
+            if b.len() > max {
+                return Err(format!("Byte string literal is {} bytes long, the most it can be is {}", b.len(), max));
             }
-            Expression::Lbracket => {
-                node = match cur {
-                    Some(Expression::Ident(_)) => read_line(
-                        Some(&vec![cur.unwrap().clone(), t.unwrap().clone()]),
-                        iter,
-                        &vec![],
-                    )?,
-                    _ => make_array(iter)?,
-                }
+            Ok(ExprNode::BytesLiteral(Box::new(b.clone())))
+        }
+        Some(Expression::Ident(i)) => Ok(ExprNode::Name(Box::new(i.to_string()))),
+        Some(t @ Expression::Key(w)) if matches!(w.as_str(), "true" | "false" | "null" | "new" | "await") => {
+            key_word(iter, Some(t), w)
+        }
+        Some(Expression::Lparen) => make_group(iter),
+        Some(Expression::Lbracket) => make_array(iter),
+        Some(op @ Expression::Operator('-')) | Some(op @ Expression::Operator('!')) => {
+            //a run of prefix operators like `!!!x` is read in a loop instead of a call for each one
+            let mut ops = vec![op];
+            while let Some(&op) = iter.peek().filter(|t| matches!(t, Expression::Operator('-') | Expression::Operator('!'))) {
+                ops.push(op);
+                iter.next();
             }
-            Expression::Semicolon => {}
-            _ => node = expr(iter, cur)?,
+            let operand = nested(ops.len() - 1, || expression(iter, PREFIX_POWER))?;
+            Ok(ops.into_iter().rev().fold(operand, |node, op| ExprNode::Unary(Box::new(op.clone()), Box::new(node))))
         }
-    } else {
-        node = ExprNode::EOF;
+        Some(Expression::Illegal(why)) => Err(why.clone()),
+        Some(t) => Err(format!("Unexpected {:?}", t)),
+        None => Err("Unexpected end of file".to_owned()),
     }
+}
 
-    Ok(node)
+///Handles the operators that come after what they act on, calls, indexing, and member access
+fn postfix(
+    left: ExprNode,
+    op: &Expression,
+    iter: &mut Peekable<Iter<'_, Expression>>,
+) -> Result<ExprNode, String> {
+    match op {
+        Expression::Lparen => match left {
+            ExprNode::Name(n) => Ok(ExprNode::Call(Box::new(Expression::Ident(*n)), find_params(iter)?)),
+            ExprNode::Operation(ref o, _, _) if **o == Expression::Operator('.') => {
+                Ok(ExprNode::MethodCall(Box::new(left), find_params(iter)?))
+            }
+            _ => Ok(ExprNode::CallExpr(Box::new(left), find_params(iter)?)),
+        },
+        Expression::Lbracket => {
+            let index = expression(iter, 0)?;
+            expect(iter, &Expression::Rbracket)?;
+            //indexing a name once is the simple case, anything past that gets chained
+            Ok(match left {
+                ExprNode::Name(_) => ExprNode::Index(Box::new(left), Box::new(index)),
+                ExprNode::Index(n, i) => ExprNode::Operation(
+                    Box::new(Expression::Lbracket),
+                    Box::new(ExprNode::Operation(Box::new(Expression::Lbracket), n, i)),
+                    Box::new(index),
+                ),
+                _ => ExprNode::Operation(Box::new(Expression::Lbracket), Box::new(left), Box::new(index)),
+            })
+        }
+        _ => match iter.next() {
+            Some(Expression::Ident(prop)) => Ok(ExprNode::Operation(
+                Box::new(op.clone()),
+                Box::new(left),
+                Box::new(ExprNode::Name(Box::new(prop.to_string()))),
+            )),
+            other => Err(format!("Expected property name, found {:?}", other)),
+        },
+    }
 }
 
 fn make_compound_op(
@@ -399,23 +670,23 @@ fn make_compound_op(
         match tmp.as_str() {
             "+=" => {
                 let op = Expression::Operator('+');
-                let right = read_line(None, iter, &vec![])?;
+                let right = expression(iter, 1)?;
                 //converts 'x += y' to 'x = x + y'
                 Ok(make_op(op, right))
             }
             "-=" => {
                 let op = Expression::Operator('-');
-                let right = read_line(None, iter, &vec![])?;
+                let right = expression(iter, 1)?;
                 Ok(make_op(op, right))
             }
             "*=" => {
                 let op = Expression::Operator('*');
-                let right = read_line(None, iter, &vec![])?;
+                let right = expression(iter, 1)?;
                 Ok(make_op(op, right))
             }
             "/=" => {
                 let op = Expression::Operator('/');
-                let right = read_line(None, iter, &vec![])?;
+                let right = expression(iter, 1)?;
                 Ok(make_op(op, right))
             }
             "++" => {
@@ -435,220 +706,190 @@ fn make_compound_op(
     }
 }
 
-fn build_chain_back(
-    ident: &Expression,
-    iter: &mut Peekable<std::iter::Rev<Iter<'_, ExprNode>>>,
-) -> Option<ExprNode> {
-    if let Some(_) = iter.peek() {
-        let index = iter.next().unwrap();
-
-        if let Some(op) = build_chain_back(ident, iter) {
-            //If the next call in the chain returns something, build it normally
-            Some(ExprNode::Operation(
-                Box::new(Expression::Lbracket),
-                Box::new(op),
-                Box::new(index.clone()),
-            ))
-        } else {
-            //If not, we've reached the end and shoud return the root node with the actual identifier
-            Some(ExprNode::Operation(
-                Box::new(Expression::Lbracket),
-                Box::new(make_node(ident)),
-                Box::new(index.clone()),
-            ))
-        }
-    } else {
-        None
-    }
-}
-
-fn index_array(
-    ident: &Expression,
-    iter: &mut Peekable<Iter<'_, Expression>>,
-) -> Result<ExprNode, String> {
-    //check if we need to skip the bracket or not
-    if let Some(Expression::Lbracket) = iter.peek() {
-        iter.next();
-    }
-    let mut multidex = vec![];
-    let index = expr(iter, None)?;
-    iter.next(); //skip the closing rbracket
-    if let Some(Expression::Lbracket) = iter.peek() {
-        //accumulate all of the index operations
-        multidex.push(index);
-        while let Some(Expression::Lbracket) = iter.next() {
-            multidex.push(expr(iter, None)?);
-            iter.next(); //skip the closing rbracket
-        }
-        Ok(build_chain_back(ident, &mut multidex.iter().rev().peekable()).unwrap())
-    } else {
-        //Don't neext to do all that fancy stuff if there's only one index instruction
-        Ok(ExprNode::Index(Box::new(make_node(ident)), Box::new(index)))
-    }
-}
-
-fn make_node(exp: &Expression) -> ExprNode {
-    //feels bad to clone here but I don't know if it's avoidable
-    match exp.clone() {
-        Expression::Word(s) => ExprNode::StrLiteral(Box::new(s)),
-        Expression::Number(n) => ExprNode::NumLiteral(Box::new(n)),
-        Expression::Ident(i) => ExprNode::Name(Box::new(i)),
-        _ => ExprNode::Illegal(Some(exp.clone())),
-    }
-}
-
+///Reads a comma separated list of expressions up to the closing paren, the opening paren should already be skipped
 fn find_params(
     peekable: &mut Peekable<Iter<'_, Expression>>,
 ) -> Result<Vec<ExprNode>, String> {
-    let mut nest = 1;
     let mut params = vec![];
+    if let Some(Expression::Rparen) = peekable.peek() {
+        peekable.next();
+        return Ok(params);
+    }
     loop {
-        // println!("{:?} is next in params", peekable.peek());
-        // println!("{:?} is the accumulated params", params);
-        // std::io::stdin().read_line(&mut String::new());
-        match peekable.peek() {
-            Some(Expression::Lparen) => {
-                peekable.next();
-                nest+=1;
-                continue;
-            }
-            Some(Expression::Rparen) => {
-                peekable.next();
-                nest-=1;
-                if nest < 1 {
-                    break;
-                }else {
-                    continue;
-                }
-            }
-            Some(Expression::Semicolon) => break,
+        params.push(expression(peekable, 0)?);
+        match peekable.next() {
+            Some(Expression::Comma) => continue,
+            Some(Expression::Rparen) => break,
             Some(Expression::Lbrace) => {
                 return Err("Can't have block in function parameters".to_owned());
             }
-            None => break,
-            _ => params.push(read_line(None, peekable, &vec![&Expression::Comma, &Expression::Rparen])?),
+            other => return Err(format!("Expected \",\" or \")\", found {:?}", other)),
         }
     }
     Ok(params)
 }
 
+///Reads the `(declaration; condition; increment)` part of a for loop and skips the opening brace of the body.
+///The declaration can be left out, either as `(; condition; increment)` or just `(condition; increment)`
 fn make_for_loop(iter: &mut Peekable<Iter<'_, Expression>>) -> Result<ExprNode, String> {
-    match iter.peek() {
-        Some(Expression::Lparen) => {
-            iter.next(); //skip the lparen after the "for" keyword
-            let name = iter.next(); //grab the next expression to pass it in as cur
-            let dec = expr(iter, name)?; //get the declaration expression (i = 0)
-            if let ExprNode::Operation(op, _, _) = &dec {
-                //double check to make sure this was an assignment op
-                if **op == Expression::Equal {
-                    let condition = read_line(None, iter, &vec![&Expression::Semicolon])?; //get the condition expression (i < 10)
-                    let increment = read_line(None, iter, &vec![&Expression::Rparen])?; //get the incrementation expression (i = i + 1)
-                    return Ok(ExprNode::ForLoopDec(
-                        Box::new(dec),
-                        Box::new(condition),
-                        Box::new(increment),
-                    ));
-                }
+    expect(iter, &Expression::Lparen)?;
+    let first = match iter.peek() {
+        Some(Expression::Semicolon) => ExprNode::NullLiteral,
+        _ => expression(iter, 0)?,
+    };
+    expect(iter, &Expression::Semicolon)?;
+    let second = expression(iter, 0)?;
+    let res = if let Some(Expression::Semicolon) = iter.peek() {
+        iter.next();
+        let increment = expression(iter, 0)?;
+        ExprNode::ForLoopDec(Box::new(first), Box::new(second), Box::new(increment))
+    } else {
+        ExprNode::ForLoopDec(Box::new(ExprNode::NullLiteral), Box::new(first), Box::new(second))
+    };
+    expect(iter, &Expression::Rparen)?;
+    expect(iter, &Expression::Lbrace)?;
+    Ok(res)
+}
+
+///`for item in items { ... }`, loops over anything a comprehension can
+fn make_for_in(iter: &mut Peekable<Iter<'_, Expression>>) -> Result<ExprNode, String> {
+    let name = match iter.next() {
+        Some(Expression::Ident(n)) => n.to_string(),
+        other => return Err(format!("Expected identifier, found {:?}", other)),
+    };
+    expect(iter, &Expression::Ident("in".to_owned()))?;
+    let source = expression(iter, 0)?;
+    expect(iter, &Expression::Lbrace)?;
+    Ok(ExprNode::ForIn(Box::new(name), Box::new(source), Box::new(make_block(iter)?)))
+}
+
+fn make_if(iter: &mut Peekable<Iter<'_, Expression>>) -> Result<ExprNode, String> {
+    //every if and elif is read in a loop and they're put together at the end, so a long elif chain
+    //doesn't recurse
+    let mut arms = vec![];
+    let mut branch = ExprNode::NullLiteral;
+    loop {
+        let condition = expression(iter, 0)?; //get the conditional statement for the if
+        expect(iter, &Expression::Lbrace)?;
+        arms.push((condition, make_block(iter)?)); //get the body of the if
+
+        match iter.peek() {
+            Some(Expression::Key(w)) if w == "else" => {
+                iter.next(); //skip the else expression
+                expect(iter, &Expression::Lbrace)?;
+                branch = make_block(iter)?; //push on the body of the else statement
+                break;
             }
-            //for loops don't need to have an assinment op, so that needs to be supported
-            iter.next(); //skip the last semicolon
-            let increment = read_line(None, iter, &vec![&Expression::Rparen])?; //get the incrementation expression (i = i + 1)
-            iter.next();
-            iter.next(); //skipping the closing paren and opening braceso that the body can be parsed properly
-            Ok(ExprNode::ForLoopDec(
-                Box::new(ExprNode::Illegal(None)),
-                Box::new(dec),
-                Box::new(increment),
-            ))
+            Some(Expression::Key(w)) if w == "elif" => {
+                iter.next();
+            }
+            _ => break,
         }
-        Some(_) | None => Err(format!("Expected \"(\", found {:?}", iter.next())),
     }
+    Ok(arms.into_iter().rev().fold(branch, |branch, (condition, block)| {
+        ExprNode::IfStatement(Box::new(condition), Box::new(block), Box::new(branch))
+    }))
 }
 
-fn make_if(iter: &mut Peekable<Iter<'_, Expression>>) -> Result<ExprNode, String> {
-    // if let Some(Expression::Lparen) = iter.peek() {
-        // iter.next();
-        let condition = read_line(None, iter, &vec![&Expression::Lbrace])?; //get the conditional statement for the if
-        // iter.next(); //skip the closing paren
-        // iter.next(); //skip the opening brace
-        let block = make_block(iter)?; //get the body of the if
-
-        let mut branch = ExprNode::Illegal(None);
-
-
-        if let Some(Expression::Key(w)) = iter.peek() {
-            match w.as_str() {
-                "else" => {
-                    iter.next(); //skip the else expression
-                    iter.next(); //skip the opening brace
-                    branch = make_block(iter)?; //push on the body of the else statement
-                }
-                "elif" => {
-                    iter.next();
-                    branch = make_if(iter)?;
-                }
-                _ => {}
-            }
+///Reads what's in parens, which is a tuple if there's a comma anywhere in it, like `(1, 2)` or
+///`(1,)`. `()` is the empty tuple. The opening paren should already be skipped
+fn make_group(iter: &mut Peekable<Iter<'_, Expression>>) -> Result<ExprNode, String> {
+    if let Some(Expression::Rparen) = iter.peek() {
+        iter.next();
+        return Ok(ExprNode::Tuple(vec![]));
+    }
+    let first = expression(iter, 0)?;
+    if let Some(Expression::Rparen) = iter.peek() {
+        iter.next();
+        return Ok(first);
+    }
+    let mut items = vec![first];
+    loop {
+        expect(iter, &Expression::Comma)?;
+        if let Some(Expression::Rparen) = iter.peek() {
+            iter.next();
+            return Ok(ExprNode::Tuple(items));
         }
-        Ok(ExprNode::IfStatement(
-            Box::new(condition),
-            Box::new(block),
-            Box::new(branch),
-        ))
-    // } else {
-        // Err(format!("Expected \"(\" found {}", iter.next().unwrap()))
-    // }
+        items.push(expression(iter, 0)?);
+        if let Some(Expression::Rparen) = iter.peek() {
+            iter.next();
+            return Ok(ExprNode::Tuple(items));
+        }
+    }
 }
 
+///Reads the elements of an array literal, the opening bracket should already be skipped
 fn make_array(iter: &mut Peekable<Iter<'_, Expression>>) -> Result<ExprNode, String> {
     let mut res = vec![];
     loop {
-        // println!("{:?} is next in make_array", iter.peek());
-        // println!("{:?} is the accumulated array", res);
-        // std::io::stdin().read_line(&mut String::new());
         match iter.peek() {
-            Some(Expression::Rbracket) | Some(Expression::Semicolon) => {
+            Some(Expression::Rbracket) => {
                 iter.next();
                 return Ok(ExprNode::Array(res));
             }
-            Some(Expression::Lbracket) => {
+            Some(Expression::Comma) => {
                 iter.next();
-                res.push(make_array(iter)?)
             }
-            Some(Expression::Comma) => {
+            Some(Expression::Key(k)) if k == "for" && res.len() == 1 => {
                 iter.next();
-                continue;
+                return make_comprehension(res.remove(0), iter);
             }
-            None => return Ok(ExprNode::Array(res)),//return Err("Unexpected end of file".to_owned()),
-            _ => res.push(read_line(None, iter, &vec![&Expression::Comma, &Expression::Rbracket])?),
+            None => return Err("Unexpected end of file in array".to_owned()),
+            _ => res.push(expression(iter, 0)?),
         }
     }
 }
 
+///Reads the rest of something like `[x * 2 for x in items if x > 0]` once the `for` has been skipped
+fn make_comprehension(item: ExprNode, iter: &mut Peekable<Iter<'_, Expression>>) -> Result<ExprNode, String> {
+    let name = match iter.next() {
+        Some(Expression::Ident(n)) => n.to_string(),
+        other => return Err(format!("Expected identifier, found {:?}", other)),
+    };
+    expect(iter, &Expression::Ident("in".to_owned()))?;
+    let source = expression(iter, 0)?;
+    let condition = match iter.peek() {
+        Some(Expression::Key(k)) if k == "if" => {
+            iter.next();
+            expression(iter, 0)?
+        }
+        //no condition keeps everything
+        _ => ExprNode::BoolLiteral(true),
+    };
+    expect(iter, &Expression::Rbracket)?;
+    Ok(ExprNode::Comprehension(
+        Box::new(item),
+        Box::new(name),
+        Box::new(source),
+        Box::new(condition),
+    ))
+}
+
 fn define_class(iter: &mut Peekable<Iter<'_, Expression>>) -> Result<ExprNode, String> {
-    // println!("defining class");
     let name = if let Some(Expression::Ident(_)) = iter.peek(){
         iter.next().unwrap()
     }else {
         return Err(format!("Expected identifier, found {:?}", iter.peek()));
     };
 
-    iter.next();
+    expect(iter, &Expression::Lbrace)?;
     let body = make_block(iter)?;
 
     Ok(ExprNode::Class(Box::new(name.clone()), Box::new(body)))
 }
 
+///The class can be a plain name or any expression in parens, like `new (classes[0])()`
 fn new_object(iter: &mut Peekable<Iter<'_, Expression>>) -> Result<ExprNode, String> {
-    let name = if let Some(Expression::Ident(_)) = iter.peek() {
-        iter.next().unwrap()
-    }else {
-        return Err(format!("Expected identifier, found {:?}", iter.peek()));
+    let class = match iter.next() {
+        Some(Expression::Ident(n)) => ExprNode::Name(Box::new(n.to_string())),
+        Some(Expression::Lparen) => {
+            let class = expression(iter, 0)?;
+            expect(iter, &Expression::Rparen)?;
+            class
+        }
+        other => return Err(format!("Expected identifier, found {:?}", other)),
     };
 
-    iter.next();
-    match find_params(iter) {
-        Ok(params) => Ok(ExprNode::New(Box::new(name.clone()), params)),
-        Err(e) => Err(e)
-    }
-}
\ No newline at end of file
+    expect(iter, &Expression::Lparen)?;
+    Ok(ExprNode::New(Box::new(class), find_params(iter)?))
+}
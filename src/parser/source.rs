@@ -0,0 +1,215 @@
+use super::ExprNode;
+use crate::lexer::Expression;
+
+///Turns a tree back into code that parses to the same tree. Operations always get parens so
+///precedence never changes the meaning
+pub fn to_source(node: &ExprNode) -> String {
+    let mut out = String::new();
+    match node {
+        //a whole program is a block too, but without the braces around it
+        ExprNode::Block(statements) => {
+            for s in statements.iter().filter(|s| !matches!(s, ExprNode::At(_))) {
+                write_node(s, 0, &mut out);
+                out.push_str(";\n");
+            }
+        }
+        other => write_node(other, 0, &mut out),
+    }
+    out
+}
+
+fn indent(level: usize) -> String {
+    "    ".repeat(level)
+}
+
+fn op_str(op: &Expression) -> String {
+    match op {
+        Expression::Operator(c) => c.to_string(),
+        Expression::BoolOp(s) | Expression::CompoundOp(s) => s.clone(),
+        Expression::Equal => "=".to_owned(),
+        Expression::DotDot => "..".to_owned(),
+        Expression::Ident(s) | Expression::Key(s) => s.clone(),
+        other => format!("{:?}", other),
+    }
+}
+
+fn join(nodes: &[ExprNode], level: usize) -> String {
+    nodes
+        .iter()
+        .map(|n| {
+            let mut s = String::new();
+            write_node(n, level, &mut s);
+            s
+        })
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+///Writes the statements of a block between braces, one per line
+fn write_block(node: &ExprNode, level: usize, out: &mut String) {
+    let statements = match node {
+        ExprNode::Generator(body) | ExprNode::Async(body) => return write_block(body, level, out),
+        ExprNode::Block(v) => v.as_slice(),
+        other => std::slice::from_ref(other),
+    };
+    out.push_str("{\n");
+    for s in statements.iter().filter(|s| !matches!(s, ExprNode::At(_))) {
+        out.push_str(&indent(level + 1));
+        write_node(s, level + 1, out);
+        out.push_str(";\n");
+    }
+    out.push_str(&indent(level));
+    out.push('}');
+}
+
+fn write_node(node: &ExprNode, level: usize, out: &mut String) {
+    match node {
+        ExprNode::Operation(op, l, r) => match &**op {
+            Expression::Equal => {
+                write_node(l, level, out);
+                out.push_str(" = ");
+                write_node(r, level, out);
+            }
+            Expression::Operator('.') => {
+                write_node(l, level, out);
+                out.push('.');
+                write_node(r, level, out);
+            }
+            Expression::Lbracket => {
+                write_node(l, level, out);
+                out.push('[');
+                write_node(r, level, out);
+                out.push(']');
+            }
+            _ => {
+                out.push('(');
+                write_node(l, level, out);
+                out.push_str(&format!(" {} ", op_str(op)));
+                write_node(r, level, out);
+                out.push(')');
+            }
+        },
+        ExprNode::Unary(op, operand) => {
+            out.push_str(&format!("({}", op_str(op)));
+            write_node(operand, level, out);
+            out.push(')');
+        }
+        ExprNode::StrLiteral(s) => out.push_str(&format!("\"{}\"", s)),
+        ExprNode::BytesLiteral(b) => out.push_str(&format!("b\"{}\"", crate::lexer::escape_bytes(b))),
+        ExprNode::NumLiteral(n) => out.push_str(&n.to_string()),
+        ExprNode::BoolLiteral(b) => out.push_str(&b.to_string()),
+        ExprNode::Name(n) => out.push_str(n),
+        ExprNode::Constant(inner, _) => write_node(inner, level, out),
+        ExprNode::NullLiteral => out.push_str("null"),
+        ExprNode::Illegal(Some(e)) => out.push_str(&op_str(e)),
+        ExprNode::Illegal(None) => out.push_str("<illegal>"),
+        ExprNode::Call(name, args) => out.push_str(&format!("{}({})", op_str(name), join(args, level))),
+        ExprNode::MethodCall(target, args) | ExprNode::CallExpr(target, args) => {
+            write_node(target, level, out);
+            out.push_str(&format!("({})", join(args, level)));
+        }
+        ExprNode::Block(_) => write_block(node, level, out),
+        ExprNode::Func(name, params, body) => {
+            out.push_str(&format!("fn {}({}) ", op_str(name), join(params, level)));
+            write_block(body, level, out);
+        }
+        ExprNode::Class(name, body) => {
+            out.push_str(&format!("class {} ", op_str(name)));
+            write_block(body, level, out);
+        }
+        ExprNode::New(class, args) => {
+            out.push_str("new ");
+            match &**class {
+                ExprNode::Name(n) => out.push_str(n),
+                other => {
+                    out.push('(');
+                    write_node(other, level, out);
+                    out.push(')');
+                }
+            }
+            out.push_str(&format!("({})", join(args, level)));
+        }
+        ExprNode::Delete(target) => {
+            out.push_str("delete ");
+            write_node(target, level, out);
+        }
+        ExprNode::Loop(kind, con, body) => {
+            out.push_str(&format!("{} ", kind));
+            write_node(con, level, out);
+            out.push(' ');
+            write_block(body, level, out);
+        }
+        ExprNode::ForIn(name, source, body) => {
+            out.push_str(&format!("for {} in ", name));
+            write_node(source, level, out);
+            out.push(' ');
+            write_block(body, level, out);
+        }
+        ExprNode::ForLoopDec(dec, con, inc) => {
+            out.push('(');
+            if let ExprNode::NullLiteral = **dec {
+            } else {
+                write_node(dec, level, out);
+            }
+            out.push_str("; ");
+            write_node(con, level, out);
+            out.push_str("; ");
+            write_node(inc, level, out);
+            out.push(')');
+        }
+        ExprNode::Statement(inner) | ExprNode::ElseStatement(inner) => write_node(inner, level, out),
+        ExprNode::ReturnVal(val) => {
+            out.push_str("return ");
+            write_node(val, level, out);
+        }
+        ExprNode::Yield(val) => {
+            out.push_str("yield ");
+            write_node(val, level, out);
+        }
+        ExprNode::Await(val) => {
+            out.push_str("(await ");
+            write_node(val, level, out);
+            out.push(')');
+        }
+        ExprNode::Generator(body) | ExprNode::Async(body) => write_block(body, level, out),
+        ExprNode::IfStatement(con, body, branch) => {
+            out.push_str("if ");
+            write_node(con, level, out);
+            out.push(' ');
+            write_block(body, level, out);
+            match &**branch {
+                ExprNode::NullLiteral => {}
+                ExprNode::IfStatement(..) => {
+                    //elif is just another if without the keyword
+                    out.push_str(" el");
+                    write_node(branch, level, out);
+                }
+                other => {
+                    out.push_str(" else ");
+                    write_block(other, level, out);
+                }
+            }
+        }
+        ExprNode::Array(items) => out.push_str(&format!("[{}]", join(items, level))),
+        ExprNode::Tuple(items) if items.len() == 1 => out.push_str(&format!("({},)", join(items, level))),
+        ExprNode::Tuple(items) => out.push_str(&format!("({})", join(items, level))),
+        ExprNode::Comprehension(item, name, source, condition) => {
+            out.push('[');
+            write_node(item, level, out);
+            out.push_str(&format!(" for {} in ", name));
+            write_node(source, level, out);
+            if !matches!(**condition, ExprNode::BoolLiteral(true)) {
+                out.push_str(" if ");
+                write_node(condition, level, out);
+            }
+            out.push(']');
+        }
+        ExprNode::Index(target, index) => {
+            write_node(target, level, out);
+            out.push('[');
+            write_node(index, level, out);
+            out.push(']');
+        }
+        ExprNode::At(_) | ExprNode::EOF => {}
+    }
+}
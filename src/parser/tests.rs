@@ -20,10 +20,128 @@ fn ast_generation() {
         Box::new(Expression::Ident("test".to_owned())),
         vec![],
         Box::new(ExprNode::Block(vec![ExprNode::Call(
-            Box::new(Expression::Key("print".to_owned())),
+            Box::new(Expression::Ident("print".to_owned())),
             vec![ExprNode::StrLiteral(Box::new("hello world".to_owned()))],
         )])),
     )]);
 
     assert_eq!(parse(dummy).unwrap(), expected);
 }
+
+#[test]
+fn precedence() {
+    let num = |n: f64| ExprNode::NumLiteral(Box::new(n));
+    let op = |o: char, l, r| ExprNode::Operation(Box::new(Expression::Operator(o)), Box::new(l), Box::new(r));
+
+    //1 - 2 * 3 - 4 should be (1 - (2 * 3)) - 4
+    let expected = ExprNode::Block(vec![op('-', op('-', num(1.0), op('*', num(2.0), num(3.0))), num(4.0))]);
+    assert_eq!(parse(crate::lexer::run("1 - 2 * 3 - 4;")).unwrap(), expected);
+}
+
+#[test]
+fn source_round_trip() {
+    let code = "class Pt { fn ~init(self, x) { self.x = x; } }
+    fn main(args) {
+        p = new Pt(-1 + 2 * 3);
+        for (i = 0; i < 3; i++) { p.x += i; }
+        while p.x > 0 && !false { p.x -= 1; }
+        odd = [n * 2 for n in [1, 2, 3] if n != 2];
+        for i in 0..len(odd) - 1 { println(odd[i..-1]); }
+        if p.x == 0 { return [1, \"two\"][0]; } elif p.x < 0 { delete p.x; } else { println(p.get()); }
+    }";
+    let tree = parse(crate::lexer::run(code)).unwrap();
+    let source = to_source(&tree);
+    assert_eq!(parse(crate::lexer::run(&source)).unwrap(), tree);
+}
+
+#[test]
+fn comprehensions() {
+    let tree = parse(crate::lexer::run("[x * 2 for x in items if x > 0]\n")).unwrap();
+    assert_eq!(to_source(&tree), "[(x * 2) for x in items if (x > 0)];\n");
+    let tree = parse(crate::lexer::run("[c for c in \"abc\"]\n")).unwrap();
+    assert_eq!(to_source(&tree), "[c for c in \"abc\"];\n");
+    assert!(parse(crate::lexer::run("[x for x items]\n")).is_err());
+}
+
+#[test]
+fn ranges() {
+    let tree = parse(crate::lexer::run("0..n - 1\n")).unwrap();
+    assert_eq!(to_source(&tree), "(0 .. (n - 1));\n");
+    let tree = parse(crate::lexer::run("for x in items { println(x); }\n")).unwrap();
+    assert_eq!(to_source(&tree), "for x in items {\n    println(x);\n};\n");
+    assert!(parse(crate::lexer::run("for x items {}\n")).is_err());
+}
+
+#[test]
+fn generators() {
+    let tree = parse(crate::lexer::run("fn count(n) { for i in 0..n { yield i; } }\nfn plain() { fn inner() { yield 1; } }\n")).unwrap();
+    let funcs = match &tree {
+        ExprNode::Block(v) => v,
+        other => panic!("expected a block, got {:?}", other),
+    };
+    assert!(matches!(&funcs[0], ExprNode::Func(_, _, body) if matches!(**body, ExprNode::Generator(_))));
+    //only the function that actually yields becomes a generator
+    assert!(matches!(&funcs[1], ExprNode::Func(_, _, body) if matches!(**body, ExprNode::Block(_))));
+    assert_eq!(parse(crate::lexer::run(&to_source(&tree))).unwrap(), tree);
+}
+
+#[test]
+fn async_functions() {
+    let tree = parse(crate::lexer::run("fn f(t) { println(await t + 1); }\n")).unwrap();
+    //the await gets its own statement so the task can pause there
+    assert_eq!(to_source(&tree), "fn f(t) {\n    ~await0 = (await t);\n    println((~await0 + 1));\n};\n");
+    assert_eq!(parse(crate::lexer::run(&to_source(&tree))).unwrap(), tree);
+}
+
+#[test]
+fn nesting_and_literal_limits() {
+    let lex = |src: &str| crate::lexer::run(&format!("{}\n", src));
+    let limits = Limits { max_depth: 16, max_literal: 8 };
+    let parens = |n: usize| format!("x = {}1{};", "(".repeat(n), ")".repeat(n));
+    assert!(parse_with_limits(lex(&parens(5)), limits).is_ok());
+    let err = parse_with_limits(lex(&parens(50)), limits).unwrap_err();
+    assert!(err.contains("16 levels"), "{}", err);
+    //prefix operators count too, even though they're read in a loop
+    assert!(parse_with_limits(lex(&format!("x = {}true;", "! ".repeat(50))), limits).is_err());
+    assert_eq!(parse(lex("x = - - 1;")).unwrap(), parse(lex("x = -(-1);")).unwrap());
+    assert!(parse_with_limits(lex("x = [[[[[[[[[[[[[[[[[[[[1]]]]]]]]]]]]]]]]]];"), limits).is_err());
+    assert!(parse_with_limits(lex("x = \"short\";"), limits).is_ok());
+    let err = parse_with_limits(lex("x = \"far too long\";"), limits).unwrap_err();
+    assert!(err.contains("12 bytes"), "{}", err);
+    //the defaults are back once a limited parse is done
+    assert!(parse(lex(&parens(50))).is_ok());
+    //long elif chains don't count as nesting
+    let chain: String = (0..100).map(|i| format!(" elif x == {} {{ y = {}; }}", i, i)).collect();
+    assert!(parse_with_limits(lex(&format!("if x {{ }}{} else {{ }}", chain)), limits).is_ok());
+}
+
+#[test]
+fn recovers_from_errors() {
+    let lex = |src: &str| crate::lexer::run(&format!("{}\n", src));
+    let src = "x = 1 +; y = 2; fn f(a, 3) { return a; } z = ); fn g() { a = *; b = 1; } w = 3;";
+    let errs = parse_all(lex(src)).unwrap_err();
+    assert_eq!(errs.len(), 4, "{:?}", errs);
+    assert_eq!(errs[1], "Expected a parameter name, found Number(3.0)");
+    //plain parse still stops at the first one
+    assert_eq!(parse(lex(src)).unwrap_err(), errs[0]);
+    //without errors it's the same tree
+    let good = "y = 2; w = 3;";
+    assert_eq!(parse_all(lex(good)), Ok(parse(lex(good)).unwrap()));
+    //when the `;` is the token that was wrong, the statement after it still gets checked
+    assert_eq!(parse_all(lex("x = ;\ny = 1 +;\nz = );")).unwrap_err().len(), 3);
+    assert_eq!(parse_all(lex("fn f() {\n    y = 1 +;\n    z = );\n}")).unwrap_err().len(), 2);
+}
+
+#[test]
+fn tuples_and_groups() {
+    let lex = |src: &str| crate::lexer::run(&format!("{}\n", src));
+    let num = |n: f64| ExprNode::NumLiteral(Box::new(n));
+    assert_eq!(parse_single(lex("(1)")).unwrap(), num(1.0));
+    assert_eq!(parse_single(lex("(1,)")).unwrap(), ExprNode::Tuple(vec![num(1.0)]));
+    assert_eq!(parse_single(lex("(1, 2,)")).unwrap(), ExprNode::Tuple(vec![num(1.0), num(2.0)]));
+    assert_eq!(parse_single(lex("()")).unwrap(), ExprNode::Tuple(vec![]));
+    //let is only there for reading, it's the same assignment without it
+    assert_eq!(parse(lex("let (a, b) = t;")).unwrap(), parse(lex("(a, b) = t;")).unwrap());
+    assert_eq!(parse(lex("let x;")).unwrap_err(), "Expected an assignment after let, found x");
+    assert_eq!(to_source(&parse_single(lex("((1,), (2, 3))")).unwrap()).trim(), "((1,), (2, 3))");
+}
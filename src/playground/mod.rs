@@ -0,0 +1,42 @@
+//! Exports a script as one self-contained HTML page with an editor and an output pane. The
+//! interpreter runs in the browser as WebAssembly (built from bindings/wasm), and the module gets
+//! inlined into the page so it works from anywhere, even opened straight from disk
+
+#[cfg(test)]
+mod tests;
+
+use crate::interpreter::bytes::base64_encode;
+
+const TEMPLATE: &str = include_str!("playground.html");
+
+///Where building bindings/wasm for the wasm32 target puts the module
+pub const DEFAULT_WASM: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/bindings/wasm/target/wasm32-unknown-unknown/release/emerald_playground.wasm"
+);
+
+///Builds the page, `wasm` is the compiled module from bindings/wasm
+pub fn export(title: &str, script: &str, wasm: &[u8]) -> Result<String, String> {
+    if !wasm.starts_with(b"\0asm") {
+        return Err("That isn't a WebAssembly module, build the one in bindings/wasm".to_owned());
+    }
+    //filled in the order they show up in the template, so nothing inserted gets filled in again
+    Ok(fill(&[("{{TITLE}}", escape_html(title)), ("{{SCRIPT}}", escape_html(script)), ("{{WASM}}", base64_encode(wasm))]))
+}
+
+fn fill(values: &[(&str, String)]) -> String {
+    let mut out = String::new();
+    let mut rest = TEMPLATE;
+    for (key, val) in values {
+        let at = rest.find(key).unwrap_or(rest.len());
+        out.push_str(&rest[..at]);
+        out.push_str(val);
+        rest = rest.get(at + key.len()..).unwrap_or_default();
+    }
+    out.push_str(rest);
+    out
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
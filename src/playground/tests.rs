@@ -0,0 +1,21 @@
+use super::*;
+
+#[test]
+fn base64_vectors() {
+    //RFC 4648 section 10
+    let cases = [("", ""), ("f", "Zg=="), ("fo", "Zm8="), ("foo", "Zm9v"), ("foob", "Zm9vYg=="), ("fooba", "Zm9vYmE="), ("foobar", "Zm9vYmFy")];
+    for (plain, encoded) in &cases {
+        assert_eq!(base64_encode(plain.as_bytes()), *encoded);
+    }
+}
+
+#[test]
+fn page_has_everything() {
+    let wasm = b"\0asm\x01\0\0\0";
+    let page = export("Demo", "fn main(args) { println(\"</textarea>{{WASM}}\" + 1 < 2); }", wasm).unwrap();
+    assert!(page.contains("<title>Demo</title>"));
+    assert!(page.contains("{ println(&quot;&lt;/textarea&gt;{{WASM}}&quot; + 1 &lt; 2); }</textarea>"));
+    assert!(page.contains(&format!("const WASM = \"{}\";", base64_encode(wasm))));
+    assert!(!page.contains("{{SCRIPT}}") && !page.contains("{{TITLE}}"));
+    assert_eq!(export("Demo", "", b"not wasm"), Err("That isn't a WebAssembly module, build the one in bindings/wasm".to_owned()));
+}
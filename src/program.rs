@@ -0,0 +1,144 @@
+use crate::interpreter::{self, Bindings, Runtime, Value};
+use crate::parser::ExprNode;
+use crate::{lexer, parser};
+use crate::digest::sha256;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+///What a compiled file holds. The version is checked when it's loaded, since a tree saved by another
+///version of the parser might not mean the same thing to this interpreter
+#[derive(Serialize, Deserialize)]
+struct Compiled {
+    version: String,
+    //files from before there was a format number are format 0
+    #[serde(default)]
+    format: u32,
+    //SHA-256 of the script, only cache entries have one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    source: Option<String>,
+    tree: ExprNode,
+}
+
+//bumped whenever the nodes change in a way old files can't be read as, so files from a build of the
+//same version before the change (like ones in the cache) get compiled again. 2 added where statements are
+const FORMAT: u32 = 2;
+
+///A script that's been parsed once so it can be run over and over, like once for every record
+///a host has to process
+#[derive(Debug, Clone)]
+pub struct Program {
+    tree: ExprNode,
+}
+
+impl Program {
+    pub fn compile(src: &str) -> Result<Program, String> {
+        Ok(Program::from_tree(&parse(src)?))
+    }
+
+    fn from_tree(tree: &ExprNode) -> Program {
+        //marked here once instead of every time it runs
+        Program { tree: interpreter::mark_constants(tree) }
+    }
+
+    ///Parses a script and saves the tree to `path`, so `load_compiled` can skip lexing and parsing it
+    ///again. The file only works with the same version of the interpreter
+    pub fn compile_to_file(src: &str, path: &str) -> Result<(), String> {
+        save(parse(src)?, None, Path::new(path))
+    }
+
+    ///Gives back the compiled script for `src` from the cache in `dir`, compiling it and adding it
+    ///to the cache if it isn't there yet. Entries are named after the SHA-256 of the source and keep
+    ///it inside, so editing a script just makes a new one and an entry is only used for the script it
+    ///was compiled from. A cache entry that can't be read, is from another version, or is for another
+    ///script gets compiled again, and failing to write one doesn't stop the script from running
+    pub fn cached(src: &str, dir: &Path) -> Result<Program, String> {
+        let hash: String = sha256(src.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect();
+        let path = dir.join(format!("{}.emc", hash));
+        let entry = fs::read_to_string(&path).map_err(|e| e.to_string()).and_then(|d| read(&d));
+        if let Ok(compiled) = entry {
+            if compiled.source.as_ref() == Some(&hash) {
+                return Ok(Program::from_tree(&compiled.tree));
+            }
+        }
+        let tree = parse(src)?;
+        let program = Program::from_tree(&tree);
+        //written somewhere else first so another run never reads half a file
+        let tmp = path.with_extension(format!("emc.{}", std::process::id()));
+        if fs::create_dir_all(dir).is_ok() && save(tree, Some(hash), &tmp).is_ok() && fs::rename(&tmp, &path).is_err() {
+            fs::remove_file(&tmp).unwrap_or(());
+        }
+        Ok(program)
+    }
+
+    ///Where `gem-bin` keeps its cache: `$EM_CACHE_DIR` if it's set, otherwise `emeraldscript` in the
+    ///user's cache directory
+    pub fn default_cache_dir() -> Option<PathBuf> {
+        if let Some(dir) = env::var_os("EM_CACHE_DIR") {
+            return Some(dir.into());
+        }
+        let base = env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("LOCALAPPDATA").map(PathBuf::from))
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))?;
+        Some(base.join("emeraldscript"))
+    }
+
+    ///Reads a file written by `compile_to_file`
+    pub fn load_compiled(path: &str) -> Result<Program, String> {
+        let data = fs::read_to_string(path).map_err(|e| format!("Couldn't read {}: {}", path, e))?;
+        Program::from_compiled(&data).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    ///Same as `load_compiled` for a file that's already been read
+    pub fn from_compiled(data: &str) -> Result<Program, String> {
+        Ok(Program::from_tree(&read(data)?.tree))
+    }
+
+    ///Runs the program with `bindings` as its globals and gives back the value of the last
+    ///statement. Globals it sets don't carry over to the next run, but functions it defines stay
+    ///in the runtime
+    pub fn run_with(&self, runtime: &mut Runtime, bindings: &Bindings) -> Result<Value, String> {
+        runtime.eval_bound(&self.tree, bindings)
+    }
+
+    ///Runs the program as a script, calling `main` with `args` afterwards like `gem-bin` does. Gives
+    ///back the exit code the same way `gem::run` does
+    pub fn run_main(&self, runtime: Runtime, args: Vec<Value>) -> i32 {
+        interpreter::run_with(runtime, self.tree.clone(), args)
+    }
+}
+
+fn read(data: &str) -> Result<Compiled, String> {
+    let compiled: Compiled = serde_json::from_str(data).map_err(|e| format!("not a compiled script ({})", e))?;
+    if compiled.version != env!("CARGO_PKG_VERSION") {
+        return Err(format!(
+            "compiled by version {} but this is {}, compile it again",
+            compiled.version,
+            env!("CARGO_PKG_VERSION")
+        ));
+    }
+    if compiled.format != FORMAT {
+        return Err("compiled by an older build of this version, compile it again".to_owned());
+    }
+    Ok(compiled)
+}
+
+fn save(tree: ExprNode, source: Option<String>, path: &Path) -> Result<(), String> {
+    let compiled = Compiled {
+        version: env!("CARGO_PKG_VERSION").to_owned(),
+        format: FORMAT,
+        source,
+        tree,
+    };
+    let data = serde_json::to_string(&compiled).map_err(|e| format!("Couldn't save the compiled script: {}", e))?;
+    fs::write(path, data).map_err(|e| format!("Couldn't write {}: {}", path.display(), e))
+}
+
+//statements keep where they are, so errors from a cached script still point at the right line
+fn parse(src: &str) -> Result<ExprNode, String> {
+    //the lexer only finishes a token when something comes after it
+    let (tokens, spans) = lexer::run_spanned(&format!("{}\n", src));
+    parser::parse_spanned(&tokens, &spans).map_err(|(e, _)| e)
+}